@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use tracing::warn;
+
+use crate::retry::RetryPolicy;
+
+/// Turns alarm action ARNs into names a person recognizes, e.g. `sns:oncall-pager (2
+/// subscriptions)` or `autoscaling:web-asg/scale-out`. Resolved targets are kept in `cache`, as
+/// most alarms of an account notify the same few topics.
+pub(crate) async fn resolve_actions(
+    sns: &aws_sdk_sns::Client,
+    retry: &RetryPolicy,
+    arns: &[String],
+    cache: &mut HashMap<String, String>,
+) -> Vec<String> {
+    let mut targets = vec![];
+    for arn in arns {
+        if let Some(target) = cache.get(arn) {
+            targets.push(target.clone());
+            continue;
+        }
+        let target = resolve_action(sns, retry, arn).await;
+        cache.insert(arn.clone(), target.clone());
+        targets.push(target);
+    }
+    targets
+}
+
+async fn resolve_action(sns: &aws_sdk_sns::Client, retry: &RetryPolicy, arn: &str) -> String {
+    // arn:partition:service:region:account:resource
+    let parts: Vec<&str> = arn.splitn(6, ':').collect();
+    let (service, account, resource) = match parts.as_slice() {
+        [_, _, service, _, account, resource] => (*service, *account, *resource),
+        _ => return String::from(arn),
+    };
+    match service {
+        "sns" => match subscriptions_confirmed(sns, retry, arn).await {
+            Some(subscriptions) => format!("sns:{} ({} subscriptions)", resource, subscriptions),
+            None => format!("sns:{}", resource),
+        },
+        // scalingPolicy:id:autoScalingGroupName/group:policyName/policy
+        "autoscaling" => {
+            let field = |prefix: &str| {
+                resource
+                    .split(':')
+                    .find_map(|part| part.strip_prefix(prefix))
+                    .unwrap_or_default()
+            };
+            format!(
+                "autoscaling:{}/{}",
+                field("autoScalingGroupName/"),
+                field("policyName/")
+            )
+        }
+        "lambda" => format!("lambda:{}", resource.trim_start_matches("function:")),
+        // EC2 actions are arn:aws:automate:region:ec2:stop, with no account
+        "automate" => format!("{}:{}", account, resource),
+        service => format!("{}:{}", service, resource),
+    }
+}
+
+/// Calls AWS SNS GetTopicAttributes API for the topic's confirmed subscription count. Topics in
+/// other accounts often can't be read, so a failure is logged rather than failing the account.
+/// API Reference: [GetTopicAttributes](https://docs.aws.amazon.com/sns/latest/api/API_GetTopicAttributes.html)
+async fn subscriptions_confirmed(
+    sns: &aws_sdk_sns::Client,
+    retry: &RetryPolicy,
+    topic_arn: &str,
+) -> Option<String> {
+    let request = sns.get_topic_attributes().topic_arn(topic_arn);
+    match retry
+        .call("GetTopicAttributes", || request.clone().send())
+        .await
+    {
+        Ok(resp) => resp
+            .attributes()
+            .and_then(|attributes| attributes.get("SubscriptionsConfirmed"))
+            .cloned(),
+        Err(e) => {
+            warn!(topic = %topic_arn, error = %aws_sdk_sns::Error::from(e), "unable to read SNS topic");
+            None
+        }
+    }
+}
@@ -0,0 +1,640 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{ComparisonOperator, MetricAlarm, StateValue, Statistic};
+use aws_smithy_types::date_time::Format;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::actions::resolve_actions;
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::output::text_table;
+use crate::plan::PlannedCall;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::template::{render_str, template_context};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetricAlarmDetails {
+    pub program_name: String,
+    pub alarm_name: String,
+    pub alarm_arn: String,
+    pub alarm_description: String,
+    pub namespace: String,
+    pub metric_name: String,
+    pub dimensions: Vec<AlarmDimension>,
+    pub actions_enabled: bool,
+    pub period: i32,
+    pub evaluation_periods: i32,
+    /// How many of the evaluation periods must breach, unset means all of them
+    pub datapoints_to_alarm: Option<i32>,
+    pub threshold: f64,
+    pub comparison_operator: String,
+    pub treat_missing_data: String,
+    pub statistic: String,
+    /// Percentile statistic like `p99`, set instead of `statistic`
+    #[serde(default)]
+    pub extended_statistic: String,
+    pub state_value: String,
+    pub state_reason: String,
+    /// When the alarm last changed state, as an RFC3339 timestamp
+    #[serde(default)]
+    pub state_updated_timestamp: String,
+    /// ARNs notified when the alarm fires, e.g. SNS topics
+    pub alarm_actions: Vec<String>,
+    /// Readable names of the alarm actions, like `sns:oncall-pager (2 subscriptions)`, when
+    /// they were resolved
+    #[serde(default)]
+    pub alarm_action_targets: Vec<String>,
+    /// Tags on the alarm, when they were listed
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// An `OK` Lambda errors alarm in the `Orders` account for tests to adjust
+#[cfg(test)]
+pub(crate) fn test_alarm(name: &str) -> MetricAlarmDetails {
+    serde_json::from_value(serde_json::json!({
+        "program_name": "Orders",
+        "alarm_name": name,
+        "alarm_arn": format!("arn:aws:cloudwatch:us-east-1:111111111111:alarm:{}", name),
+        "alarm_description": "",
+        "namespace": "AWS/Lambda",
+        "metric_name": "Errors",
+        "dimensions": [],
+        "actions_enabled": true,
+        "period": 60,
+        "evaluation_periods": 1,
+        "threshold": 1.0,
+        "comparison_operator": "GreaterThanThreshold",
+        "treat_missing_data": "missing",
+        "statistic": "Sum",
+        "state_value": "OK",
+        "state_reason": "",
+        "alarm_actions": [],
+    }))
+    .unwrap()
+}
+
+/// ListTagsForResource calls in flight at once for one account
+const TAG_CONCURRENCY: usize = 8;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AlarmDimension {
+    pub name: String,
+    pub value: String,
+}
+
+/// Describes every metric alarm in each account
+#[derive(Debug, Clone, Default)]
+pub struct AlarmExporter {
+    state_value: Option<StateValue>,
+    alarm_name_prefix: Option<String>,
+    alarm_regex: Option<Regex>,
+    resolve_actions: bool,
+    list_tags: bool,
+    tag_filters: Vec<(String, String)>,
+    options: RunOptions,
+}
+
+impl AlarmExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only describe alarms currently in this state
+    pub fn state_value(mut self, state_value: Option<StateValue>) -> Self {
+        self.state_value = state_value;
+        self
+    }
+
+    /// Only describe alarms whose name starts with this prefix, filtered by DescribeAlarms
+    pub fn alarm_name_prefix(mut self, prefix: Option<String>) -> Self {
+        self.alarm_name_prefix = prefix;
+        self
+    }
+
+    /// Only keep alarms whose name or description matches this regex, filtered locally
+    pub fn alarm_regex(mut self, regex: Option<Regex>) -> Self {
+        self.alarm_regex = regex;
+        self
+    }
+
+    /// Resolve alarm action ARNs into readable targets, with the subscription count of SNS topics
+    pub fn resolve_actions(mut self, resolve_actions: bool) -> Self {
+        self.resolve_actions = resolve_actions;
+        self
+    }
+
+    /// List each alarm's tags with ListTagsForResource
+    pub fn list_tags(mut self, list_tags: bool) -> Self {
+        self.list_tags = list_tags;
+        self
+    }
+
+    /// Only keep alarms with every one of these tag keys and values. Implies listing tags.
+    pub fn tag_filters(mut self, tag_filters: Vec<(String, String)>) -> Self {
+        self.tag_filters = tag_filters;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(
+        &self,
+        accounts: Vec<AccountConfig>,
+    ) -> AccountResults<Vec<MetricAlarmDetails>> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.describe(&acc).await }
+            })
+            .await
+    }
+
+    pub async fn describe(&self, acc: &AccountConfig) -> Result<Vec<MetricAlarmDetails>, Error> {
+        debug!(account = %acc.label(), "describing alarms");
+        let client = self.options.cloudwatch_client(acc).await?;
+        let alarms = describe_alarms(
+            &client,
+            &self.options.retry,
+            self.state_value.clone(),
+            self.alarm_name_prefix.clone(),
+        )
+        .await?;
+        let mut alarms: Vec<MetricAlarmDetails> = alarms
+            .iter()
+            .map(|item| to_alarm_details(&acc.namespace, item))
+            .filter(|alarm| self.matches(alarm))
+            .collect();
+        if self.list_tags || !self.tag_filters.is_empty() {
+            let arns = alarms.iter().map(|alarm| alarm.alarm_arn.clone()).collect();
+            let mut tags = list_alarm_tags(&client, &self.options.retry, arns).await?;
+            for alarm in &mut alarms {
+                alarm.tags = tags.remove(&alarm.alarm_arn).unwrap_or_default();
+            }
+            alarms.retain(|alarm| {
+                self.tag_filters
+                    .iter()
+                    .all(|(key, value)| alarm.tags.get(key) == Some(value))
+            });
+        }
+        if self.resolve_actions {
+            let sns = self.options.sns_client(acc).await?;
+            let mut cache = HashMap::new();
+            for alarm in &mut alarms {
+                alarm.alarm_action_targets =
+                    resolve_actions(&sns, &self.options.retry, &alarm.alarm_actions, &mut cache)
+                        .await;
+            }
+        }
+        Ok(alarms)
+    }
+
+    /// The AWS calls [`Self::describe`] would make for the account
+    pub fn plan(&self, acc: &AccountConfig) -> Vec<PlannedCall> {
+        let mut filters = vec![];
+        if let Some(state) = &self.state_value {
+            filters.push(format!("state {}", state.as_str()));
+        }
+        if let Some(prefix) = &self.alarm_name_prefix {
+            filters.push(format!("prefix {}", prefix));
+        }
+        let mut calls = vec![
+            PlannedCall::assume_role(acc, &self.options),
+            PlannedCall::new(
+                acc,
+                "DescribeAlarms",
+                if filters.is_empty() {
+                    String::from("every alarm")
+                } else {
+                    filters.join(", ")
+                },
+            ),
+        ];
+        if self.list_tags || !self.tag_filters.is_empty() {
+            calls.push(PlannedCall::new(acc, "ListTagsForResource", "each alarm"));
+        }
+        if self.resolve_actions {
+            calls.push(PlannedCall::new(
+                acc,
+                "GetTopicAttributes",
+                "each SNS topic in alarm actions",
+            ));
+        }
+        calls
+    }
+
+    fn matches(&self, alarm: &MetricAlarmDetails) -> bool {
+        match &self.alarm_regex {
+            Some(regex) => {
+                regex.is_match(&alarm.alarm_name) || regex.is_match(&alarm.alarm_description)
+            }
+            None => true,
+        }
+    }
+}
+
+/// Sets an alarm's state in each account with SetAlarmState, e.g. to check its actions page
+/// someone. The alarm goes back to its real state at its next evaluation.
+#[derive(Debug, Clone)]
+pub struct AlarmStateSetter {
+    alarm_name: String,
+    state_value: StateValue,
+    reason: String,
+    options: RunOptions,
+}
+
+impl AlarmStateSetter {
+    /// `alarm_name` is templated per account like widget JSON, e.g. `{{NAMESPACE}}-HighLatency`
+    pub fn new(alarm_name: impl Into<String>, state_value: StateValue) -> Self {
+        AlarmStateSetter {
+            alarm_name: alarm_name.into(),
+            state_value,
+            reason: String::from("set by cw-metrics"),
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Reason recorded in the alarm's history
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = reason.into();
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the state in every account and returns the alarm name used in each
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<String> {
+        let setter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let setter = setter.clone();
+                async move { setter.set_state(&acc).await }
+            })
+            .await
+    }
+
+    /// Calls AWS CloudWatch SetAlarmState API for the account's alarm
+    /// API Reference: [SetAlarmState](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_SetAlarmState.html)
+    pub async fn set_state(&self, acc: &AccountConfig) -> Result<String, Error> {
+        let context = template_context(acc, "", "", "", &Default::default());
+        let alarm_name = render_str(&self.alarm_name, &self.alarm_name, &context)?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        debug!(account = %acc.label(), alarm = %alarm_name, "setting alarm state");
+        let request = client
+            .set_alarm_state()
+            .alarm_name(&alarm_name)
+            .state_value(self.state_value.clone())
+            .state_reason(&self.reason);
+        self.options
+            .retry
+            .call("SetAlarmState", || request.clone().send())
+            .await
+            .map_err(aws_sdk_cloudwatch::Error::from)?;
+        Ok(alarm_name)
+    }
+}
+
+pub(crate) fn to_alarm_details(program_name: &str, item: &MetricAlarm) -> MetricAlarmDetails {
+    let comparison = match item.comparison_operator() {
+        Some(ComparisonOperator::GreaterThanOrEqualToThreshold) => "GreaterThanOrEqualToThreshold",
+        Some(ComparisonOperator::GreaterThanThreshold) => "GreaterThanThreshold",
+        Some(ComparisonOperator::LessThanThreshold) => "LessThanThreshold",
+        Some(ComparisonOperator::LessThanOrEqualToThreshold) => "LessThanOrEqualToThreshold",
+        _ => "Unknown",
+    };
+    let statistic = match item.statistic() {
+        Some(some) => match some {
+            Statistic::Average => "Average",
+            Statistic::Maximum => "Maximum",
+            Statistic::Minimum => "Minimum",
+            Statistic::SampleCount => "SampleCount",
+            Statistic::Sum => "Sum",
+            _ => "Unknown",
+        },
+        None => "",
+    };
+    MetricAlarmDetails {
+        program_name: String::from(program_name),
+        alarm_name: String::from(item.alarm_name().unwrap_or_default()),
+        alarm_arn: String::from(item.alarm_arn().unwrap_or_default()),
+        alarm_description: String::from(item.alarm_description().unwrap_or_default()),
+        namespace: String::from(item.namespace().unwrap_or_default()),
+        metric_name: String::from(item.metric_name().unwrap_or_default()),
+        dimensions: item
+            .dimensions()
+            .unwrap_or_default()
+            .iter()
+            .map(|i| AlarmDimension {
+                name: String::from(i.name().unwrap_or_default()),
+                value: String::from(i.value().unwrap_or_default()),
+            })
+            .collect(),
+        actions_enabled: item.actions_enabled().unwrap_or_default(),
+        period: item.period().unwrap_or_default(),
+        evaluation_periods: item.evaluation_periods().unwrap_or_default(),
+        datapoints_to_alarm: item.datapoints_to_alarm(),
+        threshold: item.threshold().unwrap_or_default(),
+        comparison_operator: String::from(comparison),
+        treat_missing_data: String::from(item.treat_missing_data().unwrap_or_default()),
+        statistic: String::from(statistic),
+        extended_statistic: String::from(item.extended_statistic().unwrap_or_default()),
+        state_value: String::from(
+            item.state_value()
+                .map(StateValue::as_str)
+                .unwrap_or_default(),
+        ),
+        state_reason: String::from(item.state_reason().unwrap_or_default()),
+        state_updated_timestamp: item
+            .state_updated_timestamp()
+            .and_then(|timestamp| timestamp.fmt(Format::DateTime).ok())
+            .unwrap_or_default(),
+        alarm_actions: item.alarm_actions().unwrap_or_default().to_vec(),
+        alarm_action_targets: vec![],
+        tags: BTreeMap::new(),
+    }
+}
+
+pub(crate) async fn describe_alarms(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    state_value: Option<StateValue>,
+    alarm_name_prefix: Option<String>,
+) -> Result<Vec<MetricAlarm>, aws_sdk_cloudwatch::Error> {
+    let mut alarms: Vec<MetricAlarm> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client
+            .describe_alarms()
+            .set_state_value(state_value.clone())
+            .set_alarm_name_prefix(alarm_name_prefix.clone())
+            .set_next_token(next_token);
+        let resp = retry
+            .call("DescribeAlarms", || request.clone().send())
+            .await?;
+        alarms.extend_from_slice(resp.metric_alarms().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(alarms)
+}
+
+/// Calls AWS CloudWatch ListTagsForResource API for every alarm, a few at a time, and returns
+/// the tags by alarm ARN
+/// API Reference: [ListTagsForResource](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_ListTagsForResource.html)
+async fn list_alarm_tags(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    arns: Vec<String>,
+) -> Result<HashMap<String, BTreeMap<String, String>>, Error> {
+    let mut tags = HashMap::new();
+    let mut tasks = JoinSet::new();
+    let mut arns = arns.into_iter();
+    loop {
+        while tasks.len() < TAG_CONCURRENCY {
+            let arn = match arns.next() {
+                Some(arn) => arn,
+                None => break,
+            };
+            let (client, retry) = (client.clone(), retry.clone());
+            tasks.spawn(async move {
+                let request = client.list_tags_for_resource().resource_arn(&arn);
+                let res = retry
+                    .call("ListTagsForResource", || request.clone().send())
+                    .await;
+                (arn, res)
+            });
+        }
+        let (arn, res) = match tasks.join_next().await {
+            Some(joined) => joined.map_err(|e| Error::Panic(e.to_string()))?,
+            None => break,
+        };
+        let resp = res.map_err(aws_sdk_cloudwatch::Error::from)?;
+        let alarm_tags = resp
+            .tags()
+            .unwrap_or_default()
+            .iter()
+            .map(|tag| {
+                (
+                    String::from(tag.key().unwrap_or_default()),
+                    String::from(tag.value().unwrap_or_default()),
+                )
+            })
+            .collect();
+        tags.insert(arn, alarm_tags);
+    }
+    Ok(tags)
+}
+
+pub(crate) const ALARM_COLUMNS: [&str; 22] = [
+    "program_name",
+    "alarm_name",
+    "alarm_arn",
+    "alarm_description",
+    "namespace",
+    "metric_name",
+    "dimensions",
+    "actions_enabled",
+    "period",
+    "evaluation_periods",
+    "datapoints_to_alarm",
+    "threshold",
+    "comparison_operator",
+    "treat_missing_data",
+    "statistic",
+    "extended_statistic",
+    "state_value",
+    "state_reason",
+    "state_updated_timestamp",
+    "alarm_actions",
+    "alarm_action_targets",
+    "tags",
+];
+
+/// Flattens an alarm into one string per column of [`ALARM_COLUMNS`]
+pub(crate) fn alarm_row(alarm: &MetricAlarmDetails) -> Vec<String> {
+    vec![
+        alarm.program_name.clone(),
+        alarm.alarm_name.clone(),
+        alarm.alarm_arn.clone(),
+        alarm.alarm_description.clone(),
+        alarm.namespace.clone(),
+        alarm.metric_name.clone(),
+        alarm
+            .dimensions
+            .iter()
+            .map(|d| format!("{}={}", d.name, d.value))
+            .collect::<Vec<_>>()
+            .join(";"),
+        alarm.actions_enabled.to_string(),
+        alarm.period.to_string(),
+        alarm.evaluation_periods.to_string(),
+        alarm
+            .datapoints_to_alarm
+            .map(|datapoints| datapoints.to_string())
+            .unwrap_or_default(),
+        alarm.threshold.to_string(),
+        alarm.comparison_operator.clone(),
+        alarm.treat_missing_data.clone(),
+        alarm.statistic.clone(),
+        alarm.extended_statistic.clone(),
+        alarm.state_value.clone(),
+        alarm.state_reason.clone(),
+        alarm.state_updated_timestamp.clone(),
+        alarm.alarm_actions.join(";"),
+        alarm.alarm_action_targets.join(";"),
+        alarm
+            .tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join(";"),
+    ]
+}
+
+/// Parses an `--alarm-tag` filter, splitting on the first `=`. The key may use anything AWS allows
+/// in a tag key, e.g. `aws:cloudformation:stack-name` or `app.kubernetes.io/name`.
+pub fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    match tag.split_once('=') {
+        Some((key, value))
+            if (1..=128).contains(&key.chars().count())
+                && key
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c.is_whitespace() || "_.:/+-@".contains(c)) =>
+        {
+            Ok((String::from(key), String::from(value)))
+        }
+        _ => Err(String::from(
+            "expected KEY=VALUE with a tag KEY of letters, digits, spaces and _ . : / + - @",
+        )),
+    }
+}
+
+/// Writes alarms as CSV with one row per alarm, dimensions flattened to `name=value` pairs joined
+/// by `;` so the file imports cleanly into a spreadsheet
+pub fn alarms_to_csv(alarms: &[MetricAlarmDetails]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(ALARM_COLUMNS)?;
+    for alarm in alarms {
+        writer.write_record(alarm_row(alarm))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Renders alarms as a plain text table with space-padded columns
+pub fn alarms_to_table(alarms: &[MetricAlarmDetails]) -> String {
+    let rows: Vec<Vec<String>> = alarms.iter().map(alarm_row).collect();
+    text_table(&ALARM_COLUMNS, &rows)
+}
+
+/// Renders the state of each account's alarms, the latest state change first. States are colored
+/// red for ALARM, green for OK and yellow otherwise when `color` is set.
+pub fn alarm_states_to_table(alarms: &[(String, MetricAlarmDetails)], color: bool) -> String {
+    let mut alarms: Vec<&(String, MetricAlarmDetails)> = alarms.iter().collect();
+    alarms.sort_by(|(_, a), (_, b)| b.state_updated_timestamp.cmp(&a.state_updated_timestamp));
+    let paint = |state: &str| {
+        let padded = format!("{:17}", state);
+        let code = match state {
+            "ALARM" => "31",
+            "OK" => "32",
+            _ => "33",
+        };
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, padded)
+        } else {
+            padded
+        }
+    };
+    let width = alarms
+        .iter()
+        .map(|(account, _)| account.chars().count())
+        .chain([7])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:17}  {:20}  {:width$}  ALARM\n",
+        "STATE",
+        "CHANGED",
+        "ACCOUNT",
+        width = width
+    );
+    for (account, alarm) in alarms {
+        table.push_str(&format!(
+            "{}  {:20}  {:width$}  {}\n",
+            paint(&alarm.state_value),
+            alarm.state_updated_timestamp,
+            account,
+            alarm.alarm_name,
+            width = width
+        ));
+    }
+    table
+}
+
+/// Renders alarms as a markdown table that can be pasted into a wiki page
+pub fn alarms_to_markdown(alarms: &[MetricAlarmDetails]) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace('\n', " ");
+    let mut table = format!("| {} |\n", ALARM_COLUMNS.join(" | "));
+    table.push_str(&format!("|{}\n", " --- |".repeat(ALARM_COLUMNS.len())));
+    for alarm in alarms {
+        let cells: Vec<String> = alarm_row(alarm).iter().map(|cell| escape(cell)).collect();
+        table.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tag_accepts_aws_tag_keys() {
+        for (tag, key, value) in [
+            ("team=payments", "team", "payments"),
+            (
+                "aws:cloudformation:stack-name=orders-prod",
+                "aws:cloudformation:stack-name",
+                "orders-prod",
+            ),
+            ("team-name=core", "team-name", "core"),
+            (
+                "app.kubernetes.io/name=checkout",
+                "app.kubernetes.io/name",
+                "checkout",
+            ),
+            ("Cost Center=a=b", "Cost Center", "a=b"),
+            ("owner=", "owner", ""),
+        ] {
+            assert_eq!(
+                parse_tag(tag),
+                Ok((String::from(key), String::from(value))),
+                "{}",
+                tag
+            );
+        }
+    }
+
+    #[test]
+    fn parse_tag_rejects_bad_keys() {
+        let long = format!("{}=x", "k".repeat(129));
+        for tag in ["team", "=payments", "team!=payments", &long] {
+            assert!(parse_tag(tag).is_err(), "{}", tag);
+        }
+    }
+}
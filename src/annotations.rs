@@ -0,0 +1,122 @@
+use aws_sdk_cloudwatch::model::MetricAlarm;
+use serde_json::{json, Value};
+
+/// Adds a horizontal annotation at the threshold of every alarm watching one of the widget's
+/// metrics, so the graph shows where it would fire. An alarm matches a metric row when the
+/// namespace, metric name and dimensions are the same. Returns how many annotations were added.
+pub(crate) fn annotate_alarm_thresholds(widget: &mut Value, alarms: &[MetricAlarm]) -> usize {
+    let mut annotations = vec![];
+    for metric in widget_metrics(widget) {
+        for alarm in alarms.iter().filter(|alarm| watches(alarm, &metric)) {
+            let threshold = match alarm.threshold() {
+                Some(threshold) => threshold,
+                None => continue,
+            };
+            annotations.push(json!({
+                "label": format!(
+                    "{} ({} {})",
+                    alarm.alarm_name().unwrap_or_default(),
+                    alarm
+                        .comparison_operator()
+                        .map(|op| op.as_str())
+                        .unwrap_or_default(),
+                    threshold
+                ),
+                "value": threshold,
+            }));
+        }
+    }
+    let added = annotations.len();
+    if added > 0 {
+        let horizontal = &mut widget["annotations"]["horizontal"];
+        if !horizontal.is_array() {
+            *horizontal = Value::Array(vec![]);
+        }
+        if let Some(horizontal) = horizontal.as_array_mut() {
+            horizontal.extend(annotations);
+        }
+    }
+    added
+}
+
+/// A metric graphed by a widget: namespace, metric name and dimension name/value pairs
+#[derive(Debug, PartialEq)]
+struct WidgetMetric {
+    namespace: String,
+    metric_name: String,
+    dimensions: Vec<(String, String)>,
+}
+
+/// Reads the widget's `metrics` rows, expanding the `.` (same value as the row above) and `...`
+/// (the rest of the row above) shorthands. Expression rows are skipped.
+fn widget_metrics(widget: &Value) -> Vec<WidgetMetric> {
+    let mut metrics = vec![];
+    let mut previous: Vec<String> = vec![];
+    for row in widget["metrics"].as_array().into_iter().flatten() {
+        let values: Vec<String> = row
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map_while(|value| value.as_str().map(String::from))
+            .collect();
+        if values.len() < 2 {
+            continue;
+        }
+        let values = expand_row(values, &previous);
+        let dimensions = values[2..]
+            .chunks_exact(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        metrics.push(WidgetMetric {
+            namespace: values[0].clone(),
+            metric_name: values[1].clone(),
+            dimensions,
+        });
+        previous = values;
+    }
+    metrics
+}
+
+fn expand_row(values: Vec<String>, previous: &[String]) -> Vec<String> {
+    let values = match values.iter().position(|value| value == "...") {
+        Some(index) => {
+            let after = &values[index + 1..];
+            let filled = previous.len().saturating_sub(index + after.len());
+            let mut expanded = values[..index].to_vec();
+            expanded.extend(previous.iter().skip(index).take(filled).cloned());
+            expanded.extend_from_slice(after);
+            expanded
+        }
+        None => values,
+    };
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| match previous.get(index) {
+            Some(above) if value == "." => above.clone(),
+            _ => value,
+        })
+        .collect()
+}
+
+fn watches(alarm: &MetricAlarm, metric: &WidgetMetric) -> bool {
+    if alarm.namespace() != Some(metric.namespace.as_str())
+        || alarm.metric_name() != Some(metric.metric_name.as_str())
+    {
+        return false;
+    }
+    let mut alarm_dimensions: Vec<(&str, &str)> = alarm
+        .dimensions()
+        .unwrap_or_default()
+        .iter()
+        .map(|d| (d.name().unwrap_or_default(), d.value().unwrap_or_default()))
+        .collect();
+    let mut widget_dimensions: Vec<(&str, &str)> = metric
+        .dimensions
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    alarm_dimensions.sort_unstable();
+    widget_dimensions.sort_unstable();
+    alarm_dimensions == widget_dimensions
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{AnomalyDetector, MetricDataQuery};
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::alarms::AlarmDimension;
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnomalyDetectorDetails {
+    pub program_name: String,
+    pub region: String,
+    /// `single_metric` or `metric_math`
+    pub kind: String,
+    pub namespace: String,
+    pub metric_name: String,
+    pub dimensions: Vec<AlarmDimension>,
+    pub stat: String,
+    /// The queries of a metric math detector, as `id: expression` or
+    /// `id: namespace metric_name stat`
+    pub metric_math: Vec<String>,
+    /// Periods left out of the model's training, as `start/end` timestamps
+    pub excluded_time_ranges: Vec<String>,
+    pub metric_timezone: String,
+    pub state_value: String,
+}
+
+/// Describes the anomaly detection models of each account
+#[derive(Debug, Clone, Default)]
+pub struct AnomalyDetectorExporter {
+    options: RunOptions,
+}
+
+impl AnomalyDetectorExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(
+        &self,
+        accounts: Vec<AccountConfig>,
+    ) -> AccountResults<Vec<AnomalyDetectorDetails>> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.describe(&acc).await }
+            })
+            .await
+    }
+
+    pub async fn describe(
+        &self,
+        acc: &AccountConfig,
+    ) -> Result<Vec<AnomalyDetectorDetails>, Error> {
+        debug!(account = %acc.label(), "describing anomaly detectors");
+        let client = self.options.cloudwatch_client(acc).await?;
+        let detectors = describe_anomaly_detectors(&client, &self.options.retry).await?;
+        Ok(detectors
+            .iter()
+            .map(|detector| to_detector_details(acc, detector))
+            .collect())
+    }
+}
+
+fn to_detector_details(acc: &AccountConfig, detector: &AnomalyDetector) -> AnomalyDetectorDetails {
+    let mut details = AnomalyDetectorDetails {
+        program_name: acc.namespace.clone(),
+        region: acc.region.clone(),
+        kind: String::new(),
+        namespace: String::new(),
+        metric_name: String::new(),
+        dimensions: vec![],
+        stat: String::new(),
+        metric_math: vec![],
+        excluded_time_ranges: vec![],
+        metric_timezone: String::new(),
+        state_value: String::from(
+            detector
+                .state_value()
+                .map(|state| state.as_str())
+                .unwrap_or_default(),
+        ),
+    };
+    if let Some(single) = detector.single_metric_anomaly_detector() {
+        details.kind = String::from("single_metric");
+        details.namespace = String::from(single.namespace().unwrap_or_default());
+        details.metric_name = String::from(single.metric_name().unwrap_or_default());
+        details.stat = String::from(single.stat().unwrap_or_default());
+        details.dimensions = single
+            .dimensions()
+            .unwrap_or_default()
+            .iter()
+            .map(|d| AlarmDimension {
+                name: String::from(d.name().unwrap_or_default()),
+                value: String::from(d.value().unwrap_or_default()),
+            })
+            .collect();
+    }
+    if let Some(math) = detector.metric_math_anomaly_detector() {
+        details.kind = String::from("metric_math");
+        details.metric_math = math
+            .metric_data_queries()
+            .unwrap_or_default()
+            .iter()
+            .map(query_summary)
+            .collect();
+    }
+    if let Some(configuration) = detector.configuration() {
+        details.metric_timezone = String::from(configuration.metric_timezone().unwrap_or_default());
+        details.excluded_time_ranges = configuration
+            .excluded_time_ranges()
+            .unwrap_or_default()
+            .iter()
+            .map(|range| {
+                let format = |time: Option<&DateTime>| {
+                    time.and_then(|time| time.fmt(Format::DateTime).ok())
+                        .unwrap_or_default()
+                };
+                format!(
+                    "{}/{}",
+                    format(range.start_time()),
+                    format(range.end_time())
+                )
+            })
+            .collect();
+    }
+    details
+}
+
+fn query_summary(query: &MetricDataQuery) -> String {
+    let id = query.id().unwrap_or_default();
+    if let Some(expression) = query.expression() {
+        return format!("{}: {}", id, expression);
+    }
+    let stat = query.metric_stat();
+    let metric = stat.and_then(|stat| stat.metric());
+    format!(
+        "{}: {} {} {}",
+        id,
+        metric.and_then(|m| m.namespace()).unwrap_or_default(),
+        metric.and_then(|m| m.metric_name()).unwrap_or_default(),
+        stat.and_then(|stat| stat.stat()).unwrap_or_default()
+    )
+}
+
+const DETECTOR_COLUMNS: [&str; 11] = [
+    "program_name",
+    "region",
+    "kind",
+    "namespace",
+    "metric_name",
+    "dimensions",
+    "stat",
+    "metric_math",
+    "excluded_time_ranges",
+    "metric_timezone",
+    "state_value",
+];
+
+/// Writes anomaly detectors as CSV with one row per detector, lists joined by `;`
+pub fn detectors_to_csv(detectors: &[AnomalyDetectorDetails]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(DETECTOR_COLUMNS)?;
+    for detector in detectors {
+        writer.write_record([
+            detector.program_name.as_str(),
+            detector.region.as_str(),
+            detector.kind.as_str(),
+            detector.namespace.as_str(),
+            detector.metric_name.as_str(),
+            detector
+                .dimensions
+                .iter()
+                .map(|d| format!("{}={}", d.name, d.value))
+                .collect::<Vec<_>>()
+                .join(";")
+                .as_str(),
+            detector.stat.as_str(),
+            detector.metric_math.join(";").as_str(),
+            detector.excluded_time_ranges.join(";").as_str(),
+            detector.metric_timezone.as_str(),
+            detector.state_value.as_str(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Calls AWS CloudWatch DescribeAnomalyDetectors API, following `next_token` until every
+/// detector is returned
+/// API Reference: [DescribeAnomalyDetectors](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_DescribeAnomalyDetectors.html)
+async fn describe_anomaly_detectors(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+) -> Result<Vec<AnomalyDetector>, aws_sdk_cloudwatch::Error> {
+    let mut detectors: Vec<AnomalyDetector> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client
+            .describe_anomaly_detectors()
+            .set_next_token(next_token);
+        let resp = retry
+            .call("DescribeAnomalyDetectors", || request.clone().send())
+            .await?;
+        detectors.extend_from_slice(resp.anomaly_detectors().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(detectors)
+}
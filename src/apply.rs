@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{ComparisonOperator, Dimension, MetricAlarm, Statistic};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::output::text_table;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::template::{render_template, template_context};
+
+/// DescribeAlarms takes at most this many alarm names per call
+const MAX_ALARM_NAMES: usize = 100;
+
+/// Alarm definitions for the `alarms apply` subcommand, templated the same way as widget JSON
+#[derive(Deserialize, Debug)]
+pub struct AlarmDefinitions {
+    pub alarms: Vec<AlarmDefinition>,
+}
+
+/// One alarm to create or update with PutMetricAlarm
+#[derive(Deserialize, Debug, Clone)]
+pub struct AlarmDefinition {
+    pub alarm_name: String,
+    #[serde(default)]
+    pub alarm_description: String,
+    pub namespace: String,
+    pub metric_name: String,
+    #[serde(default)]
+    pub dimensions: BTreeMap<String, String>,
+    pub statistic: String,
+    pub period: i32,
+    pub evaluation_periods: i32,
+    pub datapoints_to_alarm: Option<i32>,
+    pub threshold: f64,
+    pub comparison_operator: String,
+    #[serde(default = "default_treat_missing_data")]
+    pub treat_missing_data: String,
+    #[serde(default = "default_actions_enabled")]
+    pub actions_enabled: bool,
+    #[serde(default)]
+    pub alarm_actions: Vec<String>,
+}
+
+fn default_treat_missing_data() -> String {
+    String::from("missing")
+}
+
+fn default_actions_enabled() -> bool {
+    true
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyOutcome {
+    Created,
+    Updated,
+    /// The alarm already matches its definition, so PutMetricAlarm wasn't called
+    Unchanged,
+}
+
+impl ApplyOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApplyOutcome::Created => "created",
+            ApplyOutcome::Updated => "updated",
+            ApplyOutcome::Unchanged => "unchanged",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AppliedAlarm {
+    pub program_name: String,
+    pub region: String,
+    pub alarm_name: String,
+    pub outcome: ApplyOutcome,
+}
+
+/// Creates or updates alarms in each account from a templated definitions file
+#[derive(Debug, Clone)]
+pub struct AlarmApplier {
+    definitions_path: PathBuf,
+    vars: BTreeMap<String, String>,
+    dry_run: bool,
+    options: RunOptions,
+}
+
+impl AlarmApplier {
+    /// `definitions_path` is a JSON file with an `alarms` list, templated like widget JSON so
+    /// names and dimensions can use `{{NAMESPACE}}` and the other account variables
+    pub fn new(definitions_path: impl Into<PathBuf>) -> Self {
+        AlarmApplier {
+            definitions_path: definitions_path.into(),
+            vars: BTreeMap::new(),
+            dry_run: false,
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Extra `{{KEY}}` template variables, overriding the same key in an account's `vars`
+    pub fn vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    /// Only report what would be created or updated, without calling PutMetricAlarm
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<Vec<AppliedAlarm>> {
+        let applier = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let applier = applier.clone();
+                async move { applier.apply(&acc).await }
+            })
+            .await
+    }
+
+    /// Puts every alarm of the definitions that is missing or differs in the account
+    pub async fn apply(&self, acc: &AccountConfig) -> Result<Vec<AppliedAlarm>, Error> {
+        let definitions = render_template(
+            &self.definitions_path,
+            &template_context(acc, "", "", "", &self.vars),
+        )?;
+        let definitions: AlarmDefinitions =
+            serde_json::from_str(&definitions).map_err(|source| Error::Template {
+                path: self.definitions_path.clone(),
+                source,
+            })?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        let names: Vec<String> = definitions
+            .alarms
+            .iter()
+            .map(|alarm| alarm.alarm_name.clone())
+            .collect();
+        let existing = describe_alarms_by_name(&client, &self.options.retry, &names).await?;
+
+        let mut applied = vec![];
+        for definition in &definitions.alarms {
+            let outcome = match existing
+                .iter()
+                .find(|alarm| alarm.alarm_name() == Some(definition.alarm_name.as_str()))
+            {
+                Some(alarm) if matches(definition, alarm) => ApplyOutcome::Unchanged,
+                Some(_) => ApplyOutcome::Updated,
+                None => ApplyOutcome::Created,
+            };
+            if outcome != ApplyOutcome::Unchanged && !self.dry_run {
+                debug!(account = %acc.label(), alarm = %definition.alarm_name, "putting alarm");
+                put_metric_alarm(&client, &self.options.retry, definition).await?;
+            }
+            applied.push(AppliedAlarm {
+                program_name: acc.namespace.clone(),
+                region: acc.region.clone(),
+                alarm_name: definition.alarm_name.clone(),
+                outcome,
+            });
+        }
+        Ok(applied)
+    }
+}
+
+/// Whether the existing alarm already has every setting of the definition
+fn matches(definition: &AlarmDefinition, alarm: &MetricAlarm) -> bool {
+    let mut dimensions: Vec<(&str, &str)> = alarm
+        .dimensions()
+        .unwrap_or_default()
+        .iter()
+        .map(|d| (d.name().unwrap_or_default(), d.value().unwrap_or_default()))
+        .collect();
+    dimensions.sort_unstable();
+    let mut actions: Vec<&String> = alarm.alarm_actions().unwrap_or_default().iter().collect();
+    actions.sort_unstable();
+    let mut wanted_actions: Vec<&String> = definition.alarm_actions.iter().collect();
+    wanted_actions.sort_unstable();
+
+    alarm.alarm_description().unwrap_or_default() == definition.alarm_description
+        && alarm.namespace() == Some(definition.namespace.as_str())
+        && alarm.metric_name() == Some(definition.metric_name.as_str())
+        && dimensions
+            == definition
+                .dimensions
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect::<Vec<_>>()
+        && alarm.statistic().map(Statistic::as_str) == Some(definition.statistic.as_str())
+        && alarm.period() == Some(definition.period)
+        && alarm.evaluation_periods() == Some(definition.evaluation_periods)
+        && alarm.datapoints_to_alarm() == definition.datapoints_to_alarm
+        && alarm.threshold() == Some(definition.threshold)
+        && alarm.comparison_operator().map(ComparisonOperator::as_str)
+            == Some(definition.comparison_operator.as_str())
+        && alarm.treat_missing_data().unwrap_or("missing") == definition.treat_missing_data
+        && alarm.actions_enabled().unwrap_or(true) == definition.actions_enabled
+        && actions == wanted_actions
+}
+
+const APPLIED_COLUMNS: [&str; 4] = ["program_name", "region", "alarm_name", "outcome"];
+
+/// Renders the outcome of every alarm as a plain text table with space-padded columns
+pub fn applied_to_table(applied: &[AppliedAlarm]) -> String {
+    let rows: Vec<Vec<String>> = applied
+        .iter()
+        .map(|alarm| {
+            vec![
+                alarm.program_name.clone(),
+                alarm.region.clone(),
+                alarm.alarm_name.clone(),
+                String::from(alarm.outcome.as_str()),
+            ]
+        })
+        .collect();
+    text_table(&APPLIED_COLUMNS, &rows)
+}
+
+/// Calls AWS CloudWatch DescribeAlarms API for the given alarm names, in batches of 100
+/// API Reference: [DescribeAlarms](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_DescribeAlarms.html)
+async fn describe_alarms_by_name(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    names: &[String],
+) -> Result<Vec<MetricAlarm>, aws_sdk_cloudwatch::Error> {
+    let mut alarms: Vec<MetricAlarm> = vec![];
+    for batch in names.chunks(MAX_ALARM_NAMES) {
+        let mut next_token: Option<String> = None;
+        loop {
+            let request = client
+                .describe_alarms()
+                .set_alarm_names(Some(batch.to_vec()))
+                .set_next_token(next_token);
+            let resp = retry
+                .call("DescribeAlarms", || request.clone().send())
+                .await?;
+            alarms.extend_from_slice(resp.metric_alarms().unwrap_or_default());
+            next_token = resp.next_token().map(String::from);
+            if next_token.is_none() {
+                break;
+            }
+        }
+    }
+    Ok(alarms)
+}
+
+/// Calls AWS CloudWatch PutMetricAlarm API, which creates the alarm or replaces its settings
+/// API Reference: [PutMetricAlarm](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_PutMetricAlarm.html)
+async fn put_metric_alarm(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    definition: &AlarmDefinition,
+) -> Result<(), aws_sdk_cloudwatch::Error> {
+    let dimensions = definition
+        .dimensions
+        .iter()
+        .map(|(name, value)| Dimension::builder().name(name).value(value).build())
+        .collect();
+    let request = client
+        .put_metric_alarm()
+        .alarm_name(&definition.alarm_name)
+        .alarm_description(&definition.alarm_description)
+        .namespace(&definition.namespace)
+        .metric_name(&definition.metric_name)
+        .set_dimensions(Some(dimensions))
+        .statistic(Statistic::from(definition.statistic.as_str()))
+        .period(definition.period)
+        .evaluation_periods(definition.evaluation_periods)
+        .set_datapoints_to_alarm(definition.datapoints_to_alarm)
+        .threshold(definition.threshold)
+        .comparison_operator(ComparisonOperator::from(
+            definition.comparison_operator.as_str(),
+        ))
+        .treat_missing_data(&definition.treat_missing_data)
+        .actions_enabled(definition.actions_enabled)
+        .set_alarm_actions(Some(definition.alarm_actions.clone()));
+    retry
+        .call("PutMetricAlarm", || request.clone().send())
+        .await?;
+    Ok(())
+}
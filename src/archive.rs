@@ -0,0 +1,67 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+use crate::error::Error;
+
+/// Bundles files into one archive, e.g. to attach a whole run to a ticket. The format follows
+/// the extension: `.zip`, `.tar`, or `.tar.gz`/`.tgz`. Files keep their relative paths.
+pub fn write_archive(path: &Path, files: &[PathBuf]) -> Result<(), Error> {
+    let write_error = |source| Error::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|source| Error::Write {
+            path: dir.to_path_buf(),
+            source,
+        })?;
+    }
+    let archive = File::create(path).map_err(write_error)?;
+    let name = path.to_string_lossy();
+    if name.ends_with(".zip") {
+        write_zip(archive, files).map_err(write_error)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let encoder = GzEncoder::new(archive, Compression::default());
+        write_tar(encoder, files)
+            .and_then(|encoder| encoder.finish())
+            .map(|_| ())
+            .map_err(write_error)
+    } else {
+        write_tar(archive, files).map(|_| ()).map_err(write_error)
+    }
+}
+
+fn write_zip(archive: File, files: &[PathBuf]) -> std::io::Result<()> {
+    let mut zip = ZipWriter::new(archive);
+    for file in files {
+        zip.start_file(entry_name(file), FileOptions::default())?;
+        zip.write_all(&std::fs::read(file)?)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn write_tar<W: Write>(archive: W, files: &[PathBuf]) -> std::io::Result<W> {
+    let mut tar = tar::Builder::new(archive);
+    for file in files {
+        tar.append_path_with_name(file, entry_name(file))?;
+    }
+    tar.into_inner()
+}
+
+/// The path inside the archive, without any root or `..` that would escape it when extracted
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
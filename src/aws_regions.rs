@@ -0,0 +1,39 @@
+use aws_types::region::Region;
+
+pub trait AWSRegionName {
+    fn name(self: Self) -> &'static str;
+}
+
+impl AWSRegionName for AirportCode {
+    fn name(self: Self) -> &'static str {
+        match self {
+            AirportCode::IAD => "us-east-1",
+            AirportCode::PDX => "us-west-2",
+            AirportCode::DUB => "eu-west-1",
+        }
+    }
+}
+
+/// Builds a [`Region`] from a name like `ap-southeast-2`. Anything that isn't shaped like
+/// an AWS region is rejected rather than falling back to a default region.
+pub fn parse_region(region: &str) -> Result<Region, String> {
+    let parts: Vec<&str> = region.split('-').collect();
+    let is_word = |p: &&str| !p.is_empty() && p.chars().all(|c| c.is_ascii_lowercase());
+    let valid = parts.len() >= 3
+        && parts[0].len() == 2
+        && parts[..parts.len() - 1].iter().all(is_word)
+        && parts[parts.len() - 1].parse::<u8>().is_ok();
+    if valid {
+        Ok(Region::new(String::from(region)))
+    } else {
+        Err(format!("invalid AWS region {:?}", region))
+    }
+}
+
+/// AirportCode enum represents the 3-letter international airport code closest to a data center region
+#[derive(Debug, Copy, Clone)]
+pub enum AirportCode {
+    IAD,
+    PDX,
+    DUB,
+}
@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::alarms::MetricAlarmDetails;
+use crate::error::Error;
+use crate::output::text_table;
+
+/// Expected settings of one alarm. A golden file is a JSON list of these, and the JSON written
+/// by `alarms --format json` can be used as one as is.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct BaselineAlarm {
+    pub alarm_name: String,
+    /// Left out to accept any threshold
+    pub threshold: Option<f64>,
+    /// Left out to accept any comparison operator
+    pub comparison_operator: Option<String>,
+}
+
+impl BaselineAlarm {
+    /// Reads a golden file of expected alarms
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Vec<BaselineAlarm>, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        serde_json::from_str(&contents).map_err(|source| Error::Baseline {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+impl From<&MetricAlarmDetails> for BaselineAlarm {
+    fn from(alarm: &MetricAlarmDetails) -> Self {
+        BaselineAlarm {
+            alarm_name: alarm.alarm_name.clone(),
+            threshold: Some(alarm.threshold),
+            comparison_operator: Some(alarm.comparison_operator.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DifferenceKind {
+    /// The account has no alarm with the baseline's name
+    Missing,
+    /// The alarm is new since the previous snapshot
+    Created,
+    /// The alarm was in the previous snapshot but is gone
+    Deleted,
+    Threshold,
+    ComparisonOperator,
+    AlarmActions,
+}
+
+impl DifferenceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DifferenceKind::Missing => "missing",
+            DifferenceKind::Created => "created",
+            DifferenceKind::Deleted => "deleted",
+            DifferenceKind::Threshold => "threshold",
+            DifferenceKind::ComparisonOperator => "comparison_operator",
+            DifferenceKind::AlarmActions => "alarm_actions",
+        }
+    }
+}
+
+/// One way an account's alarms differ from the baseline, or from the previous snapshot
+#[derive(Serialize, Debug, Clone)]
+pub struct AlarmDifference {
+    pub account: String,
+    pub alarm_name: String,
+    pub kind: DifferenceKind,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Compares an account's alarms with the baseline, matching them by name. Alarms the baseline
+/// doesn't list are ignored.
+pub fn diff_alarms(
+    account: &str,
+    baseline: &[BaselineAlarm],
+    alarms: &[MetricAlarmDetails],
+) -> Vec<AlarmDifference> {
+    let mut differences = vec![];
+    let mut push = |alarm_name: &str, kind, expected: String, actual: String| {
+        differences.push(AlarmDifference {
+            account: String::from(account),
+            alarm_name: String::from(alarm_name),
+            kind,
+            expected,
+            actual,
+        })
+    };
+    for expected in baseline {
+        let alarm = match alarms.iter().find(|a| a.alarm_name == expected.alarm_name) {
+            Some(alarm) => alarm,
+            None => {
+                push(
+                    &expected.alarm_name,
+                    DifferenceKind::Missing,
+                    String::from("present"),
+                    String::from("missing"),
+                );
+                continue;
+            }
+        };
+        if let Some(threshold) = expected.threshold.filter(|t| *t != alarm.threshold) {
+            push(
+                &alarm.alarm_name,
+                DifferenceKind::Threshold,
+                threshold.to_string(),
+                alarm.threshold.to_string(),
+            );
+        }
+        if let Some(operator) = expected
+            .comparison_operator
+            .as_ref()
+            .filter(|op| **op != alarm.comparison_operator)
+        {
+            push(
+                &alarm.alarm_name,
+                DifferenceKind::ComparisonOperator,
+                operator.clone(),
+                alarm.comparison_operator.clone(),
+            );
+        }
+    }
+    differences
+}
+
+const DIFFERENCE_COLUMNS: [&str; 5] = ["account", "alarm_name", "kind", "expected", "actual"];
+
+fn difference_row(difference: &AlarmDifference) -> Vec<String> {
+    vec![
+        difference.account.clone(),
+        difference.alarm_name.clone(),
+        String::from(difference.kind.as_str()),
+        difference.expected.clone(),
+        difference.actual.clone(),
+    ]
+}
+
+/// Writes alarm differences as CSV with one row per difference
+pub fn differences_to_csv(differences: &[AlarmDifference]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(DIFFERENCE_COLUMNS)?;
+    for difference in differences {
+        writer.write_record(difference_row(difference))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Renders alarm differences as a plain text table with space-padded columns
+pub fn differences_to_table(differences: &[AlarmDifference]) -> String {
+    let rows: Vec<Vec<String>> = differences.iter().map(difference_row).collect();
+    text_table(&DIFFERENCE_COLUMNS, &rows)
+}
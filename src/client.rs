@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use aws_config::environment::region::EnvironmentVariableRegionProvider;
+use aws_config::meta::region::RegionProviderChain;
+use aws_config::profile::{ProfileFileCredentialsProvider, ProfileFileRegionProvider};
+use aws_sdk_cloudwatch::{Client as cloudwatchClient, PKG_VERSION};
+use aws_sdk_sts::Client as stsClient;
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_client::http_connector::HttpConnector;
+use aws_smithy_client::{conns, hyper_ext};
+use aws_smithy_types::retry::RetryConfig;
+use aws_types::credentials::SharedCredentialsProvider;
+use aws_types::region::Region;
+use aws_types::sdk_config::Builder as SdkConfigBuilder;
+use aws_types::SdkConfig;
+use tokio::sync::OnceCell;
+use tracing::{debug, trace, warn};
+
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::sso;
+
+/// Role session name used when neither the caller nor the account config sets one
+pub const DEFAULT_SESSION_NAME: &str = "dev-cli";
+
+/// Assumed-role credentials are refreshed once they are this close to expiring
+pub const CREDENTIAL_REFRESH_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// The SDK config loaded from the environment, shared by every client of the process
+static BASE_CONFIG: OnceCell<SdkConfig> = OnceCell::const_new();
+
+/// Where the base config takes its starting credentials from, see [`set_base_credentials`]
+static BASE_CREDENTIALS: OnceLock<BaseCredentials> = OnceLock::new();
+
+/// Where the starting credentials that assume every account's role come from
+#[derive(Debug, Clone, Default)]
+pub struct BaseCredentials {
+    /// Profile used in place of the default chain's, which picks it from `$AWS_PROFILE`
+    pub profile: Option<String>,
+    /// Run `aws sso login` when the profile is an SSO profile without a valid token
+    pub sso_login: bool,
+}
+
+/// Sets where the [`base_config`] takes its credentials from. Has to be called before the
+/// first client is built; later calls are ignored.
+pub fn set_base_credentials(credentials: BaseCredentials) {
+    BASE_CREDENTIALS.get_or_init(|| credentials);
+}
+
+/// Settings for the `sts:AssumeRole` call made for an account
+#[derive(Debug, Clone)]
+pub struct AssumeRoleOptions {
+    pub role_arn: String,
+    pub session_name: String,
+    pub session_duration: Option<i32>,
+    pub external_id: Option<String>,
+    /// Role assumed first, whose session assumes `role_arn`. AWS limits chained sessions to
+    /// one hour.
+    pub via_role_arn: Option<String>,
+    /// MFA device whose code is sent with the call made with the starting credentials, which is
+    /// the `via_role_arn` one when chaining
+    pub mfa_serial: Option<String>,
+}
+
+impl AssumeRoleOptions {
+    /// Combines the assume-role settings for an account; explicit values win over the account's config
+    pub fn for_account(
+        acc: &AccountConfig,
+        session_name: Option<&str>,
+        session_duration: Option<i32>,
+    ) -> Self {
+        AssumeRoleOptions {
+            role_arn: acc.role_arn.clone(),
+            session_name: session_name
+                .map(String::from)
+                .or_else(|| acc.session_name.clone())
+                .unwrap_or_else(|| String::from(DEFAULT_SESSION_NAME)),
+            session_duration: session_duration.or(acc.session_duration),
+            external_id: acc.external_id.clone(),
+            via_role_arn: acc.via_role_arn.clone(),
+            mfa_serial: acc.mfa_serial.clone(),
+        }
+    }
+}
+
+/// Loads the credential chain and region from the environment the first time it's called, with
+/// one HTTPS connector. Reading profiles and IMDS is slow, so every other config is derived from
+/// this one instead. An SSO profile's sign-in is checked first, see [`sso::check_login`].
+pub async fn base_config() -> &'static SdkConfig {
+    BASE_CONFIG
+        .get_or_init(|| async {
+            let credentials = BASE_CREDENTIALS.get().cloned().unwrap_or_default();
+            let profile = credentials.profile.as_deref();
+            if let Err(e) = sso::check_login(profile, credentials.sso_login).await {
+                warn!(error = %e, "unable to sign in to AWS SSO");
+            }
+            // a prebuilt connector is cloned into every client, sharing its connection pool
+            let connector = DynConnector::new(hyper_ext::Adapter::builder().build(conns::https()));
+            let mut loader =
+                aws_config::from_env().http_connector(HttpConnector::Prebuilt(Some(connector)));
+            if let Some(profile) = profile {
+                debug!(profile, "loading base credentials from profile");
+                loader = loader
+                    .credentials_provider(
+                        ProfileFileCredentialsProvider::builder()
+                            .profile_name(profile)
+                            .build(),
+                    )
+                    // $AWS_REGION still wins over the profile's region, as for the default chain
+                    .region(
+                        RegionProviderChain::first_try(EnvironmentVariableRegionProvider::new())
+                            .or_else(
+                                ProfileFileRegionProvider::builder()
+                                    .profile_name(profile)
+                                    .build(),
+                            ),
+                    );
+            }
+            let shared_config = loader.load().await;
+            trace!(?shared_config, "loaded SdkConfig");
+            shared_config
+        })
+        .await
+}
+
+/// Starts a config for `region` from the [`base_config`], using `credentials` in place of the
+/// default chain when given. Clients built from it share the base config's connection pool.
+pub async fn config_builder(
+    region: &Region,
+    credentials: Option<aws_types::Credentials>,
+) -> SdkConfigBuilder {
+    let base = base_config().await;
+    let credentials = match credentials {
+        Some(creds) => Some(SharedCredentialsProvider::new(creds)),
+        None => base.credentials_provider().cloned(),
+    };
+    let mut builder = SdkConfig::builder().region(region.clone());
+    builder.set_credentials_provider(credentials);
+    builder.set_endpoint_resolver(base.endpoint_resolver());
+    builder.set_retry_config(base.retry_config().cloned());
+    builder.set_timeout_config(base.timeout_config().cloned());
+    builder.set_sleep_impl(base.sleep_impl());
+    builder.set_app_name(base.app_name().cloned());
+    builder.set_http_connector(base.http_connector().cloned());
+    builder
+}
+
+pub async fn get_cw_client(region: &Region) -> cloudwatchClient {
+    debug!(
+        version = PKG_VERSION,
+        region = region.as_ref(),
+        "creating CloudWatch client"
+    );
+    cloudwatchClient::new(&config_builder(region, None).await.build())
+}
+
+pub(crate) async fn get_sts_client(region: &Region) -> stsClient {
+    debug!(
+        version = PKG_VERSION,
+        region = region.as_ref(),
+        "creating STS client"
+    );
+
+    let shared_config = config_builder(region, None)
+        .await
+        .retry_config(RetryConfig::disabled())
+        .build();
+    stsClient::new(&shared_config)
+}
+
+/// Loads an SDK config for the region using the account's assumed-role credentials, shared by
+/// every service client built for the account
+pub(crate) async fn get_config_with_role(
+    region: &Region,
+    assume_role: &AssumeRoleOptions,
+    sts_client: &stsClient,
+    credential_cache: &CredentialCache,
+    retry: &RetryPolicy,
+) -> Result<SdkConfig, Error> {
+    debug!(
+        region = region.as_ref(),
+        role_arn = %assume_role.role_arn,
+        "loading config with assumed role"
+    );
+
+    let creds = credential_cache
+        .get_or_assume(sts_client, assume_role, region, retry)
+        .await?;
+
+    // the region is the account's own, which has to match the account's infrastructure region
+    let shared_config = config_builder(region, Some(creds))
+        .await
+        // throttling and transient errors are retried by the RetryPolicy instead
+        .retry_config(RetryConfig::disabled())
+        .timeout_config(retry.timeout_config())
+        .build();
+    Ok(shared_config)
+}
+
+/// Caches assumed-role credentials per role ARN and region so repeated operations against the
+/// same account reuse one session until it gets close to expiring.
+#[derive(Debug)]
+pub struct CredentialCache {
+    refresh_window: Duration,
+    entries: Mutex<HashMap<(String, String), aws_types::Credentials>>,
+    /// The `--mfa-code` not used yet. Held while assuming a role with MFA, so accounts waiting
+    /// for the same role reuse its session instead of asking for another code.
+    mfa_code: tokio::sync::Mutex<Option<String>>,
+}
+
+impl Default for CredentialCache {
+    fn default() -> Self {
+        CredentialCache::new(CREDENTIAL_REFRESH_WINDOW)
+    }
+}
+
+impl CredentialCache {
+    pub fn new(refresh_window: Duration) -> Self {
+        CredentialCache {
+            refresh_window,
+            entries: Mutex::new(HashMap::new()),
+            mfa_code: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Code for the first role assumed with MFA; later ones are asked for on the terminal, as
+    /// AWS accepts each code only once
+    pub fn mfa_code(mut self, mfa_code: Option<String>) -> Self {
+        self.mfa_code = tokio::sync::Mutex::new(mfa_code);
+        self
+    }
+
+    fn cached(&self, key: &(String, String)) -> Option<aws_types::Credentials> {
+        let refresh_after = SystemTime::now() + self.refresh_window;
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key)
+            .filter(|creds| creds.expiry().is_some_and(|expiry| expiry > refresh_after))
+            .cloned()
+    }
+
+    /// Credentials for `assume_role`, assuming its `via_role_arn` first when it has one. Both
+    /// sessions are cached, so accounts behind the same intermediate role share its session.
+    pub async fn get_or_assume(
+        &self,
+        sts_client: &stsClient,
+        assume_role: &AssumeRoleOptions,
+        region: &Region,
+        retry: &RetryPolicy,
+    ) -> Result<aws_types::Credentials, Error> {
+        let Some(via_role_arn) = &assume_role.via_role_arn else {
+            return self
+                .cached_or_assume(sts_client, assume_role, region, retry)
+                .await;
+        };
+        let via_role = AssumeRoleOptions {
+            role_arn: via_role_arn.clone(),
+            session_name: assume_role.session_name.clone(),
+            session_duration: None,
+            external_id: None,
+            via_role_arn: None,
+            mfa_serial: assume_role.mfa_serial.clone(),
+        };
+        // the MFA context carries over to the chained session
+        let assume_role = AssumeRoleOptions {
+            mfa_serial: None,
+            ..assume_role.clone()
+        };
+        let via_creds = self
+            .cached_or_assume(sts_client, &via_role, region, retry)
+            .await?;
+        let via_config = config_builder(region, Some(via_creds))
+            .await
+            .retry_config(RetryConfig::disabled())
+            .timeout_config(retry.timeout_config())
+            .build();
+        self.cached_or_assume(&stsClient::new(&via_config), &assume_role, region, retry)
+            .await
+    }
+
+    async fn cached_or_assume(
+        &self,
+        sts_client: &stsClient,
+        assume_role: &AssumeRoleOptions,
+        region: &Region,
+        retry: &RetryPolicy,
+    ) -> Result<aws_types::Credentials, Error> {
+        let key = (assume_role.role_arn.clone(), String::from(region.as_ref()));
+        if let Some(creds) = self.cached(&key) {
+            return Ok(creds);
+        }
+
+        let mut mfa_guard = None;
+        let mut token_code = None;
+        if let Some(serial) = &assume_role.mfa_serial {
+            let mut guard = self.mfa_code.lock().await;
+            // another account may have assumed the role while this one waited
+            if let Some(creds) = self.cached(&key) {
+                return Ok(creds);
+            }
+            token_code = Some(match guard.take() {
+                Some(code) => code,
+                None => prompt_mfa_code(serial).await?,
+            });
+            mfa_guard = Some(guard);
+        }
+
+        let request = sts_client
+            .assume_role()
+            .role_arn(&assume_role.role_arn)
+            .role_session_name(&assume_role.session_name)
+            .set_duration_seconds(assume_role.session_duration)
+            .set_external_id(assume_role.external_id.clone())
+            .set_serial_number(assume_role.mfa_serial.clone())
+            .set_token_code(token_code);
+        let assumed_role = retry
+            .call("AssumeRole", || request.clone().send())
+            .await
+            .map_err(|e| Error::AssumeRole {
+                role_arn: assume_role.role_arn.clone(),
+                source: e.into(),
+            })?;
+        let missing = || Error::MissingCredentials(assume_role.role_arn.clone());
+        let assumed = assumed_role.credentials().ok_or_else(missing)?;
+        let expiry = assumed.expiration().map(|expiration| {
+            std::time::UNIX_EPOCH + Duration::from_secs(expiration.secs() as u64)
+        });
+
+        let creds = aws_types::Credentials::new(
+            assumed.access_key_id().ok_or_else(missing)?,
+            assumed.secret_access_key().ok_or_else(missing)?,
+            assumed.session_token().map(String::from),
+            expiry,
+            "dev-cli-metrics-observer",
+        );
+        self.entries.lock().unwrap().insert(key, creds.clone());
+        drop(mfa_guard);
+        Ok(creds)
+    }
+}
+
+/// Asks for a code of the MFA device on the terminal
+async fn prompt_mfa_code(serial: &str) -> Result<String, Error> {
+    let serial = String::from(serial);
+    tokio::task::spawn_blocking(move || {
+        if !std::io::stdin().is_terminal() {
+            return Err(Error::MfaRequired(serial));
+        }
+        eprint!("MFA code for {}: ", serial);
+        std::io::stderr().flush().map_err(Error::Terminal)?;
+        let mut code = String::new();
+        std::io::stdin()
+            .read_line(&mut code)
+            .map_err(Error::Terminal)?;
+        let code = code.trim();
+        if code.len() != 6 || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::InvalidArgument {
+                name: "MFA code",
+                value: String::from(code),
+            });
+        }
+        Ok(String::from(code))
+    })
+    .await
+    .expect("MFA prompt doesn't panic")
+}
@@ -0,0 +1,561 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use tracing::debug;
+
+use crate::aws_regions;
+use crate::error::Error;
+
+/// Environment variable with the accounts config to use when none is given
+pub const CONFIG_ENV_VAR: &str = "CW_DL_CONFIG";
+
+/// The accounts config to use when none is given: `$CW_DL_CONFIG`, or else
+/// `cloudwatch-metrics-dl/accounts.toml` in the XDG config directory (`~/.config` by default)
+/// if it exists
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV_VAR).filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    let path = config_dir
+        .join("cloudwatch-metrics-dl")
+        .join("accounts.toml");
+    path.is_file().then(|| path)
+}
+
+/// An `[[account]]` entry for a generated accounts file
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountEntry {
+    pub namespace: String,
+    pub account_id: String,
+    pub region: String,
+    pub role_arn: String,
+}
+
+impl AccountEntry {
+    /// Entry assuming `role_name` in the account, named after the account unless given a namespace
+    pub fn new(account_id: &str, region: &str, role_name: &str) -> Self {
+        AccountEntry {
+            namespace: String::from(account_id),
+            account_id: String::from(account_id),
+            region: String::from(region),
+            role_arn: role_arn(account_id, role_name),
+        }
+    }
+}
+
+/// ARN of the role named `role_name` in the account
+pub fn role_arn(account_id: &str, role_name: &str) -> String {
+    format!("arn:aws:iam::{}:role/{}", account_id, role_name)
+}
+
+/// Renders entries as the contents of an accounts TOML file
+pub fn accounts_to_toml(entries: &[AccountEntry]) -> String {
+    #[derive(Serialize)]
+    struct File<'a> {
+        account: &'a [AccountEntry],
+    }
+    toml::to_string(&File { account: entries }).expect("account entries serialize to TOML")
+}
+
+/// Accounts to run against, usually loaded from a TOML file of `[[account]]` tables
+#[derive(Deserialize, Debug, Default)]
+pub struct AccountsConfig {
+    /// More accounts files to merge in, relative to this file
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    #[serde(default)]
+    pub defaults: AccountDefaults,
+    pub account: Vec<AccountConfig>,
+}
+
+/// The `[defaults]` table, used for any account that doesn't set the same key
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AccountDefaults {
+    pub region: Option<String>,
+    /// Role ARN with `{{ACCOUNT_ID}}`, `{{NAMESPACE}}` and `{{REGION}}` placeholders filled
+    /// from each account, e.g. `arn:aws:iam::{{ACCOUNT_ID}}:role/CloudWatchReadOnly`
+    pub role_arn: Option<String>,
+    /// Intermediate role assumed first for every account that doesn't set its own
+    pub via_role_arn: Option<String>,
+    pub mfa_serial: Option<String>,
+    pub period: Option<u32>,
+    /// Directory `images` saves to when `--output-path` isn't given
+    pub output_path: Option<PathBuf>,
+    /// Widget template (or directory of templates) used by `images` when none is given on the
+    /// command line. Accounts with their own `template` still use that.
+    pub template_path: Option<PathBuf>,
+    /// Slack incoming webhook `images` and `alarms` post their run summary to when
+    /// `--notify-slack` isn't given
+    pub slack_webhook: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccountConfig {
+    pub namespace: String,
+    #[serde(default)]
+    pub region: String,
+    #[serde(default)]
+    pub role_arn: String,
+    /// Only needed to fill `{{ACCOUNT_ID}}` in the default role ARN
+    pub account_id: Option<String>,
+    pub session_name: Option<String>,
+    pub session_duration: Option<i32>,
+    pub external_id: Option<String>,
+    /// Intermediate "jump" role assumed first, whose session then assumes `role_arn`
+    pub via_role_arn: Option<String>,
+    /// ARN of the MFA device the role's trust policy requires, e.g.
+    /// `arn:aws:iam::999999999999:mfa/alice`
+    pub mfa_serial: Option<String>,
+    /// Extra `{{KEY}}` template variables for this account
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    /// Period in seconds used instead of the command's, e.g. for low-traffic accounts
+    pub period: Option<u32>,
+    /// Start used instead of the command's, e.g. `8760H` or `2024-01-01T00:00:00Z`
+    pub start: Option<String>,
+    /// End used instead of the command's
+    pub end: Option<String>,
+    /// Widget template (or directory of templates) used instead of the command's for `images`
+    pub template: Option<PathBuf>,
+    pub group: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Which accounts of the config to run against. An account is selected when it matches every
+/// criterion that is set.
+#[derive(Debug, Clone, Default)]
+pub struct AccountFilter {
+    patterns: Vec<Regex>,
+    excludes: Vec<Regex>,
+    regions: Vec<String>,
+    groups: Vec<String>,
+    tags: Vec<String>,
+}
+
+impl AccountsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn account(mut self, account: AccountConfig) -> Self {
+        self.account.push(account);
+        self
+    }
+
+    /// Reads an accounts TOML file and the files it includes, fills in their `[defaults]` and
+    /// validates the result
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_paths(&[path])
+    }
+
+    /// Reads and merges several accounts files in order, keeping the `[defaults]` of the first
+    pub fn from_paths(paths: &[impl AsRef<Path>]) -> Result<Self, Error> {
+        let mut merged = AccountsConfig::default();
+        let mut loaded = vec![];
+        for (index, path) in paths.iter().enumerate() {
+            let config = Self::load(path.as_ref(), &mut loaded)?;
+            if index == 0 {
+                merged.defaults = config.defaults;
+            }
+            merged.account.extend(config.account);
+        }
+        merged.validate()?;
+        Ok(merged)
+    }
+
+    /// Parses one file and merges its includes. Included accounts get their own file's
+    /// `[defaults]` first and then the including file's.
+    fn load(path: &Path, loaded: &mut Vec<PathBuf>) -> Result<Self, Error> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if loaded.contains(&canonical) {
+            return Err(Error::DuplicateInclude(path.to_path_buf()));
+        }
+        loaded.push(canonical);
+
+        let contents = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: PathBuf::from(path),
+            source,
+        })?;
+        let mut accounts_config: AccountsConfig =
+            toml::from_str(&contents).map_err(|source| Error::Config {
+                path: PathBuf::from(path),
+                source,
+            })?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in std::mem::take(&mut accounts_config.include) {
+            let included = Self::load(&dir.join(include), loaded)?;
+            accounts_config.account.extend(included.account);
+        }
+        accounts_config.apply_defaults();
+        Ok(accounts_config)
+    }
+
+    /// Copies every `[defaults]` value into the accounts that don't set it themselves
+    pub fn apply_defaults(&mut self) {
+        let defaults = &self.defaults;
+        for acc in &mut self.account {
+            if acc.region.is_empty() {
+                acc.region = defaults.region.clone().unwrap_or_default();
+            }
+            if acc.role_arn.is_empty() {
+                if let Some(role_arn) = &defaults.role_arn {
+                    acc.role_arn = role_arn
+                        .replace("{{NAMESPACE}}", &acc.namespace)
+                        .replace("{{REGION}}", &acc.region);
+                    if let Some(account_id) = &acc.account_id {
+                        acc.role_arn = acc.role_arn.replace("{{ACCOUNT_ID}}", account_id);
+                    }
+                }
+            }
+            if acc.via_role_arn.is_none() {
+                acc.via_role_arn = defaults.via_role_arn.clone();
+            }
+            if acc.mfa_serial.is_none() {
+                acc.mfa_serial = defaults.mfa_serial.clone();
+            }
+            acc.period = acc.period.or(defaults.period);
+        }
+    }
+
+    /// The widget template `images` uses for accounts without their own `template`: `cli` if
+    /// given, else `[defaults] template_path`
+    pub fn template_path(&self, cli: Option<&Path>) -> Option<PathBuf> {
+        cli.map(Path::to_path_buf)
+            .or_else(|| self.defaults.template_path.clone())
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let mut seen = HashSet::new();
+        for acc in &self.account {
+            if acc.region.is_empty() {
+                return Err(Error::MissingAccountField {
+                    namespace: acc.namespace.clone(),
+                    field: "region",
+                });
+            }
+            if acc.role_arn.is_empty() || acc.role_arn.contains("{{ACCOUNT_ID}}") {
+                return Err(Error::MissingAccountField {
+                    namespace: acc.namespace.clone(),
+                    field: if acc.role_arn.is_empty() {
+                        "role_arn"
+                    } else {
+                        "account_id"
+                    },
+                });
+            }
+            aws_regions::parse_region(&acc.region)
+                .map_err(|e| Error::Region(format!("{} for account {}", e, acc.role_arn)))?;
+            // the same role is often used in several regions, so only both together must be unique
+            if !seen.insert((&acc.role_arn, &acc.region)) {
+                return Err(Error::DuplicateAccount {
+                    role_arn: acc.role_arn.clone(),
+                    region: acc.region.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the accounts whose namespace contains `pattern`, or all of them without a pattern
+    pub fn filter(self, pattern: Option<&str>) -> Vec<AccountConfig> {
+        let patterns = pattern
+            .map(|pattern| Regex::new(&regex::escape(pattern)).expect("escaped pattern is valid"));
+        self.select(&AccountFilter::new().patterns(patterns.into_iter().collect()))
+    }
+
+    /// Keeps the accounts selected by `filter`
+    pub fn select(self, filter: &AccountFilter) -> Vec<AccountConfig> {
+        let total = self.account.len();
+        let selected: Vec<AccountConfig> = self
+            .account
+            .into_iter()
+            .filter(|acc| filter.matches(acc))
+            .collect();
+        debug!(?filter, "{} of {} accounts selected", selected.len(), total);
+        selected
+    }
+}
+
+impl AccountFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accounts where any of these regexes matches the namespace, region or role ARN
+    pub fn patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.patterns = patterns;
+        self
+    }
+
+    /// Leave out accounts where any of these regexes matches the namespace, region or role ARN
+    pub fn excludes(mut self, excludes: Vec<Regex>) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// Only accounts in one of these regions
+    pub fn regions(mut self, regions: Vec<String>) -> Self {
+        self.regions = regions;
+        self
+    }
+
+    /// Only accounts in one of these groups
+    pub fn groups(mut self, groups: Vec<String>) -> Self {
+        self.groups = groups;
+        self
+    }
+
+    /// Only accounts with every one of these tags
+    pub fn tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn matches(&self, acc: &AccountConfig) -> bool {
+        let fields = [&acc.namespace, &acc.region, &acc.role_arn];
+        let matches_any = |regexes: &[Regex]| {
+            regexes
+                .iter()
+                .any(|re| fields.iter().any(|f| re.is_match(f)))
+        };
+        let pattern = self.patterns.is_empty() || matches_any(&self.patterns);
+        let excluded = matches_any(&self.excludes);
+        let region = self.regions.is_empty() || self.regions.contains(&acc.region);
+        let group = self.groups.is_empty()
+            || acc
+                .group
+                .as_ref()
+                .map_or(false, |group| self.groups.contains(group));
+        let tags = self.tags.iter().all(|tag| acc.tags.contains(tag));
+        pattern && !excluded && region && group && tags
+    }
+}
+
+impl AccountConfig {
+    pub fn new(
+        namespace: impl Into<String>,
+        region: impl Into<String>,
+        role_arn: impl Into<String>,
+    ) -> Self {
+        AccountConfig {
+            namespace: namespace.into(),
+            region: region.into(),
+            role_arn: role_arn.into(),
+            account_id: None,
+            session_name: None,
+            session_duration: None,
+            external_id: None,
+            via_role_arn: None,
+            mfa_serial: None,
+            vars: BTreeMap::new(),
+            period: None,
+            start: None,
+            end: None,
+            template: None,
+            group: None,
+            tags: vec![],
+        }
+    }
+
+    pub fn session_name(mut self, session_name: impl Into<String>) -> Self {
+        self.session_name = Some(session_name.into());
+        self
+    }
+
+    pub fn session_duration(mut self, seconds: i32) -> Self {
+        self.session_duration = Some(seconds);
+        self
+    }
+
+    pub fn external_id(mut self, external_id: impl Into<String>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    /// Assume `via_role_arn` first and the account's role from its session
+    pub fn via_role_arn(mut self, via_role_arn: impl Into<String>) -> Self {
+        self.via_role_arn = Some(via_role_arn.into());
+        self
+    }
+
+    pub fn mfa_serial(mut self, mfa_serial: impl Into<String>) -> Self {
+        self.mfa_serial = Some(mfa_serial.into());
+        self
+    }
+
+    pub fn var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Short name used when reporting per-account results
+    pub fn label(&self) -> String {
+        format!("{} ({})", self.namespace, self.region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> AccountsConfig {
+        let mut config: AccountsConfig = toml::from_str(toml).unwrap();
+        config.apply_defaults();
+        config
+    }
+
+    #[test]
+    fn template_precedence() {
+        let config = parse(
+            r#"
+            [defaults]
+            region = "us-east-1"
+            template_path = "defaults.json"
+
+            [[account]]
+            namespace = "Orders"
+            template = "orders.json"
+
+            [[account]]
+            namespace = "Payments"
+            "#,
+        );
+        // the account's own template wins over the command's and the default
+        assert_eq!(
+            config.account[0].template,
+            Some(PathBuf::from("orders.json"))
+        );
+        // the default isn't copied into the accounts, so it can't override the command's
+        assert_eq!(config.account[1].template, None);
+        assert_eq!(
+            config.template_path(Some(Path::new("cli.json"))),
+            Some(PathBuf::from("cli.json"))
+        );
+        assert_eq!(
+            config.template_path(None),
+            Some(PathBuf::from("defaults.json"))
+        );
+
+        let config = parse(
+            r#"
+            [[account]]
+            namespace = "Orders"
+            region = "us-east-1"
+            "#,
+        );
+        assert_eq!(config.template_path(None), None);
+    }
+
+    #[test]
+    fn account_filter_matches() {
+        let re = |re: &str| Regex::new(re).unwrap();
+        let strings = |values: &[&str]| values.iter().map(|v| String::from(*v)).collect();
+        let orders = AccountConfig::new(
+            "OrdersProd",
+            "us-east-1",
+            "arn:aws:iam::111111111111:role/ReadOnly",
+        )
+        .group("tier1")
+        .tag("prod")
+        .tag("payments");
+        let payments = AccountConfig::new(
+            "PaymentsBeta",
+            "eu-west-1",
+            "arn:aws:iam::222222222222:role/ReadOnly",
+        )
+        .tag("beta");
+        let cases = [
+            ("everything", AccountFilter::new(), true, true),
+            (
+                "namespace",
+                AccountFilter::new().patterns(vec![re("^Orders")]),
+                true,
+                false,
+            ),
+            (
+                "role arn",
+                AccountFilter::new().patterns(vec![re("222222222222")]),
+                false,
+                true,
+            ),
+            (
+                "any pattern",
+                AccountFilter::new().patterns(vec![re("Orders"), re("Payments")]),
+                true,
+                true,
+            ),
+            (
+                "region",
+                AccountFilter::new().regions(strings(&["eu-west-1"])),
+                false,
+                true,
+            ),
+            (
+                "group",
+                AccountFilter::new().groups(strings(&["tier1", "tier2"])),
+                true,
+                false,
+            ),
+            (
+                "every tag",
+                AccountFilter::new().tags(strings(&["prod", "payments"])),
+                true,
+                false,
+            ),
+            (
+                "missing tag",
+                AccountFilter::new().tags(strings(&["prod", "beta"])),
+                false,
+                false,
+            ),
+            (
+                "exclude",
+                AccountFilter::new().excludes(vec![re("Beta")]),
+                true,
+                false,
+            ),
+            (
+                "exclude overrides include",
+                AccountFilter::new()
+                    .patterns(vec![re("Prod|Beta")])
+                    .excludes(vec![re("us-east-1")]),
+                false,
+                true,
+            ),
+            (
+                "every criterion",
+                AccountFilter::new()
+                    .patterns(vec![re("Orders")])
+                    .regions(strings(&["eu-west-1"])),
+                false,
+                false,
+            ),
+        ];
+        for (name, filter, selects_orders, selects_payments) in cases {
+            assert_eq!(filter.matches(&orders), selects_orders, "{}: orders", name);
+            assert_eq!(
+                filter.matches(&payments),
+                selects_payments,
+                "{}: payments",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{Dimension, Metric, MetricAlarm};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::alarms::{describe_alarms, AlarmDimension};
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::output::text_table;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::template::{render_str, template_context};
+
+/// A metric that no alarm in its account watches
+#[derive(Serialize, Debug, Clone)]
+pub struct UnalarmedMetric {
+    pub program_name: String,
+    pub region: String,
+    pub namespace: String,
+    pub metric_name: String,
+    pub dimensions: Vec<AlarmDimension>,
+}
+
+/// Namespace, metric name and sorted dimension pairs identifying one metric
+type MetricKey = (String, String, Vec<(String, String)>);
+
+/// Lists the metrics of each account that have no alarm, by cross-referencing ListMetrics with
+/// DescribeAlarms
+#[derive(Debug, Clone, Default)]
+pub struct CoverageExporter {
+    namespaces: Vec<String>,
+    options: RunOptions,
+}
+
+impl CoverageExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// CloudWatch namespaces to check, e.g. `AWS/Kinesis`. When empty, each account's own
+    /// `namespace` is checked.
+    pub fn namespaces(mut self, namespaces: Vec<String>) -> Self {
+        self.namespaces = namespaces;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<Vec<UnalarmedMetric>> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.check(&acc).await }
+            })
+            .await
+    }
+
+    /// Lists the account's metrics in each namespace and keeps those no alarm watches, either
+    /// directly or as part of a metric math alarm
+    pub async fn check(&self, acc: &AccountConfig) -> Result<Vec<UnalarmedMetric>, Error> {
+        debug!(account = %acc.label(), "checking alarm coverage");
+        let client = self.options.cloudwatch_client(acc).await?;
+        let alarms = describe_alarms(&client, &self.options.retry, None, None).await?;
+        let watched: HashSet<MetricKey> = alarms.iter().flat_map(alarm_metrics).collect();
+
+        let namespaces = if self.namespaces.is_empty() {
+            vec![acc.namespace.clone()]
+        } else {
+            self.namespaces.clone()
+        };
+        let mut unalarmed = vec![];
+        for namespace in &namespaces {
+            for metric in list_metrics(&client, &self.options.retry, Some(namespace)).await? {
+                let key = metric_key(&metric);
+                if watched.contains(&key) {
+                    continue;
+                }
+                let (namespace, metric_name, dimensions) = key;
+                unalarmed.push(UnalarmedMetric {
+                    program_name: acc.namespace.clone(),
+                    region: acc.region.clone(),
+                    namespace,
+                    metric_name,
+                    dimensions: dimensions
+                        .into_iter()
+                        .map(|(name, value)| AlarmDimension { name, value })
+                        .collect(),
+                });
+            }
+        }
+        Ok(unalarmed)
+    }
+}
+
+/// Keys of every metric the alarm watches: its single metric, or each metric of its math
+/// expression
+fn alarm_metrics(alarm: &MetricAlarm) -> Vec<MetricKey> {
+    if alarm.metric_name().is_some() {
+        return vec![key(
+            alarm.namespace(),
+            alarm.metric_name(),
+            alarm.dimensions(),
+        )];
+    }
+    alarm
+        .metrics()
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|query| query.metric_stat()?.metric())
+        .map(metric_key)
+        .collect()
+}
+
+fn metric_key(metric: &Metric) -> MetricKey {
+    key(
+        metric.namespace(),
+        metric.metric_name(),
+        metric.dimensions(),
+    )
+}
+
+fn key(
+    namespace: Option<&str>,
+    metric_name: Option<&str>,
+    dimensions: Option<&[Dimension]>,
+) -> MetricKey {
+    let mut dimensions: Vec<(String, String)> = dimensions
+        .unwrap_or_default()
+        .iter()
+        .map(|d| {
+            (
+                String::from(d.name().unwrap_or_default()),
+                String::from(d.value().unwrap_or_default()),
+            )
+        })
+        .collect();
+    dimensions.sort_unstable();
+    (
+        String::from(namespace.unwrap_or_default()),
+        String::from(metric_name.unwrap_or_default()),
+        dimensions,
+    )
+}
+
+const COVERAGE_COLUMNS: [&str; 5] = [
+    "program_name",
+    "region",
+    "namespace",
+    "metric_name",
+    "dimensions",
+];
+
+fn coverage_row(metric: &UnalarmedMetric) -> Vec<String> {
+    vec![
+        metric.program_name.clone(),
+        metric.region.clone(),
+        metric.namespace.clone(),
+        metric.metric_name.clone(),
+        metric
+            .dimensions
+            .iter()
+            .map(|d| format!("{}={}", d.name, d.value))
+            .collect::<Vec<_>>()
+            .join(";"),
+    ]
+}
+
+/// Writes metrics without alarms as CSV, dimensions flattened to `name=value` pairs joined by `;`
+pub fn coverage_to_csv(metrics: &[UnalarmedMetric]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(COVERAGE_COLUMNS)?;
+    for metric in metrics {
+        writer.write_record(coverage_row(metric))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Renders metrics without alarms as a plain text table with space-padded columns
+pub fn coverage_to_table(metrics: &[UnalarmedMetric]) -> String {
+    let rows: Vec<Vec<String>> = metrics.iter().map(coverage_row).collect();
+    text_table(&COVERAGE_COLUMNS, &rows)
+}
+
+/// Calls AWS CloudWatch ListMetrics API for one namespace, or every namespace when it's `None`,
+/// following `next_token` until every metric is returned
+/// API Reference: [ListMetrics](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_ListMetrics.html)
+pub(crate) async fn list_metrics(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    namespace: Option<&str>,
+) -> Result<Vec<Metric>, aws_sdk_cloudwatch::Error> {
+    let mut metrics: Vec<Metric> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client
+            .list_metrics()
+            .set_namespace(namespace.map(String::from))
+            .set_next_token(next_token);
+        let resp = retry.call("ListMetrics", || request.clone().send()).await?;
+        metrics.extend_from_slice(resp.metrics().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(metrics)
+}
+
+/// The alarms one account has on a metric
+#[derive(Serialize, Debug, Clone)]
+pub struct MetricAlarms {
+    pub program_name: String,
+    pub region: String,
+    pub alarm_names: Vec<String>,
+}
+
+/// Looks up which alarms watch one exact metric in each account, with DescribeAlarmsForMetric
+#[derive(Debug, Clone)]
+pub struct MetricAlarmLookup {
+    namespace: String,
+    metric_name: String,
+    dimensions: BTreeMap<String, String>,
+    options: RunOptions,
+}
+
+impl MetricAlarmLookup {
+    /// Dimension values are templated per account like widget JSON, e.g.
+    /// `StreamName={{NAMESPACE}}`
+    pub fn new(
+        namespace: impl Into<String>,
+        metric_name: impl Into<String>,
+        dimensions: BTreeMap<String, String>,
+    ) -> Self {
+        MetricAlarmLookup {
+            namespace: namespace.into(),
+            metric_name: metric_name.into(),
+            dimensions,
+            options: RunOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<MetricAlarms> {
+        let lookup = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let lookup = lookup.clone();
+                async move { lookup.lookup(&acc).await }
+            })
+            .await
+    }
+
+    pub async fn lookup(&self, acc: &AccountConfig) -> Result<MetricAlarms, Error> {
+        let context = template_context(acc, "", "", "", &BTreeMap::new());
+        let dimensions = self
+            .dimensions
+            .iter()
+            .map(|(name, value)| {
+                Ok(Dimension::builder()
+                    .name(name)
+                    .value(render_str(name, value, &context)?)
+                    .build())
+            })
+            .collect::<Result<Vec<Dimension>, Error>>()?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        let alarms = describe_alarms_for_metric(
+            &client,
+            &self.options.retry,
+            &self.namespace,
+            &self.metric_name,
+            dimensions,
+        )
+        .await?;
+        Ok(MetricAlarms {
+            program_name: acc.namespace.clone(),
+            region: acc.region.clone(),
+            alarm_names: alarms
+                .iter()
+                .map(|alarm| String::from(alarm.alarm_name().unwrap_or_default()))
+                .collect(),
+        })
+    }
+}
+
+/// Renders whether each account alarms on the metric as a plain text table
+pub fn metric_alarms_to_table(accounts: &[MetricAlarms]) -> String {
+    let rows: Vec<Vec<String>> = accounts
+        .iter()
+        .map(|acc| {
+            vec![
+                acc.program_name.clone(),
+                acc.region.clone(),
+                String::from(if acc.alarm_names.is_empty() {
+                    "no"
+                } else {
+                    "yes"
+                }),
+                acc.alarm_names.join(";"),
+            ]
+        })
+        .collect();
+    text_table(&["program_name", "region", "alarmed", "alarm_names"], &rows)
+}
+
+/// Calls AWS CloudWatch DescribeAlarmsForMetric API, which only returns alarms on exactly these
+/// dimensions
+/// API Reference: [DescribeAlarmsForMetric](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_DescribeAlarmsForMetric.html)
+async fn describe_alarms_for_metric(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    namespace: &str,
+    metric_name: &str,
+    dimensions: Vec<Dimension>,
+) -> Result<Vec<MetricAlarm>, aws_sdk_cloudwatch::Error> {
+    let request = client
+        .describe_alarms_for_metric()
+        .namespace(namespace)
+        .metric_name(metric_name)
+        .set_dimensions(Some(dimensions));
+    let resp = retry
+        .call("DescribeAlarmsForMetric", || request.clone().send())
+        .await?;
+    Ok(resp.metric_alarms().unwrap_or_default().to_vec())
+}
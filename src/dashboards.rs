@@ -0,0 +1,318 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::DashboardEntry;
+use aws_smithy_types::date_time::Format;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::duration::time_range;
+use crate::error::Error;
+use crate::images::{get_metric_image, ImageFormat};
+use crate::output::write_output;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DashboardSummary {
+    pub program_name: String,
+    pub region: String,
+    pub dashboard_name: String,
+    pub dashboard_arn: String,
+    pub last_modified: String,
+    pub size: i64,
+}
+
+/// Image size of one dashboard grid unit, dashboards are 24 units wide
+const GRID_UNIT_WIDTH: i64 = 64;
+const GRID_UNIT_HEIGHT: i64 = 48;
+
+/// Lists dashboards, saves their body JSON and renders their widgets for each account
+#[derive(Debug, Clone)]
+pub struct DashboardExporter {
+    start: String,
+    end: String,
+    output_path: Option<PathBuf>,
+    options: RunOptions,
+}
+
+impl Default for DashboardExporter {
+    fn default() -> Self {
+        DashboardExporter {
+            start: String::from("4320H"),
+            end: String::from("0H"),
+            output_path: None,
+            options: RunOptions::default(),
+        }
+    }
+}
+
+impl DashboardExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start of rendered widgets, e.g. `4320H` before now or `2024-01-01T00:00:00Z`
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = start.into();
+        self
+    }
+
+    /// End of rendered widgets, e.g. `0H` before now or `2024-01-01T00:00:00Z`
+    pub fn end(mut self, end: impl Into<String>) -> Self {
+        self.end = end.into();
+        self
+    }
+
+    /// Directory to save dashboard bodies in instead of the current directory
+    pub fn output_path(mut self, output_path: Option<PathBuf>) -> Self {
+        self.output_path = output_path;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Lists the dashboards of every account
+    pub async fn list(
+        &self,
+        accounts: Vec<AccountConfig>,
+    ) -> AccountResults<Vec<DashboardSummary>> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.list_account(&acc).await }
+            })
+            .await
+    }
+
+    /// Saves the body of dashboard `name` from every account, returning the saved path per account
+    pub async fn download(
+        &self,
+        accounts: Vec<AccountConfig>,
+        name: &str,
+    ) -> AccountResults<PathBuf> {
+        let exporter = Arc::new(self.clone());
+        let name = Arc::new(String::from(name));
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                let name = name.clone();
+                async move { exporter.download_account(&acc, &name).await }
+            })
+            .await
+    }
+
+    /// Renders every metric widget of dashboard `name` in every account as a PNG, returning the
+    /// saved paths per account
+    pub async fn render(
+        &self,
+        accounts: Vec<AccountConfig>,
+        name: &str,
+    ) -> AccountResults<Vec<PathBuf>> {
+        let exporter = Arc::new(self.clone());
+        let name = Arc::new(String::from(name));
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                let name = name.clone();
+                async move { exporter.render_account(&acc, &name).await }
+            })
+            .await
+    }
+
+    pub async fn list_account(&self, acc: &AccountConfig) -> Result<Vec<DashboardSummary>, Error> {
+        let client = self.options.cloudwatch_client(acc).await?;
+        let entries = list_dashboards(&client, &self.options.retry).await?;
+        Ok(entries
+            .iter()
+            .map(|entry| to_dashboard_summary(acc, entry))
+            .collect())
+    }
+
+    pub async fn download_account(
+        &self,
+        acc: &AccountConfig,
+        name: &str,
+    ) -> Result<PathBuf, Error> {
+        let client = self.options.cloudwatch_client(acc).await?;
+        let body = get_dashboard_body(&client, &self.options.retry, name).await?;
+        let saved_path = self
+            .output_path
+            .clone()
+            .unwrap_or_default()
+            .join(format!("{}-{}-{}", &acc.namespace, &acc.region, name))
+            .with_extension("json");
+        write_output(&saved_path, body).await?;
+        Ok(saved_path)
+    }
+
+    pub async fn render_account(
+        &self,
+        acc: &AccountConfig,
+        name: &str,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let client = self.options.cloudwatch_client(acc).await?;
+        let body = get_dashboard_body(&client, &self.options.retry, name).await?;
+        let (start, end) = time_range(&self.start, &self.end)?;
+        let widgets = metric_widgets(
+            &body,
+            &acc.region,
+            &start.widget_value(),
+            &end.widget_value(),
+        )
+        .map_err(|source| Error::Dashboard {
+            name: String::from(name),
+            source,
+        })?;
+
+        let mut saved_paths = vec![];
+        for (index, (title, widget)) in widgets.iter().enumerate() {
+            let saved_image_name = format!(
+                "{}-{}-{}-{:02}-{}",
+                &acc.namespace,
+                file_name_part(name),
+                &acc.region,
+                index,
+                file_name_part(title)
+            );
+            let saved_image_path = self
+                .output_path
+                .clone()
+                .unwrap_or_default()
+                .join(saved_image_name)
+                .with_extension("png");
+            get_metric_image(
+                &client,
+                &self.options.retry,
+                widget,
+                ImageFormat::Png,
+                &saved_image_path,
+            )
+            .await?;
+            saved_paths.push(saved_image_path);
+        }
+        Ok(saved_paths)
+    }
+}
+
+/// Turns each metric widget of a dashboard body into a GetMetricWidgetImage request, returning
+/// `(title, widget JSON)` pairs. The widget's grid size becomes the image size, the time range is
+/// replaced with `start`/`end` (e.g. `-PT4320H` or `2024-01-01T00:00:00Z`) and widgets without a
+/// region get the account's region.
+pub fn metric_widgets(
+    body: &str,
+    region: &str,
+    start: &str,
+    end: &str,
+) -> Result<Vec<(String, String)>, serde_json::Error> {
+    let dashboard: Value = serde_json::from_str(body)?;
+    let widgets = dashboard["widgets"].as_array().cloned().unwrap_or_default();
+    let mut rendered = vec![];
+    for widget in widgets {
+        if widget["type"] != "metric" {
+            continue;
+        }
+        let mut properties = widget["properties"].clone();
+        let Some(props) = properties.as_object_mut() else {
+            continue;
+        };
+        props.entry("region").or_insert_with(|| Value::from(region));
+        props.insert(String::from("start"), Value::from(start));
+        props.insert(String::from("end"), Value::from(end));
+        if let Some(width) = widget["width"].as_i64() {
+            props.insert(String::from("width"), Value::from(width * GRID_UNIT_WIDTH));
+        }
+        if let Some(height) = widget["height"].as_i64() {
+            props.insert(
+                String::from("height"),
+                Value::from(height * GRID_UNIT_HEIGHT),
+            );
+        }
+        // dashboard-only properties are rejected by GetMetricWidgetImage
+        props.remove("liveData");
+        props.remove("sparkline");
+        props.remove("setPeriodToTimeRange");
+        let title = String::from(
+            props
+                .get("title")
+                .and_then(Value::as_str)
+                .unwrap_or("widget"),
+        );
+        rendered.push((title, serde_json::to_string(&properties)?));
+    }
+    Ok(rendered)
+}
+
+/// Replaces characters that don't belong in a file name, e.g. `p99 latency/us` -> `p99_latency_us`
+fn file_name_part(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn to_dashboard_summary(acc: &AccountConfig, entry: &DashboardEntry) -> DashboardSummary {
+    DashboardSummary {
+        program_name: acc.namespace.clone(),
+        region: acc.region.clone(),
+        dashboard_name: String::from(entry.dashboard_name().unwrap_or_default()),
+        dashboard_arn: String::from(entry.dashboard_arn().unwrap_or_default()),
+        last_modified: entry
+            .last_modified()
+            .and_then(|t| t.fmt(Format::DateTime).ok())
+            .unwrap_or_default(),
+        size: entry.size(),
+    }
+}
+
+/// Calls AWS CloudWatch ListDashboards API, following `next_token` until every dashboard is returned
+/// API Reference: [ListDashboards](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_ListDashboards.html)
+async fn list_dashboards(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+) -> Result<Vec<DashboardEntry>, aws_sdk_cloudwatch::Error> {
+    debug!("listing dashboards");
+    let mut entries: Vec<DashboardEntry> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client.list_dashboards().set_next_token(next_token);
+        let resp = retry
+            .call("ListDashboards", || request.clone().send())
+            .await?;
+        entries.extend_from_slice(resp.dashboard_entries().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(entries)
+}
+
+/// Calls AWS CloudWatch GetDashboard API and returns the dashboard body JSON
+/// API Reference: [GetDashboard](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_GetDashboard.html)
+pub(crate) async fn get_dashboard_body(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    name: &str,
+) -> Result<String, Error> {
+    debug!(name, "getting dashboard");
+    let request = client.get_dashboard().dashboard_name(name);
+    let resp = retry
+        .call("GetDashboard", || request.clone().send())
+        .await
+        .map_err(aws_sdk_cloudwatch::Error::from)?;
+    resp.dashboard_body()
+        .map(String::from)
+        .ok_or(Error::EmptyResponse("GetDashboard"))
+}
@@ -0,0 +1,729 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, StringArray, TimestampMillisecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use aws_sdk_cloudwatch::model::{Dimension, Metric, MetricDataQuery, MetricStat, ScanBy};
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::AccountConfig;
+use crate::duration::{fit_period, time_range};
+use crate::error::Error;
+use crate::output::text_table;
+use crate::plan::PlannedCall;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::template::{check_template, render_template, template_context};
+
+/// GetMetricData takes at most this many queries per call
+const MAX_QUERIES: usize = 500;
+/// GetMetricData returns at most this many datapoints per call across its queries
+const MAX_DATAPOINTS: i64 = 100_800;
+
+/// Query spec for the `data` subcommand, templated the same way as widget JSON
+#[derive(Deserialize, Debug)]
+pub struct MetricDataSpec {
+    pub queries: Vec<MetricQuerySpec>,
+}
+
+/// A metric to fetch with `namespace`, `metric_name` and `stat`, or a metric math `expression`
+/// over the ids of other queries like `errors/invocations*100`
+#[derive(Deserialize, Debug)]
+pub struct MetricQuerySpec {
+    pub id: String,
+    #[serde(default)]
+    pub namespace: String,
+    #[serde(default)]
+    pub metric_name: String,
+    #[serde(default)]
+    pub dimensions: BTreeMap<String, String>,
+    /// A statistic like `Sum` or `Average`, or a percentile like `p99`, which CloudWatch
+    /// calls an extended statistic
+    #[serde(default, alias = "extended_statistic")]
+    pub stat: String,
+    pub expression: Option<String>,
+    pub label: Option<String>,
+    /// Whether the query's datapoints are exported, `false` for metrics only used by an
+    /// expression
+    #[serde(default = "default_return_data")]
+    pub return_data: bool,
+}
+
+fn default_return_data() -> bool {
+    true
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountMetricData {
+    pub program_name: String,
+    pub region: String,
+    pub account_id: Option<String>,
+    pub series: Vec<MetricSeries>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct MetricSeries {
+    pub id: String,
+    pub label: String,
+    /// The queried metric, empty for an expression
+    pub namespace: String,
+    pub metric_name: String,
+    pub dimensions: BTreeMap<String, String>,
+    pub stat: String,
+    /// Seconds each datapoint aggregates, starting at its timestamp
+    pub period: i32,
+    pub datapoints: Vec<Datapoint>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Datapoint {
+    pub timestamp: String,
+    pub value: f64,
+}
+
+impl Datapoint {
+    /// The RFC3339 `timestamp` parsed back, `None` if it somehow isn't one
+    pub fn date_time(&self) -> Option<DateTime> {
+        DateTime::from_str(&self.timestamp, Format::DateTime).ok()
+    }
+}
+
+/// Latest exported timestamp per `(program name, region)` and query id
+pub type Watermarks = HashMap<(String, String), HashMap<String, DateTime>>;
+
+/// Exports raw datapoints for each account with GetMetricData, using a templated query spec
+#[derive(Debug, Clone)]
+pub struct MetricDataExporter {
+    spec_path: PathBuf,
+    start: String,
+    end: String,
+    period: String,
+    watermarks: Arc<Watermarks>,
+    vars: BTreeMap<String, String>,
+    options: RunOptions,
+}
+
+impl MetricDataExporter {
+    pub fn new(spec_path: impl Into<PathBuf>) -> Self {
+        MetricDataExporter {
+            spec_path: spec_path.into(),
+            start: String::from("4320H"),
+            end: String::from("0H"),
+            period: String::from("3600"),
+            watermarks: Arc::new(Watermarks::new()),
+            vars: BTreeMap::new(),
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Start of the export, e.g. `4320H` before now or `2024-01-01T00:00:00Z`
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = start.into();
+        self
+    }
+
+    /// End of the export, e.g. `0H` before now or `2024-01-01T00:00:00Z`
+    pub fn end(mut self, end: impl Into<String>) -> Self {
+        self.end = end.into();
+        self
+    }
+
+    /// Period in seconds for every query
+    pub fn period(mut self, period: impl Into<String>) -> Self {
+        self.period = period.into();
+        self
+    }
+
+    /// Start each account's export at its previous high-watermark instead of `start` when every
+    /// query in the spec already has one. The latest datapoint is fetched again in case its
+    /// period was still filling up.
+    pub fn watermarks(mut self, watermarks: Watermarks) -> Self {
+        self.watermarks = Arc::new(watermarks);
+        self
+    }
+
+    /// Extra `{{KEY}}` template variables, overriding the same key in an account's `vars`
+    pub fn vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Reads and parses the query spec once, before it's rendered for any account
+    pub fn check_spec(&self) -> Result<(), Error> {
+        check_template(&self.spec_path)
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<AccountMetricData> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.export(&acc).await }
+            })
+            .await
+    }
+
+    /// Exports the account's datapoints, with its `start`, `end` and `period` overrides
+    pub async fn export(&self, acc: &AccountConfig) -> Result<AccountMetricData, Error> {
+        let (spec, start_time, end_time, period) = self.prepare(acc)?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        let series = get_metric_data(
+            &client,
+            &self.options.retry,
+            &spec,
+            start_time,
+            end_time,
+            period as i32,
+        )
+        .await?;
+        Ok(AccountMetricData {
+            program_name: acc.namespace.clone(),
+            region: acc.region.clone(),
+            account_id: acc.account_id.clone(),
+            series,
+        })
+    }
+
+    /// The AWS calls [`Self::export`] would make for the account, after rendering its query spec:
+    /// one GetMetricData call per batch of queries and time slice, before paging
+    pub fn plan(&self, acc: &AccountConfig) -> Result<Vec<PlannedCall>, Error> {
+        let (spec, start_time, end_time, period) = self.prepare(acc)?;
+        let time = |t: DateTime| t.fmt(Format::DateTime).unwrap_or_default();
+        let mut calls = vec![PlannedCall::assume_role(acc, &self.options)];
+        for batch in spec.queries.chunks(MAX_QUERIES) {
+            for (slice_start, slice_end) in
+                time_slices(start_time, end_time, period as i32, batch.len())
+            {
+                calls.push(PlannedCall::new(
+                    acc,
+                    "GetMetricData",
+                    format!(
+                        "{} queries from {} to {} every {}s",
+                        batch.len(),
+                        time(slice_start),
+                        time(slice_end),
+                        period
+                    ),
+                ));
+            }
+        }
+        Ok(calls)
+    }
+
+    /// Renders the account's query spec, returning it with the time range and period to export
+    fn prepare(
+        &self,
+        acc: &AccountConfig,
+    ) -> Result<(MetricDataSpec, DateTime, DateTime, u32), Error> {
+        let (start, end) = time_range(
+            acc.start.as_deref().unwrap_or(&self.start),
+            acc.end.as_deref().unwrap_or(&self.end),
+        )?;
+        let period = acc
+            .period
+            .map_or_else(|| self.period.clone(), |p| p.to_string());
+        let period = fit_period(&period, &start)?;
+        let spec = render_template(
+            &self.spec_path,
+            &template_context(
+                acc,
+                &start.template_value(),
+                &end.template_value(),
+                &period.to_string(),
+                &self.vars,
+            ),
+        )?;
+        let spec: MetricDataSpec =
+            serde_json::from_str(&spec).map_err(|source| Error::Template {
+                path: self.spec_path.clone(),
+                source,
+            })?;
+        if spec.queries.len() > MAX_QUERIES && spec.queries.iter().any(|q| q.expression.is_some()) {
+            return Err(Error::TooManyExpressionQueries {
+                path: self.spec_path.clone(),
+                queries: spec.queries.len(),
+                limit: MAX_QUERIES,
+            });
+        }
+        let (start_time, end_time) = (start.date_time(), end.date_time());
+        let start_time = match self.watermark(acc, &spec) {
+            Some(watermark) if watermark.secs() > start_time.secs() => {
+                debug!(account = %acc.label(), ?watermark, "resuming from watermark");
+                watermark
+            }
+            _ => start_time,
+        };
+        Ok((spec, start_time, end_time, period))
+    }
+
+    /// Oldest of the account's per-query watermarks, if every exported query has one
+    fn watermark(&self, acc: &AccountConfig, spec: &MetricDataSpec) -> Option<DateTime> {
+        let account = self
+            .watermarks
+            .get(&(acc.namespace.clone(), acc.region.clone()))?;
+        spec.queries
+            .iter()
+            .filter(|q| q.return_data)
+            .map(|q| account.get(&q.id).copied())
+            .collect::<Option<Vec<DateTime>>>()?
+            .into_iter()
+            .min_by_key(|timestamp| timestamp.secs())
+    }
+}
+
+/// Writes exported datapoints as CSV. The wide layout has one row per timestamp and one column
+/// per account/region/query, the long layout has one row per datapoint.
+pub fn metric_data_to_csv(
+    all_data: &[AccountMetricData],
+    wide: bool,
+) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    if wide {
+        let mut columns: Vec<String> = vec![];
+        let mut rows: BTreeMap<&str, HashMap<usize, f64>> = BTreeMap::new();
+        for acc in all_data {
+            for series in &acc.series {
+                let column = columns.len();
+                columns.push(format!("{}/{}/{}", acc.program_name, acc.region, series.id));
+                for dp in &series.datapoints {
+                    rows.entry(dp.timestamp.as_str())
+                        .or_default()
+                        .insert(column, dp.value);
+                }
+            }
+        }
+        let mut header = vec![String::from("timestamp")];
+        header.extend(columns.iter().cloned());
+        writer.write_record(&header)?;
+        for (timestamp, values) in rows {
+            let mut record = vec![String::from(timestamp)];
+            record.extend((0..columns.len()).map(|column| {
+                values
+                    .get(&column)
+                    .map(|v| v.to_string())
+                    .unwrap_or_default()
+            }));
+            writer.write_record(&record)?;
+        }
+    } else {
+        writer.write_record([
+            "program_name",
+            "region",
+            "id",
+            "label",
+            "timestamp",
+            "value",
+        ])?;
+        for acc in all_data {
+            for series in &acc.series {
+                for dp in &series.datapoints {
+                    writer.write_record([
+                        acc.program_name.as_str(),
+                        acc.region.as_str(),
+                        series.id.as_str(),
+                        series.label.as_str(),
+                        dp.timestamp.as_str(),
+                        dp.value.to_string().as_str(),
+                    ])?;
+                }
+            }
+        }
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Writes exported datapoints as a snappy-compressed Parquet file with one row per datapoint, for
+/// querying large exports with Athena or DuckDB. Dimensions are flattened to `name=value` pairs
+/// joined by `;`, and expressions have an empty namespace, metric and stat.
+pub fn metric_data_to_parquet(all_data: &[AccountMetricData]) -> Result<Vec<u8>, ParquetError> {
+    let mut columns: [Vec<String>; 7] = Default::default();
+    let mut timestamps: Vec<i64> = vec![];
+    let mut values: Vec<f64> = vec![];
+    for acc in all_data {
+        for series in &acc.series {
+            let dimensions = series
+                .dimensions
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join(";");
+            for dp in &series.datapoints {
+                let timestamp = match dp.date_time() {
+                    Some(timestamp) => timestamp,
+                    None => continue,
+                };
+                let row = [
+                    &acc.program_name,
+                    &acc.region,
+                    &series.namespace,
+                    &series.metric_name,
+                    &dimensions,
+                    &series.stat,
+                    &series.id,
+                ];
+                for (column, value) in columns.iter_mut().zip(row) {
+                    column.push(value.clone());
+                }
+                timestamps.push(
+                    timestamp.secs() * 1000 + i64::from(timestamp.subsec_nanos()) / 1_000_000,
+                );
+                values.push(dp.value);
+            }
+        }
+    }
+    let names = [
+        "account",
+        "region",
+        "namespace",
+        "metric",
+        "dimensions",
+        "stat",
+        "id",
+    ];
+    let mut fields: Vec<Field> = names
+        .iter()
+        .map(|name| Field::new(*name, DataType::Utf8, false))
+        .collect();
+    fields.push(Field::new(
+        "timestamp",
+        DataType::Timestamp(TimeUnit::Millisecond, Some(String::from("UTC"))),
+        false,
+    ));
+    fields.push(Field::new("value", DataType::Float64, false));
+    let mut arrays: Vec<ArrayRef> = columns
+        .into_iter()
+        .map(|column| Arc::new(StringArray::from(column)) as ArrayRef)
+        .collect();
+    arrays.push(Arc::new(
+        TimestampMillisecondArray::from(timestamps).with_timezone_utc(),
+    ));
+    arrays.push(Arc::new(Float64Array::from(values)));
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(vec![], schema, Some(properties))?;
+    writer.write(&batch)?;
+    writer.into_inner()
+}
+
+pub(crate) const SUMMARY_COLUMNS: [&str; 10] = [
+    "program_name",
+    "region",
+    "id",
+    "count",
+    "min",
+    "max",
+    "mean",
+    "p95",
+    "p99",
+    "latest",
+];
+
+/// Renders min, max, mean, p95, p99 and the latest value of every exported series as a plain
+/// text table, one row per account and query. Series without datapoints only show a count of 0.
+pub fn metric_summary_to_table(all_data: &[AccountMetricData]) -> String {
+    let rows: Vec<Vec<String>> = all_data
+        .iter()
+        .flat_map(|acc| {
+            acc.series
+                .iter()
+                .map(move |series| metric_summary_row(acc, series))
+        })
+        .collect();
+    text_table(&SUMMARY_COLUMNS, &rows)
+}
+
+/// One string per column of [`SUMMARY_COLUMNS`] for a series
+pub(crate) fn metric_summary_row(acc: &AccountMetricData, series: &MetricSeries) -> Vec<String> {
+    let mut row = vec![
+        acc.program_name.clone(),
+        acc.region.clone(),
+        series.id.clone(),
+        series.datapoints.len().to_string(),
+    ];
+    let mut values: Vec<f64> = series.datapoints.iter().map(|dp| dp.value).collect();
+    values.sort_by(f64::total_cmp);
+    if let (Some(min), Some(max), Some(latest)) =
+        (values.first(), values.last(), series.datapoints.last())
+    {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        row.extend(
+            [
+                *min,
+                *max,
+                mean,
+                percentile(&values, 95.0),
+                percentile(&values, 99.0),
+                latest.value,
+            ]
+            .iter()
+            .map(|value| format!("{:.2}", value)),
+        );
+    } else {
+        row.extend(std::iter::repeat(String::new()).take(6));
+    }
+    row
+}
+
+/// Nearest-rank percentile of sorted, non-empty values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Calls AWS CloudWatch GetMetricData API in batches of 500 queries, following each batch's
+/// `next_token` until every datapoint is returned. A spec with expressions is sent in one batch,
+/// as an expression can only use queries of the same call.
+/// API Reference: [GetMetricData](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_GetMetricData.html)
+async fn get_metric_data(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    spec: &MetricDataSpec,
+    start_time: DateTime,
+    end_time: DateTime,
+    period: i32,
+) -> Result<Vec<MetricSeries>, aws_sdk_cloudwatch::Error> {
+    let queries: Vec<MetricDataQuery> = spec
+        .queries
+        .iter()
+        .map(|q| {
+            if let Some(expression) = &q.expression {
+                return MetricDataQuery::builder()
+                    .id(&q.id)
+                    .set_label(q.label.clone())
+                    .expression(expression)
+                    .return_data(q.return_data)
+                    .build();
+            }
+            let dimensions = q
+                .dimensions
+                .iter()
+                .map(|(name, value)| Dimension::builder().name(name).value(value).build())
+                .collect();
+            let metric = Metric::builder()
+                .namespace(&q.namespace)
+                .metric_name(&q.metric_name)
+                .set_dimensions(Some(dimensions))
+                .build();
+            MetricDataQuery::builder()
+                .id(&q.id)
+                .set_label(q.label.clone())
+                .metric_stat(
+                    MetricStat::builder()
+                        .metric(metric)
+                        .period(period)
+                        .stat(&q.stat)
+                        .build(),
+                )
+                .return_data(q.return_data)
+                .build()
+        })
+        .collect();
+
+    // specs with an expression fit in one batch, as checked when preparing them
+    let batches: Vec<&[MetricDataQuery]> = queries.chunks(MAX_QUERIES).collect();
+    debug!(
+        queries = queries.len(),
+        batches = batches.len(),
+        "getting metric data"
+    );
+    let mut series: Vec<MetricSeries> = vec![];
+    for batch in batches {
+        let slices = time_slices(start_time, end_time, period, batch.len());
+        if slices.len() > 1 {
+            info!(
+                queries = batch.len(),
+                slices = slices.len(),
+                "splitting time range to stay under the GetMetricData datapoint limit"
+            );
+        }
+        for (slice_start, slice_end) in slices {
+            get_metric_data_batch(client, retry, batch, slice_start, slice_end, &mut series)
+                .await?;
+        }
+    }
+    for s in &mut series {
+        s.period = period;
+        if let Some(q) = spec.queries.iter().find(|q| q.id == s.id) {
+            s.namespace = q.namespace.clone();
+            s.metric_name = q.metric_name.clone();
+            s.dimensions = q.dimensions.clone();
+            s.stat = q.stat.clone();
+        }
+    }
+    Ok(series)
+}
+
+/// Splits the time range into consecutive slices where every query returns at most its share of
+/// the 100,800 datapoints one call can return. Slices are a whole number of periods long, so each
+/// period falls in exactly one of them.
+fn time_slices(
+    start_time: DateTime,
+    end_time: DateTime,
+    period: i32,
+    queries: usize,
+) -> Vec<(DateTime, DateTime)> {
+    let periods_per_slice = (MAX_DATAPOINTS / queries.max(1) as i64).max(1);
+    let slice_secs = periods_per_slice * i64::from(period.max(1));
+    let mut slices = vec![];
+    let mut slice_start = start_time;
+    while slice_start.secs() + slice_secs < end_time.secs() {
+        let slice_end = DateTime::from_secs(slice_start.secs() + slice_secs);
+        slices.push((slice_start, slice_end));
+        slice_start = slice_end;
+    }
+    slices.push((slice_start, end_time));
+    slices
+}
+
+/// Runs one batch of queries, appending each page's datapoints to the series with the same id
+async fn get_metric_data_batch(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    queries: &[MetricDataQuery],
+    start_time: DateTime,
+    end_time: DateTime,
+    series: &mut Vec<MetricSeries>,
+) -> Result<(), aws_sdk_cloudwatch::Error> {
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client
+            .get_metric_data()
+            .set_metric_data_queries(Some(queries.to_vec()))
+            .start_time(start_time)
+            .end_time(end_time)
+            .scan_by(ScanBy::TimestampAscending)
+            .set_next_token(next_token);
+        let resp = retry
+            .call("GetMetricData", || request.clone().send())
+            .await?;
+
+        for result in resp.metric_data_results().unwrap_or_default() {
+            let id = result.id().unwrap_or_default();
+            let datapoints: Vec<Datapoint> = result
+                .timestamps()
+                .unwrap_or_default()
+                .iter()
+                .zip(result.values().unwrap_or_default())
+                .map(|(timestamp, value)| Datapoint {
+                    timestamp: timestamp.fmt(Format::DateTime).unwrap_or_default(),
+                    value: *value,
+                })
+                .collect();
+            match series.iter_mut().find(|s| s.id == id) {
+                Some(existing) => existing.datapoints.extend(datapoints),
+                None => series.push(MetricSeries {
+                    id: String::from(id),
+                    label: String::from(result.label().unwrap_or_default()),
+                    datapoints,
+                    ..MetricSeries::default()
+                }),
+            }
+        }
+
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: i64 = 1_700_000_000;
+
+    /// Checks the slices run back to back from `start` to `end` and each stays in the budget
+    fn assert_slices(slices: &[(DateTime, DateTime)], end: i64, period: i64, queries: i64) {
+        assert_eq!(slices.first().unwrap().0.secs(), START);
+        assert_eq!(slices.last().unwrap().1.secs(), end);
+        for pair in slices.windows(2) {
+            assert_eq!(pair[0].1.secs(), pair[1].0.secs());
+        }
+        for (slice_start, slice_end) in slices {
+            let secs = slice_end.secs() - slice_start.secs();
+            assert!(secs > 0);
+            // every period of the slice returns a datapoint per query
+            let periods = (secs + period - 1) / period;
+            assert!(periods * queries <= MAX_DATAPOINTS);
+        }
+        // only the last slice may end part way through a period
+        for (slice_start, slice_end) in &slices[..slices.len() - 1] {
+            assert_eq!((slice_end.secs() - slice_start.secs()) % period, 0);
+        }
+    }
+
+    #[test]
+    fn one_slice_under_the_limit() {
+        let end = START + 86_400;
+        let slices = time_slices(DateTime::from_secs(START), DateTime::from_secs(end), 60, 10);
+        assert_eq!(slices.len(), 1);
+        assert_slices(&slices, end, 60, 10);
+    }
+
+    #[test]
+    fn slices_share_the_limit_between_queries() {
+        // 30 days every minute for a full batch: 201 periods per slice
+        let end = START + 30 * 86_400;
+        let slices = time_slices(
+            DateTime::from_secs(START),
+            DateTime::from_secs(end),
+            60,
+            500,
+        );
+        assert_eq!(slices.len(), 215);
+        assert_eq!(slices[0].1.secs() - slices[0].0.secs(), 201 * 60);
+        assert_slices(&slices, end, 60, 500);
+    }
+
+    #[test]
+    fn single_query_at_the_limit() {
+        let end = START + 2 * MAX_DATAPOINTS;
+        let slices = time_slices(DateTime::from_secs(START), DateTime::from_secs(end), 1, 1);
+        assert_eq!(slices.len(), 2);
+        assert_slices(&slices, end, 1, 1);
+
+        let end = START + MAX_DATAPOINTS;
+        let slices = time_slices(DateTime::from_secs(START), DateTime::from_secs(end), 1, 1);
+        assert_eq!(slices.len(), 1);
+    }
+
+    #[test]
+    fn range_that_isnt_a_whole_number_of_periods() {
+        let end = START + 250 * 3600 + 1800;
+        let slices = time_slices(
+            DateTime::from_secs(START),
+            DateTime::from_secs(end),
+            3600,
+            500,
+        );
+        assert_eq!(slices.len(), 2);
+        assert_slices(&slices, end, 3600, 500);
+
+        let end = START + 30;
+        let slices = time_slices(DateTime::from_secs(START), DateTime::from_secs(end), 60, 1);
+        assert_eq!(slices.len(), 1);
+        assert_slices(&slices, end, 60, 1);
+    }
+}
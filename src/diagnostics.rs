@@ -0,0 +1,473 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use toml::Value;
+
+use crate::aws_regions;
+use crate::config::{AccountDefaults, AccountsConfig};
+
+/// Keys understood at the top level of an accounts file
+const FILE_KEYS: [&str; 3] = ["include", "defaults", "account"];
+
+/// Keys understood in `[defaults]`, matching [`crate::config::AccountDefaults`]
+const DEFAULTS_KEYS: [&str; 8] = [
+    "region",
+    "role_arn",
+    "via_role_arn",
+    "mfa_serial",
+    "period",
+    "output_path",
+    "template_path",
+    "slack_webhook",
+];
+
+/// Keys understood in `[[account]]`, matching [`crate::config::AccountConfig`]
+const ACCOUNT_KEYS: [&str; 16] = [
+    "namespace",
+    "region",
+    "role_arn",
+    "account_id",
+    "session_name",
+    "session_duration",
+    "external_id",
+    "via_role_arn",
+    "mfa_serial",
+    "vars",
+    "period",
+    "start",
+    "end",
+    "template",
+    "group",
+    "tags",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Unknown keys, which are ignored unless checking strictly
+    Warning,
+    Error,
+}
+
+/// A problem in an accounts file, pointing at the line and key to fix
+#[derive(Debug, Clone)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub path: PathBuf,
+    /// 1-based line, when the problem can be tied to one
+    pub line: Option<usize>,
+    /// Where in the file, e.g. `account[2].role_arn`
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, ": {}", field)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Checks accounts files and the files they include, reporting every problem found instead of
+/// stopping at the first: TOML syntax and types, unknown keys, missing keys, region names, role
+/// ARN format and accounts configured more than once.
+pub fn check_config(paths: &[impl AsRef<Path>]) -> Vec<ConfigIssue> {
+    let mut checker = Checker::default();
+    for path in paths {
+        checker.check_file(path.as_ref(), &[]);
+    }
+    checker.issues
+}
+
+#[derive(Default)]
+struct Checker {
+    issues: Vec<ConfigIssue>,
+    checked: Vec<PathBuf>,
+    /// Where each `(role_arn, region)` pair was first configured
+    seen: HashMap<(String, String), (PathBuf, Option<usize>)>,
+}
+
+impl Checker {
+    /// Checks one file and its includes. `inherited` holds the `[defaults]` of the files that
+    /// include it, nearest first, since those fill in its accounts too.
+    fn check_file(&mut self, path: &Path, inherited: &[AccountDefaults]) {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if self.checked.contains(&canonical) {
+            self.error(path, None, None, "file is included more than once");
+            return;
+        }
+        self.checked.push(canonical);
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => return self.error(path, None, None, &format!("unable to read: {}", e)),
+        };
+        let value: Value = match toml::from_str(&contents) {
+            Ok(value) => value,
+            Err(e) => return self.toml_error(path, &e),
+        };
+        let lines = Lines::new(&contents);
+        self.check_keys(path, &lines, &value);
+
+        let mut config: AccountsConfig = match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => return self.toml_error(path, &e),
+        };
+        let own_defaults = config.defaults.clone();
+        config.apply_defaults();
+        for defaults in inherited {
+            config.defaults = defaults.clone();
+            config.apply_defaults();
+        }
+        for (index, acc) in config.account.iter().enumerate() {
+            let at = |field: &str| {
+                (
+                    lines.account_field(index, field),
+                    Some(format!("account[{}].{}", index, field)),
+                )
+            };
+            if acc.region.is_empty() {
+                let (line, field) = at("region");
+                self.error(path, line, field, "missing, set it here or in [defaults]");
+            } else if let Err(e) = aws_regions::parse_region(&acc.region) {
+                let (line, field) = at("region");
+                self.error(path, line, field, &e);
+            }
+            if acc.role_arn.is_empty() {
+                let (line, field) = at("role_arn");
+                self.error(path, line, field, "missing, set it here or in [defaults]");
+            } else if acc.role_arn.contains("{{ACCOUNT_ID}}") {
+                let (line, field) = at("account_id");
+                self.error(
+                    path,
+                    line,
+                    field,
+                    "missing, needed for {{ACCOUNT_ID}} in the default role_arn",
+                );
+            } else if !role_arn_regex().is_match(&acc.role_arn) {
+                let (line, field) = at("role_arn");
+                self.error(
+                    path,
+                    line,
+                    field,
+                    &format!(
+                        "{:?} is not an IAM role ARN like arn:aws:iam::111111111111:role/Name",
+                        acc.role_arn
+                    ),
+                );
+            }
+            if !acc.role_arn.is_empty() && !acc.region.is_empty() {
+                let (line, field) = at("role_arn");
+                let key = (acc.role_arn.clone(), acc.region.clone());
+                match self.seen.get(&key) {
+                    Some((first_path, first_line)) => {
+                        let message = format!(
+                            "{} in {} is already configured at {}:{}",
+                            acc.role_arn,
+                            acc.region,
+                            first_path.display(),
+                            first_line.map(|l| l.to_string()).unwrap_or_default()
+                        );
+                        self.error(path, line, field, &message);
+                    }
+                    None => {
+                        self.seen.insert(key, (path.to_path_buf(), line));
+                    }
+                }
+            }
+        }
+
+        let mut chain = vec![own_defaults];
+        chain.extend_from_slice(inherited);
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for include in &config.include {
+            self.check_file(&dir.join(include), &chain);
+        }
+    }
+
+    /// Reports keys the config doesn't know about, often a typo that silently does nothing
+    fn check_keys(&mut self, path: &Path, lines: &Lines, value: &Value) {
+        let unknown = |table: &toml::value::Table, known: &[&str]| -> Vec<String> {
+            table
+                .keys()
+                .filter(|key| !known.contains(&key.as_str()))
+                .cloned()
+                .collect()
+        };
+        let table = match value.as_table() {
+            Some(table) => table,
+            None => return,
+        };
+        for key in unknown(table, &FILE_KEYS) {
+            let line = lines.key(0, usize::MAX, &key);
+            self.warning(path, line, Some(key), "unknown key");
+        }
+        if let Some(defaults) = table.get("defaults").and_then(Value::as_table) {
+            for key in unknown(defaults, &DEFAULTS_KEYS) {
+                let line = lines.defaults_field(&key);
+                self.warning(path, line, Some(format!("defaults.{}", key)), "unknown key");
+            }
+        }
+        let accounts = table.get("account").and_then(Value::as_array);
+        for (index, account) in accounts.into_iter().flatten().enumerate() {
+            for key in account
+                .as_table()
+                .map(|account| unknown(account, &ACCOUNT_KEYS))
+                .unwrap_or_default()
+            {
+                let line = lines.account_field(index, &key);
+                let field = format!("account[{}].{}", index, key);
+                self.warning(path, line, Some(field), "unknown key");
+            }
+        }
+    }
+
+    fn toml_error(&mut self, path: &Path, e: &toml::de::Error) {
+        let line = e.line_col().map(|(line, _)| line + 1);
+        self.error(path, line, None, &e.to_string());
+    }
+
+    fn error(&mut self, path: &Path, line: Option<usize>, field: Option<String>, message: &str) {
+        self.push(Severity::Error, path, line, field, message);
+    }
+
+    fn warning(&mut self, path: &Path, line: Option<usize>, field: Option<String>, message: &str) {
+        self.push(Severity::Warning, path, line, field, message);
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        path: &Path,
+        line: Option<usize>,
+        field: Option<String>,
+        message: &str,
+    ) {
+        self.issues.push(ConfigIssue {
+            severity,
+            path: path.to_path_buf(),
+            line,
+            field,
+            message: String::from(message),
+        });
+    }
+}
+
+fn role_arn_regex() -> Regex {
+    Regex::new(r"^arn:aws[a-z-]*:iam::\d{12}:role/[\w+=,.@/-]+$").expect("role ARN regex is valid")
+}
+
+/// Finds the lines of tables and keys, good enough for the flat accounts files
+struct Lines<'a> {
+    lines: Vec<&'a str>,
+    /// 0-based line of each `[[account]]` header, in order
+    accounts: Vec<usize>,
+    defaults: Option<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(contents: &'a str) -> Self {
+        let lines: Vec<&str> = contents.lines().collect();
+        let accounts = (0..lines.len())
+            .filter(|i| lines[*i].trim() == "[[account]]")
+            .collect();
+        let defaults = (0..lines.len()).find(|i| lines[*i].trim() == "[defaults]");
+        Lines {
+            lines,
+            accounts,
+            defaults,
+        }
+    }
+
+    /// 1-based line of `key = ...` between the 0-based `start` and `end` lines
+    fn key(&self, start: usize, end: usize, key: &str) -> Option<usize> {
+        let end = end.min(self.lines.len());
+        (start..end)
+            .find(|i| {
+                let line = self.lines[*i].trim_start();
+                line.strip_prefix(key)
+                    .map_or(false, |rest| rest.trim_start().starts_with('='))
+            })
+            .map(|i| i + 1)
+    }
+
+    /// 1-based line of a key in the table starting at the 0-based `header` line
+    fn table_key(&self, header: usize, key: &str) -> Option<usize> {
+        let end = (header + 1..self.lines.len())
+            .find(|i| self.lines[*i].trim_start().starts_with('['))
+            .unwrap_or(self.lines.len());
+        self.key(header + 1, end, key).or(Some(header + 1))
+    }
+
+    fn account_field(&self, index: usize, key: &str) -> Option<usize> {
+        let header = *self.accounts.get(index)?;
+        self.table_key(header, key)
+    }
+
+    fn defaults_field(&self, key: &str) -> Option<usize> {
+        self.table_key(self.defaults?, key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cw-metrics-diagnostics-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// The single issue found in an accounts file with `contents`
+    fn issue(name: &str, contents: &str) -> ConfigIssue {
+        let path = config_dir(name).join("accounts.toml");
+        std::fs::write(&path, contents).unwrap();
+        let mut issues = check_config(&[&path]);
+        assert_eq!(issues.len(), 1, "{}: {:?}", name, issues);
+        issues.remove(0)
+    }
+
+    const ROLE: &str = "arn:aws:iam::111111111111:role/ReadOnly";
+
+    #[test]
+    fn each_problem_points_at_its_line_and_field() {
+        let cases = [
+            (
+                "syntax",
+                String::from("[[account]]\nnamespace = \"Orders\nregion = \"us-east-1\"\n"),
+                Severity::Error,
+                Some(2),
+                None,
+                "",
+            ),
+            (
+                "unknown-key",
+                format!(
+                    "[[account]]\nnamespace = \"Orders\"\nregion = \"us-east-1\"\nrole_arn = \"{}\"\nrgeion = \"us-west-2\"\n",
+                    ROLE
+                ),
+                Severity::Warning,
+                Some(5),
+                Some("account[0].rgeion"),
+                "unknown key",
+            ),
+            (
+                "unknown-default",
+                format!(
+                    "[defaults]\nregion = \"us-east-1\"\nperiods = 60\n\n[[account]]\nnamespace = \"Orders\"\nrole_arn = \"{}\"\n",
+                    ROLE
+                ),
+                Severity::Warning,
+                Some(3),
+                Some("defaults.periods"),
+                "unknown key",
+            ),
+            (
+                "missing-region",
+                format!(
+                    "[[account]]\nnamespace = \"Orders\"\nrole_arn = \"{}\"\n",
+                    ROLE
+                ),
+                Severity::Error,
+                Some(1),
+                Some("account[0].region"),
+                "missing, set it here or in [defaults]",
+            ),
+            (
+                "bad-region",
+                format!(
+                    "[[account]]\nnamespace = \"Orders\"\nregion = \"us-east\"\nrole_arn = \"{}\"\n",
+                    ROLE
+                ),
+                Severity::Error,
+                Some(3),
+                Some("account[0].region"),
+                "invalid AWS region \"us-east\"",
+            ),
+            (
+                "missing-role",
+                String::from("[[account]]\nnamespace = \"Orders\"\nregion = \"us-east-1\"\n"),
+                Severity::Error,
+                Some(1),
+                Some("account[0].role_arn"),
+                "missing, set it here or in [defaults]",
+            ),
+            (
+                "missing-account-id",
+                String::from(
+                    "[defaults]\nrole_arn = \"arn:aws:iam::{{ACCOUNT_ID}}:role/ReadOnly\"\n\n[[account]]\nnamespace = \"Orders\"\nregion = \"us-east-1\"\n",
+                ),
+                Severity::Error,
+                Some(4),
+                Some("account[0].account_id"),
+                "missing, needed for {{ACCOUNT_ID}}",
+            ),
+            (
+                "bad-role",
+                String::from(
+                    "[[account]]\nnamespace = \"Orders\"\nregion = \"us-east-1\"\nrole_arn = \"ReadOnly\"\n",
+                ),
+                Severity::Error,
+                Some(4),
+                Some("account[0].role_arn"),
+                "\"ReadOnly\" is not an IAM role ARN",
+            ),
+            (
+                "duplicate",
+                format!(
+                    "[[account]]\nnamespace = \"Orders\"\nregion = \"us-east-1\"\nrole_arn = \"{0}\"\n\n[[account]]\nnamespace = \"Again\"\nregion = \"us-east-1\"\nrole_arn = \"{0}\"\n",
+                    ROLE
+                ),
+                Severity::Error,
+                Some(9),
+                Some("account[1].role_arn"),
+                "is already configured at",
+            ),
+        ];
+        for (name, contents, severity, line, field, message) in cases {
+            let issue = issue(name, &contents);
+            assert_eq!(issue.severity, severity, "{}", name);
+            assert_eq!(issue.line, line, "{}", name);
+            assert_eq!(issue.field.as_deref(), field, "{}", name);
+            assert!(issue.message.contains(message), "{}: {}", name, issue);
+        }
+    }
+
+    #[test]
+    fn unreadable_and_repeated_includes() {
+        let dir = config_dir("includes");
+        let missing = dir.join("missing.toml");
+        let issues = check_config(&[&missing]);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.starts_with("unable to read: "));
+
+        let path = dir.join("accounts.toml");
+        std::fs::write(
+            &path,
+            "include = [\"team.toml\", \"team.toml\"]\naccount = []\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("team.toml"), "account = []\n").unwrap();
+        let issues = check_config(&[&path]);
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        assert_eq!(issues[0].path, dir.join("team.toml"));
+        assert_eq!(issues[0].message, "file is included more than once");
+    }
+}
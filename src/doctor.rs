@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use aws_types::region::Region;
+use tracing::debug;
+
+use crate::client::get_sts_client;
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+
+/// How long each step took for an account that is reachable
+#[derive(Debug, Clone)]
+pub struct AccountCheck {
+    pub role_arn: String,
+    pub assume_role_ms: u64,
+    pub list_metrics_ms: u64,
+}
+
+/// Checks every account can be used before a long run: its role can be assumed and CloudWatch
+/// answers with the assumed credentials
+#[derive(Debug, Clone, Default)]
+pub struct Doctor {
+    options: RunOptions,
+}
+
+impl Doctor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<AccountCheck> {
+        let doctor = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let doctor = doctor.clone();
+                async move { doctor.check(&acc).await }
+            })
+            .await
+    }
+
+    /// Assumes the account's role, then reads one page of ListMetrics with it
+    pub async fn check(&self, acc: &AccountConfig) -> Result<AccountCheck, Error> {
+        debug!(account = %acc.label(), "checking account");
+        let started = Instant::now();
+        let client = self.options.cloudwatch_client(acc).await?;
+        let assume_role_ms = started.elapsed().as_millis() as u64;
+
+        // ListMetrics has no page size, but the first page is enough to prove access
+        let started = Instant::now();
+        let request = client.list_metrics();
+        self.options
+            .retry
+            .call("ListMetrics", || request.clone().send())
+            .await
+            .map_err(|e| Error::Check {
+                step: "ListMetrics",
+                source: e.into(),
+            })?;
+        Ok(AccountCheck {
+            role_arn: acc.role_arn.clone(),
+            assume_role_ms,
+            list_metrics_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+/// The ARN of the credentials the CLI starts with, which assume every account's role
+pub async fn caller_identity(region: &Region, retry: &RetryPolicy) -> Result<String, Error> {
+    let client = get_sts_client(region).await;
+    let request = client.get_caller_identity();
+    let resp = retry
+        .call("GetCallerIdentity", || request.clone().send())
+        .await
+        .map_err(|e| Error::Credentials(e.into()))?;
+    resp.arn()
+        .map(String::from)
+        .ok_or(Error::EmptyResponse("GetCallerIdentity"))
+}
+
+/// Renders one row per account with whether it's reachable and why not. Statuses are colored
+/// green and red when `color` is set.
+pub fn checks_to_table(checks: &AccountResults<AccountCheck>, color: bool) -> String {
+    let paint = |status: &str, code: &str| {
+        let padded = format!("{:11}", status);
+        if color {
+            format!("\x1b[{}m{}\x1b[0m", code, padded)
+        } else {
+            padded
+        }
+    };
+    let width = checks
+        .iter()
+        .map(|(account, _)| account.chars().count())
+        .chain([7])
+        .max()
+        .unwrap_or_default();
+
+    let mut table = format!(
+        "{:11}  {:width$}  DETAIL\n",
+        "STATUS",
+        "ACCOUNT",
+        width = width
+    );
+    for (account, res) in checks {
+        let (status, detail) = match res {
+            Ok(check) => (
+                paint("reachable", "32"),
+                format!(
+                    "assumed {} in {}ms, ListMetrics in {}ms",
+                    check.role_arn, check.assume_role_ms, check.list_metrics_ms
+                ),
+            ),
+            Err(e) => (paint("unreachable", "31"), e.to_string()),
+        };
+        table.push_str(&format!(
+            "{}  {:width$}  {}\n",
+            status,
+            account,
+            detail,
+            width = width
+        ));
+    }
+    table
+}
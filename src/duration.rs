@@ -0,0 +1,344 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use chrono::{Offset, Utc};
+use chrono_tz::Tz;
+use tracing::warn;
+
+use crate::error::Error;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// How long CloudWatch keeps datapoints by their period: `(max age in seconds, smallest period)`
+/// (see [metrics retention](https://docs.aws.amazon.com/AmazonCloudWatch/latest/monitoring/cloudwatch_concepts.html#metrics-retention))
+const RETENTION: [(i64, u32); 4] = [
+    (3 * 60 * 60, 1),
+    (15 * DAY, 60),
+    (63 * DAY, 300),
+    (455 * DAY, 3600),
+];
+
+/// Parses an amount with a unit of `s`, `m`, `h` (or `H`), `d` or `w`, using `default_unit` for
+/// a bare number
+fn parse_duration(value: &str, default_unit: &str) -> Option<Duration> {
+    let (amount, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, default_unit),
+    };
+    let amount: u64 = amount.parse().ok()?;
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" | "H" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    amount.checked_mul(unit_seconds).map(Duration::from_secs)
+}
+
+/// Parses an interval like `90s`, `15m` or `1h`; a bare number is taken as seconds
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    match parse_duration(value, "s") {
+        Some(interval) if !interval.is_zero() => Ok(interval),
+        _ => Err(format!(
+            "invalid interval {:?}, expected e.g. 90s, 15m or 1h",
+            value
+        )),
+    }
+}
+
+/// The start or end of a time range: an offset before now, or an absolute time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeBound {
+    Ago(Duration),
+    At(DateTime),
+}
+
+impl TimeBound {
+    pub fn is_absolute(&self) -> bool {
+        matches!(self, TimeBound::At(_))
+    }
+
+    pub fn date_time(&self) -> DateTime {
+        self.date_time_from(SystemTime::now())
+    }
+
+    fn date_time_from(&self, now: SystemTime) -> DateTime {
+        match self {
+            TimeBound::Ago(offset) => {
+                DateTime::from(now.checked_sub(*offset).unwrap_or(UNIX_EPOCH))
+            }
+            TimeBound::At(at) => *at,
+        }
+    }
+
+    /// The value templates get as `{{PERIOD_START}}`/`{{PERIOD_END}}`: offsets in the form
+    /// CloudWatch expects after `-PT`, e.g. `3d` becomes `72H`, and timestamps in RFC3339
+    pub fn template_value(&self) -> String {
+        match self {
+            TimeBound::Ago(offset) => {
+                let secs = offset.as_secs();
+                if secs % 3600 == 0 {
+                    format!("{}H", secs / 3600)
+                } else if secs % 60 == 0 {
+                    format!("{}M", secs / 60)
+                } else {
+                    format!("{}S", secs)
+                }
+            }
+            TimeBound::At(at) => at.fmt(Format::DateTime).unwrap_or_default(),
+        }
+    }
+
+    /// The `start`/`end` value of a GetMetricWidgetImage request, e.g. `-PT4320H` or
+    /// `2024-01-01T00:00:00Z`
+    pub fn widget_value(&self) -> String {
+        match self {
+            TimeBound::Ago(_) => format!("-PT{}", self.template_value()),
+            TimeBound::At(_) => self.template_value(),
+        }
+    }
+}
+
+/// Parses a time before now like `90m`, `36h`, `3d` or `2w` (a bare number or `4320H` is in
+/// hours), or an RFC3339 timestamp like `2024-01-01T00:00:00Z` or `2024-01-01T09:00:00+09:00`
+pub fn parse_time(value: &str) -> Result<TimeBound, String> {
+    if let Some(offset) = parse_duration(value, "h") {
+        return Ok(TimeBound::Ago(offset));
+    }
+    // Smithy's parser only takes UTC timestamps ending in Z
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|at| {
+            TimeBound::At(DateTime::from_secs_and_nanos(
+                at.timestamp(),
+                at.timestamp_subsec_nanos(),
+            ))
+        })
+        .map_err(|_| {
+            format!(
+                "invalid time {:?}, expected a duration before now like 90m, 36h, 3d or 2w, or an RFC3339 timestamp like 2024-01-01T00:00:00Z",
+                value
+            )
+        })
+}
+
+/// Parses the start and end of a time range, checking the end comes after the start
+pub fn time_range(start: &str, end: &str) -> Result<(TimeBound, TimeBound), Error> {
+    let parse = |name, value: &str| {
+        parse_time(value).map_err(|_| Error::InvalidArgument {
+            name,
+            value: String::from(value),
+        })
+    };
+    let range = (parse("start time", start)?, parse("end time", end)?);
+    // both offsets from the same now, so equal ones aren't a range
+    let now = SystemTime::now();
+    let (from, to) = (range.0.date_time_from(now), range.1.date_time_from(now));
+    if (to.secs(), to.subsec_nanos()) <= (from.secs(), from.subsec_nanos()) {
+        return Err(Error::TimeRange {
+            start: String::from(start),
+            end: String::from(end),
+        });
+    }
+    Ok(range)
+}
+
+/// Parses a timezone into the `+HHMM` offset GetMetricWidgetImage expects: an offset like
+/// `+0900` or `-05:30`, or an IANA name like `Asia/Tokyo` converted with its current offset
+pub fn parse_timezone(value: &str) -> Result<String, String> {
+    let invalid = || {
+        format!(
+            "invalid timezone {:?}, expected an offset like +0900 or a name like Asia/Tokyo",
+            value
+        )
+    };
+    if value.starts_with(['+', '-']) {
+        let (sign, offset) = value.split_at(1);
+        let digits = offset.replace(':', "");
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let hours: u32 = digits[..2].parse().map_err(|_| invalid())?;
+        let minutes: u32 = digits[2..].parse().map_err(|_| invalid())?;
+        if hours > 14 || minutes >= 60 {
+            return Err(invalid());
+        }
+        return Ok(format!("{}{}", sign, digits));
+    }
+    let tz: Tz = value.parse().map_err(|_| invalid())?;
+    let offset = Utc::now()
+        .with_timezone(&tz)
+        .offset()
+        .fix()
+        .local_minus_utc();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.abs() / 60;
+    Ok(format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60))
+}
+
+/// Parses a period in seconds, which must be at least 1
+pub fn parse_period(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(period) if period > 0 => Ok(period),
+        _ => Err(format!(
+            "invalid period {:?}, expected a whole number of seconds",
+            value
+        )),
+    }
+}
+
+/// The smallest period CloudWatch keeps for data `age` seconds old, `None` past 455 days
+fn retained_period(age: i64) -> Option<u32> {
+    RETENTION
+        .iter()
+        .find(|(max_age, _)| age <= *max_age)
+        .map(|(_, period)| *period)
+}
+
+/// Parses a period in seconds and raises it to what CloudWatch still keeps for data as old as
+/// `start`, e.g. 60 second data is only kept for 15 days and is aggregated to 300 seconds after
+/// that. Asking for a smaller period returns no data or degraded graphs, so the period is
+/// rounded up to a multiple of the retained one with a warning. Data older than 455 days isn't
+/// kept at all.
+pub fn fit_period(period: &str, start: &TimeBound) -> Result<u32, Error> {
+    let requested = parse_period(period).map_err(|_| Error::InvalidArgument {
+        name: "period",
+        value: String::from(period),
+    })?;
+    let age = DateTime::from(SystemTime::now()).secs() - start.date_time().secs();
+    let retained = retained_period(age).ok_or_else(|| Error::Retention(start.template_value()))?;
+    if requested % retained == 0 {
+        return Ok(requested);
+    }
+    let fitted = (requested / retained + 1) * retained;
+    warn!(
+        requested,
+        period = fitted,
+        start = %start.template_value(),
+        "period is finer than CloudWatch keeps for the start time, raising it"
+    );
+    Ok(fitted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60;
+
+    fn ago(secs: u64) -> TimeBound {
+        TimeBound::Ago(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn zero_hours_is_now() {
+        let now = parse_time("0H").unwrap();
+        assert_eq!(now, ago(0));
+        assert_eq!(now.template_value(), "0H");
+        assert_eq!(now.widget_value(), "-PT0H");
+        assert!(time_range("3d", "0H").is_ok());
+        assert!(matches!(
+            time_range("0H", "0H"),
+            Err(Error::TimeRange { .. })
+        ));
+    }
+
+    #[test]
+    fn end_before_start_is_rejected() {
+        assert!(matches!(
+            time_range("0H", "24H"),
+            Err(Error::TimeRange { .. })
+        ));
+        assert!(matches!(
+            time_range("2024-01-02T00:00:00Z", "2024-01-01T00:00:00Z"),
+            Err(Error::TimeRange { .. })
+        ));
+        assert!(matches!(
+            time_range("yesterday", "0H"),
+            Err(Error::InvalidArgument {
+                name: "start time",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rfc3339_offsets_are_converted_to_utc() {
+        let utc = parse_time("2024-01-01T00:00:00Z").unwrap();
+        assert_eq!(parse_time("2024-01-01T09:00:00+09:00").unwrap(), utc);
+        assert_eq!(parse_time("2023-12-31T19:00:00-05:00").unwrap(), utc);
+        assert_eq!(utc.template_value(), "2024-01-01T00:00:00Z");
+        assert_eq!(utc.widget_value(), "2024-01-01T00:00:00Z");
+        // 08:00 in Tokyo is still the day before in UTC
+        assert!(matches!(
+            time_range("2024-01-01T00:00:00Z", "2024-01-01T08:00:00+09:00"),
+            Err(Error::TimeRange { .. })
+        ));
+        assert!(parse_time("2024-01-01T00:00:00").is_err());
+    }
+
+    #[test]
+    fn fit_period_rounds_up_to_the_retained_period() {
+        // recent data keeps any period
+        assert_eq!(fit_period("1", &ago(HOUR)).unwrap(), 1);
+        // 60 second data up to 15 days
+        assert_eq!(fit_period("60", &ago(24 * HOUR)).unwrap(), 60);
+        assert_eq!(fit_period("90", &ago(24 * HOUR)).unwrap(), 120);
+        // 300 second data up to 63 days
+        assert_eq!(fit_period("60", &ago(30 * 24 * HOUR)).unwrap(), 300);
+        assert_eq!(fit_period("301", &ago(30 * 24 * HOUR)).unwrap(), 600);
+        // hourly data up to 455 days
+        assert_eq!(fit_period("300", &ago(100 * 24 * HOUR)).unwrap(), 3600);
+        assert_eq!(fit_period("86400", &ago(100 * 24 * HOUR)).unwrap(), 86400);
+    }
+
+    #[test]
+    fn retention_tiers_end_on_their_last_day() {
+        for (age, period) in [
+            (0, Some(1)),
+            (3 * 60 * 60, Some(1)),
+            (3 * 60 * 60 + 1, Some(60)),
+            (15 * DAY, Some(60)),
+            (15 * DAY + 1, Some(300)),
+            (63 * DAY, Some(300)),
+            (63 * DAY + 1, Some(3600)),
+            (455 * DAY, Some(3600)),
+            (455 * DAY + 1, None),
+        ] {
+            assert_eq!(retained_period(age), period, "{}", age);
+        }
+    }
+
+    #[test]
+    fn fit_period_around_the_retention_boundaries() {
+        let day = 24 * HOUR;
+        // a minute either side, the clock moves between building the start and fitting
+        assert_eq!(fit_period("60", &ago(15 * day - 60)).unwrap(), 60);
+        assert_eq!(fit_period("60", &ago(15 * day + 60)).unwrap(), 300);
+        assert_eq!(fit_period("300", &ago(63 * day - 60)).unwrap(), 300);
+        assert_eq!(fit_period("300", &ago(63 * day + 60)).unwrap(), 3600);
+        assert_eq!(fit_period("3600", &ago(455 * day - 60)).unwrap(), 3600);
+        assert!(matches!(
+            fit_period("3600", &ago(455 * day + 60)),
+            Err(Error::Retention(_))
+        ));
+    }
+
+    #[test]
+    fn fit_period_rejects_bad_periods() {
+        for period in ["0", "-60", "1.5", "1h", ""] {
+            assert!(
+                matches!(
+                    fit_period(period, &ago(HOUR)),
+                    Err(Error::InvalidArgument { name: "period", .. })
+                ),
+                "{:?}",
+                period
+            );
+            assert!(parse_period(period).is_err(), "{:?}", period);
+        }
+        assert_eq!(parse_period("300"), Ok(300));
+    }
+}
@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use aws_sdk_sesv2::model::{Destination, EmailContent, RawMessage};
+use aws_sdk_sesv2::types::Blob;
+use aws_sdk_sesv2::Client as sesClient;
+use tracing::debug;
+
+use crate::client::base_config;
+use crate::error::Error;
+use crate::report::escape_html;
+use crate::retry::RetryPolicy;
+
+/// Base64 bodies are wrapped at the 76 characters MIME allows per line
+const MIME_LINE_LEN: usize = 76;
+
+/// How the report's images are added to the email
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailImages {
+    /// Shown in the HTML report's body, attached otherwise
+    Inline,
+    Attach,
+}
+
+/// Sends a saved report and its images with SES, using the default credentials. SES accepts
+/// messages of up to 40 MB, images included.
+#[derive(Debug, Clone)]
+pub struct ReportMailer {
+    client: sesClient,
+    from: String,
+    to: Vec<String>,
+    images: EmailImages,
+    retry: RetryPolicy,
+}
+
+impl ReportMailer {
+    /// `from` has to be an address or domain verified in SES
+    pub async fn new(from: impl Into<String>, to: Vec<String>, retry: RetryPolicy) -> Self {
+        ReportMailer {
+            client: sesClient::new(base_config().await),
+            from: from.into(),
+            to,
+            images: EmailImages::Inline,
+            retry,
+        }
+    }
+
+    pub fn images(mut self, images: EmailImages) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Calls AWS SES SendEmail API with a raw MIME message, returning the message ID. An HTML
+    /// report is the email's body, a markdown report is sent as plain text.
+    /// API Reference: [SendEmail](https://docs.aws.amazon.com/ses/latest/APIReference-V2/API_SendEmail.html)
+    pub async fn send(
+        &self,
+        subject: &str,
+        report_path: &Path,
+        report: &str,
+        images: &[PathBuf],
+    ) -> Result<String, Error> {
+        let mut attachments = vec![];
+        for path in images {
+            let contents = tokio::fs::read(path).await.map_err(|source| Error::Read {
+                path: path.clone(),
+                source,
+            })?;
+            attachments.push((file_name(path), contents));
+        }
+        let html = report_path.extension().map_or(false, |ext| ext == "html");
+        let message = self.mime_message(subject, report, html, &attachments);
+        debug!(bytes = message.len(), to = ?self.to, "sending report email");
+
+        let request = self
+            .client
+            .send_email()
+            .from_email_address(&self.from)
+            .destination(
+                Destination::builder()
+                    .set_to_addresses(Some(self.to.clone()))
+                    .build(),
+            )
+            .content(
+                EmailContent::builder()
+                    .raw(RawMessage::builder().data(Blob::new(message)).build())
+                    .build(),
+            );
+        let resp = self
+            .retry
+            .call("SendEmail", || request.clone().send())
+            .await
+            .map_err(|e| Error::Email(aws_sdk_sesv2::Error::from(e)))?;
+        Ok(String::from(resp.message_id().unwrap_or_default()))
+    }
+
+    /// Builds the message: `multipart/related` when images are shown in an HTML body,
+    /// `multipart/mixed` with the images as attachments otherwise
+    fn mime_message(
+        &self,
+        subject: &str,
+        report: &str,
+        html: bool,
+        images: &[(String, Vec<u8>)],
+    ) -> Vec<u8> {
+        let boundary = format!("cw-metrics-{:016x}", rand::random::<u64>());
+        let inline = html && self.images == EmailImages::Inline;
+        let (subtype, disposition) = if inline {
+            ("related", "inline")
+        } else {
+            ("mixed", "attachment")
+        };
+        let body = if inline {
+            // the report links images by file name, point those links at the attached parts
+            images.iter().fold(String::from(report), |body, (name, _)| {
+                let name = escape_html(name);
+                body.replace(
+                    &format!("src=\"{}\"", name),
+                    &format!("src=\"cid:{}\"", name),
+                )
+                .replace(
+                    &format!("href=\"{}\"", name),
+                    &format!("href=\"cid:{}\"", name),
+                )
+            })
+        } else {
+            String::from(report)
+        };
+
+        let mut message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: =?UTF-8?B?{}?=\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/{}; boundary=\"{}\"\r\n\r\n",
+            self.from,
+            self.to.join(", "),
+            base64::encode(subject),
+            subtype,
+            boundary
+        );
+        message.push_str(&format!(
+            "--{}\r\nContent-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}",
+            boundary,
+            if html { "text/html" } else { "text/plain" },
+            mime_base64(body.as_bytes())
+        ));
+        for (name, contents) in images {
+            let content_type = if name.ends_with(".svg") {
+                "image/svg+xml"
+            } else {
+                "image/png"
+            };
+            message.push_str(&format!(
+                "--{}\r\nContent-Type: {}; name=\"{}\"\r\nContent-Transfer-Encoding: base64\r\n\
+                 Content-ID: <{}>\r\nContent-Disposition: {}; filename=\"{}\"\r\n\r\n{}",
+                boundary,
+                content_type,
+                name,
+                name,
+                disposition,
+                name,
+                mime_base64(contents)
+            ));
+        }
+        message.push_str(&format!("--{}--\r\n", boundary));
+        message.into_bytes()
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().replace('"', "_"))
+        .unwrap_or_default()
+}
+
+fn mime_base64(contents: &[u8]) -> String {
+    let encoded = base64::encode(contents);
+    let mut wrapped = String::with_capacity(encoded.len() + encoded.len() / MIME_LINE_LEN * 2 + 2);
+    for line in encoded.as_bytes().chunks(MIME_LINE_LEN) {
+        wrapped.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+        wrapped.push_str("\r\n");
+    }
+    wrapped
+}
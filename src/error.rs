@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("unable to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unable to write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("unable to parse accounts config {path}: {source}")]
+    Config {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("unable to parse schedule {path}: {source}")]
+    Schedule {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("invalid schedule: {0}")]
+    InvalidSchedule(String),
+    #[error("{0}")]
+    Region(String),
+    #[error("account {namespace} has no {field}, set it on the account or in [defaults]")]
+    MissingAccountField {
+        namespace: String,
+        field: &'static str,
+    },
+    #[error("account {role_arn} in {region} is configured more than once")]
+    DuplicateAccount { role_arn: String, region: String },
+    #[error("no accounts selected, check the account filters")]
+    NoAccounts,
+    #[error("no accounts config given, and neither $CW_DL_CONFIG nor ~/.config/cloudwatch-metrics-dl/accounts.toml is set up")]
+    NoConfig,
+    #[error("no template-path given and the accounts config has no [defaults] template_path")]
+    NoTemplate,
+    #[error("accounts config has {0} problem(s), see above")]
+    InvalidConfig(usize),
+    #[error("accounts config {0} is included more than once")]
+    DuplicateInclude(PathBuf),
+    #[error("unable to parse {path} after templating: {source}")]
+    Template {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("{path} has {queries} queries, but specs with an expression can have at most {limit} since expressions only see the queries of their own GetMetricData call")]
+    TooManyExpressionQueries {
+        path: PathBuf,
+        queries: usize,
+        limit: usize,
+    },
+    #[error("unable to parse alarm baseline {path}: {source}")]
+    Baseline {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("unable to parse alarm snapshot {path}: {source}")]
+    Snapshot {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("unable to parse datapoints {path}: {reason}")]
+    Datapoints { path: PathBuf, reason: String },
+    #[error("{0} has fewer than two alarm snapshots, save them with `alarms --snapshot-dir`")]
+    NotEnoughSnapshots(PathBuf),
+    #[error(
+        "timed out waiting for alarms to be {state}, {pending} alarm(s) still in another state"
+    )]
+    WaitTimeout { state: String, pending: usize },
+    #[error("no account matching {0} has alarms to compare against")]
+    NoBaseline(String),
+    #[error("unable to render template {name}: {}", render_error(.source))]
+    Render { name: String, source: tera::Error },
+    #[error("unable to parse dashboard {name}: {source}")]
+    Dashboard {
+        name: String,
+        source: serde_json::Error,
+    },
+    #[error("--dry-run is not supported by {0}, only by images, data, alarms and alarms apply")]
+    DryRunUnsupported(String),
+    #[error("{0} already exists, pass --force to overwrite it")]
+    AlreadyExists(PathBuf),
+    #[error("end time {end} is not after start time {start}")]
+    TimeRange { start: String, end: String },
+    #[error("CloudWatch keeps no data older than 455 days, start time {0} is too far back")]
+    Retention(String),
+    #[error("{0} already exists, pass --overwrite to replace it or --skip-existing to keep it")]
+    ImageExists(PathBuf),
+    #[error("invalid {name} {value:?}")]
+    InvalidArgument { name: &'static str, value: String },
+    #[error("unable to assume role {role_arn}: {source}")]
+    AssumeRole {
+        role_arn: String,
+        source: aws_sdk_sts::Error,
+    },
+    #[error("unable to check the credentials in use: {0}")]
+    Credentials(aws_sdk_sts::Error),
+    #[error("{step} failed: {source}")]
+    Check {
+        step: &'static str,
+        source: aws_sdk_cloudwatch::Error,
+    },
+    #[error("AssumeRole for {0} returned no credentials")]
+    MissingCredentials(String),
+    #[error("{0} returned an empty response")]
+    EmptyResponse(&'static str),
+    #[error(transparent)]
+    CloudWatch(#[from] aws_sdk_cloudwatch::Error),
+    #[error(transparent)]
+    CloudWatchLogs(#[from] aws_sdk_cloudwatchlogs::Error),
+    #[error(transparent)]
+    Organizations(#[from] aws_sdk_organizations::Error),
+    #[error("logs insights query {query_id} ended with status {status}")]
+    Query { query_id: String, status: String },
+    #[error("unable to upload {key} to S3: {source}")]
+    Upload {
+        key: String,
+        source: aws_sdk_s3::Error,
+    },
+    #[error("unable to export to OTLP endpoint {endpoint}: {reason}")]
+    Otlp { endpoint: String, reason: String },
+    #[error("unable to publish to {topic_arn}: {source}")]
+    Publish {
+        topic_arn: String,
+        source: aws_sdk_sns::Error,
+    },
+    #[error("unable to email the report: {0}")]
+    Email(aws_sdk_sesv2::Error),
+    #[error("unable to update metric store: {0}")]
+    Store(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("unable to write workbook: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    #[error("unable to write parquet: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("unable to notify Slack: {0}")]
+    Slack(String),
+    #[error("unable to serve: {0}")]
+    Serve(hyper::Error),
+    #[error("terminal error: {0}")]
+    Terminal(std::io::Error),
+    #[error(
+        "assuming the role needs a code of MFA device {0}, pass --mfa-code or run in a terminal"
+    )]
+    MfaRequired(String),
+    #[error("`{command}` failed: {reason}")]
+    SsoLogin { command: String, reason: String },
+    #[error("interrupted before the account finished")]
+    Interrupted,
+    #[error("the run deadline passed before the account finished")]
+    DeadlineExceeded,
+    #[error("skipped because {0} failed first and --fail-fast is set")]
+    Skipped(String),
+    #[error("account task panicked: {0}")]
+    Panic(String),
+}
+
+/// Tera keeps the reason a render failed, like an undefined variable, in its source chain
+fn render_error(error: &tera::Error) -> String {
+    std::iter::successors(Some(error as &dyn std::error::Error), |e| e.source())
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::dashboards::get_dashboard_body;
+use crate::error::Error;
+use crate::runner::RunOptions;
+use crate::template::{render_template, template_context};
+
+/// Grid size of a panel converted from a widget template, which has no position of its own
+const TEMPLATE_PANEL_WIDTH: i64 = 24;
+const TEMPLATE_PANEL_HEIGHT: i64 = 8;
+
+/// Builds a Grafana dashboard using the CloudWatch datasource from widget templates or an
+/// existing CloudWatch dashboard. The accounts' namespaces, regions and account IDs become
+/// `$namespace`, `$region` and `$account` dashboard variables.
+#[derive(Debug, Clone)]
+pub struct GrafanaConverter {
+    title: String,
+    from: String,
+    period: String,
+    templates: Vec<PathBuf>,
+    dashboard_name: Option<String>,
+    vars: BTreeMap<String, String>,
+    options: RunOptions,
+}
+
+impl GrafanaConverter {
+    pub fn new(title: impl Into<String>) -> Self {
+        GrafanaConverter {
+            title: title.into(),
+            from: String::from("now-7d"),
+            period: String::from("300"),
+            templates: vec![],
+            dashboard_name: None,
+            vars: Default::default(),
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Start of the dashboard's default time range in Grafana's syntax, e.g. `now-7d`
+    pub fn from(mut self, from: impl Into<String>) -> Self {
+        self.from = from.into();
+        self
+    }
+
+    /// Seconds substituted for `{{PERIOD}}` in templates
+    pub fn period(mut self, period: impl Into<String>) -> Self {
+        self.period = period.into();
+        self
+    }
+
+    /// Widget templates to convert, each a JSON file or a directory of them, in order
+    pub fn templates(mut self, templates: Vec<PathBuf>) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// CloudWatch dashboard to convert instead of templates, fetched from the first account
+    pub fn dashboard_name(mut self, dashboard_name: Option<String>) -> Self {
+        self.dashboard_name = dashboard_name;
+        self
+    }
+
+    pub fn vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn convert(&self, accounts: &[AccountConfig]) -> Result<Value, Error> {
+        let first = accounts.first().ok_or(Error::NoAccounts)?;
+        let mut panels = match &self.dashboard_name {
+            Some(name) => self.dashboard_panels(first, name).await?,
+            None => self.template_panels(first)?,
+        };
+        let account_ids: BTreeSet<String> = accounts
+            .iter()
+            .filter_map(|acc| acc.account_id.clone())
+            .collect();
+        let mut variables = vec![
+            json!({
+                "name": "datasource",
+                "label": "Data source",
+                "type": "datasource",
+                "query": "cloudwatch",
+            }),
+            custom_variable("namespace", accounts.iter().map(|acc| &acc.namespace)),
+            custom_variable("region", accounts.iter().map(|acc| &acc.region)),
+        ];
+        if account_ids.is_empty() {
+            for target in panels
+                .iter_mut()
+                .filter_map(|panel| panel["targets"].as_array_mut())
+                .flatten()
+            {
+                if let Some(target) = target.as_object_mut() {
+                    target.remove("accountId");
+                }
+            }
+        } else {
+            variables.push(custom_variable("account", account_ids.iter()));
+        }
+        Ok(json!({
+            "title": self.title,
+            "tags": ["cloudwatch"],
+            "timezone": "browser",
+            "schemaVersion": 36,
+            "time": { "from": self.from, "to": "now" },
+            "templating": { "list": variables },
+            "panels": panels,
+        }))
+    }
+
+    /// Renders the templates with Grafana variables in place of the account's namespace and
+    /// region, stacking one full-width panel per template
+    fn template_panels(&self, first: &AccountConfig) -> Result<Vec<Value>, Error> {
+        let mut acc = first.clone();
+        acc.namespace = String::from("$namespace");
+        acc.region = String::from("$region");
+        let context = template_context(&acc, "", "", &self.period, &self.vars);
+        let mut panels = vec![];
+        for path in self.template_files()? {
+            let rendered = render_template(&path, &context)?;
+            let properties: Value =
+                serde_json::from_str(&rendered).map_err(|source| Error::Template {
+                    path: path.clone(),
+                    source,
+                })?;
+            let y = panels.len() as i64 * TEMPLATE_PANEL_HEIGHT;
+            let grid_pos =
+                json!({ "x": 0, "y": y, "w": TEMPLATE_PANEL_WIDTH, "h": TEMPLATE_PANEL_HEIGHT });
+            panels.push(widget_to_panel(panels.len() + 1, &properties, grid_pos));
+        }
+        Ok(panels)
+    }
+
+    fn template_files(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut files = vec![];
+        for path in &self.templates {
+            if !path.is_dir() {
+                files.push(path.clone());
+                continue;
+            }
+            let read_error = |source| Error::Read {
+                path: path.clone(),
+                source,
+            };
+            let mut dir_files = vec![];
+            for entry in std::fs::read_dir(path).map_err(read_error)? {
+                let path = entry.map_err(read_error)?.path();
+                if path.extension().map_or(false, |ext| ext == "json") && path.is_file() {
+                    dir_files.push(path);
+                }
+            }
+            dir_files.sort();
+            files.extend(dir_files);
+        }
+        Ok(files)
+    }
+
+    /// Converts each metric widget of the dashboard at its grid position. Regions and dimension
+    /// values matching the account's are replaced with `$region` and `$namespace`.
+    async fn dashboard_panels(&self, acc: &AccountConfig, name: &str) -> Result<Vec<Value>, Error> {
+        debug!(account = %acc.label(), name, "converting dashboard");
+        let client = self.options.cloudwatch_client(acc).await?;
+        let body = get_dashboard_body(&client, &self.options.retry, name).await?;
+        let dashboard: Value = serde_json::from_str(&body).map_err(|source| Error::Dashboard {
+            name: String::from(name),
+            source,
+        })?;
+        let widgets = dashboard["widgets"].as_array().cloned().unwrap_or_default();
+        let mut panels = vec![];
+        for widget in widgets.iter().filter(|widget| widget["type"] == "metric") {
+            let properties = parameterize(&widget["properties"], acc);
+            let grid_pos = json!({
+                "x": widget["x"].as_i64().unwrap_or_default(),
+                "y": widget["y"].as_i64().unwrap_or_default(),
+                "w": widget["width"].as_i64().unwrap_or(6),
+                "h": widget["height"].as_i64().unwrap_or(6),
+            });
+            panels.push(widget_to_panel(panels.len() + 1, &properties, grid_pos));
+        }
+        Ok(panels)
+    }
+}
+
+/// Replaces every string equal to the account's region or namespace with its variable
+fn parameterize(value: &Value, acc: &AccountConfig) -> Value {
+    match value {
+        Value::String(s) if *s == acc.region => Value::from("$region"),
+        Value::String(s) if *s == acc.namespace => Value::from("$namespace"),
+        Value::Array(values) => values.iter().map(|v| parameterize(v, acc)).collect(),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, v)| (key.clone(), parameterize(v, acc)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// A dashboard variable offering each distinct value, the first selected
+fn custom_variable<'a>(name: &str, values: impl Iterator<Item = &'a String>) -> Value {
+    let values: Vec<&String> = values.collect::<BTreeSet<_>>().into_iter().collect();
+    let current = values.first().map(|v| v.as_str()).unwrap_or_default();
+    json!({
+        "name": name,
+        "type": "custom",
+        "query": values.iter().map(|v| v.as_str()).collect::<Vec<_>>().join(","),
+        "current": { "text": current, "value": current },
+        "options": values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| json!({ "text": v, "value": v, "selected": i == 0 }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn datasource() -> Value {
+    json!({ "type": "cloudwatch", "uid": "${datasource}" })
+}
+
+/// Converts the properties of a CloudWatch metric widget to a Grafana panel
+fn widget_to_panel(id: usize, properties: &Value, grid_pos: Value) -> Value {
+    let panel_type = match properties["view"].as_str() {
+        Some("singleValue") => "stat",
+        Some("gauge") => "gauge",
+        Some("bar") => "barchart",
+        Some("pie") => "piechart",
+        Some("table") => "table",
+        _ => "timeseries",
+    };
+    let mut defaults = json!({ "custom": {} });
+    let left_axis = &properties["yAxis"]["left"];
+    for bound in ["min", "max"] {
+        if left_axis[bound].is_number() {
+            defaults[bound] = left_axis[bound].clone();
+        }
+    }
+    if let Some(label) = left_axis["label"].as_str() {
+        defaults["custom"]["axisLabel"] = Value::from(label);
+    }
+    if properties["stacked"] == true {
+        defaults["custom"]["stacking"] = json!({ "mode": "normal" });
+    }
+    json!({
+        "id": id,
+        "type": panel_type,
+        "title": properties["title"].as_str().unwrap_or_default(),
+        "gridPos": grid_pos,
+        "datasource": datasource(),
+        "targets": metric_targets(properties),
+        "fieldConfig": { "defaults": defaults, "overrides": [] },
+    })
+}
+
+/// Converts the widget's `metrics` rows to CloudWatch datasource queries, expanding the `.` and
+/// `...` shorthands that repeat the previous row
+fn metric_targets(properties: &Value) -> Vec<Value> {
+    let rows = properties["metrics"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let mut previous: Vec<Value> = vec![];
+    let mut targets = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        let Some(row) = row.as_array() else {
+            continue;
+        };
+        let (mut parts, options) = match row.split_last() {
+            Some((Value::Object(options), parts)) => {
+                (parts.to_vec(), Value::Object(options.clone()))
+            }
+            _ => (row.clone(), json!({})),
+        };
+        if parts.first().map_or(false, |part| part == "...") {
+            let rest = &parts[1..];
+            let kept = previous.len().saturating_sub(rest.len());
+            parts = previous[..kept].iter().chain(rest).cloned().collect();
+        } else {
+            for (j, part) in parts.iter_mut().enumerate() {
+                if part == "." {
+                    *part = previous.get(j).cloned().unwrap_or_default();
+                }
+            }
+        }
+        previous = parts.clone();
+
+        let option = |name: &str| -> Value {
+            if options[name].is_null() {
+                properties[name].clone()
+            } else {
+                options[name].clone()
+            }
+        };
+        let period = match option("period") {
+            Value::Number(period) => period.to_string(),
+            _ => String::new(),
+        };
+        let mut target = json!({
+            "refId": ref_id(i),
+            "id": options["id"].as_str().map(String::from).unwrap_or_else(|| format!("m{}", i + 1)),
+            "datasource": datasource(),
+            "queryMode": "Metrics",
+            "metricQueryType": 0,
+            "region": option("region").as_str().unwrap_or("$region"),
+            "accountId": "$account",
+            "statistic": option("stat").as_str().unwrap_or("Average"),
+            "period": period,
+            "label": options["label"].as_str().unwrap_or_default(),
+            "hide": options["visible"] == false,
+            "matchExact": true,
+        });
+        if let Some(expression) = options["expression"].as_str() {
+            target["expression"] = Value::from(expression);
+            target["metricEditorMode"] = Value::from(1);
+        } else {
+            let text = |part: Option<&Value>| {
+                part.and_then(Value::as_str)
+                    .map(String::from)
+                    .unwrap_or_default()
+            };
+            let dimensions: serde_json::Map<String, Value> = parts
+                .get(2..)
+                .unwrap_or_default()
+                .chunks(2)
+                .map(|pair| (text(pair.first()), Value::from(text(pair.get(1)))))
+                .collect();
+            target["namespace"] = Value::from(text(parts.first()));
+            target["metricName"] = Value::from(text(parts.get(1)));
+            target["dimensions"] = Value::Object(dimensions);
+            target["metricEditorMode"] = Value::from(0);
+        }
+        targets.push(target);
+    }
+    targets
+}
+
+/// Grafana query IDs: `A` to `Z`, then `AA`, `AB` and so on
+fn ref_id(index: usize) -> String {
+    let mut id = String::new();
+    let mut n = index + 1;
+    while n > 0 {
+        n -= 1;
+        id.insert(0, char::from(b'A' + (n % 26) as u8));
+        n /= 26;
+    }
+    id
+}
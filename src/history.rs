@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{AlarmHistoryItem, HistoryItemType, ScanBy};
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use regex::Regex;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::duration::time_range;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AlarmHistoryRecord {
+    pub program_name: String,
+    pub region: String,
+    pub alarm_name: String,
+    pub alarm_type: String,
+    pub timestamp: String,
+    pub history_item_type: String,
+    pub history_summary: String,
+    pub history_data: String,
+}
+
+/// Exports DescribeAlarmHistory items (state transitions, actions, config updates) for each account
+#[derive(Debug, Clone)]
+pub struct AlarmHistoryExporter {
+    start: String,
+    end: String,
+    alarm_name: Option<String>,
+    alarm_regex: Option<Regex>,
+    history_item_type: Option<HistoryItemType>,
+    options: RunOptions,
+}
+
+impl Default for AlarmHistoryExporter {
+    fn default() -> Self {
+        AlarmHistoryExporter {
+            start: String::from("168H"),
+            end: String::from("0H"),
+            alarm_name: None,
+            alarm_regex: None,
+            history_item_type: None,
+            options: RunOptions::default(),
+        }
+    }
+}
+
+impl AlarmHistoryExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start of the history, e.g. `168H` before now or `2024-01-01T00:00:00Z`
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = start.into();
+        self
+    }
+
+    /// End of the history, e.g. `0H` before now or `2024-01-01T00:00:00Z`
+    pub fn end(mut self, end: impl Into<String>) -> Self {
+        self.end = end.into();
+        self
+    }
+
+    /// Only fetch history for the alarm with exactly this name
+    pub fn alarm_name(mut self, alarm_name: Option<String>) -> Self {
+        self.alarm_name = alarm_name;
+        self
+    }
+
+    /// Only keep history items whose alarm name matches this regex, filtered locally
+    pub fn alarm_regex(mut self, regex: Option<Regex>) -> Self {
+        self.alarm_regex = regex;
+        self
+    }
+
+    /// Only fetch one kind of history item, e.g. `StateUpdate` or `Action`
+    pub fn history_item_type(mut self, history_item_type: Option<HistoryItemType>) -> Self {
+        self.history_item_type = history_item_type;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(
+        &self,
+        accounts: Vec<AccountConfig>,
+    ) -> AccountResults<Vec<AlarmHistoryRecord>> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.export(&acc).await }
+            })
+            .await
+    }
+
+    pub async fn export(&self, acc: &AccountConfig) -> Result<Vec<AlarmHistoryRecord>, Error> {
+        let client = self.options.cloudwatch_client(acc).await?;
+        let (start_time, end_time) = time_range(&self.start, &self.end)?;
+        let (start_time, end_time) = (start_time.date_time(), end_time.date_time());
+        let items = describe_alarm_history(
+            &client,
+            &self.options.retry,
+            self.alarm_name.clone(),
+            self.history_item_type.clone(),
+            start_time,
+            end_time,
+        )
+        .await?;
+        Ok(items
+            .iter()
+            .filter(|item| match &self.alarm_regex {
+                Some(regex) => regex.is_match(item.alarm_name().unwrap_or_default()),
+                None => true,
+            })
+            .map(|item| to_history_record(acc, item))
+            .collect())
+    }
+}
+
+fn to_history_record(acc: &AccountConfig, item: &AlarmHistoryItem) -> AlarmHistoryRecord {
+    AlarmHistoryRecord {
+        program_name: acc.namespace.clone(),
+        region: acc.region.clone(),
+        alarm_name: String::from(item.alarm_name().unwrap_or_default()),
+        alarm_type: String::from(item.alarm_type().map(|t| t.as_str()).unwrap_or_default()),
+        timestamp: item
+            .timestamp()
+            .and_then(|t| t.fmt(Format::DateTime).ok())
+            .unwrap_or_default(),
+        history_item_type: String::from(
+            item.history_item_type()
+                .map(|t| t.as_str())
+                .unwrap_or_default(),
+        ),
+        history_summary: String::from(item.history_summary().unwrap_or_default()),
+        history_data: String::from(item.history_data().unwrap_or_default()),
+    }
+}
+
+/// Writes alarm history as CSV with one row per history item
+pub fn history_to_csv(records: &[AlarmHistoryRecord]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Calls AWS CloudWatch DescribeAlarmHistory API, following `next_token` until every item is returned
+/// API Reference: [DescribeAlarmHistory](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_DescribeAlarmHistory.html)
+async fn describe_alarm_history(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    alarm_name: Option<String>,
+    history_item_type: Option<HistoryItemType>,
+    start_date: DateTime,
+    end_date: DateTime,
+) -> Result<Vec<AlarmHistoryItem>, aws_sdk_cloudwatch::Error> {
+    debug!("describing alarm history");
+    let mut items: Vec<AlarmHistoryItem> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client
+            .describe_alarm_history()
+            .set_alarm_name(alarm_name.clone())
+            .set_history_item_type(history_item_type.clone())
+            .start_date(start_date)
+            .end_date(end_date)
+            .scan_by(ScanBy::TimestampDescending)
+            .set_next_token(next_token);
+        let resp = retry
+            .call("DescribeAlarmHistory", || request.clone().send())
+            .await?;
+        items.extend_from_slice(resp.alarm_history_items().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(items)
+}
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+
+use crate::alarms::MetricAlarmDetails;
+
+/// Infrastructure-as-code formats alarms can be exported as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IacFormat {
+    /// `aws_cloudwatch_metric_alarm` resources in HCL
+    Terraform,
+    /// A template of `AWS::CloudWatch::Alarm` resources in YAML
+    CloudFormation,
+}
+
+impl IacFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            IacFormat::Terraform => "tf",
+            IacFormat::CloudFormation => "yaml",
+        }
+    }
+
+    /// Renders the alarms in this format. Alarms on metric math or with a statistic or
+    /// comparison operator the export doesn't know are left out with a comment saying so.
+    /// Percentile alarms keep their `extended_statistic`.
+    pub fn render(&self, alarms: &[MetricAlarmDetails]) -> String {
+        match self {
+            IacFormat::Terraform => alarms_to_terraform(alarms),
+            IacFormat::CloudFormation => alarms_to_cloudformation(alarms),
+        }
+    }
+}
+
+/// Why an alarm can't be exported, if it can't
+fn unsupported(alarm: &MetricAlarmDetails) -> Option<&'static str> {
+    if alarm.metric_name.is_empty() {
+        Some("metric math alarms are not exported")
+    } else if alarm.statistic == "Unknown"
+        || (alarm.statistic.is_empty() && alarm.extended_statistic.is_empty())
+    {
+        Some("unknown statistics are not exported")
+    } else if alarm.comparison_operator == "Unknown" {
+        Some("anomaly detection comparison operators are not exported")
+    } else {
+        None
+    }
+}
+
+/// Turns `alarm_name` into an identifier made of the allowed characters, adding a number when
+/// it's already taken
+fn identifier(
+    alarm_name: &str,
+    allowed: impl Fn(char) -> bool,
+    separator: &str,
+    taken: &mut HashSet<String>,
+) -> String {
+    let words: Vec<String> = alarm_name
+        .split(|c: char| !allowed(c))
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect();
+    let base = match words.join(separator) {
+        name if name.starts_with(|c: char| c.is_ascii_alphabetic()) => name,
+        name => format!("alarm{}{}", separator, name),
+    };
+    let mut name = base.clone();
+    let mut n = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{}{}{}", base, separator, n);
+        n += 1;
+    }
+    name
+}
+
+/// Quotes a string for HCL, escaping `${` and `%{` so they aren't read as template sequences
+fn hcl_string(value: &str) -> String {
+    serde_json::to_string(value)
+        .expect("strings serialize to JSON")
+        .replace("${", "$${")
+        .replace("%{", "%%{")
+}
+
+/// Quotes a string for YAML, a JSON string being a valid double-quoted YAML scalar
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).expect("strings serialize to JSON")
+}
+
+/// Renders alarms as `aws_cloudwatch_metric_alarm` Terraform resources
+pub fn alarms_to_terraform(alarms: &[MetricAlarmDetails]) -> String {
+    let mut taken = HashSet::new();
+    let mut hcl = String::new();
+    for alarm in alarms {
+        if let Some(reason) = unsupported(alarm) {
+            hcl.push_str(&format!("# skipped {}: {}\n\n", alarm.alarm_name, reason));
+            continue;
+        }
+        let name = identifier(
+            &alarm.alarm_name.to_lowercase(),
+            |c| c.is_ascii_alphanumeric(),
+            "_",
+            &mut taken,
+        );
+        hcl.push_str(&format!(
+            "resource \"aws_cloudwatch_metric_alarm\" \"{}\" {{\n",
+            name
+        ));
+        let mut attribute = |key: &str, value: String| {
+            hcl.push_str(&format!("  {:<25} = {}\n", key, value));
+        };
+        attribute("alarm_name", hcl_string(&alarm.alarm_name));
+        if !alarm.alarm_description.is_empty() {
+            attribute("alarm_description", hcl_string(&alarm.alarm_description));
+        }
+        attribute("namespace", hcl_string(&alarm.namespace));
+        attribute("metric_name", hcl_string(&alarm.metric_name));
+        if alarm.statistic.is_empty() {
+            attribute("extended_statistic", hcl_string(&alarm.extended_statistic));
+        } else {
+            attribute("statistic", hcl_string(&alarm.statistic));
+        }
+        attribute("period", alarm.period.to_string());
+        attribute("evaluation_periods", alarm.evaluation_periods.to_string());
+        if let Some(datapoints) = alarm.datapoints_to_alarm {
+            attribute("datapoints_to_alarm", datapoints.to_string());
+        }
+        attribute("threshold", alarm.threshold.to_string());
+        attribute(
+            "comparison_operator",
+            hcl_string(&alarm.comparison_operator),
+        );
+        if !alarm.treat_missing_data.is_empty() {
+            attribute("treat_missing_data", hcl_string(&alarm.treat_missing_data));
+        }
+        attribute("actions_enabled", alarm.actions_enabled.to_string());
+        if !alarm.alarm_actions.is_empty() {
+            let actions: Vec<String> = alarm.alarm_actions.iter().map(|a| hcl_string(a)).collect();
+            attribute("alarm_actions", format!("[{}]", actions.join(", ")));
+        }
+        for (block, pairs) in [
+            (
+                "dimensions",
+                alarm
+                    .dimensions
+                    .iter()
+                    .map(|d| (&d.name, &d.value))
+                    .collect::<Vec<_>>(),
+            ),
+            ("tags", alarm.tags.iter().collect()),
+        ] {
+            if pairs.is_empty() {
+                continue;
+            }
+            hcl.push_str(&format!("\n  {} = {{\n", block));
+            for (key, value) in pairs {
+                hcl.push_str(&format!(
+                    "    {} = {}\n",
+                    hcl_string(key),
+                    hcl_string(value)
+                ));
+            }
+            hcl.push_str("  }\n");
+        }
+        hcl.push_str("}\n\n");
+    }
+    hcl
+}
+
+/// Renders alarms as a CloudFormation template of `AWS::CloudWatch::Alarm` resources
+pub fn alarms_to_cloudformation(alarms: &[MetricAlarmDetails]) -> String {
+    let mut taken = HashSet::new();
+    let mut yaml = String::from("AWSTemplateFormatVersion: \"2010-09-09\"\nResources:\n");
+    let mut skipped = vec![];
+    for alarm in alarms {
+        if let Some(reason) = unsupported(alarm) {
+            skipped.push(format!("# skipped {}: {}\n", alarm.alarm_name, reason));
+            continue;
+        }
+        // logical IDs may only contain letters and digits
+        let logical_id = identifier(
+            &alarm.alarm_name,
+            |c| c.is_ascii_alphanumeric(),
+            "",
+            &mut taken,
+        );
+        yaml.push_str(&format!(
+            "  {}:\n    Type: AWS::CloudWatch::Alarm\n    Properties:\n",
+            logical_id
+        ));
+        let mut property = |key: &str, value: String| {
+            yaml.push_str(&format!("      {}: {}\n", key, value));
+        };
+        property("AlarmName", yaml_string(&alarm.alarm_name));
+        if !alarm.alarm_description.is_empty() {
+            property("AlarmDescription", yaml_string(&alarm.alarm_description));
+        }
+        property("Namespace", yaml_string(&alarm.namespace));
+        property("MetricName", yaml_string(&alarm.metric_name));
+        if alarm.statistic.is_empty() {
+            property("ExtendedStatistic", yaml_string(&alarm.extended_statistic));
+        } else {
+            property("Statistic", alarm.statistic.clone());
+        }
+        property("Period", alarm.period.to_string());
+        property("EvaluationPeriods", alarm.evaluation_periods.to_string());
+        if let Some(datapoints) = alarm.datapoints_to_alarm {
+            property("DatapointsToAlarm", datapoints.to_string());
+        }
+        property("Threshold", alarm.threshold.to_string());
+        property("ComparisonOperator", alarm.comparison_operator.clone());
+        if !alarm.treat_missing_data.is_empty() {
+            property("TreatMissingData", yaml_string(&alarm.treat_missing_data));
+        }
+        property("ActionsEnabled", alarm.actions_enabled.to_string());
+        if !alarm.alarm_actions.is_empty() {
+            yaml.push_str("      AlarmActions:\n");
+            for action in &alarm.alarm_actions {
+                yaml.push_str(&format!("        - {}\n", yaml_string(action)));
+            }
+        }
+        if !alarm.dimensions.is_empty() {
+            yaml.push_str("      Dimensions:\n");
+            for dimension in &alarm.dimensions {
+                yaml.push_str(&format!(
+                    "        - Name: {}\n          Value: {}\n",
+                    yaml_string(&dimension.name),
+                    yaml_string(&dimension.value)
+                ));
+            }
+        }
+        if !alarm.tags.is_empty() {
+            yaml.push_str("      Tags:\n");
+            for (key, value) in &alarm.tags {
+                yaml.push_str(&format!(
+                    "        - Key: {}\n          Value: {}\n",
+                    yaml_string(key),
+                    yaml_string(value)
+                ));
+            }
+        }
+    }
+    if taken.is_empty() {
+        // a template needs at least one resource, so an empty account only gets the comments
+        yaml.clear();
+    }
+    yaml.extend(skipped);
+    yaml
+}
@@ -0,0 +1,617 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use regex::Regex;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::alarms::{describe_alarms, AlarmExporter};
+use crate::annotations::annotate_alarm_thresholds;
+use crate::config::AccountConfig;
+use crate::duration::{fit_period, time_range, TimeBound};
+use crate::email::{EmailImages, ReportMailer};
+use crate::error::Error;
+use crate::output::write_output;
+use crate::plan::PlannedCall;
+use crate::report::ImageReport;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::summary::RunSummary;
+use crate::template::{check_template, render_template, template_context};
+
+/// Image names unique to each run
+pub const DEFAULT_NAME_TEMPLATE: &str = "{namespace}-{title}-{region}-{start}-{timestamp}";
+
+/// Image names that stay the same between runs, so each run replaces the previous images
+pub const STABLE_NAME_TEMPLATE: &str = "{namespace}-{title}-{region}-{start}";
+
+const NAME_PLACEHOLDERS: [&str; 8] = [
+    "namespace",
+    "title",
+    "region",
+    "account_id",
+    "start",
+    "end",
+    "date",
+    "timestamp",
+];
+
+/// What to do when an image is about to be saved over one that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingImages {
+    /// Fail the account, so reruns don't silently replace images
+    Fail,
+    Overwrite,
+    /// Keep the existing image and don't download it again
+    Skip,
+}
+
+/// Image formats GetMetricWidgetImage can return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Scales without blurring, e.g. in HTML reports
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+
+    fn output_format(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// Report written after each round of images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// An `index.html` gallery of every image grouped by namespace and region
+    Html,
+    /// A `report.md` with image links and an alarms summary
+    Markdown,
+}
+
+/// Sends the report through SES once it's written
+#[derive(Debug, Clone)]
+pub struct ReportEmail {
+    pub from: String,
+    pub to: Vec<String>,
+    pub images: EmailImages,
+}
+
+/// What is done with each round of images besides saving them
+#[derive(Debug, Clone, Default)]
+pub struct RoundOutputs {
+    pub report: Option<ReportFormat>,
+    /// Only sent when there is a report
+    pub email: Option<ReportEmail>,
+}
+
+/// What one round of images saved and sent, for the caller to report
+#[derive(Debug, Clone, Default)]
+pub struct RoundOutcome {
+    /// Every image saved, in account order
+    pub images: Vec<PathBuf>,
+    pub report: Option<PathBuf>,
+    /// Recipients of the report email, empty when none was sent
+    pub emailed: Vec<String>,
+}
+
+/// Renders a widget template for each account and saves the GetMetricWidgetImage image
+#[derive(Debug, Clone)]
+pub struct MetricsDownloader {
+    template_path: PathBuf,
+    title: String,
+    start: String,
+    end: String,
+    period: String,
+    output_path: Option<PathBuf>,
+    name_template: String,
+    existing: ExistingImages,
+    timezone: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    format: ImageFormat,
+    theme: Option<String>,
+    alarm_thresholds: bool,
+    vars: BTreeMap<String, String>,
+    options: RunOptions,
+}
+
+impl MetricsDownloader {
+    pub fn new(template_path: impl Into<PathBuf>) -> Self {
+        MetricsDownloader {
+            template_path: template_path.into(),
+            title: String::from("metric"),
+            start: String::from("4320H"),
+            end: String::from("0H"),
+            period: String::from("3600"),
+            output_path: None,
+            name_template: String::from(DEFAULT_NAME_TEMPLATE),
+            existing: ExistingImages::Fail,
+            timezone: None,
+            width: None,
+            height: None,
+            format: ImageFormat::Png,
+            theme: None,
+            alarm_thresholds: false,
+            vars: BTreeMap::new(),
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Title used to identify the downloaded images, unless `template_path` is a directory
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Start of the graph, e.g. `4320H` before now or `2024-01-01T00:00:00Z`
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = start.into();
+        self
+    }
+
+    /// End of the graph, e.g. `0H` before now or `2024-01-01T00:00:00Z`
+    pub fn end(mut self, end: impl Into<String>) -> Self {
+        self.end = end.into();
+        self
+    }
+
+    /// Period in seconds substituted for `{{PERIOD}}`
+    pub fn period(mut self, period: impl Into<String>) -> Self {
+        self.period = period.into();
+        self
+    }
+
+    /// Directory to save images in instead of the current directory
+    pub fn output_path(mut self, output_path: Option<PathBuf>) -> Self {
+        self.output_path = output_path;
+        self
+    }
+
+    /// Name of each saved image without its extension, with `{namespace}`, `{title}`,
+    /// `{region}`, `{account_id}`, `{start}`, `{end}`, `{date}` (UTC, `YYYY-MM-DD`) and
+    /// `{timestamp}` (epoch seconds) placeholders
+    pub fn name_template(mut self, name_template: impl Into<String>) -> Self {
+        self.name_template = name_template.into();
+        self
+    }
+
+    /// What to do when an image with the same name was already saved
+    pub fn existing(mut self, existing: ExistingImages) -> Self {
+        self.existing = existing;
+        self
+    }
+
+    /// `+HHMM` offset the graphs are drawn in instead of UTC, see [`parse_timezone`]
+    ///
+    /// [`parse_timezone`]: crate::duration::parse_timezone
+    pub fn timezone(mut self, timezone: Option<String>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Image width in pixels, replacing the template's `width`
+    pub fn width(mut self, width: Option<u32>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Image height in pixels, replacing the template's `height`
+    pub fn height(mut self, height: Option<u32>) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// `dark` or `light`, replacing the template's `theme`
+    pub fn theme(mut self, theme: Option<String>) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// Draw a line at the threshold of each of the account's alarms on a graphed metric. Needs a
+    /// DescribeAlarms call per account, so [`Self::render`] leaves them out.
+    pub fn alarm_thresholds(mut self, alarm_thresholds: bool) -> Self {
+        self.alarm_thresholds = alarm_thresholds;
+        self
+    }
+
+    /// Extra `{{KEY}}` template variables, overriding the same key in an account's `vars`
+    pub fn vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Downloads the images for every account, returning the saved paths per account
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<Vec<PathBuf>> {
+        let downloader = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let downloader = downloader.clone();
+                async move { downloader.download(&acc).await }
+            })
+            .await
+    }
+
+    /// Downloads one round of images for every account, recording each account and saved file in
+    /// the summary, then writes and sends the report
+    pub async fn download_round(
+        &self,
+        accounts: &[AccountConfig],
+        outputs: &RoundOutputs,
+        summary: &mut RunSummary,
+    ) -> Result<RoundOutcome, Error> {
+        let mut report = ImageReport::new(&self.title, &self.start, &self.end);
+        let mut outcome = RoundOutcome::default();
+        let results = self.run(accounts.to_vec()).await;
+        for (acc, (account, res)) in accounts.iter().zip(results) {
+            summary.record(&account, &res);
+            match res {
+                Ok(paths) => {
+                    info!(account = %account, images = paths.len(), "successful query");
+                    for path in paths {
+                        summary.account_output(&path, acc);
+                        outcome.images.push(path.clone());
+                        report.image(&acc.namespace, &acc.region, path);
+                    }
+                }
+                Err(e) => warn!(account = %account, error = %e, "failed to download image"),
+            };
+        }
+        let Some(report_format) = outputs.report else {
+            return Ok(outcome);
+        };
+        let report_dir = self.output_path.clone().unwrap_or_default();
+        let (path, contents) = match report_format {
+            ReportFormat::Markdown => {
+                let exporter = AlarmExporter::new().options(self.options.clone());
+                let results = exporter.run(accounts.to_vec()).await;
+                for (acc, (account, res)) in accounts.iter().zip(results) {
+                    match res {
+                        Ok(alarms) => report.alarms(&acc.namespace, &acc.region, alarms),
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to describe alarms")
+                        }
+                    }
+                }
+                (report_dir.join("report.md"), report.to_markdown())
+            }
+            ReportFormat::Html => (report_dir.join("index.html"), report.to_html()),
+        };
+        let email = contents.clone();
+        write_output(&path, contents).await?;
+        summary.output(&path);
+
+        if let Some(email_to) = &outputs.email {
+            let mailer = ReportMailer::new(
+                &email_to.from,
+                email_to.to.clone(),
+                self.options.retry.clone(),
+            )
+            .await
+            .images(email_to.images);
+            let message_id = mailer
+                .send(&self.title, &path, &email, &outcome.images)
+                .await?;
+            debug!(%message_id, "sent report email");
+            outcome.emailed = email_to.to.clone();
+        }
+        outcome.report = Some(path);
+        Ok(outcome)
+    }
+
+    /// The templates to download with the title of their images. When `template_path` is a
+    /// directory that's every `*.json` file in it, titled after the file name.
+    pub fn templates(&self) -> Result<Vec<(String, PathBuf)>, Error> {
+        if !self.template_path.is_dir() {
+            return Ok(vec![(self.title.clone(), self.template_path.clone())]);
+        }
+        let read_error = |source| Error::Read {
+            path: self.template_path.clone(),
+            source,
+        };
+        let mut templates = vec![];
+        for entry in std::fs::read_dir(&self.template_path).map_err(read_error)? {
+            let path = entry.map_err(read_error)?.path();
+            if path.extension().map_or(false, |ext| ext == "json") && path.is_file() {
+                let title = path.file_stem().unwrap_or_default().to_string_lossy();
+                templates.push((title.into_owned(), path));
+            }
+        }
+        templates.sort();
+        Ok(templates)
+    }
+
+    /// Reads and parses every template once, before any account is rendered
+    pub fn check_templates(&self) -> Result<(), Error> {
+        for (_, path) in self.templates()? {
+            check_template(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Substitutes every template for the account and checks the results are valid JSON,
+    /// without making any AWS calls. Returns the widget JSON with its image title, with the
+    /// properties from [`Self::widget_overrides`] replacing the template's.
+    pub fn render(&self, acc: &AccountConfig) -> Result<Vec<(String, String)>, Error> {
+        let this = self.for_account(acc);
+        let (start, end) = time_range(&this.start, &this.end)?;
+        let period = fit_period(&this.period, &start)?;
+        let context = template_context(
+            acc,
+            &start.template_value(),
+            &end.template_value(),
+            &period.to_string(),
+            &this.vars,
+        );
+        let overrides = this.widget_overrides(&start, &end);
+        this.templates()?
+            .into_iter()
+            .map(|(title, path)| {
+                let metrics = render_template(&path, &context)?;
+                let mut widget: Value = serde_json::from_str(&metrics)
+                    .map_err(|source| Error::Template { path, source })?;
+                if overrides.is_empty() {
+                    return Ok((title, metrics));
+                }
+                for (property, value) in &overrides {
+                    widget[*property] = value.clone();
+                }
+                Ok((title, widget.to_string()))
+            })
+            .collect()
+    }
+
+    /// The AWS calls [`Self::download`] would make for the account, after rendering its
+    /// templates. Fails like the download would when an image already exists.
+    pub fn plan(&self, acc: &AccountConfig) -> Result<Vec<PlannedCall>, Error> {
+        let this = self.for_account(acc);
+        let rendered = this.render(acc)?;
+        let mut calls = vec![PlannedCall::assume_role(acc, &self.options)];
+        if self.alarm_thresholds {
+            calls.push(PlannedCall::new(
+                acc,
+                "DescribeAlarms",
+                "every alarm, for thresholds",
+            ));
+        }
+        for (title, metrics) in rendered {
+            debug!(account = %acc.label(), title = %title, widget = %metrics, "rendered widget");
+            let path = this.image_path(acc, &title);
+            if path.exists() {
+                match self.existing {
+                    ExistingImages::Fail => return Err(Error::ImageExists(path)),
+                    ExistingImages::Skip => continue,
+                    ExistingImages::Overwrite => {}
+                }
+            }
+            calls.push(PlannedCall::new(
+                acc,
+                "GetMetricWidgetImage",
+                path.display().to_string(),
+            ));
+        }
+        Ok(calls)
+    }
+
+    /// Downloads an image for every template, returning the saved paths
+    pub async fn download(&self, acc: &AccountConfig) -> Result<Vec<PathBuf>, Error> {
+        let this = self.for_account(acc);
+        let rendered = this.render(acc)?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        let alarms = if self.alarm_thresholds {
+            describe_alarms(&client, &self.options.retry, None, None).await?
+        } else {
+            vec![]
+        };
+        let mut saved = vec![];
+        for (title, mut metrics) in rendered {
+            if !alarms.is_empty() {
+                let mut widget: Value =
+                    serde_json::from_str(&metrics).expect("rendered widgets are valid JSON");
+                let added = annotate_alarm_thresholds(&mut widget, &alarms);
+                debug!(title = %title, annotations = added, "annotated alarm thresholds");
+                if added > 0 {
+                    metrics = widget.to_string();
+                }
+            }
+            let saved_image_path = this.image_path(acc, &title);
+            if saved_image_path.exists() {
+                match self.existing {
+                    ExistingImages::Fail => return Err(Error::ImageExists(saved_image_path)),
+                    ExistingImages::Skip => {
+                        debug!(path = %saved_image_path.display(), "keeping existing image");
+                        saved.push(saved_image_path);
+                        continue;
+                    }
+                    ExistingImages::Overwrite => {}
+                }
+            }
+            get_metric_image(
+                &client,
+                &self.options.retry,
+                &metrics,
+                self.format,
+                &saved_image_path,
+            )
+            .await?;
+            saved.push(saved_image_path);
+        }
+        Ok(saved)
+    }
+
+    /// Widget properties set by the downloader's options. When start or end is a timestamp it
+    /// replaces the widget's `start` and `end`, since templates usually hold a relative
+    /// `-PT{{PERIOD_START}}`.
+    fn widget_overrides(&self, start: &TimeBound, end: &TimeBound) -> Vec<(&'static str, Value)> {
+        let mut overrides = vec![];
+        if start.is_absolute() || end.is_absolute() {
+            overrides.push(("start", Value::from(start.widget_value())));
+            overrides.push(("end", Value::from(end.widget_value())));
+        }
+        if let Some(timezone) = &self.timezone {
+            overrides.push(("timezone", Value::from(timezone.as_str())));
+        }
+        if let Some(theme) = &self.theme {
+            overrides.push(("theme", Value::from(theme.as_str())));
+        }
+        if let Some(width) = self.width {
+            overrides.push(("width", Value::from(width)));
+        }
+        if let Some(height) = self.height {
+            overrides.push(("height", Value::from(height)));
+        }
+        overrides
+    }
+
+    /// A copy with the account's `template`, `start`, `end` and `period` overrides applied
+    fn for_account(&self, acc: &AccountConfig) -> Self {
+        let mut downloader = self.clone();
+        if let Some(template) = &acc.template {
+            downloader.template_path = template.clone();
+        }
+        if let Some(start) = &acc.start {
+            downloader.start = start.clone();
+        }
+        if let Some(end) = &acc.end {
+            downloader.end = end.clone();
+        }
+        if let Some(period) = acc.period {
+            downloader.period = period.to_string();
+        }
+        downloader
+    }
+
+    fn image_path(&self, acc: &AccountConfig, title: &str) -> PathBuf {
+        let now = DateTime::from(SystemTime::now());
+        let date = now.fmt(Format::DateTime).unwrap_or_default();
+        let timestamp = now.secs().to_string();
+        let placeholders = [
+            ("namespace", acc.namespace.as_str()),
+            ("title", title),
+            ("region", acc.region.as_str()),
+            ("account_id", acc.account_id.as_deref().unwrap_or_default()),
+            ("start", self.start.as_str()),
+            ("end", self.end.as_str()),
+            ("date", date.get(..10).unwrap_or_default()),
+            ("timestamp", timestamp.as_str()),
+        ];
+        let mut saved_image_name = self.name_template.clone();
+        for (placeholder, value) in placeholders {
+            saved_image_name = saved_image_name.replace(&format!("{{{}}}", placeholder), value);
+        }
+        // timestamps have colons, which aren't allowed in Windows file names
+        let saved_image_name = saved_image_name.replace(':', "");
+        self.output_path.clone().unwrap_or_default().join(format!(
+            "{}.{}",
+            saved_image_name,
+            self.format.extension()
+        ))
+    }
+}
+
+/// Checks a `--name-template` only uses the placeholders [`MetricsDownloader::name_template`]
+/// fills in
+pub fn parse_name_template(value: &str) -> Result<String, String> {
+    let placeholder = Regex::new(r"\{([^{}]*)\}").expect("placeholder regex is valid");
+    for captures in placeholder.captures_iter(value) {
+        if !NAME_PLACEHOLDERS.contains(&&captures[1]) {
+            return Err(format!(
+                "unknown placeholder {} in name template, expected one of {{{}}}",
+                &captures[0],
+                NAME_PLACEHOLDERS.join("}, {")
+            ));
+        }
+    }
+    Ok(String::from(value))
+}
+
+/// Parses an image width or height, GetMetricWidgetImage accepts 1 to 2000 pixels
+pub fn parse_image_size(value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(size) if (1..=2000).contains(&size) => Ok(size),
+        _ => Err(format!(
+            "invalid image size {:?}, expected 1 to 2000 pixels",
+            value
+        )),
+    }
+}
+
+/// Calls AWS CloudWatch GetMetricImage API and downloads locally
+/// API Reference: [GetMetricWidgetImage](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_GetMetricWidgetImage.html)
+pub(crate) async fn get_metric_image(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    metric_json: &str,
+    format: ImageFormat,
+    saved_image_path: &Path,
+) -> Result<(), Error> {
+    debug!(format = format.extension(), "getting metric image");
+
+    let request = client
+        .get_metric_widget_image()
+        .output_format(format.output_format())
+        .set_metric_widget(Some(String::from(metric_json)));
+    let image = match retry
+        .call("GetMetricWidgetImage", || request.clone().send())
+        .await
+    {
+        Ok(resp) => resp
+            .metric_widget_image
+            .ok_or(Error::EmptyResponse("GetMetricWidgetImage"))?
+            .into_inner(),
+        Err(e) => match raw_svg(&e) {
+            Some(svg) if format == ImageFormat::Svg => svg,
+            _ => return Err(aws_sdk_cloudwatch::Error::from(e).into()),
+        },
+    };
+
+    // wait to finish saving file
+    write_output(saved_image_path, image).await?;
+    debug!(path = %saved_image_path.display(), "saved metric image");
+    Ok(())
+}
+
+/// The SDK only parses the XML response with a base64 PNG. An SVG comes back as the raw response
+/// body, which the SDK reports as an unparseable response, so it is taken from the error.
+fn raw_svg<E>(err: &SdkError<E>) -> Option<Vec<u8>> {
+    let raw = match err {
+        SdkError::ServiceError { raw, .. } => raw.http(),
+        _ => return None,
+    };
+    let is_svg = raw
+        .headers()
+        .get("content-type")
+        .and_then(|content_type| content_type.to_str().ok())
+        .map_or(false, |content_type| {
+            content_type.starts_with("image/svg+xml")
+        });
+    if !(raw.status().is_success() && is_svg) {
+        return None;
+    }
+    raw.body().bytes().map(<[u8]>::to_vec)
+}
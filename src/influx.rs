@@ -0,0 +1,121 @@
+use crate::data::AccountMetricData;
+
+/// Escapes commas and spaces, plus `=` in tag keys and values, which line protocol treats as
+/// delimiters
+fn escape(value: &str, equals: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == ',' || c == ' ' || (equals && c == '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Writes exported datapoints as InfluxDB line protocol, one line per datapoint measured by metric
+/// name (or query id for an expression) with the statistic as its field, e.g.
+/// `IncomingRecords,account=orders,region=us-west-2,namespace=AWS/Kinesis,StreamName=orders Sum=12 1664582400000000000`.
+/// Timestamps are in nanoseconds, the default precision of `influx write`.
+pub fn metric_data_to_influx(all_data: &[AccountMetricData]) -> String {
+    let mut text = String::new();
+    for acc in all_data {
+        for series in &acc.series {
+            let (measurement, field) = if series.metric_name.is_empty() {
+                (series.id.as_str(), "value")
+            } else {
+                (series.metric_name.as_str(), series.stat.as_str())
+            };
+            let mut tags = vec![
+                ("account", acc.program_name.as_str()),
+                ("region", acc.region.as_str()),
+                ("namespace", series.namespace.as_str()),
+            ];
+            tags.extend(
+                series
+                    .dimensions
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            );
+            // empty tag values aren't allowed, so those tags are left out
+            let tags: String = tags
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(name, value)| format!(",{}={}", escape(name, true), escape(value, true)))
+                .collect();
+            for dp in &series.datapoints {
+                let timestamp = match dp.date_time() {
+                    Some(timestamp) if dp.value.is_finite() => timestamp,
+                    _ => continue,
+                };
+                text.push_str(&format!(
+                    "{}{} {}={} {}\n",
+                    escape(measurement, false),
+                    tags,
+                    escape(field, true),
+                    dp.value,
+                    timestamp.secs() * 1_000_000_000 + i64::from(timestamp.subsec_nanos())
+                ));
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::data::{Datapoint, MetricSeries};
+
+    #[test]
+    fn escapes_leading_formula_characters_only_where_they_delimit() {
+        for (value, tag, measurement) in [
+            ("=SUM(A1)", r"\=SUM(A1)", "=SUM(A1)"),
+            ("+1", "+1", "+1"),
+            ("-1", "-1", "-1"),
+            ("@admin", "@admin", "@admin"),
+            ("a=b,c d", r"a\=b\,c\ d", r"a=b\,c\ d"),
+        ] {
+            assert_eq!(escape(value, true), tag, "{:?}", value);
+            assert_eq!(escape(value, false), measurement, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn keeps_non_ascii_text() {
+        assert_eq!(escape("注文-東京", true), "注文-東京");
+        assert_eq!(escape("café au lait", true), r"café\ au\ lait");
+        assert_eq!(escape("Ünïcödé=✓", true), r"Ünïcödé\=✓");
+    }
+
+    #[test]
+    fn lines_escape_tags_fields_and_measurements() {
+        let data = AccountMetricData {
+            program_name: String::from("=orders"),
+            region: String::from("ap-northeast-1"),
+            account_id: None,
+            series: vec![MetricSeries {
+                id: String::from("latency"),
+                namespace: String::from("AWS/ApiGateway"),
+                metric_name: String::from("Latency p99"),
+                dimensions: BTreeMap::from([
+                    (String::from("ApiName"), String::from("注文 API")),
+                    (String::from("Stage"), String::new()),
+                ]),
+                stat: String::from("p99=x"),
+                period: 60,
+                datapoints: vec![Datapoint {
+                    timestamp: String::from("2024-01-01T00:00:00Z"),
+                    value: 1.5,
+                }],
+                ..Default::default()
+            }],
+        };
+        assert_eq!(
+            metric_data_to_influx(&[data]),
+            "Latency\\ p99,account=\\=orders,region=ap-northeast-1,namespace=AWS/ApiGateway,ApiName=注文\\ API p99\\=x=1.5 1704067200000000000\n"
+        );
+    }
+}
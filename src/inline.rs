@@ -0,0 +1,87 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+
+/// Kitty takes the image in escape sequences of at most this many base64 bytes
+const KITTY_CHUNK: usize = 4096;
+
+/// Terminal protocols for showing images inline. Sixel needs the PNG decoded and quantized, so
+/// it isn't supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// iTerm2's `OSC 1337 File` sequence, also understood by WezTerm
+    Iterm2,
+    /// Kitty's graphics protocol, also understood by Ghostty
+    Kitty,
+}
+
+impl ImageProtocol {
+    /// The protocol of the terminal stdout is attached to, from the variables the terminals set.
+    /// `None` when stdout isn't a terminal or the terminal isn't known to show images.
+    pub fn detect() -> Option<ImageProtocol> {
+        use std::io::IsTerminal;
+
+        if !std::io::stdout().is_terminal() {
+            return None;
+        }
+        let var = |name| std::env::var(name).unwrap_or_default();
+        if var("TERM") == "xterm-kitty"
+            || std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || var("TERM_PROGRAM") == "ghostty"
+        {
+            Some(ImageProtocol::Kitty)
+        } else if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm")
+            || var("LC_TERMINAL") == "iTerm2"
+        {
+            Some(ImageProtocol::Iterm2)
+        } else {
+            None
+        }
+    }
+
+    /// Writes the PNG at `path` to stdout as an inline image
+    pub fn display(&self, path: &Path) -> Result<(), Error> {
+        let png = std::fs::read(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let encoded = base64::encode(&png);
+        let mut sequence = String::new();
+        match self {
+            ImageProtocol::Iterm2 => {
+                sequence.push_str(&format!(
+                    "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07",
+                    png.len(),
+                    encoded
+                ));
+            }
+            ImageProtocol::Kitty => {
+                // base64 is ASCII, so splitting the bytes keeps every chunk valid UTF-8
+                let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let more = u8::from(i + 1 < chunks.len());
+                    let control = if i == 0 {
+                        format!("a=T,f=100,m={}", more)
+                    } else {
+                        format!("m={}", more)
+                    };
+                    sequence.push_str(&format!(
+                        "\x1b_G{};{}\x1b\\",
+                        control,
+                        String::from_utf8_lossy(chunk)
+                    ));
+                }
+            }
+        }
+        sequence.push('\n');
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(sequence.as_bytes())
+            .and_then(|_| stdout.flush())
+            .map_err(|source| Error::Write {
+                path: PathBuf::from("<stdout>"),
+                source,
+            })
+    }
+}
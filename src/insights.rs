@@ -0,0 +1,288 @@
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::InsightRule;
+use aws_smithy_types::DateTime;
+use regex::Regex;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::duration::time_range;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InsightRuleDetails {
+    pub program_name: String,
+    pub region: String,
+    pub name: String,
+    pub state: String,
+    pub schema: String,
+    pub definition: String,
+}
+
+/// One of the top contributors of a rule over the report's time range
+#[derive(Serialize, Debug, Clone)]
+pub struct TopContributor {
+    pub program_name: String,
+    pub region: String,
+    pub rule_name: String,
+    /// Values of the rule's contributor keys, e.g. an IP address and a URL path
+    pub keys: Vec<String>,
+    pub approximate_aggregate_value: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountInsights {
+    pub rules: Vec<InsightRuleDetails>,
+    /// Empty unless reports were requested
+    pub contributors: Vec<TopContributor>,
+}
+
+/// Lists the Contributor Insights rules of each account, optionally with the top contributors of
+/// every enabled rule
+#[derive(Debug, Clone)]
+pub struct InsightRulesExporter {
+    report: bool,
+    start: String,
+    end: String,
+    period: i32,
+    max_contributors: i32,
+    rule_regex: Option<Regex>,
+    options: RunOptions,
+}
+
+impl Default for InsightRulesExporter {
+    fn default() -> Self {
+        InsightRulesExporter {
+            report: false,
+            start: String::from("24H"),
+            end: String::from("0H"),
+            period: 3600,
+            max_contributors: 10,
+            rule_regex: None,
+            options: RunOptions::default(),
+        }
+    }
+}
+
+impl InsightRulesExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetch GetInsightRuleReport for every enabled rule
+    pub fn report(mut self, report: bool) -> Self {
+        self.report = report;
+        self
+    }
+
+    /// Start of the report, e.g. `24H` before now or `2024-01-01T00:00:00Z`
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = start.into();
+        self
+    }
+
+    /// End of the report, e.g. `0H` before now or `2024-01-01T00:00:00Z`
+    pub fn end(mut self, end: impl Into<String>) -> Self {
+        self.end = end.into();
+        self
+    }
+
+    /// Period of the report's datapoints in seconds
+    pub fn period(mut self, period: i32) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Number of top contributors reported per rule
+    pub fn max_contributors(mut self, max_contributors: i32) -> Self {
+        self.max_contributors = max_contributors;
+        self
+    }
+
+    /// Only keep rules whose name matches this regex
+    pub fn rule_regex(mut self, regex: Option<Regex>) -> Self {
+        self.rule_regex = regex;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<AccountInsights> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.describe(&acc).await }
+            })
+            .await
+    }
+
+    pub async fn describe(&self, acc: &AccountConfig) -> Result<AccountInsights, Error> {
+        let (start, end) = time_range(
+            acc.start.as_deref().unwrap_or(&self.start),
+            acc.end.as_deref().unwrap_or(&self.end),
+        )?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        let rules: Vec<InsightRuleDetails> = describe_insight_rules(&client, &self.options.retry)
+            .await?
+            .iter()
+            .map(|rule| InsightRuleDetails {
+                program_name: acc.namespace.clone(),
+                region: acc.region.clone(),
+                name: String::from(rule.name().unwrap_or_default()),
+                state: String::from(rule.state().unwrap_or_default()),
+                schema: String::from(rule.schema().unwrap_or_default()),
+                definition: String::from(rule.definition().unwrap_or_default()),
+            })
+            .filter(|rule| {
+                self.rule_regex
+                    .as_ref()
+                    .map_or(true, |regex| regex.is_match(&rule.name))
+            })
+            .collect();
+
+        let mut contributors = vec![];
+        if self.report {
+            for rule in rules.iter().filter(|rule| rule.state == "ENABLED") {
+                debug!(account = %acc.label(), rule = %rule.name, "getting insight rule report");
+                let top = get_insight_rule_report(
+                    &client,
+                    &self.options.retry,
+                    &rule.name,
+                    start.date_time(),
+                    end.date_time(),
+                    self.period,
+                    self.max_contributors,
+                )
+                .await?;
+                contributors.extend(top.into_iter().map(|(keys, value)| TopContributor {
+                    program_name: acc.namespace.clone(),
+                    region: acc.region.clone(),
+                    rule_name: rule.name.clone(),
+                    keys,
+                    approximate_aggregate_value: value,
+                }));
+            }
+        }
+        Ok(AccountInsights {
+            rules,
+            contributors,
+        })
+    }
+}
+
+/// Writes the rules as CSV with one row per rule
+pub fn rules_to_csv(rules: &[InsightRuleDetails]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record([
+        "program_name",
+        "region",
+        "name",
+        "state",
+        "schema",
+        "definition",
+    ])?;
+    for rule in rules {
+        writer.write_record([
+            &rule.program_name,
+            &rule.region,
+            &rule.name,
+            &rule.state,
+            &rule.schema,
+            &rule.definition,
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Writes top contributors as CSV with one row per contributor, its keys joined by `;`
+pub fn contributors_to_csv(contributors: &[TopContributor]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record([
+        "program_name",
+        "region",
+        "rule_name",
+        "keys",
+        "approximate_aggregate_value",
+    ])?;
+    for contributor in contributors {
+        writer.write_record([
+            contributor.program_name.as_str(),
+            contributor.region.as_str(),
+            contributor.rule_name.as_str(),
+            contributor.keys.join(";").as_str(),
+            contributor.approximate_aggregate_value.to_string().as_str(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Calls AWS CloudWatch DescribeInsightRules API, following `next_token` until every rule is
+/// returned
+/// API Reference: [DescribeInsightRules](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_DescribeInsightRules.html)
+async fn describe_insight_rules(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+) -> Result<Vec<InsightRule>, aws_sdk_cloudwatch::Error> {
+    let mut rules: Vec<InsightRule> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client.describe_insight_rules().set_next_token(next_token);
+        let resp = retry
+            .call("DescribeInsightRules", || request.clone().send())
+            .await?;
+        rules.extend_from_slice(resp.insight_rules().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(rules)
+}
+
+/// Calls AWS CloudWatch GetInsightRuleReport API and returns each top contributor's keys and
+/// approximate aggregate value
+/// API Reference: [GetInsightRuleReport](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_GetInsightRuleReport.html)
+async fn get_insight_rule_report(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    rule_name: &str,
+    start_time: DateTime,
+    end_time: DateTime,
+    period: i32,
+    max_contributors: i32,
+) -> Result<Vec<(Vec<String>, f64)>, aws_sdk_cloudwatch::Error> {
+    let request = client
+        .get_insight_rule_report()
+        .rule_name(rule_name)
+        .start_time(start_time)
+        .end_time(end_time)
+        .period(period)
+        .max_contributor_count(max_contributors);
+    let resp = retry
+        .call("GetInsightRuleReport", || request.clone().send())
+        .await?;
+    Ok(resp
+        .contributors()
+        .unwrap_or_default()
+        .iter()
+        .map(|contributor| {
+            (
+                contributor.keys().unwrap_or_default().to_vec(),
+                contributor
+                    .approximate_aggregate_value()
+                    .unwrap_or_default(),
+            )
+        })
+        .collect())
+}
@@ -0,0 +1,92 @@
+//! Library behind the `cw-metrics` dev CLI for gathering CloudWatch metrics across many AWS
+//! accounts.
+//!
+//! Load the accounts with [`AccountsConfig::from_path`] and hand them to one of the exporters:
+//! [`MetricsDownloader`] saves metric widget images, [`AlarmExporter`] describes alarms,
+//! [`CoverageExporter`] finds metrics without alarms, [`AlarmHistoryExporter`] pulls alarm state
+//! transitions and actions, [`DashboardExporter`] backs up dashboard definitions,
+//! [`MetricDataExporter`] pulls raw datapoints with GetMetricData and [`LogsInsightsExporter`]
+//! runs Logs Insights queries. Every exporter takes a
+//! [`RunOptions`] for concurrency and assume-role settings and returns one result per account,
+//! so a failing account never hides the others.
+
+mod actions;
+pub mod alarms;
+mod annotations;
+pub mod anomaly;
+pub mod apply;
+pub mod archive;
+pub mod aws_regions;
+pub mod baseline;
+pub mod client;
+pub mod config;
+pub mod coverage;
+pub mod dashboards;
+pub mod data;
+pub mod diagnostics;
+pub mod doctor;
+pub mod duration;
+pub mod email;
+pub mod error;
+pub mod grafana;
+pub mod history;
+pub mod iac;
+pub mod images;
+pub mod influx;
+pub mod inline;
+pub mod insights;
+pub mod logs;
+pub mod manifest;
+pub mod metrics;
+pub mod notify;
+pub mod organizations;
+pub mod otlp;
+pub mod output;
+pub mod plan;
+pub mod plot;
+pub mod prometheus;
+pub mod put;
+pub mod report;
+pub mod retry;
+pub mod runner;
+pub mod schedule;
+pub mod serve;
+pub mod slack;
+pub mod snapshot;
+pub mod sso;
+pub mod store;
+pub mod streams;
+pub mod summary;
+mod template;
+pub mod tui;
+pub mod upload;
+pub mod watch;
+pub mod xlsx;
+
+pub use alarms::{AlarmDimension, AlarmExporter, AlarmStateSetter, MetricAlarmDetails};
+pub use anomaly::{AnomalyDetectorDetails, AnomalyDetectorExporter};
+pub use apply::{AlarmApplier, AppliedAlarm};
+pub use baseline::{AlarmDifference, BaselineAlarm};
+pub use client::CredentialCache;
+pub use config::{AccountConfig, AccountDefaults, AccountEntry, AccountFilter, AccountsConfig};
+pub use coverage::{CoverageExporter, MetricAlarmLookup, MetricAlarms, UnalarmedMetric};
+pub use dashboards::{DashboardExporter, DashboardSummary};
+pub use data::{AccountMetricData, MetricDataExporter};
+pub use doctor::{AccountCheck, Doctor};
+pub use error::Error;
+pub use history::{AlarmHistoryExporter, AlarmHistoryRecord};
+pub use iac::IacFormat;
+pub use images::{ExistingImages, ImageFormat, MetricsDownloader};
+pub use insights::{AccountInsights, InsightRulesExporter};
+pub use logs::{AccountQueryResults, LogsInsightsExporter};
+pub use manifest::{Manifest, ManifestFile};
+pub use put::{Datapoint, MetricDataPublisher};
+pub use report::ImageReport;
+pub use retry::RetryPolicy;
+pub use runner::{AccountResults, RunOptions};
+pub use snapshot::AlarmSnapshot;
+pub use store::MetricStore;
+pub use streams::{MetricStreamDetails, MetricStreamExporter};
+pub use summary::RunSummary;
+pub use tui::Browser;
+pub use upload::S3Uploader;
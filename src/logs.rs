@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_cloudwatchlogs::model::{QueryStatus, ResultField};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::duration::time_range;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::template::{render_str, render_template, template_context};
+
+/// How long to wait between GetQueryResults calls while a query is running
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AccountQueryResults {
+    pub program_name: String,
+    pub region: String,
+    pub rows: Vec<BTreeMap<String, String>>,
+}
+
+/// Runs a Logs Insights query for each account and waits for its results
+#[derive(Debug, Clone)]
+pub struct LogsInsightsExporter {
+    query_path: PathBuf,
+    log_groups: Vec<String>,
+    start: String,
+    end: String,
+    limit: Option<i32>,
+    vars: BTreeMap<String, String>,
+    options: RunOptions,
+}
+
+impl LogsInsightsExporter {
+    /// `query_path` is templated like widget JSON and so are the log group names, e.g.
+    /// `/aws/lambda/{{NAMESPACE}}`
+    pub fn new(query_path: impl Into<PathBuf>, log_groups: Vec<String>) -> Self {
+        LogsInsightsExporter {
+            query_path: query_path.into(),
+            log_groups,
+            start: String::from("24H"),
+            end: String::from("0H"),
+            limit: None,
+            vars: BTreeMap::new(),
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Start of the query, e.g. `24H` before now or `2024-01-01T00:00:00Z`
+    pub fn start(mut self, start: impl Into<String>) -> Self {
+        self.start = start.into();
+        self
+    }
+
+    /// End of the query, e.g. `0H` before now or `2024-01-01T00:00:00Z`
+    pub fn end(mut self, end: impl Into<String>) -> Self {
+        self.end = end.into();
+        self
+    }
+
+    /// Maximum number of rows returned per account
+    pub fn limit(mut self, limit: Option<i32>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Extra `{{KEY}}` template variables, overriding the same key in an account's `vars`
+    pub fn vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<AccountQueryResults> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.query(&acc).await }
+            })
+            .await
+    }
+
+    /// Runs the query in the account, with its `start` and `end` overrides
+    pub async fn query(&self, acc: &AccountConfig) -> Result<AccountQueryResults, Error> {
+        let (start, end) = time_range(
+            acc.start.as_deref().unwrap_or(&self.start),
+            acc.end.as_deref().unwrap_or(&self.end),
+        )?;
+        let client = self.options.logs_client(acc).await?;
+        let context = template_context(
+            acc,
+            &start.template_value(),
+            &end.template_value(),
+            "",
+            &self.vars,
+        );
+        let query = render_template(&self.query_path, &context)?;
+        let log_groups = self
+            .log_groups
+            .iter()
+            .map(|group| render_str(group, group, &context))
+            .collect::<Result<Vec<String>, Error>>()?;
+        let (start_time, end_time) = (start.date_time(), end.date_time());
+        let rows = run_query(
+            &client,
+            &self.options.retry,
+            &query,
+            log_groups,
+            start_time.secs(),
+            end_time.secs(),
+            self.limit,
+        )
+        .await?;
+        Ok(AccountQueryResults {
+            program_name: acc.namespace.clone(),
+            region: acc.region.clone(),
+            rows,
+        })
+    }
+}
+
+/// Writes query results as CSV with one row per result row. Columns are the union of every
+/// field returned, after the account's program name and region.
+pub fn query_results_to_csv(all_results: &[AccountQueryResults]) -> Result<Vec<u8>, csv::Error> {
+    let mut fields: Vec<&str> = vec![];
+    for row in all_results.iter().flat_map(|acc| &acc.rows) {
+        for field in row.keys() {
+            if !fields.contains(&field.as_str()) {
+                fields.push(field);
+            }
+        }
+    }
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut header = vec!["program_name", "region"];
+    header.extend(&fields);
+    writer.write_record(&header)?;
+    for acc in all_results {
+        for row in &acc.rows {
+            let mut record = vec![acc.program_name.as_str(), acc.region.as_str()];
+            record.extend(
+                fields
+                    .iter()
+                    .map(|field| row.get(*field).map(String::as_str).unwrap_or_default()),
+            );
+            writer.write_record(&record)?;
+        }
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+fn to_row(fields: &[ResultField]) -> BTreeMap<String, String> {
+    fields
+        .iter()
+        // @ptr is an opaque pointer for GetLogRecord, not part of the query output
+        .filter(|field| field.field() != Some("@ptr"))
+        .map(|field| {
+            (
+                String::from(field.field().unwrap_or_default()),
+                String::from(field.value().unwrap_or_default()),
+            )
+        })
+        .collect()
+}
+
+/// Calls AWS CloudWatch Logs StartQuery API and polls GetQueryResults until the query finishes
+/// API Reference: [StartQuery](https://docs.aws.amazon.com/AmazonCloudWatchLogs/latest/APIReference/API_StartQuery.html)
+async fn run_query(
+    client: &aws_sdk_cloudwatchlogs::Client,
+    retry: &RetryPolicy,
+    query: &str,
+    log_groups: Vec<String>,
+    start_time: i64,
+    end_time: i64,
+    limit: Option<i32>,
+) -> Result<Vec<BTreeMap<String, String>>, Error> {
+    debug!("starting logs insights query");
+    let request = client
+        .start_query()
+        .set_log_group_names(Some(log_groups))
+        .query_string(query)
+        .start_time(start_time)
+        .end_time(end_time)
+        .set_limit(limit);
+    let started = retry
+        .call("StartQuery", || request.clone().send())
+        .await
+        .map_err(aws_sdk_cloudwatchlogs::Error::from)?;
+    let query_id = started
+        .query_id()
+        .ok_or(Error::EmptyResponse("StartQuery"))?;
+
+    loop {
+        let request = client.get_query_results().query_id(query_id);
+        let resp = retry
+            .call("GetQueryResults", || request.clone().send())
+            .await
+            .map_err(aws_sdk_cloudwatchlogs::Error::from)?;
+        match resp.status() {
+            Some(QueryStatus::Complete) => {
+                return Ok(resp
+                    .results()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|fields| to_row(fields))
+                    .collect());
+            }
+            Some(QueryStatus::Running) | Some(QueryStatus::Scheduled) => {
+                tokio::time::sleep(POLL_INTERVAL).await
+            }
+            status => {
+                return Err(Error::Query {
+                    query_id: String::from(query_id),
+                    status: String::from(status.map(|s| s.as_str()).unwrap_or("Unknown")),
+                })
+            }
+        }
+    }
+}
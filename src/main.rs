@@ -1,654 +1,3203 @@
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{BufRead, IsTerminal};
+use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::process::ExitCode;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use aws_sdk_cloudwatch::model::{ComparisonOperator, MetricAlarm, Statistic};
-use aws_sdk_cloudwatch::{Client as cloudwatchClient, Error, PKG_VERSION};
-use aws_sdk_sts::Client as stsClient;
-use clap::{Arg, Command};
-use tokio::fs;
+use aws_sdk_cloudwatch::model::{HistoryItemType, StateValue};
+use clap::{Arg, ArgMatches, Command};
+use cw_metrics::alarms::{
+    alarm_states_to_table, alarms_to_csv, alarms_to_markdown, alarms_to_table, parse_tag,
+};
+use cw_metrics::anomaly::detectors_to_csv;
+use cw_metrics::apply::{applied_to_table, ApplyOutcome};
+use cw_metrics::archive::write_archive;
+use cw_metrics::aws_regions;
+use cw_metrics::baseline::{diff_alarms, differences_to_csv, differences_to_table};
+use cw_metrics::client::{get_cw_client, set_base_credentials, BaseCredentials};
+use cw_metrics::config::{accounts_to_toml, default_config_path, role_arn};
+use cw_metrics::coverage::{coverage_to_csv, coverage_to_table, metric_alarms_to_table};
+use cw_metrics::data::{
+    metric_data_to_csv, metric_data_to_parquet, metric_summary_to_table, Watermarks,
+};
+use cw_metrics::diagnostics::{check_config, Severity};
+use cw_metrics::doctor::{caller_identity, checks_to_table};
+use cw_metrics::duration::{parse_interval, parse_period, parse_time, parse_timezone};
+use cw_metrics::email::EmailImages;
+use cw_metrics::grafana::GrafanaConverter;
+use cw_metrics::history::history_to_csv;
+use cw_metrics::iac::IacFormat;
+use cw_metrics::images::{
+    parse_image_size, parse_name_template, ReportEmail, ReportFormat, RoundOutcome, RoundOutputs,
+    DEFAULT_NAME_TEMPLATE, STABLE_NAME_TEMPLATE,
+};
+use cw_metrics::influx::metric_data_to_influx;
+use cw_metrics::inline::ImageProtocol;
+use cw_metrics::insights::{contributors_to_csv, rules_to_csv};
+use cw_metrics::logs::query_results_to_csv;
+use cw_metrics::metrics::show_metrics;
+use cw_metrics::organizations::discover_accounts;
+use cw_metrics::otlp::{OtlpExporter, OtlpProtocol};
+use cw_metrics::output::write_output;
+use cw_metrics::plan::{plan_to_table, PlannedCall};
+use cw_metrics::plot::{metric_data_to_sparklines, PlotStyle};
+use cw_metrics::prometheus::metric_data_to_prometheus;
+use cw_metrics::put::{Datapoint, DatapointFormat};
+use cw_metrics::schedule::{ScheduleConfig, Scheduler};
+use cw_metrics::serve::GalleryServer;
+use cw_metrics::slack::SlackNotifier;
+use cw_metrics::snapshot::write_drift;
+use cw_metrics::streams::{streams_to_csv, streams_to_table};
+use cw_metrics::summary::{notify_sns, RunSummary, EXIT_FATAL};
+use cw_metrics::upload::upload_outputs;
+use cw_metrics::watch::{wait_for_alarm_state, watch_alarms};
+use cw_metrics::xlsx::{review_to_xlsx, SheetGrouping};
+use cw_metrics::{
+    AccountConfig, AccountEntry, AccountFilter, AccountMetricData, AccountQueryResults,
+    AccountsConfig, AlarmApplier, AlarmExporter, AlarmHistoryExporter, AlarmHistoryRecord,
+    AlarmSnapshot, AlarmStateSetter, AnomalyDetectorExporter, BaselineAlarm, Browser,
+    CoverageExporter, CredentialCache, DashboardExporter, DashboardSummary, Doctor, Error,
+    ExistingImages, ImageFormat, InsightRulesExporter, LogsInsightsExporter, MetricAlarmDetails,
+    MetricAlarmLookup, MetricDataExporter, MetricDataPublisher, MetricStore, MetricStreamExporter,
+    MetricsDownloader, RetryPolicy, RunOptions, UnalarmedMetric,
+};
+use regex::Regex;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-#[derive(Deserialize, Debug)]
-struct AccountsConfig {
-    account: Vec<AccountConfig>,
-}
-
-#[derive(Deserialize, Debug)]
-struct AccountConfig {
-    namespace: String,
-    region: String,
-    role_arn: String,
-}
-
-#[derive(Debug)]
-struct GetWidgetProps {
-    app_name: String,
-    end: String,
-    period: String,
-    region: Option<String>,
-    role_arn: String,
-    start: String,
-    template_path: PathBuf,
-    title: String,
-    verbose: bool,
-}
-
-#[derive(Serialize, Debug)]
-struct MetricAlarmDetails {
-    program_name: String,
-    alarm_name: String,
-    alarm_arn: String,
-    alarm_description: String,
-    dimensions: Vec<String>,
-    actions_enabled: bool,
-    period: i32,
-    threshold: f64,
-    comparison_operator: String,
-    treat_missing_data: String,
-    statistic: String,
-}
-
-#[derive(Debug)]
-struct DescribeAlarmsProps {
-    region: Option<String>,
-    role_arn: String,
-    verbose: bool,
-}
-
-pub mod aws_regions {
-
-    pub trait AWSRegionName {
-        fn name(self: Self) -> &'static str;
-    }
-
-    impl AWSRegionName for AirportCode {
-        fn name(self: Self) -> &'static str {
-            match self {
-                AirportCode::IAD => "us-east-1",
-                AirportCode::PDX => "us-west-2",
-                AirportCode::DUB => "eu-west-1",
-            }
-        }
-    }
+/// When the run started, so every exporter of one run counts `--run-deadline` from the same instant
+static RUN_STARTED: OnceLock<Instant> = OnceLock::new();
 
-    // this is dumb, not sure how to force provide a static string in this case though...
-    pub fn convert_to_name(region: &str) -> &'static str {
-        match region {
-            "us-east-1" => "us-east-1",
-            "us-west-2" => "us-west-2",
-            "eu-west-1" => "eu-west-1",
-            _ => "us-west-2",
-        }
-    }
-
-    /// AirportCode enum represents the 3-letter international airport code closest to a data center region
-    #[derive(Debug, Copy, Clone)]
-    pub enum AirportCode {
-        IAD,
-        PDX,
-        DUB,
-    }
-}
+/// Assumed-role sessions shared by every exporter of the run, so an MFA code is asked for once
+static CREDENTIAL_CACHE: OnceLock<Arc<CredentialCache>> = OnceLock::new();
 
-/// Dev CLI for repetitive AWS account tasks
-///
-/// ## Accounts Config
-///
-/// The accounts are defined in [TOML](https://toml.io) syntax. The file should be a list of tables containing `namespace`, `account_id`, and `region` for each account.
-///
-/// Example (from the repo's accounts.toml):
-///
-/// ```toml
-/// [[account]]
-/// namespace = "SomeDataProcessingProgram"
-/// account_id = "111111111111"
-/// region = "us-east-1"
-/// ```
-///
-/// To validate accounts config is parsed properly:
-///
-/// ```bash
-/// cargo run -- config <ACCOUNT.TOML FILE>
-///
-/// # example
-/// cargo run -- config accounts.toml
-/// AccountConfig { namespace: "SomeDataProcessingProgram", account_id: "111111111111", region: "us-east-1" }
-/// AccountConfig { namespace: "SomeDataProcessingProgram", account_id: "222222222222", region: "eu-west-1" }
-/// AccountConfig { namespace: "SomeDataProcessingProgram", account_id: "222222222222", region: "us-west-2" }
-/// ...
-/// ```
-///
-/// ## Commands
-///
-/// You can use `cargo run --` to build and pass commands to the CLI.
-///
-/// ```bash
-/// # run retry counts, replace START_TIME in retry-counts graph to start 6 months ago
-/// cargo run -- images --period 3600 --pattern ItemDPP -s 4320H ./resources/traffic.json ../accounts.toml
-///
-/// # omit the pattern to run this command for all accounts
-/// cargo run -- images --period 3600  -s 7200H ./resources/traffic.json ../accounts.toml
-/// ```
+/// Dev CLI for repetitive AWS account tasks, see the README for the accounts config and examples
 #[tokio::main]
-async fn main() -> Result<(), Error> {
-    tracing_subscriber::fmt::init();
-
+async fn main() -> ExitCode {
     let matches = Command::new("dev")
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .short('q')
+                .global(true)
+                .conflicts_with("verbose")
+                .help("don't show the progress bar and only log errors, e.g. in CI logs"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .global(true)
+                .multiple_occurrences(true)
+                .help("log more detail to stderr, repeat for more (-vv, -vvv); RUST_LOG overrides"),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .global(true)
+                .help("format of the logs on stderr, json writes one object per line for CI pipelines")
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .global(true)
+                .help("render and check the templates of every account and list the AWS calls images, data and alarms would make, without making them; alarms apply reports what would change")
+        )
+        .arg(
+            Arg::new("fail-fast")
+                .long("fail-fast")
+                .global(true)
+                .conflicts_with("continue-on-error")
+                .help("stop starting accounts after the first one fails, the rest are reported as skipped"),
+        )
+        .arg(
+            Arg::new("continue-on-error")
+                .long("continue-on-error")
+                .global(true)
+                .help("keep going when an account fails and report it at the end (the default)"),
+        )
+        .arg(
+            Arg::new("max-attempts")
+                .long("max-attempts")
+                .global(true)
+                .help("attempts per AWS call before giving up on an account, including the first")
+                .default_value("5")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("retry-base-delay-ms")
+                .long("retry-base-delay-ms")
+                .global(true)
+                .help("backoff before the first retry, doubled for every retry after it")
+                .default_value("200")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("retry-max-delay-ms")
+                .long("retry-max-delay-ms")
+                .global(true)
+                .help("upper bound for the backoff between two attempts")
+                .default_value("20000")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("retry-on")
+                .long("retry-on")
+                .global(true)
+                .help("error classes to retry: throttling (Throttling, RequestLimitExceeded, ...) and transient (timeouts, 5xx)")
+                .possible_values(["throttling", "transient"])
+                .use_value_delimiter(true)
+                .multiple_values(true)
+                .default_value("throttling,transient")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .global(true)
+                .help("AWS profile for the starting credentials that assume each account's role, instead of $AWS_PROFILE or the default chain")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("mfa-code")
+                .long("mfa-code")
+                .global(true)
+                .help("current code of the MFA device set as mfa_serial, instead of being asked for it; AWS accepts a code only once, so later roles needing MFA still ask")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("sso-login")
+                .long("sso-login")
+                .global(true)
+                .help("when the profile is an AWS SSO (IAM Identity Center) profile without a valid token, sign in with `aws sso login` before the run"),
+        )
+        .arg(
+            Arg::new("request-timeout")
+                .long("request-timeout")
+                .global(true)
+                .help("give up on an AWS call attempt with no response after this long, like 30s or 2m; it is retried as a transient error")
+                .validator(parse_interval)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("run-deadline")
+                .long("run-deadline")
+                .global(true)
+                .help("stop the run after this long, like 45m or 2h: no more accounts are started and those still running fail")
+                .validator(parse_interval)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .global(true)
+                .help("write a JSON manifest of every saved file (account, region, checksum) and each account's status to this path")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("archive")
+                .long("archive")
+                .global(true)
+                .help("also bundle every saved file and the manifest into this .zip, .tar or .tar.gz archive")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("notify-sns")
+                .long("notify-sns")
+                .global(true)
+                .help("publish a run summary with each account's failure to this SNS topic ARN, using the default credentials")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("notify-sns-on")
+                .long("notify-sns-on")
+                .global(true)
+                .requires("notify-sns")
+                .help("publish after every run, or only when the run or an account failed")
+                .possible_values(["always", "failure"])
+                .default_value("always")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("s3-uri")
+                .long("s3-uri")
+                .global(true)
+                .help("also upload saved files to s3://bucket/prefix/ under a YYYY/MM/DD/ partition")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("s3-role-arn")
+                .long("s3-role-arn")
+                .global(true)
+                .requires("s3-uri")
+                .help("role to assume for the upload instead of the default credentials")
+                .takes_value(true),
+        )
         .subcommand(
             Command::new("alarms")
                 .about("describe alarms for all accounts")
+                .args(session_args())
+                .args(slack_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .args(alarm_filter_args())
+                .arg(
+                    Arg::new("list-tags")
+                        .long("list-tags")
+                        .help("include each alarm's tags, listed with one ListTagsForResource call per alarm"),
+                )
+                .arg(
+                    Arg::new("resolve-actions")
+                        .long("resolve-actions")
+                        .help("resolve alarm action ARNs into SNS topic names with their subscription counts, Auto Scaling policies and other targets"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format for the alarms report")
+                        .possible_values(["json", "csv", "table", "markdown"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("file to save the report to, defaults to describe-alarms.<ext>")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("snapshot-dir")
+                        .long("snapshot-dir")
+                        .help("also save every account's alarms as a timestamped snapshot in this directory, for `alarms drift`")
+                        .takes_value(true),
+                )
+                .arg(config_path_arg())
+                .subcommand(
+                    Command::new("for-metric")
+                        .about("show which accounts have an alarm on one exact metric, with DescribeAlarmsForMetric")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("namespace")
+                                .long("namespace")
+                                .short('n')
+                                .help("CloudWatch namespace of the metric, e.g. AWS/Kinesis")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("metric-name")
+                                .long("metric-name")
+                                .short('m')
+                                .help("name of the metric, e.g. GetRecords.IteratorAgeMilliseconds")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("dimension")
+                                .long("dimension")
+                                .short('d')
+                                .help("NAME=VALUE dimension of the metric, repeat for more; values are templated, e.g. StreamName={{NAMESPACE}}")
+                                .validator(parse_var)
+                                .multiple_occurrences(true)
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("watch")
+                        .about("refresh the state of every account's alarms in the terminal, latest state change first, until Ctrl-C")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .args(alarm_filter_args())
+                        .arg(
+                            Arg::new("list-tags")
+                                .long("list-tags")
+                                .hide(true)
+                                .help("list each alarm's tags, which the table doesn't show"),
+                        )
+                        .arg(
+                            Arg::new("interval")
+                                .long("interval")
+                                .help("time between refreshes, e.g. 30s")
+                                .validator(parse_interval)
+                                .default_value("1m")
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("wait")
+                        .about("poll alarms until every matching alarm is in a state, e.g. as a deployment gate; exits with status 1 on timeout")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("alarm-prefix")
+                                .long("alarm-prefix")
+                                .help("only wait for alarms whose name starts with this prefix")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("alarm-regex")
+                                .long("alarm-regex")
+                                .help("only wait for alarms whose name or description matches this regex")
+                                .validator(Regex::new)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("state")
+                                .long("state")
+                                .help("state every matching alarm has to reach")
+                                .possible_values(["ALARM", "OK", "INSUFFICIENT_DATA"])
+                                .default_value("OK")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("timeout")
+                                .long("timeout")
+                                .help("give up after this long, e.g. 30m")
+                                .validator(parse_interval)
+                                .default_value("30m")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("interval")
+                                .long("interval")
+                                .help("time between polls, e.g. 30s")
+                                .validator(parse_interval)
+                                .default_value("30s")
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("set-state")
+                        .about("set an alarm's state in every account to test its actions, until its next evaluation")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("alarm-name")
+                                .long("alarm-name")
+                                .help("name of the alarm, templated per account, e.g. {{NAMESPACE}}-HighLatency")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("state")
+                                .long("state")
+                                .help("state to set the alarm to")
+                                .possible_values(["ALARM", "OK", "INSUFFICIENT_DATA"])
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("reason")
+                                .long("reason")
+                                .help("reason recorded in the alarm's history")
+                                .default_value("set by cw-metrics")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("yes")
+                                .long("yes")
+                                .short('y')
+                                .help("don't ask for confirmation"),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("apply")
+                        .about("create or update alarms in every account from a templated definitions file")
+                        .args(session_args())
+                        .arg(var_arg())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("definitions-path")
+                                .required(true)
+                                .help("JSON file with an `alarms` list of PutMetricAlarm settings, templated like widget JSON"),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("export")
+                        .about("convert every account's alarms into Terraform or CloudFormation, one file per account")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .args(alarm_filter_args())
+                        .arg(
+                            Arg::new("list-tags")
+                                .long("list-tags")
+                                .help("add each alarm's tags to its resource"),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .help("aws_cloudwatch_metric_alarm resources in HCL, or an AWS::CloudWatch::Alarm template in YAML")
+                                .possible_values(["terraform", "cloudformation"])
+                                .default_value("terraform")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help("directory to save <namespace>-<region>-alarms.<tf|yaml> files to, defaults to the current directory")
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("drift")
+                        .about("report alarms created, deleted or changed between the two latest snapshots")
+                        .arg(
+                            Arg::new("snapshot-dir")
+                                .long("snapshot-dir")
+                                .help("directory the snapshots were saved to with `alarms --snapshot-dir`")
+                                .required(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .help("output format for the changes")
+                                .possible_values(["json", "csv", "table"])
+                                .default_value("json")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help("file to save the changes to, defaults to alarms-drift.<ext>")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("diff")
+                        .about("report missing alarms, threshold and comparison operator differences against a golden file or reference accounts")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("baseline")
+                                .long("baseline")
+                                .help("JSON list of expected alarms with alarm_name, threshold and comparison_operator, e.g. the output of `alarms`")
+                                .required_unless_present("against")
+                                .conflicts_with("against")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("against")
+                                .long("against")
+                                .help("compare with the alarms of the accounts whose namespace, region or role ARN matches this regex")
+                                .validator(Regex::new)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("alarm-regex")
+                                .long("alarm-regex")
+                                .help("only compare alarms whose name or description matches this regex")
+                                .validator(Regex::new)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .help("output format for the differences")
+                                .possible_values(["json", "csv", "table"])
+                                .default_value("json")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help("file to save the differences to, defaults to alarms-diff.<ext>")
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                ),
+        )
+        .subcommand(
+            Command::new("anomaly-detectors")
+                .about("export the anomaly detection models of all accounts, with their metrics and excluded time ranges")
+                .args(session_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format for the anomaly detectors")
+                        .possible_values(["json", "csv"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("file to save the anomaly detectors to, defaults to anomaly-detectors.<ext>")
+                        .takes_value(true),
+                )
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("serve a directory of downloaded images and reports over HTTP with a gallery index")
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .short('p')
+                        .help("port to listen on")
+                        .default_value("8080")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("bind")
+                        .long("bind")
+                        .help("address to listen on, 0.0.0.0 to let teammates connect")
+                        .default_value("127.0.0.1")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("refresh")
+                        .long("refresh")
+                        .help("reload the gallery in the browser every interval (e.g. 1m), to follow images --watch")
+                        .validator(parse_interval)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("basic-auth")
+                        .long("basic-auth")
+                        .help("require these USER:PASSWORD credentials")
+                        .validator(|value| {
+                            value
+                                .contains(':')
+                                .then_some(())
+                                .ok_or("expected USER:PASSWORD")
+                        })
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .help("directory to serve, e.g. the images --output-path")
+                        .default_value("."),
+                ),
+        )
+        .subcommand(
+            Command::new("schedule")
+                .about("run the jobs of a schedule file on their cron expressions until Ctrl-C, each as its own cw-metrics command")
+                .arg(
+                    Arg::new("state-dir")
+                        .long("state-dir")
+                        .help("directory for each job's log and the state.json of their last runs")
+                        .default_value("schedule-state")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("list")
+                        .long("list")
+                        .help("print the next runs of every job and exit"),
+                )
+                .arg(
+                    Arg::new("schedule-path")
+                        .required(true)
+                        .help("TOML file of [[job]] tables with a name, a cron expression and the args to run"),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("browse the metrics and alarms of an account interactively, downloading widget images of metrics")
+                .args(session_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("directory to save metric images in, created if it does not exist")
+                        .takes_value(true),
+                )
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("put")
+                .about("publish datapoints from a CSV or JSON file into a namespace of every account with PutMetricData")
+                .args(session_args())
+                .arg(var_arg())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("namespace")
+                        .long("namespace")
+                        .short('n')
+                        .required(true)
+                        .help("CloudWatch namespace to publish the datapoints in, like Synthetic/Backfill")
+                        .takes_value(true),
+                )
                 .arg(
-                    Arg::new("pattern")
-                        .long("pattern")
-                        .takes_value(true)
-                        .short('f'),
+                    Arg::new("format")
+                        .long("format")
+                        .help("format of the datapoints, defaults to json for .json files and csv otherwise")
+                        .possible_values(["json", "csv"])
+                        .takes_value(true),
                 )
                 .arg(
-                    Arg::new("config-path")
+                    Arg::new("datapoints-path")
                         .required(true)
-                        .help("the path to the TOML config file with accounts"),
+                        .help("CSV with a metric_name,value,timestamp,unit,dimensions header, or a JSON list of the same fields; - reads stdin"),
+                )
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("metric-streams")
+                .about("audit CloudWatch metric streams for all accounts")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("list the metric streams of every account with their Firehose destination and included or excluded namespaces")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("format")
+                                .long("format")
+                                .help("output format for the metric streams")
+                                .possible_values(["json", "csv", "table"])
+                                .default_value("json")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help("file to save the metric streams to, defaults to metric-streams.<ext>")
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                ),
+        )
+        .subcommand(
+            Command::new("insights")
+                .about("list Contributor Insights rules for all accounts, optionally with each enabled rule's top contributors")
+                .args(session_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("rule-regex")
+                        .long("rule-regex")
+                        .help("only keep rules whose name matches this regex")
+                        .validator(Regex::new)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("report")
+                        .long("report")
+                        .help("also fetch the top contributors of every enabled rule with GetInsightRuleReport"),
+                )
+                .arg(
+                    Arg::new("start-time")
+                        .short('s')
+                        .default_value("24H")
+                        .long("start-time")
+                        .alias("start")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("end-time")
+                        .short('e')
+                        .default_value("0H")
+                        .long("end-time")
+                        .alias("end")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("period")
+                        .short('p')
+                        .long("period")
+                        .help("period of the report in seconds")
+                        .validator(parse_period)
+                        .default_value("3600")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("max-contributors")
+                        .long("max-contributors")
+                        .help("number of top contributors to report per rule")
+                        .default_value("10")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format: JSON has the rules and contributors, CSV has the contributors with --report and the rules otherwise")
+                        .possible_values(["json", "csv"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("file to save the rules to, defaults to insight-rules.<ext>")
+                        .takes_value(true),
+                )
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("coverage")
+                .about("list metrics that no alarm watches, for every account")
+                .args(session_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("namespace")
+                        .long("namespace")
+                        .short('n')
+                        .help("CloudWatch namespace to check, e.g. AWS/Kinesis; repeat for several, defaults to each account's namespace")
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format for the coverage report")
+                        .possible_values(["json", "csv", "table"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("file to save the report to, defaults to alarm-coverage.<ext>")
+                        .takes_value(true),
+                )
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("alarm-history")
+                .about("export alarm state transitions and actions for all accounts")
+                .args(session_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("start-time")
+                        .short('s')
+                        .default_value("168H")
+                        .long("start-time")
+                        .alias("start")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("end-time")
+                        .short('e')
+                        .default_value("0H")
+                        .long("end-time")
+                        .alias("end")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("alarm-name")
+                        .long("alarm-name")
+                        .help("only export history for the alarm with exactly this name")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("alarm-regex")
+                        .long("alarm-regex")
+                        .help("only keep history for alarms whose name matches this regex")
+                        .validator(Regex::new)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("history-type")
+                        .long("history-type")
+                        .help("only export one kind of history item")
+                        .possible_values(["ConfigurationUpdate", "StateUpdate", "Action"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format for the alarm history")
+                        .possible_values(["json", "csv"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("file to save the history to, defaults to alarm-history.<ext>")
+                        .takes_value(true),
+                )
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("dashboards")
+                .about("list and back up CloudWatch dashboards for all accounts")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("list dashboards in every account")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help(
+                                    "directory to save dashboards in, created if it does not exist",
+                                )
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("save a dashboard body as JSON from every account")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help(
+                                    "directory to save dashboards in, created if it does not exist",
+                                )
+                                .takes_value(true),
+                        )
+                        .arg(Arg::new("name").required(true).help("the dashboard name"))
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("render")
+                        .about("save every metric widget of a dashboard as PNGs for every account")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("start-time")
+                                .short('s')
+                                .default_value("4320H")
+                                .long("start-time")
+                                .alias("start")
+                                .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                                .validator(parse_time)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("end-time")
+                                .short('e')
+                                .default_value("0H")
+                                .long("end-time")
+                                .alias("end")
+                                .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                                .validator(parse_time)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help("directory to save images in, created if it does not exist")
+                                .takes_value(true),
+                        )
+                        .arg(Arg::new("name").required(true).help("the dashboard name"))
+                        .arg(config_path_arg()),
+                )
+                .subcommand(
+                    Command::new("grafana")
+                        .about("convert widget templates or a dashboard to a Grafana dashboard using the CloudWatch datasource")
+                        .args(session_args())
+                        .arg(concurrency_arg())
+                        .arg(var_arg())
+                        .args(filter_args())
+                        .arg(
+                            Arg::new("template")
+                                .long("template")
+                                .short('t')
+                                .help("widget template, or directory of templates, to convert to a panel each; can be repeated")
+                                .multiple_occurrences(true)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("name")
+                                .long("name")
+                                .help("convert this dashboard from the first account instead of templates")
+                                .conflicts_with("template")
+                                .required_unless_present("template")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("title")
+                                .long("title")
+                                .help("title of the Grafana dashboard")
+                                .default_value("CloudWatch")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("period")
+                                .short('p')
+                                .long("period")
+                                .help("period in seconds substituted for {{PERIOD}} in templates")
+                                .validator(parse_period)
+                                .default_value("300")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("from")
+                                .long("from")
+                                .help("start of the dashboard's default time range, e.g. now-24h")
+                                .default_value("now-7d")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .short('o')
+                                .help("file to save the dashboard JSON to, defaults to grafana-dashboard.json")
+                                .takes_value(true),
+                        )
+                        .arg(config_path_arg().help("the TOML config files with accounts, merged in order; defaults to $CW_DL_CONFIG or ~/.config/cloudwatch-metrics-dl/accounts.toml; account namespaces, regions and IDs become dashboard variables")),
                 ),
         )
+        .subcommand(
+            Command::new("logs-insights")
+                .about("run a CloudWatch Logs Insights query for all accounts")
+                .args(session_args())
+                .arg(var_arg())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("start-time")
+                        .short('s')
+                        .default_value("24H")
+                        .long("start-time")
+                        .alias("start")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("end-time")
+                        .short('e')
+                        .default_value("0H")
+                        .long("end-time")
+                        .alias("end")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("log-group")
+                        .long("log-group")
+                        .short('g')
+                        .help("log group to query, may use {{NAMESPACE}} and {{REGION}}; repeat for more groups")
+                        .required(true)
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .short('l')
+                        .help("maximum number of rows returned per account")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format for the query results")
+                        .possible_values(["json", "csv"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("output-path")
+                        .long("output-path")
+                        .short('o')
+                        .help("file to save the results to, defaults to logs-insights.<ext>")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("query-path")
+                        .required(true)
+                        .help("the path to the Logs Insights query file"),
+                )
+                .arg(config_path_arg()),
+        )
         .subcommand(
             Command::new("images")
                 .about("download metric widget images from CloudWatch")
+                .args(session_args())
+                .args(slack_args())
+                .arg(var_arg())
+                .arg(concurrency_arg())
                 .arg(
-                    Arg::new("region")
-                        .help("AWS region (e.g. us-east-1, eu-west-1)")
-                        .long("region")
-                        .short('r')
+                    Arg::new("start-time")
+                        .short('s')
+                        .default_value("4320H")
+                        .long("start-time")
+                        .alias("start")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("end-time")
+                        .short('e')
+                        .default_value("0H")
+                        .long("end-time")
+                        .alias("end")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("period")
+                        .short('p')
+                        .long("period")
+                        .help("period of the graphs in seconds, raised to a multiple of what CloudWatch keeps for the start time")
+                        .validator(parse_period)
+                        .default_value("3600")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("title")
+                        .long("title")
+                        .help("title to identify the image downloaded")
+                        .default_value("metric")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("report")
+                        .long("report")
+                        .help("also write a report of the saved images next to them, the markdown report includes an alarms summary")
+                        .possible_values(["html", "markdown"])
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::new("email-to")
+                        .long("email-to")
+                        .help("email the report and its images to these addresses with SES once the run is done")
+                        .requires_all(&["report", "email-from"])
+                        .conflicts_with("watch")
+                        .multiple_occurrences(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("email-from")
+                        .long("email-from")
+                        .help("sender of the report email, an address or domain verified in SES")
+                        .requires("email-to")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("email-images")
+                        .long("email-images")
+                        .help("show the images in the body of an HTML report, or attach them; markdown reports always attach them")
+                        .possible_values(["inline", "attach"])
+                        .default_value("inline")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("watch")
+                        .long("watch")
+                        .help("keep running and refresh the images every interval (e.g. 15m), overwriting them in place")
+                        .validator(parse_interval)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("name-template")
+                        .long("name-template")
+                        .help("image file names without the extension, with {namespace}, {title}, {region}, {account_id}, {start}, {end}, {date} and {timestamp} placeholders")
+                        .validator(parse_name_template)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("overwrite")
+                        .long("overwrite")
+                        .conflicts_with("skip-existing")
+                        .help("replace images that already exist"),
+                )
+                .arg(
+                    Arg::new("skip-existing")
+                        .long("skip-existing")
+                        .help("keep images that already exist instead of downloading them again"),
+                )
+                .arg(
+                    Arg::new("width")
+                        .long("width")
+                        .help("image width in pixels, replacing the template's")
+                        .validator(parse_image_size)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("height")
+                        .long("height")
+                        .help("image height in pixels, replacing the template's")
+                        .validator(parse_image_size)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("image-format")
+                        .long("image-format")
+                        .help("image format to download, SVG scales better in HTML reports")
+                        .possible_values(["png", "svg"])
+                        .default_value("png")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("inline")
+                        .long("inline")
+                        .help("also show each downloaded PNG in the terminal, when it supports the iTerm2 or kitty image protocol"),
+                )
+                .arg(
+                    Arg::new("alarm-thresholds")
+                        .long("alarm-thresholds")
+                        .help("draw a line at the threshold of each alarm on a graphed metric"),
+                )
+                .arg(
+                    Arg::new("theme")
+                        .long("theme")
+                        .help("color theme of the images, replacing the template's")
+                        .possible_values(["dark", "light"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("timezone")
+                        .long("timezone")
+                        .help("draw graphs in this timezone instead of UTC, an offset like +0900 or a name like Asia/Tokyo")
+                        .validator(parse_timezone)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("template-path")
+                        .help("widget JSON template, or a directory whose *.json templates are each downloaded and titled after their file name; defaults to the config's [defaults] template_path"),
+                )
+                .arg(config_path_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("output-path")
+                        .required(false)
+                        .long("output-path")
+                        .short('o')
+                        .help("directory to save images in, created if it does not exist")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            Command::new("data")
+                .about("export metric datapoints from CloudWatch with GetMetricData")
+                .args(session_args())
+                .arg(var_arg())
+                .arg(concurrency_arg())
                 .arg(
                     Arg::new("start-time")
                         .short('s')
                         .default_value("4320H")
                         .long("start-time")
                         .alias("start")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
                         .takes_value(true),
                 )
                 .arg(
                     Arg::new("end-time")
                         .short('e')
                         .default_value("0H")
+                        .long("end-time")
                         .alias("end")
+                        .help("time before now like 90m, 36h, 3d or 2w (bare numbers are hours), or an RFC3339 timestamp like 2024-01-01T00:00:00Z")
+                        .validator(parse_time)
                         .takes_value(true),
                 )
                 .arg(
                     Arg::new("period")
                         .short('p')
-                        .default_value("3600")
                         .long("period")
+                        .help("period of the datapoints in seconds, raised to a multiple of what CloudWatch keeps for the start time")
+                        .validator(parse_period)
+                        .default_value("3600")
                         .takes_value(true),
                 )
                 .arg(
                     Arg::new("title")
                         .long("title")
-                        .help("title to identify the image downloaded")
+                        .help("title to identify the exported data file")
                         .default_value("metric")
                         .takes_value(true),
                 )
-                .arg(Arg::new("template-path").required(true))
-                .arg(
-                    Arg::new("config-path")
-                        .required(true)
-                        .help("the path to the TOML config file with accounts"),
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("output format for the exported datapoints, prometheus writes OpenMetrics text, influx writes line protocol and xlsx writes a review workbook with metric summaries and alarms")
+                        .possible_values(["json", "csv", "parquet", "prometheus", "influx", "xlsx"])
+                        .default_value("json")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("layout")
+                        .long("layout")
+                        .help("CSV layout: one row per timestamp (wide) or per datapoint (long)")
+                        .possible_values(["wide", "long"])
+                        .default_value("wide")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("summary")
+                        .long("summary")
+                        .help("also print the min, max, mean, p95, p99 and latest value of every series as a table"),
+                )
+                .arg(
+                    Arg::new("xlsx-sheets")
+                        .long("xlsx-sheets")
+                        .help("with --format xlsx, whether the workbook has a sheet per account or per CloudWatch namespace")
+                        .possible_values(["account", "namespace"])
+                        .default_value("account")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("otlp-endpoint")
+                        .long("otlp-endpoint")
+                        .help("also push the datapoints to this OpenTelemetry collector, e.g. http://localhost:4317")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("otlp-protocol")
+                        .long("otlp-protocol")
+                        .help("send to the OTLP endpoint over gRPC or protobuf over HTTP")
+                        .possible_values(["grpc", "http"])
+                        .default_value("grpc")
+                        .requires("otlp-endpoint")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("plot")
+                        .long("plot")
+                        .help("also print every series as a sparkline, --plot=ascii for terminals without unicode")
+                        .possible_values(["unicode", "ascii"])
+                        .min_values(0)
+                        .require_equals(true)
+                        .default_missing_value("unicode")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("plot-width")
+                        .long("plot-width")
+                        .help("number of characters in each sparkline")
+                        .default_value("60")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("sqlite")
+                        .long("sqlite")
+                        .help("also upsert the datapoints into this SQLite database, created if it does not exist")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("incremental")
+                        .long("incremental")
+                        .requires("sqlite")
+                        .help("only request datapoints newer than the latest ones already in the SQLite database"),
+                )
+                .arg(
+                    Arg::new("spec-path")
+                        .required(true)
+                        .help("the path to the JSON metric query spec"),
+                )
+                .arg(config_path_arg())
+                .args(filter_args()),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("validate and display the config file for your accounts")
+                .arg(config_path_arg())
+                .args(filter_args())
+                .arg(
+                    Arg::new("strict")
+                        .long("strict")
+                        .help("fail on unknown keys in the config instead of warning about them"),
+                )
+                .subcommand(
+                    Command::new("init")
+                        .about("write a new accounts config by answering prompts, or from a pasted list of account IDs")
+                        .arg(
+                            Arg::new("from-ids")
+                                .long("from-ids")
+                                .requires_all(&["role-name", "region"])
+                                .help("read lines of `ACCOUNT_ID [NAMESPACE]` from stdin instead of prompting"),
+                        )
+                        .arg(
+                            Arg::new("role-name")
+                                .long("role-name")
+                                .help("role to assume in every account, used to build the role ARNs")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("region")
+                                .long("region")
+                                .short('r')
+                                .help("region of every account, or the default offered when prompting")
+                                .validator(aws_regions::parse_region)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .help("overwrite the output file if it already exists"),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .default_value("accounts.toml")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    Command::new("discover")
+                        .about("generate [[account]] entries for every active account in your AWS Organization")
+                        .arg(
+                            Arg::new("role-name")
+                                .long("role-name")
+                                .required(true)
+                                .help("role to assume in every account, e.g. MetricsReadOnly")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("region")
+                                .long("region")
+                                .short('r')
+                                .help("region of the generated accounts")
+                                .default_value("us-east-1")
+                                .validator(aws_regions::parse_region)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::new("output-path")
+                                .long("output-path")
+                                .short('o')
+                                .help("accounts file to add new accounts to, created if needed; prints the entries when omitted")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("check the credentials in use and that every account's role can be assumed and read CloudWatch")
+                .args(session_args())
+                .arg(concurrency_arg())
+                .args(filter_args())
+                .arg(config_path_arg()),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("show metrics for an account")
+                .arg(
+                    Arg::new("region")
+                        .help("AWS region (e.g. us-east-1, eu-west-1)")
+                        .long("region")
+                        .short('r')
+                        .default_value("us-west-2")
+                        .validator(aws_regions::parse_region)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .long("limit")
+                        .short('l')
+                        .help("stop after printing this many metrics")
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+    init_logging(&matches);
+
+    let result = run(&matches).await;
+    if let Some(topic_arn) = matches
+        .value_of("notify-sns")
+        .filter(|_| !matches.is_present("dry-run"))
+    {
+        notify_sns(
+            topic_arn,
+            retry_policy(&matches),
+            matches.subcommand_name().unwrap_or_default(),
+            &result,
+            matches.value_of("notify-sns-on") == Some("failure"),
+        )
+        .await;
+    }
+    match result {
+        Ok(summary) => {
+            print!("{}", summary);
+            summary.exit_code()
+        }
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(EXIT_FATAL)
+        }
+    }
+}
+
+async fn run(matches: &ArgMatches) -> Result<RunSummary, Error> {
+    RUN_STARTED.get_or_init(Instant::now);
+    CREDENTIAL_CACHE.get_or_init(|| {
+        Arc::new(
+            CredentialCache::default().mfa_code(matches.value_of("mfa-code").map(String::from)),
+        )
+    });
+    // before any client is built, so every one of them starts from the profile
+    set_base_credentials(BaseCredentials {
+        profile: matches.value_of("profile").map(String::from),
+        sso_login: matches.is_present("sso-login"),
+    });
+    let mut summary = RunSummary::default();
+    let retry = retry_policy(matches);
+    if matches.is_present("dry-run") && !supports_dry_run(matches) {
+        return Err(Error::DryRunUnsupported(String::from(
+            matches.subcommand_name().unwrap_or_default(),
+        )));
+    }
+
+    match matches.subcommand() {
+        Some(("images", images)) => {
+            let start = images.value_of("start-time").unwrap();
+            let end = images.value_of("end-time").unwrap();
+            let period = images.value_of("period").unwrap();
+            let title = images.value_of("title").unwrap();
+            let output_path = images.value_of("output-path");
+            let watch = images.value_of("watch").map(|interval| {
+                parse_interval(interval).expect("interval is checked by the validator")
+            });
+            let timezone = images
+                .value_of("timezone")
+                .map(|tz| parse_timezone(tz).expect("timezone is checked by the validator"));
+            let size = |name| {
+                images
+                    .value_of(name)
+                    .map(|size| parse_image_size(size).expect("size is checked by the validator"))
+            };
+            let config = load_config(images)?;
+            let template_path = config
+                .template_path(images.value_of("template-path").map(Path::new))
+                .ok_or(Error::NoTemplate)?;
+            summary.inputs(template_path.to_str(), start, end);
+            let output_path = output_path
+                .map(PathBuf::from)
+                .or_else(|| config.defaults.output_path.clone());
+            let mut accounts = config.select(&account_filter(images));
+            summary.slack = slack_notifier(images)?;
+            cli_overrides(images, &["start-time", "end-time", "period"], &mut accounts);
+
+            let downloader = MetricsDownloader::new(template_path)
+                .vars(template_vars(images))
+                .title(title)
+                .start(start)
+                .end(end)
+                .period(period)
+                .output_path(output_path)
+                .name_template(
+                    images
+                        .value_of("name-template")
+                        .unwrap_or(if watch.is_some() {
+                            STABLE_NAME_TEMPLATE
+                        } else {
+                            DEFAULT_NAME_TEMPLATE
+                        }),
+                )
+                .existing(if images.is_present("skip-existing") {
+                    ExistingImages::Skip
+                } else if images.is_present("overwrite") || watch.is_some() {
+                    ExistingImages::Overwrite
+                } else {
+                    ExistingImages::Fail
+                })
+                .timezone(timezone)
+                .width(size("width"))
+                .height(size("height"))
+                .theme(images.value_of("theme").map(String::from))
+                .alarm_thresholds(images.is_present("alarm-thresholds"))
+                .format(match images.value_of("image-format") {
+                    Some("svg") => ImageFormat::Svg,
+                    _ => ImageFormat::Png,
+                })
+                .options(run_options(images, &retry));
+            // an unreadable or broken template would fail every account alike
+            downloader.check_templates()?;
+            let inline = images
+                .is_present("inline")
+                .then(ImageProtocol::detect)
+                .flatten();
+            if images.is_present("inline") && inline.is_none() {
+                warn!("this terminal doesn't support the iTerm2 or kitty image protocols, not showing images inline");
+            }
+            let outputs = RoundOutputs {
+                report: images.value_of("report").map(|report| match report {
+                    "markdown" => ReportFormat::Markdown,
+                    _ => ReportFormat::Html,
+                }),
+                email: images.values_of("email-to").map(|to| ReportEmail {
+                    from: String::from(images.value_of("email-from").unwrap()),
+                    to: to.map(String::from).collect(),
+                    images: match images.value_of("email-images") {
+                        Some("attach") => EmailImages::Attach,
+                        _ => EmailImages::Inline,
+                    },
+                }),
+            };
+            match watch {
+                _ if images.is_present("dry-run") => {
+                    let mut planned = vec![];
+                    for acc in &accounts {
+                        let account = acc.label();
+                        let res = downloader.plan(acc);
+                        summary.record(&account, &res);
+                        match res {
+                            Ok(calls) => planned.extend(calls),
+                            Err(e) => warn!(
+                                account = %account,
+                                error = %e,
+                                "failed to render template"
+                            ),
+                        }
+                    }
+                    print_plan(&planned);
+                    return Ok(summary);
+                }
+                None => {
+                    let outcome = downloader
+                        .download_round(&accounts, &outputs, &mut summary)
+                        .await?;
+                    print_round(&outcome, inline);
+                }
+                Some(interval) => {
+                    info!(
+                        "refreshing images every {:?}, press Ctrl-C to stop",
+                        interval
+                    );
+                    let mut ticker = tokio::time::interval(interval);
+                    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    loop {
+                        tokio::select! {
+                            _ = ticker.tick() => {}
+                            _ = tokio::signal::ctrl_c() => break,
+                        }
+                        // only the latest round counts towards the summary and upload
+                        summary.next_round();
+                        // Ctrl-C during a round lets it finish its images and report first
+                        let outcome = downloader
+                            .download_round(&accounts, &outputs, &mut summary)
+                            .await?;
+                        print_round(&outcome, inline);
+                        let deadline = run_options(images, &retry).deadline;
+                        if summary.interrupted > 0
+                            || deadline.is_some_and(|deadline| deadline <= Instant::now())
+                        {
+                            break;
+                        }
+                    }
+                    info!("stopped watching");
+                }
+            }
+        }
+        Some(("data", data)) => {
+            let start = data.value_of("start-time").unwrap();
+            let end = data.value_of("end-time").unwrap();
+            let spec_path = data.value_of("spec-path").unwrap();
+            summary.inputs(Some(spec_path), start, end);
+            let period = data.value_of("period").unwrap();
+            let title = data.value_of("title").unwrap();
+            let format = data.value_of("format").unwrap();
+            let layout = data.value_of("layout").unwrap();
+            let mut accounts = load_accounts(data)?;
+            cli_overrides(data, &["start-time", "end-time", "period"], &mut accounts);
+            if data.is_present("dry-run") {
+                let exporter = MetricDataExporter::new(spec_path)
+                    .vars(template_vars(data))
+                    .start(start)
+                    .end(end)
+                    .period(period)
+                    .options(run_options(data, &retry));
+                exporter.check_spec()?;
+                let mut planned = vec![];
+                for acc in &accounts {
+                    let account = acc.label();
+                    let res = exporter.plan(acc);
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(calls) => planned.extend(calls),
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to render query spec")
+                        }
+                    }
+                }
+                print_plan(&planned);
+                return Ok(summary);
+            }
+            let mut all_data: Vec<AccountMetricData> = vec![];
+            let mut store = data.value_of("sqlite").map(MetricStore::open).transpose()?;
+            let watermarks = match &store {
+                Some(store) if data.is_present("incremental") => store.watermarks()?,
+                _ => Watermarks::new(),
+            };
+
+            let exporter = MetricDataExporter::new(spec_path)
+                .vars(template_vars(data))
+                .start(start)
+                .end(end)
+                .period(period)
+                .watermarks(watermarks)
+                .options(run_options(data, &retry));
+            exporter.check_spec()?;
+            for (account, res) in exporter.run(accounts.clone()).await {
+                summary.record(&account, &res);
+                match res {
+                    Ok(res) => {
+                        info!(account = %account, "successful query");
+                        all_data.push(res);
+                    }
+                    Err(e) => warn!(account = %account, error = %e, "failed to get metric data"),
+                };
+            }
+            let extension = match format {
+                "prometheus" => "om",
+                "influx" => "lp",
+                _ => format,
+            };
+            let path = Path::new(&format!("{}-data", title)).with_extension(extension);
+            let contents = match format {
+                "csv" => metric_data_to_csv(&all_data, layout == "wide")?,
+                "parquet" => metric_data_to_parquet(&all_data)?,
+                "prometheus" => metric_data_to_prometheus(&all_data).into_bytes(),
+                "influx" => metric_data_to_influx(&all_data).into_bytes(),
+                "xlsx" => {
+                    let grouping = match data.value_of("xlsx-sheets").unwrap() {
+                        "namespace" => SheetGrouping::Namespace,
+                        _ => SheetGrouping::Account,
+                    };
+                    let exporter = AlarmExporter::new().options(run_options(data, &retry));
+                    let mut alarms = vec![];
+                    for (account, res) in exporter.run(accounts).await {
+                        summary.record_again(&account, &res);
+                        match res {
+                            Ok(res) => alarms.extend(res),
+                            Err(e) => {
+                                warn!(account = %account, error = %e, "failed to describe alarms")
+                            }
+                        }
+                    }
+                    review_to_xlsx(&all_data, &alarms, grouping)?
+                }
+                _ => serde_json::to_vec(&all_data).expect("metric data serializes to JSON"),
+            };
+            write_output(&path, contents).await?;
+            summary.output(&path);
+            println!("saved metric data");
+            if let Some(endpoint) = data.value_of("otlp-endpoint") {
+                let protocol = match data.value_of("otlp-protocol").unwrap() {
+                    "http" => OtlpProtocol::Http,
+                    _ => OtlpProtocol::Grpc,
+                };
+                let sent = OtlpExporter::new(endpoint)
+                    .protocol(protocol)
+                    .export(&all_data)
+                    .await?;
+                println!("pushed {} datapoints to {}", sent, endpoint);
+            }
+            if data.is_present("summary") {
+                print!("{}", metric_summary_to_table(&all_data));
+            }
+            if let Some(plot) = data.value_of("plot") {
+                let style = match plot {
+                    "ascii" => PlotStyle::Ascii,
+                    _ => PlotStyle::Unicode,
+                };
+                let width = data.value_of_t("plot-width").unwrap_or_else(|e| e.exit());
+                print!("{}", metric_data_to_sparklines(&all_data, width, style));
+            }
+            if let Some(store) = store.as_mut() {
+                for acc in &all_data {
+                    let written = store.save(acc)?;
+                    println!(
+                        "stored {} datapoints for {} ({})",
+                        written, acc.program_name, acc.region
+                    );
+                }
+            }
+        }
+        Some(("show", show_matches)) => {
+            debug!(?show_matches, "show");
+
+            let limit: Option<usize> = show_matches.is_present("limit").then(|| {
+                show_matches
+                    .value_of_t("limit")
+                    .unwrap_or_else(|e| e.exit())
+            });
+
+            let region = aws_regions::parse_region(show_matches.value_of("region").unwrap())
+                .map_err(Error::Region)?;
+            let client = get_cw_client(&region).await;
+            show_metrics(&client, limit).await?;
+        }
+        Some(("alarms", alarm_matches)) => match alarm_matches.subcommand() {
+            Some(("diff", diff)) => diff_alarm_accounts(diff, &retry, &mut summary).await?,
+            Some(("for-metric", for_metric)) => {
+                let namespace = for_metric.value_of("namespace").unwrap();
+                let metric_name = for_metric.value_of("metric-name").unwrap();
+                let dimensions = for_metric
+                    .values_of("dimension")
+                    .into_iter()
+                    .flatten()
+                    .map(|dimension| {
+                        parse_var(dimension).expect("dimension is checked by the validator")
+                    })
+                    .collect();
+                let accounts = load_accounts(for_metric)?;
+
+                let lookup = MetricAlarmLookup::new(namespace, metric_name, dimensions)
+                    .options(run_options(for_metric, &retry));
+                let mut all_accounts = vec![];
+                for (account, res) in lookup.run(accounts).await {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(res) => {
+                            info!(account = %account, alarms = res.alarm_names.len(), "successful query");
+                            all_accounts.push(res);
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to describe alarms for metric")
+                        }
+                    }
+                }
+                print!("{}", metric_alarms_to_table(&all_accounts));
+                println!(
+                    "{} of {} accounts have an alarm on {} {}",
+                    all_accounts
+                        .iter()
+                        .filter(|acc| !acc.alarm_names.is_empty())
+                        .count(),
+                    all_accounts.len(),
+                    namespace,
+                    metric_name
+                );
+            }
+            Some(("apply", apply)) => {
+                let accounts = load_accounts(apply)?;
+                let applier = AlarmApplier::new(apply.value_of("definitions-path").unwrap())
+                    .vars(template_vars(apply))
+                    .dry_run(apply.is_present("dry-run"))
+                    .options(run_options(apply, &retry));
+                let mut all_applied = vec![];
+                for (account, res) in applier.run(accounts).await {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(res) => {
+                            info!(account = %account, alarms = res.len(), "applied alarms");
+                            all_applied.extend(res);
+                        }
+                        Err(e) => warn!(account = %account, error = %e, "failed to apply alarms"),
+                    }
+                }
+                print!("{}", applied_to_table(&all_applied));
+                let count = |outcome| all_applied.iter().filter(|a| a.outcome == outcome).count();
+                println!(
+                    "{}{} created, {} updated, {} unchanged",
+                    if apply.is_present("dry-run") {
+                        "dry run: "
+                    } else {
+                        ""
+                    },
+                    count(ApplyOutcome::Created),
+                    count(ApplyOutcome::Updated),
+                    count(ApplyOutcome::Unchanged)
+                );
+            }
+            Some(("export", export)) => {
+                let format = match export.value_of("format").unwrap() {
+                    "cloudformation" => IacFormat::CloudFormation,
+                    _ => IacFormat::Terraform,
+                };
+                let output_dir = PathBuf::from(export.value_of("output-path").unwrap_or_default());
+                let accounts = load_accounts(export)?;
+
+                let exporter = alarm_exporter(export, &retry);
+                let results = exporter.run(accounts.clone()).await;
+                for (acc, (account, res)) in accounts.iter().zip(results) {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(alarms) => {
+                            let path = output_dir.join(format!(
+                                "{}-{}-alarms.{}",
+                                acc.namespace,
+                                acc.region,
+                                format.extension()
+                            ));
+                            write_output(&path, format.render(&alarms)).await?;
+                            summary.account_output(&path, acc);
+                            println!(
+                                "saved {} alarms for {} to {}",
+                                alarms.len(),
+                                account,
+                                path.display()
+                            );
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to describe alarms")
+                        }
+                    }
+                }
+            }
+            Some(("set-state", set_state)) => {
+                let alarm_name = set_state.value_of("alarm-name").unwrap();
+                let state = set_state.value_of("state").unwrap();
+                let accounts = load_accounts(set_state)?;
+                if !set_state.is_present("yes") {
+                    for acc in &accounts {
+                        eprintln!("  {}", acc.label());
+                    }
+                    let question = format!(
+                        "set {} to {} in these {} accounts? [y/N]",
+                        alarm_name,
+                        state,
+                        accounts.len()
+                    );
+                    let answer = prompt(&question, None).map_err(|source| Error::Read {
+                        path: PathBuf::from("<stdin>"),
+                        source,
+                    })?;
+                    if !answer.eq_ignore_ascii_case("y") {
+                        println!("cancelled, no alarm state was changed");
+                        return Ok(summary);
+                    }
+                }
+
+                let setter = AlarmStateSetter::new(alarm_name, StateValue::from(state))
+                    .reason(set_state.value_of("reason").unwrap())
+                    .options(run_options(set_state, &retry));
+                for (account, res) in setter.run(accounts).await {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(name) => println!("set {} to {} for {}", name, state, account),
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to set alarm state")
+                        }
+                    }
+                }
+            }
+            Some(("wait", wait)) => {
+                let timeout = parse_interval(wait.value_of("timeout").unwrap())
+                    .expect("timeout is checked by the validator");
+                let interval = parse_interval(wait.value_of("interval").unwrap())
+                    .expect("interval is checked by the validator");
+                let accounts = load_accounts(wait)?;
+                let alarm_regex = wait
+                    .value_of("alarm-regex")
+                    .map(|re| Regex::new(re).expect("regex is checked by the validator"));
+                let exporter = AlarmExporter::new()
+                    .alarm_name_prefix(wait.value_of("alarm-prefix").map(String::from))
+                    .alarm_regex(alarm_regex)
+                    .options(run_options(wait, &retry));
+                let state = wait.value_of("state").unwrap();
+                let matched = wait_for_alarm_state(
+                    || exporter.run(accounts.clone()),
+                    state,
+                    interval,
+                    timeout,
+                    &mut summary,
+                )
+                .await?;
+                println!("all {} matching alarms are {}", matched, state);
+            }
+            Some(("watch", watch)) => {
+                let interval = parse_interval(watch.value_of("interval").unwrap())
+                    .expect("interval is checked by the validator");
+                let accounts = load_accounts(watch)?;
+                let exporter = alarm_exporter(watch, &retry);
+                let color =
+                    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+                watch_alarms(
+                    || exporter.run(accounts.clone()),
+                    interval,
+                    &mut summary,
+                    |refresh| {
+                        if color {
+                            // clear the screen and move to the top left so each refresh replaces the last
+                            print!("\x1b[2J\x1b[H");
+                        }
+                        println!(
+                            "{} ALARM, {} OK, {} INSUFFICIENT_DATA across {} accounts, refreshing every {:?}, Ctrl-C to stop\n",
+                            refresh.count("ALARM"),
+                            refresh.count("OK"),
+                            refresh.count("INSUFFICIENT_DATA"),
+                            accounts.len(),
+                            interval
+                        );
+                        print!("{}", alarm_states_to_table(&refresh.alarms, color));
+                        for failure in &refresh.failed {
+                            println!("failed to describe alarms for {}", failure);
+                        }
+                    },
                 )
-                .arg(
-                    Arg::new("pattern")
-                        .long("pattern")
-                        .takes_value(true)
-                        .short('f'),
+                .await?
+            }
+            Some(("drift", drift)) => {
+                let (path, changes) = write_drift(
+                    Path::new(drift.value_of("snapshot-dir").unwrap()),
+                    drift.value_of("format").unwrap(),
+                    drift.value_of("output-path").map(Path::new),
                 )
-                .arg(
-                    Arg::new("output-path")
-                        .required(false)
-                        .long("output-path")
-                        .short('o'),
-                ),
-        )
-        .subcommand(
-            Command::new("config")
-                .about("validate and display the config file for your accounts")
-                .arg(Arg::new("config-path").required(true))
-                .arg(
-                    Arg::new("pattern")
-                        .long("pattern")
-                        .takes_value(true)
-                        .short('f'),
-                ),
-        )
-        .subcommand(Command::new("show").about("show metrics for an account"))
-        .get_matches();
+                .await?;
+                summary.output(&path);
+                println!("saved {} alarm changes to {}", changes, path.display());
+            }
+            _ => {
+                let format = alarm_matches.value_of("format").unwrap();
+                let output_path = alarm_matches.value_of("output-path");
+                let accounts = load_accounts(alarm_matches)?;
+                summary.slack = slack_notifier(alarm_matches)?;
+                let mut all_metrics: Vec<MetricAlarmDetails> = vec![];
+                let mut snapshot = AlarmSnapshot::new();
 
-    match matches.subcommand() {
-        Some(("images", images)) => {
-            let start = images.value_of("start-time").unwrap();
-            let end = images.value_of("end-time").unwrap();
-            let template_path = images.value_of("template-path").unwrap();
-            let period = images.value_of("period").unwrap();
-            let title = images.value_of("title").unwrap();
-            let config_path = images.value_of("config-path").unwrap();
-            let pattern = images.value_of("pattern");
-            let accounts = get_accounts(config_path, true);
-            let accounts = filter_accounts(pattern, accounts);
-
-            for acc in accounts {
-                let props = GetWidgetProps {
-                    title: String::from(title),
-                    region: Some(acc.region),
-                    app_name: acc.namespace,
-                    role_arn: acc.role_arn,
-                    template_path: PathBuf::from(template_path),
-                    start: String::from(start),
-                    end: String::from(end),
-                    period: String::from(period),
-                    verbose: true,
+                let exporter = alarm_exporter(alarm_matches, &retry)
+                    .resolve_actions(alarm_matches.is_present("resolve-actions"));
+                if alarm_matches.is_present("dry-run") {
+                    let planned: Vec<_> =
+                        accounts.iter().flat_map(|acc| exporter.plan(acc)).collect();
+                    print_plan(&planned);
+                    return Ok(summary);
+                }
+                for (account, res) in exporter.run(accounts).await {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(res) => {
+                            info!(account = %account, alarms = res.len(), "successful query");
+                            snapshot.accounts.insert(account, res.clone());
+                            all_metrics.extend(res);
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to describe alarms")
+                        }
+                    }
+                }
+                let (extension, contents) = match format {
+                    "csv" => ("csv", alarms_to_csv(&all_metrics)?),
+                    "table" => ("txt", alarms_to_table(&all_metrics).into_bytes()),
+                    "markdown" => ("md", alarms_to_markdown(&all_metrics).into_bytes()),
+                    _ => (
+                        "json",
+                        serde_json::to_vec(&all_metrics).expect("alarms serialize to JSON"),
+                    ),
                 };
-                match cloudwatch_image_download(props).await {
-                    Ok(_) => println!("successful query"),
-                    Err(e) => println!("cloudwatch download error: {:?}", e),
+                let path = match output_path {
+                    Some(path) => PathBuf::from(path),
+                    None => Path::new("describe-alarms").with_extension(extension),
                 };
+                write_output(&path, contents).await?;
+                summary.output(&path);
+                println!("saved metrics to {}", path.display());
+                if let Some(dir) = alarm_matches.value_of("snapshot-dir") {
+                    let path = snapshot.save(Path::new(dir)).await?;
+                    summary.output(&path);
+                    println!("saved snapshot to {}", path.display());
+                }
+            }
+        },
+        Some(("anomaly-detectors", detectors)) => {
+            let format = detectors.value_of("format").unwrap();
+            let output_path = detectors.value_of("output-path");
+            let accounts = load_accounts(detectors)?;
+            let mut all_detectors = vec![];
+
+            let exporter = AnomalyDetectorExporter::new().options(run_options(detectors, &retry));
+            for (account, res) in exporter.run(accounts).await {
+                summary.record(&account, &res);
+                match res {
+                    Ok(res) => {
+                        info!(account = %account, detectors = res.len(), "successful query");
+                        all_detectors.extend(res);
+                    }
+                    Err(e) => {
+                        warn!(account = %account, error = %e, "failed to describe anomaly detectors")
+                    }
+                }
             }
+            let (extension, contents) = match format {
+                "csv" => ("csv", detectors_to_csv(&all_detectors)?),
+                _ => (
+                    "json",
+                    serde_json::to_vec(&all_detectors)
+                        .expect("anomaly detectors serialize to JSON"),
+                ),
+            };
+            let path = match output_path {
+                Some(path) => PathBuf::from(path),
+                None => Path::new("anomaly-detectors").with_extension(extension),
+            };
+            write_output(&path, contents).await?;
+            summary.output(&path);
+            println!(
+                "saved {} anomaly detectors to {}",
+                all_detectors.len(),
+                path.display()
+            );
         }
-        Some(("show", show_matches)) => {
-            println!("show: {:?}", show_matches);
+        Some(("tui", tui)) => {
+            let accounts = load_accounts(tui)?;
+            Browser::new(accounts)
+                .output_path(tui.value_of("output-path").map(PathBuf::from))
+                .options(run_options(tui, &retry))
+                .run()
+                .await?;
+        }
+        Some(("serve", serve)) => {
+            let bind = serve.value_of("bind").unwrap();
+            let ip: IpAddr = bind.parse().map_err(|_| Error::InvalidArgument {
+                name: "bind address",
+                value: String::from(bind),
+            })?;
+            let port: u16 = serve.value_of_t("port").unwrap_or_else(|e| e.exit());
+            let address = SocketAddr::new(ip, port);
+            let refresh = serve.value_of("refresh").map(|interval| {
+                parse_interval(interval).expect("interval is checked by the validator")
+            });
+            let server = GalleryServer::new(serve.value_of("dir").unwrap())
+                .address(address)
+                .refresh(refresh)
+                .basic_auth(serve.value_of("basic-auth").map(String::from));
+            println!("serving on http://{}, press Ctrl-C to stop", address);
+            server.run().await?;
+        }
+        Some(("schedule", schedule)) => {
+            let config = ScheduleConfig::from_path(schedule.value_of("schedule-path").unwrap())?;
+            let scheduler =
+                Scheduler::new(config)?.state_dir(schedule.value_of("state-dir").unwrap());
+            if schedule.is_present("list") {
+                for (job, runs) in scheduler.upcoming(3) {
+                    let runs: Vec<String> = runs.iter().map(|at| at.to_rfc3339()).collect();
+                    println!("{}\t{}\t{}", job.name, job.cron, runs.join(", "));
+                }
+                return Ok(summary);
+            }
+            println!("running schedule, press Ctrl-C to stop");
+            scheduler.run().await?;
+        }
+        Some(("put", put)) => {
+            let path = Path::new(put.value_of("datapoints-path").unwrap());
+            let format = match put.value_of("format") {
+                Some("json") => DatapointFormat::Json,
+                Some(_) => DatapointFormat::Csv,
+                None => DatapointFormat::from_path(path),
+            };
+            let datapoints = Datapoint::from_path(path, format)?;
+            let accounts = load_accounts(put)?;
 
-            let client = get_cw_client("us-west-2", true).await;
-            let res = show_metrics(&client).await;
-            if res.is_err() {
-                println!("encountered error getting metrics: {:?}", res.err());
+            let publisher =
+                MetricDataPublisher::new(put.value_of("namespace").unwrap(), datapoints)
+                    .vars(template_vars(put))
+                    .options(run_options(put, &retry));
+            for (account, res) in publisher.run(accounts).await {
+                summary.record(&account, &res);
+                match res {
+                    Ok(published) => {
+                        info!(account = %account, datapoints = published, "published metric data");
+                        println!("published {} datapoints to {}", published, account);
+                    }
+                    Err(e) => {
+                        warn!(account = %account, error = %e, "failed to publish metric data")
+                    }
+                }
             }
         }
-        Some(("alarms", alarm_matches)) => {
-            let pattern = alarm_matches.value_of("pattern");
-            let config_path = alarm_matches.value_of("config-path").unwrap();
-            let accounts = get_accounts(config_path, true);
-            let accounts = filter_accounts(pattern, accounts);
-            let mut all_metrics: Vec<MetricAlarmDetails> = vec![];
-            for acc in accounts {
-                println!("account: {:?}", acc);
-                let props = DescribeAlarmsProps {
-                    region: Some(acc.region),
-                    role_arn: acc.role_arn,
-                    verbose: true,
+        Some(("metric-streams", streams)) => match streams.subcommand() {
+            Some(("list", list)) => {
+                let format = list.value_of("format").unwrap();
+                let output_path = list.value_of("output-path");
+                let accounts = load_accounts(list)?;
+                let mut all_streams = vec![];
+
+                let exporter = MetricStreamExporter::new().options(run_options(list, &retry));
+                for (account, res) in exporter.run(accounts).await {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(res) => {
+                            info!(account = %account, streams = res.len(), "successful query");
+                            all_streams.extend(res);
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to list metric streams")
+                        }
+                    }
+                }
+                let (extension, contents) = match format {
+                    "csv" => ("csv", streams_to_csv(&all_streams)?),
+                    "table" => ("txt", streams_to_table(&all_streams).into_bytes()),
+                    _ => (
+                        "json",
+                        serde_json::to_vec(&all_streams).expect("metric streams serialize to JSON"),
+                    ),
                 };
-                match cloudwatch_describe_alarms(props).await {
+                let path = match output_path {
+                    Some(path) => PathBuf::from(path),
+                    None => Path::new("metric-streams").with_extension(extension),
+                };
+                write_output(&path, contents).await?;
+                summary.output(&path);
+                println!(
+                    "saved {} metric streams to {}",
+                    all_streams.len(),
+                    path.display()
+                );
+            }
+            _ => unreachable!(),
+        },
+        Some(("insights", insights)) => {
+            let format = insights.value_of("format").unwrap();
+            let output_path = insights.value_of("output-path");
+            let report = insights.is_present("report");
+            let accounts = load_accounts(insights)?;
+            let rule_regex = insights
+                .value_of("rule-regex")
+                .map(|re| Regex::new(re).expect("regex is checked by the validator"));
+            let exporter = InsightRulesExporter::new()
+                .report(report)
+                .start(insights.value_of("start-time").unwrap())
+                .end(insights.value_of("end-time").unwrap())
+                .period(insights.value_of_t("period").unwrap_or_else(|e| e.exit()))
+                .max_contributors(
+                    insights
+                        .value_of_t("max-contributors")
+                        .unwrap_or_else(|e| e.exit()),
+                )
+                .rule_regex(rule_regex)
+                .options(run_options(insights, &retry));
+
+            let mut all_insights = vec![];
+            for (account, res) in exporter.run(accounts).await {
+                summary.record(&account, &res);
+                match res {
+                    Ok(res) => {
+                        info!(account = %account, rules = res.rules.len(), contributors = res.contributors.len(), "successful query");
+                        all_insights.push(res);
+                    }
+                    Err(e) => {
+                        warn!(account = %account, error = %e, "failed to describe insight rules")
+                    }
+                }
+            }
+            let (extension, contents) = match format {
+                "csv" if report => {
+                    let contributors: Vec<_> = all_insights
+                        .iter()
+                        .flat_map(|acc| acc.contributors.iter().cloned())
+                        .collect();
+                    ("csv", contributors_to_csv(&contributors)?)
+                }
+                "csv" => {
+                    let rules: Vec<_> = all_insights
+                        .iter()
+                        .flat_map(|acc| acc.rules.iter().cloned())
+                        .collect();
+                    ("csv", rules_to_csv(&rules)?)
+                }
+                _ => (
+                    "json",
+                    serde_json::to_vec(&all_insights).expect("insight rules serialize to JSON"),
+                ),
+            };
+            let path = match output_path {
+                Some(path) => PathBuf::from(path),
+                None => Path::new("insight-rules").with_extension(extension),
+            };
+            write_output(&path, contents).await?;
+            summary.output(&path);
+            println!("saved insight rules to {}", path.display());
+        }
+        Some(("coverage", coverage)) => {
+            let format = coverage.value_of("format").unwrap();
+            let output_path = coverage.value_of("output-path");
+            let namespaces: Vec<String> = coverage
+                .values_of("namespace")
+                .map(|values| values.map(String::from).collect())
+                .unwrap_or_default();
+            let accounts = load_accounts(coverage)?;
+            let mut all_metrics: Vec<UnalarmedMetric> = vec![];
+
+            let exporter = CoverageExporter::new()
+                .namespaces(namespaces)
+                .options(run_options(coverage, &retry));
+            for (account, res) in exporter.run(accounts).await {
+                summary.record(&account, &res);
+                match res {
+                    Ok(res) => {
+                        info!(account = %account, unalarmed = res.len(), "successful query");
+                        all_metrics.extend(res);
+                    }
+                    Err(e) => {
+                        warn!(account = %account, error = %e, "failed to check alarm coverage")
+                    }
+                }
+            }
+            let (extension, contents) = match format {
+                "csv" => ("csv", coverage_to_csv(&all_metrics)?),
+                "table" => ("txt", coverage_to_table(&all_metrics).into_bytes()),
+                _ => (
+                    "json",
+                    serde_json::to_vec(&all_metrics).expect("metrics serialize to JSON"),
+                ),
+            };
+            let path = match output_path {
+                Some(path) => PathBuf::from(path),
+                None => Path::new("alarm-coverage").with_extension(extension),
+            };
+            write_output(&path, contents).await?;
+            summary.output(&path);
+            println!(
+                "saved {} metrics without alarms to {}",
+                all_metrics.len(),
+                path.display()
+            );
+        }
+        Some(("alarm-history", history)) => {
+            let start = history.value_of("start-time").unwrap();
+            let end = history.value_of("end-time").unwrap();
+            summary.inputs(None, start, end);
+            let format = history.value_of("format").unwrap();
+            let output_path = history.value_of("output-path");
+            let accounts = load_accounts(history)?;
+            let mut all_history: Vec<AlarmHistoryRecord> = vec![];
+
+            let alarm_name = history.value_of("alarm-name").map(String::from);
+            let alarm_regex = history
+                .value_of("alarm-regex")
+                .map(|re| Regex::new(re).expect("regex is checked by the validator"));
+            let history_type = history.value_of("history-type").map(HistoryItemType::from);
+            let exporter = AlarmHistoryExporter::new()
+                .start(start)
+                .end(end)
+                .alarm_name(alarm_name)
+                .alarm_regex(alarm_regex)
+                .history_item_type(history_type)
+                .options(run_options(history, &retry));
+            for (account, res) in exporter.run(accounts).await {
+                summary.record(&account, &res);
+                match res {
                     Ok(res) => {
-                        println!("successful query");
-                        for item in res {
-                            let comparison = match item.comparison_operator().unwrap() {
-                                ComparisonOperator::GreaterThanOrEqualToThreshold => {
-                                    "GreaterThanOrEqualToThreshold"
-                                }
-                                ComparisonOperator::GreaterThanThreshold => "GreaterThanThreshold",
-                                ComparisonOperator::LessThanThreshold => "LessThanThreshold",
-                                ComparisonOperator::LessThanOrEqualToThreshold => {
-                                    "LessThanOrEqualToThreshold"
-                                }
-                                _ => "Unknown",
-                            };
-                            let statistic = match item.statistic() {
-                                Some(some) => match some {
-                                    Statistic::Average => "Average",
-                                    Statistic::Maximum => "Maximum",
-                                    Statistic::Minimum => "Minimum",
-                                    Statistic::SampleCount => "SampleCount",
-                                    Statistic::Sum => "Sum",
-                                    _ => "Unknown",
-                                },
-                                None => "",
-                            };
-                            all_metrics.push(MetricAlarmDetails {
-                                program_name: acc.namespace.clone(),
-                                alarm_name: String::from(item.alarm_name().unwrap_or_default()),
-                                alarm_arn: String::from(item.alarm_arn().unwrap_or_default()),
-                                alarm_description: String::from(
-                                    item.alarm_description().unwrap_or_default(),
-                                ),
-                                dimensions: item
-                                    .dimensions()
-                                    .unwrap()
-                                    .iter()
-                                    .map(|i| String::from(i.name().unwrap()))
-                                    .collect(),
-                                actions_enabled: item.actions_enabled().unwrap_or_default(),
-                                period: item.period().unwrap_or_default(),
-                                threshold: item.threshold().unwrap_or_default(),
-                                comparison_operator: String::from(comparison),
-                                treat_missing_data: String::from(
-                                    item.treat_missing_data().unwrap_or_default(),
-                                ),
-                                statistic: String::from(statistic),
-                            });
+                        info!(account = %account, history_items = res.len(), "successful query");
+                        all_history.extend(res);
+                    }
+                    Err(e) => warn!(
+                        account = %account,
+                        error = %e,
+                        "failed to describe alarm history"
+                    ),
+                }
+            }
+            let (extension, contents) = match format {
+                "csv" => ("csv", history_to_csv(&all_history)?),
+                _ => (
+                    "json",
+                    serde_json::to_vec(&all_history).expect("alarm history serializes to JSON"),
+                ),
+            };
+            let path = match output_path {
+                Some(path) => PathBuf::from(path),
+                None => Path::new("alarm-history").with_extension(extension),
+            };
+            write_output(&path, contents).await?;
+            summary.output(&path);
+            println!("saved alarm history to {}", path.display());
+        }
+        Some(("dashboards", dashboards)) => match dashboards.subcommand() {
+            Some(("list", list)) => {
+                let output_path = list.value_of("output-path");
+                let accounts = load_accounts(list)?;
+                let mut all_dashboards: Vec<DashboardSummary> = vec![];
+
+                let exporter = DashboardExporter::new().options(run_options(list, &retry));
+                for (account, res) in exporter.list(accounts).await {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(res) => {
+                            info!(account = %account, dashboards = res.len(), "successful query");
+                            for dashboard in &res {
+                                println!("  {}", dashboard.dashboard_name);
+                            }
+                            all_dashboards.extend(res);
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to list dashboards")
+                        }
+                    }
+                }
+                let path = PathBuf::from(output_path.unwrap_or_default()).join("dashboards.json");
+                let as_str =
+                    serde_json::to_string(&all_dashboards).expect("dashboards serialize to JSON");
+                write_output(&path, as_str).await?;
+                summary.output(&path);
+                println!("saved dashboard list to {}", path.display());
+            }
+            Some(("get", get)) => {
+                let name = get.value_of("name").unwrap();
+                let output_path = get.value_of("output-path");
+                let accounts = load_accounts(get)?;
+
+                let exporter = DashboardExporter::new()
+                    .output_path(output_path.map(PathBuf::from))
+                    .options(run_options(get, &retry));
+                let results = exporter.download(accounts.clone(), name).await;
+                for (acc, (account, res)) in accounts.iter().zip(results) {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(path) => {
+                            println!("saved dashboard for {} to {}", account, path.display());
+                            summary.account_output(&path, acc);
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to get dashboard")
                         }
                     }
-                    Err(e) => println!("failed describe alarms error: {:?}", e),
                 }
             }
-            let path = Path::new("describe-alarms").with_extension("json");
-            let as_str = serde_json::to_string(&all_metrics).unwrap();
-            let res = fs::write(path, as_str).await;
-            match res {
-                Ok(()) => {
-                    println!("saved metrics");
+            Some(("render", render)) => {
+                let name = render.value_of("name").unwrap();
+                let start = render.value_of("start-time").unwrap();
+                let end = render.value_of("end-time").unwrap();
+                let output_path = render.value_of("output-path");
+                let accounts = load_accounts(render)?;
+
+                let exporter = DashboardExporter::new()
+                    .start(start)
+                    .end(end)
+                    .output_path(output_path.map(PathBuf::from))
+                    .options(run_options(render, &retry));
+                summary.inputs(None, start, end);
+                let results = exporter.render(accounts.clone(), name).await;
+                for (acc, (account, res)) in accounts.iter().zip(results) {
+                    summary.record(&account, &res);
+                    match res {
+                        Ok(paths) => {
+                            info!(
+                                account = %account,
+                                widget_images = paths.len(),
+                                "successful query"
+                            );
+                            for path in &paths {
+                                summary.account_output(path, acc);
+                            }
+                        }
+                        Err(e) => {
+                            warn!(account = %account, error = %e, "failed to render dashboard")
+                        }
+                    }
                 }
-                Err(e) => {
-                    println!("error writing to file: {:?}", e);
+            }
+            Some(("grafana", grafana)) => {
+                let accounts = load_accounts(grafana)?;
+                let templates = grafana
+                    .values_of("template")
+                    .into_iter()
+                    .flatten()
+                    .map(PathBuf::from)
+                    .collect();
+                let dashboard = GrafanaConverter::new(grafana.value_of("title").unwrap())
+                    .from(grafana.value_of("from").unwrap())
+                    .period(grafana.value_of("period").unwrap())
+                    .templates(templates)
+                    .dashboard_name(grafana.value_of("name").map(String::from))
+                    .vars(template_vars(grafana))
+                    .options(run_options(grafana, &retry))
+                    .convert(&accounts)
+                    .await?;
+                let path = grafana
+                    .value_of("output")
+                    .map_or_else(|| PathBuf::from("grafana-dashboard.json"), PathBuf::from);
+                let contents =
+                    serde_json::to_vec_pretty(&dashboard).expect("dashboard serializes to JSON");
+                write_output(&path, contents).await?;
+                summary.output(&path);
+                println!("saved Grafana dashboard to {}", path.display());
+            }
+            _ => unreachable!(),
+        },
+        Some(("logs-insights", logs)) => {
+            let start = logs.value_of("start-time").unwrap();
+            let end = logs.value_of("end-time").unwrap();
+            let query_path = logs.value_of("query-path").unwrap();
+            summary.inputs(Some(query_path), start, end);
+            let log_groups: Vec<String> = logs
+                .values_of("log-group")
+                .unwrap()
+                .map(String::from)
+                .collect();
+            let limit: Option<i32> = logs
+                .is_present("limit")
+                .then(|| logs.value_of_t("limit").unwrap_or_else(|e| e.exit()));
+            let format = logs.value_of("format").unwrap();
+            let output_path = logs.value_of("output-path");
+            let mut accounts = load_accounts(logs)?;
+            cli_overrides(logs, &["start-time", "end-time"], &mut accounts);
+            let mut all_results: Vec<AccountQueryResults> = vec![];
+
+            let exporter = LogsInsightsExporter::new(query_path, log_groups)
+                .vars(template_vars(logs))
+                .start(start)
+                .end(end)
+                .limit(limit)
+                .options(run_options(logs, &retry));
+            for (account, res) in exporter.run(accounts).await {
+                summary.record(&account, &res);
+                match res {
+                    Ok(res) => {
+                        info!(account = %account, rows = res.rows.len(), "successful query");
+                        all_results.push(res);
+                    }
+                    Err(e) => warn!(
+                        account = %account,
+                        error = %e,
+                        "failed to run logs insights query"
+                    ),
                 }
             }
+            let (extension, contents) = match format {
+                "csv" => ("csv", query_results_to_csv(&all_results)?),
+                _ => (
+                    "json",
+                    serde_json::to_vec(&all_results).expect("query results serialize to JSON"),
+                ),
+            };
+            let path = match output_path {
+                Some(path) => PathBuf::from(path),
+                None => Path::new("logs-insights").with_extension(extension),
+            };
+            write_output(&path, contents).await?;
+            summary.output(&path);
+            println!("saved query results to {}", path.display());
         }
-        Some(("config", config)) => {
-            let config_path = config.value_of("config-path").unwrap();
-            let pattern = config.value_of("pattern");
-            let accounts = get_accounts(config_path, true);
-            let _filtered = filter_accounts(pattern, accounts);
+        Some(("doctor", doctor)) => {
+            let accounts = load_accounts(doctor)?;
+            let region = aws_regions::parse_region("us-east-1").map_err(Error::Region)?;
+            println!("credentials: {}", caller_identity(&region, &retry).await?);
+            let checks = Doctor::new()
+                .options(run_options(doctor, &retry))
+                .run(accounts)
+                .await;
+            for (account, res) in &checks {
+                summary.record(account, res);
+            }
+            let color = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            print!("{}", checks_to_table(&checks, color));
         }
+        Some(("config", config)) => match config.subcommand() {
+            Some(("init", init)) => init_config(init).await?,
+            Some(("discover", discover)) => discover_config(discover, &retry).await?,
+            _ => {
+                check_config_files(config)?;
+                for acc in load_accounts(config)? {
+                    println!("{:?}", acc);
+                }
+            }
+        },
         _ => unreachable!(),
     };
 
-    Ok(())
+    summary.retried = retry.retried_calls();
+    if let Some(path) = matches.value_of("manifest") {
+        let path = Path::new(path);
+        summary.manifest.command = String::from(matches.subcommand_name().unwrap_or_default());
+        summary.manifest.write(path).await?;
+        summary.output(path);
+        println!("saved manifest to {}", path.display());
+    }
+    if let Some(path) = matches.value_of("archive") {
+        let path = Path::new(path);
+        let files = summary.files();
+        write_archive(path, &files)?;
+        summary.output(path);
+        println!("saved {} files to {}", files.len(), path.display());
+    }
+    if let Some(s3_uri) = matches.value_of("s3-uri") {
+        let files = summary.files();
+        summary.uploaded =
+            upload_outputs(s3_uri, matches.value_of("s3-role-arn"), &retry, &files).await?;
+        for (path, uri) in files.iter().zip(&summary.uploaded) {
+            println!("uploaded {} to {}", path.display(), uri);
+        }
+    }
+    if let Some(uploaded) = summary
+        .notify_slack(matches.subcommand_name().unwrap_or_default())
+        .await?
+    {
+        println!("posted run summary to Slack, uploaded {} images", uploaded);
+    }
+
+    Ok(summary)
 }
 
-fn filter_accounts(pattern: Option<&str>, accounts: Option<AccountsConfig>) -> Vec<AccountConfig> {
-    if let Some(pat) = pattern {
-        let pat = String::from(pat);
-        let filtered: Vec<AccountConfig> = accounts
-            .unwrap()
-            .account
-            .into_iter()
-            .filter(|x| x.namespace.contains(&pat))
-            .collect();
-        println!("Filtered accounts:");
-        for acc in &filtered {
-            println!("{:?}", &acc);
+/// Commands that list their planned AWS calls for `--dry-run`, and `alarms apply` which reports
+/// the changes it would make
+fn supports_dry_run(matches: &ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some(("images" | "data", _)) => true,
+        Some(("alarms", alarms)) => matches!(alarms.subcommand_name(), None | Some("apply")),
+        _ => false,
+    }
+}
+
+/// Lists the report and email of an images round, showing each PNG in the terminal first when
+/// `inline` is set
+fn print_round(outcome: &RoundOutcome, inline: Option<ImageProtocol>) {
+    if let Some(protocol) = inline {
+        for path in &outcome.images {
+            if path.extension() != Some("png".as_ref()) {
+                continue;
+            }
+            println!("{}", path.display());
+            if let Err(e) = protocol.display(path) {
+                warn!(path = %path.display(), error = %e, "failed to show image inline");
+            }
         }
-        filtered
-    } else {
-        accounts.expect("expected accounts to filter").account
     }
+    if let Some(path) = &outcome.report {
+        println!("saved report to {}", path.display());
+    }
+    if !outcome.emailed.is_empty() {
+        println!("emailed report to {}", outcome.emailed.join(", "));
+    }
+}
+
+fn print_plan(planned: &[PlannedCall]) {
+    print!("{}", plan_to_table(planned));
+    let accounts: BTreeSet<&str> = planned.iter().map(|call| call.account.as_str()).collect();
+    println!(
+        "dry run: {} calls for {} accounts, nothing was sent",
+        planned.len(),
+        accounts.len()
+    );
 }
 
-async fn get_cw_client(region: &str, verbose: bool) -> cloudwatchClient {
-    let static_region = aws_regions::convert_to_name(region);
+/// Describes the alarms of every account and compares them with a golden file, or with the alarms
+/// of the accounts matching `--against`, which are left out of the comparison
+async fn diff_alarm_accounts(
+    diff: &ArgMatches,
+    retry: &RetryPolicy,
+    summary: &mut RunSummary,
+) -> Result<(), Error> {
+    let format = diff.value_of("format").unwrap();
+    let output_path = diff.value_of("output-path");
+    let accounts = load_accounts(diff)?;
+    let reference = diff.value_of("against").map(|re| {
+        AccountFilter::new().patterns(vec![
+            Regex::new(re).expect("regex is checked by the validator")
+        ])
+    });
+    let reference_accounts: Vec<String> = match &reference {
+        Some(reference) => accounts
+            .iter()
+            .filter(|acc| reference.matches(acc))
+            .map(AccountConfig::label)
+            .collect(),
+        None => vec![],
+    };
+    let alarm_regex = diff
+        .value_of("alarm-regex")
+        .map(|re| Regex::new(re).expect("regex is checked by the validator"));
+    let exporter = AlarmExporter::new()
+        .alarm_regex(alarm_regex)
+        .options(run_options(diff, retry));
 
-    if verbose {
-        println!();
-        println!("CloudWatch client version: {}", PKG_VERSION);
-        println!("Region:                    {}", static_region);
-        println!();
+    let mut described: Vec<(String, Vec<MetricAlarmDetails>)> = vec![];
+    for (account, res) in exporter.run(accounts).await {
+        summary.record(&account, &res);
+        match res {
+            Ok(res) => {
+                info!(account = %account, alarms = res.len(), "successful query");
+                described.push((account, res));
+            }
+            Err(e) => warn!(account = %account, error = %e, "failed to describe alarms"),
+        }
     }
 
-    let shared_config = aws_config::from_env().region(static_region).load().await;
+    let baseline = match diff.value_of("baseline") {
+        Some(path) => BaselineAlarm::from_path(path)?,
+        None => {
+            let mut baseline: Vec<BaselineAlarm> = vec![];
+            for (_, alarms) in described
+                .iter()
+                .filter(|(account, _)| reference_accounts.contains(account))
+            {
+                for alarm in alarms {
+                    if !baseline.iter().any(|b| b.alarm_name == alarm.alarm_name) {
+                        baseline.push(BaselineAlarm::from(alarm));
+                    }
+                }
+            }
+            if baseline.is_empty() {
+                return Err(Error::NoBaseline(String::from(
+                    diff.value_of("against").unwrap(),
+                )));
+            }
+            baseline
+        }
+    };
+    let differences: Vec<_> = described
+        .iter()
+        .filter(|(account, _)| !reference_accounts.contains(account))
+        .flat_map(|(account, alarms)| diff_alarms(account, &baseline, alarms))
+        .collect();
+
+    let (extension, contents) = match format {
+        "csv" => ("csv", differences_to_csv(&differences)?),
+        "table" => ("txt", differences_to_table(&differences).into_bytes()),
+        _ => (
+            "json",
+            serde_json::to_vec(&differences).expect("differences serialize to JSON"),
+        ),
+    };
+    let path = match output_path {
+        Some(path) => PathBuf::from(path),
+        None => Path::new("alarms-diff").with_extension(extension),
+    };
+    write_output(&path, contents).await?;
+    summary.output(&path);
+    println!(
+        "saved {} alarm differences to {}",
+        differences.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Writes a new accounts config from prompts or a list of account IDs on stdin, then checks
+/// that it loads
+async fn init_config(init: &ArgMatches) -> Result<(), Error> {
+    let path = Path::new(init.value_of("output-path").unwrap());
+    if path.exists() && !init.is_present("force") {
+        return Err(Error::AlreadyExists(path.to_path_buf()));
+    }
+    let role_name = init.value_of("role-name");
+    let region = init.value_of("region");
+    let stdin_error = |source| Error::Read {
+        path: PathBuf::from("<stdin>"),
+        source,
+    };
 
-    if verbose {
-        println!();
-        println!("SdkConfig: {:?}", shared_config);
-        println!();
+    let mut entries = vec![];
+    if init.is_present("from-ids") {
+        let (role_name, region) = (role_name.unwrap(), region.unwrap());
+        for line in std::io::stdin().lock().lines() {
+            let line = line.map_err(stdin_error)?;
+            let mut fields = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty());
+            let account_id = match fields.next() {
+                Some(account_id) => account_id,
+                None => continue,
+            };
+            let mut entry = AccountEntry::new(account_id, region, role_name);
+            if let Some(namespace) = fields.next() {
+                entry.namespace = String::from(namespace);
+            }
+            entries.push(entry);
+        }
+    } else {
+        eprintln!("Describe each account, leave the namespace empty when done.");
+        loop {
+            let namespace = prompt("namespace", None).map_err(stdin_error)?;
+            if namespace.is_empty() {
+                break;
+            }
+            let account_id = loop {
+                let id = prompt("account id", None).map_err(stdin_error)?;
+                if id.len() == 12 && id.chars().all(|c| c.is_ascii_digit()) {
+                    break id;
+                }
+                eprintln!("  an account id is 12 digits");
+            };
+            let region = loop {
+                let region =
+                    prompt("region", Some(region.unwrap_or("us-east-1"))).map_err(stdin_error)?;
+                match aws_regions::parse_region(&region) {
+                    Ok(_) => break region,
+                    Err(e) => eprintln!("  {}", e),
+                }
+            };
+            let default_role = role_name.map(|name| role_arn(&account_id, name));
+            let role_arn = loop {
+                let arn = prompt("role ARN", default_role.as_deref()).map_err(stdin_error)?;
+                if arn.starts_with("arn:aws") && arn.contains(":role/") {
+                    break arn;
+                }
+                eprintln!("  expected an IAM role ARN like arn:aws:iam::111111111111:role/Name");
+            };
+            entries.push(AccountEntry {
+                namespace,
+                account_id,
+                region,
+                role_arn,
+            });
+        }
     }
 
-    cloudwatchClient::new(&shared_config)
+    write_output(path, accounts_to_toml(&entries)).await?;
+    AccountsConfig::from_path(path)?;
+    println!("wrote {} accounts to {}", entries.len(), path.display());
+    Ok(())
 }
 
-async fn get_sts_client(region: &str, verbose: bool) -> stsClient {
-    let static_region = aws_regions::convert_to_name(region);
+/// Adds the organization's accounts that aren't in the accounts file yet, or prints them all
+async fn discover_config(discover: &ArgMatches, retry: &RetryPolicy) -> Result<(), Error> {
+    let role_name = discover.value_of("role-name").unwrap();
+    let region = discover.value_of("region").unwrap();
+    let discovered = discover_accounts(region, role_name, retry).await?;
+    let path = match discover.value_of("output-path") {
+        Some(path) => Path::new(path),
+        None => {
+            print!("{}", accounts_to_toml(&discovered));
+            return Ok(());
+        }
+    };
 
-    if verbose {
-        println!();
-        println!("CloudWatch client version: {}", PKG_VERSION);
-        println!("Region:                    {}", static_region);
-        println!();
+    let mut contents = String::new();
+    let mut known = vec![];
+    if path.exists() {
+        contents = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        known = AccountsConfig::from_path(path)?
+            .account
+            .into_iter()
+            .map(|acc| (acc.role_arn, acc.region))
+            .collect();
     }
+    let added: Vec<AccountEntry> = discovered
+        .into_iter()
+        .filter(|entry| !known.contains(&(entry.role_arn.clone(), entry.region.clone())))
+        .collect();
+    if !added.is_empty() {
+        if !contents.is_empty() && !contents.ends_with("\n\n") {
+            contents.push_str(if contents.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+        contents.push_str(&accounts_to_toml(&added));
+        write_output(path, contents).await?;
+        AccountsConfig::from_path(path)?;
+    }
+    println!(
+        "added {} new accounts to {} ({} accounts were already configured)",
+        added.len(),
+        path.display(),
+        known.len()
+    );
+    Ok(())
+}
 
-    let shared_config = aws_config::from_env().region(static_region).load().await;
-    stsClient::new(&shared_config)
+/// Asks for a value on stderr, returning `default` for an empty answer
+fn prompt(label: &str, default: Option<&str>) -> std::io::Result<String> {
+    match default {
+        Some(default) => eprint!("{} [{}]: ", label, default),
+        None => eprint!("{}: ", label),
+    }
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(String::from(match (answer, default) {
+        ("", Some(default)) => default,
+        _ => answer,
+    }))
 }
 
-async fn get_cw_client_with_role(
-    region: &str,
-    role_arn: &str,
-    sts_client: &stsClient,
-    verbose: bool,
-) -> cloudwatchClient {
-    let static_region = aws_regions::convert_to_name(region);
-
-    if verbose {
-        println!();
-        println!("Client versions: {}", PKG_VERSION);
-        println!("Region:                    {}", static_region);
-        println!("Role Arn:                  {}", role_arn);
-        println!();
+/// Loads the accounts config, logging every account
+fn load_config(matches: &ArgMatches) -> Result<AccountsConfig, Error> {
+    let accounts = AccountsConfig::from_paths(&config_paths(matches)?)?;
+    for acc in &accounts.account {
+        debug!(?acc, "loaded account");
     }
+    Ok(accounts)
+}
 
-    let assumed_role = sts_client
-        .assume_role()
-        .role_arn(role_arn)
-        .role_session_name("dev-cli")
-        .send()
-        .await
-        .unwrap();
-
-    let creds = aws_types::Credentials::new(
-        assumed_role.credentials().unwrap().access_key_id().unwrap(),
-        assumed_role
-            .credentials()
-            .unwrap()
-            .secret_access_key()
-            .unwrap(),
-        Some(
-            assumed_role
-                .credentials()
-                .unwrap()
-                .session_token()
-                .unwrap()
-                .into(),
-        ),
-        Some(std::time::UNIX_EPOCH + Duration::from_secs(1800)),
-        "dev-cli-metrics-observer",
-    );
+/// The `config-path` values, or the default accounts config when none are given
+fn config_paths(matches: &ArgMatches) -> Result<Vec<PathBuf>, Error> {
+    Ok(match matches.values_of("config-path") {
+        Some(paths) => paths.map(PathBuf::from).collect(),
+        None => vec![default_config_path().ok_or(Error::NoConfig)?],
+    })
+}
 
-    let shared_config = aws_config::from_env()
-        .region(static_region) // specify the region again for this specific account, need to make sure this matches the account's infrastructure region
-        .credentials_provider(creds)
-        .load()
-        .await;
-    cloudwatchClient::new(&shared_config)
+/// Checks the accounts config for every problem at once, printing each with its file, line and
+/// key. Unknown keys are warnings unless `--strict` is given.
+fn check_config_files(matches: &ArgMatches) -> Result<(), Error> {
+    let strict = matches.is_present("strict");
+    let issues = check_config(&config_paths(matches)?);
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| strict || issue.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(Error::InvalidConfig(errors));
+    }
+    Ok(())
 }
 
-async fn cloudwatch_describe_alarms(opts: DescribeAlarmsProps) -> Result<Vec<MetricAlarm>, Error> {
-    let DescribeAlarmsProps {
-        region,
-        role_arn,
-        verbose,
-    } = opts;
-    let replaced_region = region.clone().unwrap_or_else(|| String::from("us-west-2"));
-    let sts_client = get_sts_client(&replaced_region.as_str(), verbose).await;
-    let client = get_cw_client_with_role(
-        &replaced_region.as_str(),
-        role_arn.as_str(),
-        &sts_client,
-        verbose,
-    )
-    .await;
-    describe_alarms(&client).await
+/// Loads the accounts config and keeps the accounts selected by the filter flags
+fn load_accounts(matches: &ArgMatches) -> Result<Vec<AccountConfig>, Error> {
+    Ok(load_config(matches)?.select(&account_filter(matches)))
 }
 
-async fn cloudwatch_image_download(opts: GetWidgetProps) -> Result<(), Error> {
-    let GetWidgetProps {
-        app_name: namespace,
-        end,
-        period,
-        region,
-        role_arn,
-        start,
-        template_path: filepath,
-        title,
-        verbose,
-    } = opts;
-
-    let replaced_region = region.clone().unwrap_or_else(|| String::from("us-west-2"));
-
-    let sts_client = get_sts_client(&replaced_region.as_str(), verbose).await;
-    let client = get_cw_client_with_role(
-        &replaced_region.as_str(),
-        role_arn.as_str(),
-        &sts_client,
-        verbose,
-    )
-    .await;
-    if let Some(metrics) = get_metrics_json(
-        &filepath,
-        &replaced_region,
-        &namespace,
-        &start,
-        &end,
-        &period,
-        verbose,
-    ) {
-        let saved_image_name = format!(
-            "{}-{}-{}-{}-{}",
-            &namespace,
-            &title,
-            &replaced_region,
-            &start,
-            std::time::SystemTime::now()
-                .duration_since(std::time::SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-        );
-        get_metric_image(&client, metrics.as_ref(), &saved_image_name).await
-    } else {
-        panic!("unable to parse metrics json")
-    }
+/// Reads the `--pattern`, `--exclude`, `--region`, `--group` and `--tag` flags
+fn account_filter(matches: &ArgMatches) -> AccountFilter {
+    let regexes = |name| -> Vec<Regex> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(|re| Regex::new(re).expect("regex is checked by the validator"))
+            .collect()
+    };
+    let values = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect()
+    };
+    AccountFilter::new()
+        .patterns(regexes("pattern"))
+        .excludes(regexes("exclude"))
+        .regions(values("region"))
+        .groups(values("group"))
+        .tags(values("tag"))
 }
 
-fn get_accounts(filepath: &str, verbose: bool) -> Option<AccountsConfig> {
-    let config_file = std::fs::read_to_string(filepath);
-    if let Ok(contents) = config_file {
-        let accounts_config: AccountsConfig =
-            toml::from_str(&contents).expect("unable to parse as toml");
-        if verbose {
-            for acc in &accounts_config.account {
-                println!("{:?}", acc)
+/// Flags passed on the command line win over the same setting in the accounts config, so drop
+/// the accounts' own values for the `flags` that were given
+fn cli_overrides(matches: &ArgMatches, flags: &[&str], accounts: &mut [AccountConfig]) {
+    for flag in flags.iter().filter(|flag| matches.occurrences_of(flag) > 0) {
+        for acc in accounts.iter_mut() {
+            match *flag {
+                "start-time" => acc.start = None,
+                "end-time" => acc.end = None,
+                "period" => acc.period = None,
+                _ => unreachable!("{} has no per-account setting", flag),
             }
         }
-        Some(accounts_config)
-    } else {
-        None
     }
 }
 
-fn get_metrics_json(
-    filepath: &PathBuf,
-    region: &str,
-    namespace: &str,
-    start: &str,
-    end: &str,
-    period: &str,
-    verbose: bool,
-) -> Option<String> {
-    let template_file = std::fs::read_to_string(filepath);
-    if let Ok(contents) = template_file {
-        let mut template_params = HashMap::<&str, &str>::new();
-
-        // TODO: make this configurable
-        template_params.insert("{{NAMESPACE}}", namespace);
-        template_params.insert("{{REGION}}", region);
-        // format: 4320H
-        template_params.insert("{{PERIOD_START}}", start);
-        template_params.insert("{{PERIOD_END}}", end);
-        template_params.insert("{{PERIOD}}", period);
-
-        let mut replaced = contents;
-        template_params
-            .iter()
-            .for_each(|(k, v)| replaced = replaced.replace(k, v));
-
-        if verbose {
-            println!("templated:\n{}", &replaced);
+/// Logs to stderr so stdout only carries results. `-q` keeps errors only and each `-v` adds
+/// detail, first from this crate and then from the AWS SDK; `RUST_LOG` replaces the level.
+///
+/// JSON logs start one level more detailed so pipelines get the per-account and per-call
+/// events with their `account`, `region`, `operation` and `duration_ms` fields.
+fn init_logging(matches: &ArgMatches) {
+    let json = matches.value_of("log-format") == Some("json");
+    // anything written to stderr would draw over the TUI
+    let level = if matches.is_present("quiet") || matches.subcommand_name() == Some("tui") {
+        "error"
+    } else {
+        match matches.occurrences_of("verbose") + u64::from(json) {
+            0 => "warn,cw_metrics=info",
+            1 => "warn,cw_metrics=debug",
+            2 => "info,cw_metrics=trace",
+            _ => "trace",
         }
-
-        Some(replaced)
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level)),
+        );
+    if json {
+        // one object per line with the event and account span fields at the top level
+        subscriber
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init();
     } else {
-        None
+        subscriber.init();
     }
 }
 
-// List metrics.
-async fn show_metrics(
-    client: &aws_sdk_cloudwatch::Client,
-) -> Result<(), aws_sdk_cloudwatch::Error> {
-    let rsp = client.list_metrics().send().await?;
-    let metrics = rsp.metrics().unwrap_or_default();
+/// Reads the global retry flags
+fn retry_policy(matches: &ArgMatches) -> RetryPolicy {
+    let retry_on: Vec<&str> = matches.values_of("retry-on").unwrap().collect();
+    RetryPolicy::default()
+        .max_attempts(
+            matches
+                .value_of_t("max-attempts")
+                .unwrap_or_else(|e| e.exit()),
+        )
+        .base_delay(Duration::from_millis(
+            matches
+                .value_of_t("retry-base-delay-ms")
+                .unwrap_or_else(|e| e.exit()),
+        ))
+        .max_delay(Duration::from_millis(
+            matches
+                .value_of_t("retry-max-delay-ms")
+                .unwrap_or_else(|e| e.exit()),
+        ))
+        .retry_throttling(retry_on.contains(&"throttling"))
+        .retry_transient(retry_on.contains(&"transient"))
+        .request_timeout(
+            matches.value_of("request-timeout").map(|timeout| {
+                parse_interval(timeout).expect("timeout is checked by the validator")
+            }),
+        )
+}
 
-    let num_metrics = metrics.len();
+/// Reads the concurrency and assume-role flags shared by the multi-account subcommands
+fn run_options(matches: &ArgMatches, retry: &RetryPolicy) -> RunOptions {
+    let session_duration: Option<i32> = matches.is_present("session-duration").then(|| {
+        matches
+            .value_of_t("session-duration")
+            .unwrap_or_else(|e| e.exit())
+    });
+    let deadline = matches.value_of("run-deadline").map(|deadline| {
+        *RUN_STARTED.get_or_init(Instant::now)
+            + parse_interval(deadline).expect("deadline is checked by the validator")
+    });
+    let credential_cache = CREDENTIAL_CACHE.get_or_init(Default::default).clone();
+    RunOptions::default()
+        .concurrency(
+            matches
+                .value_of_t("concurrency")
+                .unwrap_or_else(|e| e.exit()),
+        )
+        .session_name(matches.value_of("session-name").map(String::from))
+        .session_duration(session_duration)
+        .retry(retry.clone())
+        .progress(!matches.is_present("quiet"))
+        .fail_fast(matches.is_present("fail-fast"))
+        .deadline(deadline)
+        .credential_cache(credential_cache)
+}
 
-    for metric in metrics {
-        println!("Namespace: {}", metric.namespace().unwrap_or_default());
-        println!("Name:      {}", metric.metric_name().unwrap_or_default());
-        println!("Dimensions:");
+/// Builds an alarm exporter from the `--state`, `--alarm-prefix`, `--alarm-regex`, `--alarm-tag`
+/// and `--list-tags` flags
+fn alarm_exporter(matches: &ArgMatches, retry: &RetryPolicy) -> AlarmExporter {
+    let state = matches.value_of("state").map(StateValue::from);
+    let alarm_prefix = matches.value_of("alarm-prefix").map(String::from);
+    let alarm_regex = matches
+        .value_of("alarm-regex")
+        .map(|re| Regex::new(re).expect("regex is checked by the validator"));
+    let tag_filters = matches
+        .values_of("alarm-tag")
+        .into_iter()
+        .flatten()
+        .map(|tag| parse_tag(tag).expect("tag is checked by the validator"))
+        .collect();
+    AlarmExporter::new()
+        .state_value(state)
+        .alarm_name_prefix(alarm_prefix)
+        .alarm_regex(alarm_regex)
+        .list_tags(matches.is_present("list-tags"))
+        .tag_filters(tag_filters)
+        .options(run_options(matches, retry))
+}
 
-        if let Some(dimension) = metric.dimensions.as_ref() {
-            for d in dimension {
-                println!("  Name:  {}", d.name().unwrap_or_default());
-                println!("  Value: {}", d.value().unwrap_or_default());
-                println!();
-            }
-        }
+/// Flags posting the run summary to Slack, and uploading some of the saved images
+fn slack_args() -> [Arg<'static>; 3] {
+    [
+        Arg::new("notify-slack")
+            .long("notify-slack")
+            .help("post a run summary to this Slack incoming webhook URL, defaults to slack_webhook in the config's [defaults]")
+            .takes_value(true),
+        Arg::new("slack-upload")
+            .long("slack-upload")
+            .help("also upload saved images whose file name matches this regex, with the bot token in $SLACK_BOT_TOKEN")
+            .validator(Regex::new)
+            .requires("slack-channel")
+            .takes_value(true),
+        Arg::new("slack-channel")
+            .long("slack-channel")
+            .help("ID of the channel to upload images to")
+            .takes_value(true),
+    ]
+}
 
-        println!();
-    }
+/// Slack notifier from `--notify-slack`, or the config's `slack_webhook` default
+fn slack_notifier(matches: &ArgMatches) -> Result<Option<SlackNotifier>, Error> {
+    let webhook_url = match matches.value_of("notify-slack") {
+        Some(webhook_url) => String::from(webhook_url),
+        None => match load_config(matches)?.defaults.slack_webhook {
+            Some(webhook_url) => webhook_url,
+            None => return Ok(None),
+        },
+    };
+    let notifier = SlackNotifier::new(webhook_url);
+    let Some(pattern) = matches.value_of("slack-upload") else {
+        return Ok(Some(notifier));
+    };
+    let token = std::env::var("SLACK_BOT_TOKEN").map_err(|_| {
+        Error::Slack(String::from(
+            "--slack-upload needs a bot token in $SLACK_BOT_TOKEN",
+        ))
+    })?;
+    Ok(Some(notifier.upload(
+        token,
+        matches.value_of("slack-channel").unwrap(),
+        Regex::new(pattern).expect("regex is checked by the validator"),
+    )))
+}
 
-    println!("Found {} metrics.", num_metrics);
+/// Flags selecting which alarms of each account to describe
+fn alarm_filter_args() -> [Arg<'static>; 4] {
+    [
+        Arg::new("state")
+            .long("state")
+            .help("only describe alarms in this state")
+            .possible_values(["ALARM", "OK", "INSUFFICIENT_DATA"])
+            .takes_value(true),
+        Arg::new("alarm-prefix")
+            .long("alarm-prefix")
+            .help("only describe alarms whose name starts with this prefix")
+            .takes_value(true),
+        Arg::new("alarm-regex")
+            .long("alarm-regex")
+            .help("only keep alarms whose name or description matches this regex")
+            .validator(Regex::new)
+            .takes_value(true),
+        Arg::new("alarm-tag")
+            .long("alarm-tag")
+            .help("only keep alarms tagged KEY=VALUE, repeat to require more tags")
+            .validator(parse_tag)
+            .multiple_occurrences(true)
+            .takes_value(true),
+    ]
+}
 
-    Ok(())
+/// The accounts config files a subcommand runs against
+fn config_path_arg() -> Arg<'static> {
+    Arg::new("config-path")
+        .multiple_values(true)
+        .help("the TOML config files with accounts, merged in order; defaults to $CW_DL_CONFIG or ~/.config/cloudwatch-metrics-dl/accounts.toml")
 }
 
-async fn describe_alarms(
-    client: &aws_sdk_cloudwatch::Client,
-) -> Result<Vec<MetricAlarm>, aws_sdk_cloudwatch::Error> {
-    println!("describing alarms");
-    let request = client.describe_alarms();
-    let resp = request.send().await?;
-    let alarms = resp.metric_alarms().unwrap();
-    let vec: Vec<MetricAlarm> = alarms.to_vec();
-    Ok(vec)
+/// How many accounts a subcommand runs against at once
+fn concurrency_arg() -> Arg<'static> {
+    Arg::new("concurrency")
+        .long("concurrency")
+        .short('c')
+        .help("number of accounts to process at the same time")
+        .default_value("4")
+        .takes_value(true)
 }
 
-/// Calls AWS CloudWatch GetMetricImage API and downloads locally
-/// API Reference: [GetMetricWidgetImage](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_GetMetricWidgetImage.html)
-async fn get_metric_image(
-    client: &aws_sdk_cloudwatch::Client,
-    metric_json: &str,
-    saved_image_name: &str,
-) -> Result<(), aws_sdk_cloudwatch::Error> {
-    println!("getting metric image");
-
-    let request = client
-        .get_metric_widget_image()
-        .output_format("png")
-        .set_metric_widget(Some(String::from(metric_json)));
-    let resp = request.send().await?;
-
-    if let Some(blob) = resp.metric_widget_image {
-        let path = Path::new(saved_image_name).with_extension("png");
-
-        // convert to base64 encoded byte vector
-        let base64_encoded = blob.into_inner();
-
-        // wait to finish saving file
-        let res = fs::write(path, base64_encoded).await;
-        match res {
-            Ok(()) => {
-                println!("saved metric image");
-            }
-            Err(e) => {
-                println!("error writing to file: {:?}", e);
-            }
+/// Flags selecting which accounts of the config to run against
+fn filter_args() -> [Arg<'static>; 5] {
+    [
+        Arg::new("pattern")
+            .long("pattern")
+            .short('f')
+            .help("only accounts whose namespace, region or role ARN matches this regex, repeat to allow more")
+            .validator(Regex::new)
+            .multiple_occurrences(true)
+            .takes_value(true),
+        Arg::new("exclude")
+            .long("exclude")
+            .help("leave out accounts whose namespace, region or role ARN matches this regex, repeatable")
+            .validator(Regex::new)
+            .multiple_occurrences(true)
+            .takes_value(true),
+        Arg::new("region")
+            .long("region")
+            .short('r')
+            .help("only accounts in this region (e.g. eu-west-1), repeat to allow more regions")
+            .validator(aws_regions::parse_region)
+            .multiple_occurrences(true)
+            .takes_value(true),
+        Arg::new("group")
+            .long("group")
+            .help("only accounts in this group, repeat to allow more groups")
+            .multiple_occurrences(true)
+            .takes_value(true),
+        Arg::new("tag")
+            .long("tag")
+            .help("only accounts with this tag, repeat to require more tags")
+            .multiple_occurrences(true)
+            .takes_value(true),
+    ]
+}
+
+/// Repeatable `--var KEY=VALUE` flag for subcommands that render templates
+fn var_arg() -> Arg<'static> {
+    Arg::new("var")
+        .long("var")
+        .help("extra {{KEY}} template variable as KEY=VALUE, overrides the account's vars table")
+        .validator(parse_var)
+        .multiple_occurrences(true)
+        .takes_value(true)
+}
+
+fn parse_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((key, value))
+            if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            Ok((String::from(key), String::from(value)))
         }
-    } else {
-        println!("error getting metric image");
+        _ => Err(String::from(
+            "expected KEY=VALUE with a KEY of letters, digits and _",
+        )),
     }
-    Ok(())
+}
+
+/// Reads the `--var` flags
+fn template_vars(matches: &ArgMatches) -> BTreeMap<String, String> {
+    matches
+        .values_of("var")
+        .into_iter()
+        .flatten()
+        .map(|var| parse_var(var).expect("vars are checked by the validator"))
+        .collect()
+}
+
+/// Shared assume-role flags for subcommands that run against the accounts config
+fn session_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("session-name")
+            .long("session-name")
+            .help("role session name recorded in CloudTrail for each AssumeRole call")
+            .takes_value(true),
+        Arg::new("session-duration")
+            .long("session-duration")
+            .help("requested assumed-role session duration in seconds")
+            .takes_value(true),
+    ]
 }
@@ -0,0 +1,96 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::output::write_output;
+
+/// A file written by a run
+#[derive(Serialize, Debug, Clone)]
+pub struct ManifestFile {
+    pub path: PathBuf,
+    /// Namespace of the account the file is for, unset for files covering every account
+    pub account: Option<String>,
+    pub region: Option<String>,
+    /// Size and hex SHA-256 of the contents, filled in by [`Manifest::write`]
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+impl ManifestFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ManifestFile {
+            path: path.into(),
+            account: None,
+            region: None,
+            bytes: 0,
+            sha256: String::new(),
+        }
+    }
+
+    pub fn for_account(path: impl Into<PathBuf>, acc: &AccountConfig) -> Self {
+        ManifestFile {
+            account: Some(acc.namespace.clone()),
+            region: Some(acc.region.clone()),
+            ..Self::new(path)
+        }
+    }
+}
+
+/// Whether an account's API calls succeeded
+#[derive(Serialize, Debug, Clone)]
+pub struct ManifestAccount {
+    pub account: String,
+    /// `ok` or `failed`
+    pub status: &'static str,
+    pub error: Option<String>,
+}
+
+/// Every file a run wrote and how each account went, so automation can publish the results
+/// without globbing directories
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Manifest {
+    pub command: String,
+    /// Template, spec or query the files were made from
+    pub template: Option<PathBuf>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub finished_at: String,
+    pub accounts: Vec<ManifestAccount>,
+    pub files: Vec<ManifestFile>,
+}
+
+impl Manifest {
+    pub fn account<T>(&mut self, account: &str, res: &Result<T, Error>) {
+        self.accounts.push(ManifestAccount {
+            account: String::from(account),
+            status: if res.is_ok() { "ok" } else { "failed" },
+            error: res.as_ref().err().map(ToString::to_string),
+        });
+    }
+
+    /// Fills in the size and checksum of every file and saves the manifest as JSON
+    pub async fn write(&self, path: &Path) -> Result<(), Error> {
+        let mut manifest = self.clone();
+        for file in &mut manifest.files {
+            let contents = tokio::fs::read(&file.path)
+                .await
+                .map_err(|source| Error::Read {
+                    path: file.path.clone(),
+                    source,
+                })?;
+            file.bytes = contents.len() as u64;
+            file.sha256 = format!("{:x}", Sha256::digest(&contents));
+        }
+        manifest.finished_at = DateTime::from(SystemTime::now())
+            .fmt(Format::DateTime)
+            .unwrap_or_default();
+        let json = serde_json::to_vec_pretty(&manifest).expect("manifest serializes to JSON");
+        write_output(path, json).await
+    }
+}
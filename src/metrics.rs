@@ -0,0 +1,46 @@
+// List metrics, following next_token until every page is read or the limit is reached.
+pub async fn show_metrics(
+    client: &aws_sdk_cloudwatch::Client,
+    limit: Option<usize>,
+) -> Result<(), aws_sdk_cloudwatch::Error> {
+    let mut num_metrics = 0;
+    let mut next_token: Option<String> = None;
+
+    'pages: loop {
+        let rsp = client
+            .list_metrics()
+            .set_next_token(next_token)
+            .send()
+            .await?;
+
+        for metric in rsp.metrics().unwrap_or_default() {
+            if limit.is_some_and(|limit| num_metrics >= limit) {
+                break 'pages;
+            }
+            num_metrics += 1;
+
+            println!("Namespace: {}", metric.namespace().unwrap_or_default());
+            println!("Name:      {}", metric.metric_name().unwrap_or_default());
+            println!("Dimensions:");
+
+            if let Some(dimension) = metric.dimensions.as_ref() {
+                for d in dimension {
+                    println!("  Name:  {}", d.name().unwrap_or_default());
+                    println!("  Value: {}", d.value().unwrap_or_default());
+                    println!();
+                }
+            }
+
+            println!();
+        }
+
+        next_token = rsp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    println!("Found {} metrics.", num_metrics);
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use aws_sdk_sns::Client as snsClient;
+use aws_types::region::Region;
+use tracing::debug;
+
+use crate::client::config_builder;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+
+/// SNS subjects are limited to 100 characters
+const MAX_SUBJECT_LEN: usize = 100;
+
+/// Publishes run summaries to an SNS topic with the default credentials rather than an account's
+/// role, so a run can still alert when role assumption is what broke
+#[derive(Debug, Clone)]
+pub struct SnsPublisher {
+    client: snsClient,
+    topic_arn: String,
+    retry: RetryPolicy,
+}
+
+impl SnsPublisher {
+    /// The client is created in the topic's region, read from its ARN
+    pub async fn new(topic_arn: impl Into<String>, retry: RetryPolicy) -> Result<Self, Error> {
+        let topic_arn = topic_arn.into();
+        let region = topic_arn
+            .strip_prefix("arn:")
+            .and_then(|arn| arn.split(':').nth(2))
+            .filter(|region| !region.is_empty())
+            .map(|region| Region::new(String::from(region)))
+            .ok_or_else(|| Error::InvalidArgument {
+                name: "SNS topic ARN",
+                value: topic_arn.clone(),
+            })?;
+        let shared_config = config_builder(&region, None).await.build();
+        Ok(SnsPublisher {
+            client: snsClient::new(&shared_config),
+            topic_arn,
+            retry,
+        })
+    }
+
+    /// Calls AWS SNS Publish API, returning the message ID. The subject is shortened to fit.
+    /// API Reference: [Publish](https://docs.aws.amazon.com/sns/latest/api/API_Publish.html)
+    pub async fn publish(&self, subject: &str, message: &str) -> Result<String, Error> {
+        let subject: String = subject.chars().take(MAX_SUBJECT_LEN).collect();
+        let request = self
+            .client
+            .publish()
+            .topic_arn(&self.topic_arn)
+            .subject(subject)
+            .message(message);
+        let resp = self
+            .retry
+            .call("Publish", || request.clone().send())
+            .await
+            .map_err(|e| Error::Publish {
+                topic_arn: self.topic_arn.clone(),
+                source: aws_sdk_sns::Error::from(e),
+            })?;
+        debug!(topic = %self.topic_arn, message_id = ?resp.message_id(), "published run summary");
+        Ok(String::from(resp.message_id().unwrap_or_default()))
+    }
+}
@@ -0,0 +1,62 @@
+use aws_sdk_organizations::model::{Account, AccountStatus};
+use aws_sdk_organizations::Client as organizationsClient;
+use aws_smithy_types::retry::RetryConfig;
+use aws_types::region::Region;
+use tracing::debug;
+
+use crate::client::config_builder;
+use crate::config::AccountEntry;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+
+/// Organizations is a global service served from us-east-1
+const ORGANIZATIONS_REGION: &str = "us-east-1";
+
+/// Builds an `[[account]]` entry for every active account in the organization, assuming
+/// `role_name` in `region`. Needs credentials for the management account or a delegated
+/// administrator.
+pub async fn discover_accounts(
+    region: &str,
+    role_name: &str,
+    retry: &RetryPolicy,
+) -> Result<Vec<AccountEntry>, Error> {
+    let shared_config = config_builder(&Region::new(ORGANIZATIONS_REGION), None)
+        .await
+        .retry_config(RetryConfig::disabled())
+        .build();
+    let client = organizationsClient::new(&shared_config);
+    let accounts = list_accounts(&client, retry).await?;
+    Ok(accounts
+        .iter()
+        .filter(|account| account.status() == Some(&AccountStatus::Active))
+        .filter_map(|account| {
+            let mut entry = AccountEntry::new(account.id()?, region, role_name);
+            if let Some(name) = account.name() {
+                entry.namespace = String::from(name);
+            }
+            Some(entry)
+        })
+        .collect())
+}
+
+/// API Reference: [ListAccounts](https://docs.aws.amazon.com/organizations/latest/APIReference/API_ListAccounts.html)
+async fn list_accounts(
+    client: &organizationsClient,
+    retry: &RetryPolicy,
+) -> Result<Vec<Account>, aws_sdk_organizations::Error> {
+    debug!("listing organization accounts");
+    let mut accounts: Vec<Account> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client.list_accounts().set_next_token(next_token);
+        let resp = retry
+            .call("ListAccounts", || request.clone().send())
+            .await?;
+        accounts.extend_from_slice(resp.accounts().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(accounts)
+}
@@ -0,0 +1,190 @@
+use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_client::MetricsServiceClient;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    metric, number_data_point, AggregationTemporality, Gauge, Metric, NumberDataPoint,
+    ResourceMetrics, ScopeMetrics, Sum,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use prost::Message;
+use tracing::debug;
+
+use crate::data::{AccountMetricData, MetricSeries};
+use crate::error::Error;
+
+/// How datapoints are sent to the collector: gRPC, usually on port 4317, or protobuf over HTTP,
+/// usually on port 4318
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    Grpc,
+    Http,
+}
+
+/// Pushes exported datapoints to an OpenTelemetry collector, one resource per account with
+/// `cloud.provider`, `cloud.region` and `cloud.account.id` attributes
+#[derive(Debug, Clone)]
+pub struct OtlpExporter {
+    endpoint: String,
+    protocol: OtlpProtocol,
+}
+
+impl OtlpExporter {
+    /// The collector's base URL, e.g. `http://localhost:4317`. For HTTP, `/v1/metrics` is
+    /// appended.
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        OtlpExporter {
+            endpoint: endpoint.into(),
+            protocol: OtlpProtocol::Grpc,
+        }
+    }
+
+    pub fn protocol(mut self, protocol: OtlpProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Sends every account's series in one export request, returning how many datapoints were
+    /// sent
+    pub async fn export(&self, all_data: &[AccountMetricData]) -> Result<usize, Error> {
+        let request = metric_data_to_otlp(all_data);
+        let datapoints = all_data
+            .iter()
+            .flat_map(|acc| &acc.series)
+            .map(|series| series.datapoints.len())
+            .sum();
+        debug!(endpoint = %self.endpoint, protocol = ?self.protocol, datapoints, "exporting to OTLP");
+        let result = match self.protocol {
+            OtlpProtocol::Grpc => self.export_grpc(request).await,
+            OtlpProtocol::Http => self.export_http(request).await,
+        };
+        result.map_err(|reason| Error::Otlp {
+            endpoint: self.endpoint.clone(),
+            reason,
+        })?;
+        Ok(datapoints)
+    }
+
+    async fn export_grpc(&self, request: ExportMetricsServiceRequest) -> Result<(), String> {
+        let mut client = MetricsServiceClient::connect(self.endpoint.clone())
+            .await
+            .map_err(|e| e.to_string())?;
+        client
+            .export(request)
+            .await
+            .map_err(|status| status.message().to_string())?;
+        Ok(())
+    }
+
+    async fn export_http(&self, request: ExportMetricsServiceRequest) -> Result<(), String> {
+        let url = format!("{}/v1/metrics", self.endpoint.trim_end_matches('/'));
+        reqwest::Client::new()
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+            .body(request.encode_to_vec())
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+fn string_attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: String::from(key),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(String::from(value))),
+        }),
+    }
+}
+
+/// Builds the export request, with a resource per account. `Sum` and `SampleCount` statistics
+/// become delta sums over each period, every other statistic and expression becomes a gauge.
+pub fn metric_data_to_otlp(all_data: &[AccountMetricData]) -> ExportMetricsServiceRequest {
+    let resource_metrics = all_data
+        .iter()
+        .map(|acc| {
+            let mut attributes = vec![
+                string_attribute("cloud.provider", "aws"),
+                string_attribute("cloud.region", &acc.region),
+                string_attribute("cloudwatch.program_name", &acc.program_name),
+            ];
+            if let Some(account_id) = &acc.account_id {
+                attributes.push(string_attribute("cloud.account.id", account_id));
+            }
+            ResourceMetrics {
+                resource: Some(Resource {
+                    attributes,
+                    ..Default::default()
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: String::from(env!("CARGO_PKG_NAME")),
+                        version: String::from(env!("CARGO_PKG_VERSION")),
+                        ..Default::default()
+                    }),
+                    metrics: acc.series.iter().map(series_to_metric).collect(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        })
+        .collect();
+    ExportMetricsServiceRequest { resource_metrics }
+}
+
+/// Named like CloudWatch metric streams do, e.g. `amazonaws.com/AWS/Kinesis/IncomingRecords`,
+/// with an attribute per dimension and one for the statistic
+fn series_to_metric(series: &MetricSeries) -> Metric {
+    let name = if series.metric_name.is_empty() {
+        series.id.clone()
+    } else {
+        format!("amazonaws.com/{}/{}", series.namespace, series.metric_name)
+    };
+    let mut attributes: Vec<KeyValue> = series
+        .dimensions
+        .iter()
+        .map(|(name, value)| string_attribute(name, value))
+        .collect();
+    if !series.stat.is_empty() {
+        attributes.push(string_attribute("stat", &series.stat));
+    }
+    let period_nanos = u64::try_from(series.period).unwrap_or_default() * 1_000_000_000;
+    let is_sum = series.stat == "Sum" || series.stat == "SampleCount";
+    let data_points = series
+        .datapoints
+        .iter()
+        .filter_map(|dp| {
+            let timestamp = dp.date_time()?;
+            let nanos = u64::try_from(timestamp.secs()).ok()? * 1_000_000_000
+                + u64::from(timestamp.subsec_nanos());
+            // a sum covers the period from its timestamp, a gauge is a reading at its timestamp
+            let (start_time_unix_nano, time_unix_nano) = if is_sum {
+                (nanos, nanos + period_nanos)
+            } else {
+                (0, nanos)
+            };
+            Some(NumberDataPoint {
+                attributes: attributes.clone(),
+                start_time_unix_nano,
+                time_unix_nano,
+                value: Some(number_data_point::Value::AsDouble(dp.value)),
+                ..Default::default()
+            })
+        })
+        .collect();
+    let data = if is_sum {
+        metric::Data::Sum(Sum {
+            data_points,
+            aggregation_temporality: AggregationTemporality::Delta as i32,
+            is_monotonic: false,
+        })
+    } else {
+        metric::Data::Gauge(Gauge { data_points })
+    };
+    Metric {
+        name,
+        data: Some(data),
+        ..Default::default()
+    }
+}
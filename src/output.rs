@@ -0,0 +1,50 @@
+use std::path::Path;
+
+use tokio::fs;
+
+use crate::error::Error;
+
+/// Writes `contents` to `path`, creating the parent directory if it does not exist yet
+pub async fn write_output(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), Error> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .await
+            .map_err(|source| Error::Write {
+                path: dir.to_path_buf(),
+                source,
+            })?;
+    }
+    fs::write(path, contents)
+        .await
+        .map_err(|source| Error::Write {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Renders rows as a plain text table with space-padded columns under a `---` separator
+pub(crate) fn text_table(columns: &[&str], rows: &[Vec<String>]) -> String {
+    let header: Vec<String> = columns.iter().map(|c| String::from(*c)).collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .chain([&header])
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+    let mut table = String::new();
+    for row in [&header, &separator].into_iter().chain(rows) {
+        let padded: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(padded.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
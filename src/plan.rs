@@ -0,0 +1,67 @@
+use serde::Serialize;
+
+use crate::config::AccountConfig;
+use crate::output::text_table;
+use crate::runner::RunOptions;
+
+/// An AWS call a run would make for an account, listed by `--dry-run` instead of being made
+#[derive(Serialize, Debug, Clone)]
+pub struct PlannedCall {
+    pub account: String,
+    pub region: String,
+    pub operation: &'static str,
+    /// What the call is for, e.g. the role to assume or the image to save
+    pub target: String,
+}
+
+impl PlannedCall {
+    pub fn new(acc: &AccountConfig, operation: &'static str, target: impl Into<String>) -> Self {
+        PlannedCall {
+            account: acc.label(),
+            region: acc.region.clone(),
+            operation,
+            target: target.into(),
+        }
+    }
+
+    /// The `sts:AssumeRole` call every account starts with
+    pub fn assume_role(acc: &AccountConfig, options: &RunOptions) -> Self {
+        let assume_role = options.assume_role(acc);
+        let mut target = String::new();
+        if let Some(via_role_arn) = &assume_role.via_role_arn {
+            target.push_str(&format!("{} then ", via_role_arn));
+        }
+        target.push_str(&format!(
+            "{} as {}",
+            assume_role.role_arn, assume_role.session_name
+        ));
+        if let Some(seconds) = assume_role.session_duration {
+            target.push_str(&format!(" for {}s", seconds));
+        }
+        if assume_role.external_id.is_some() {
+            target.push_str(" with external id");
+        }
+        if assume_role.mfa_serial.is_some() {
+            target.push_str(" with MFA");
+        }
+        PlannedCall::new(acc, "AssumeRole", target)
+    }
+}
+
+const PLAN_COLUMNS: [&str; 4] = ["account", "region", "operation", "target"];
+
+/// Renders planned calls as a plain text table with space-padded columns
+pub fn plan_to_table(calls: &[PlannedCall]) -> String {
+    let rows: Vec<Vec<String>> = calls
+        .iter()
+        .map(|call| {
+            vec![
+                call.account.clone(),
+                call.region.clone(),
+                String::from(call.operation),
+                call.target.clone(),
+            ]
+        })
+        .collect();
+    text_table(&PLAN_COLUMNS, &rows)
+}
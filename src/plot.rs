@@ -0,0 +1,83 @@
+use crate::data::AccountMetricData;
+use crate::output::text_table;
+
+/// Characters sparklines are drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotStyle {
+    /// Block elements from `▁` to `█`
+    Unicode,
+    /// Plain ASCII from `_` to `#`, for terminals without unicode fonts
+    Ascii,
+}
+
+impl PlotStyle {
+    /// The characters from lowest to highest value
+    fn levels(&self) -> &'static [char] {
+        match self {
+            PlotStyle::Unicode => &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'],
+            PlotStyle::Ascii => &['_', '.', '-', '~', '=', '*', '#'],
+        }
+    }
+}
+
+/// Draws values as a sparkline at most `width` characters wide, averaging neighbouring values
+/// when there are more of them than characters. A flat series is drawn at half height.
+pub fn sparkline(values: &[f64], width: usize, style: PlotStyle) -> String {
+    if values.is_empty() || width == 0 {
+        return String::new();
+    }
+    let buckets = values.len().min(width);
+    let averages: Vec<f64> = (0..buckets)
+        .map(|i| {
+            let bucket = &values[i * values.len() / buckets..(i + 1) * values.len() / buckets];
+            bucket.iter().sum::<f64>() / bucket.len() as f64
+        })
+        .collect();
+    let min = averages.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = averages.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let levels = style.levels();
+    averages
+        .iter()
+        .map(|value| {
+            let level = if max > min {
+                ((value - min) / (max - min) * (levels.len() - 1) as f64).round() as usize
+            } else {
+                levels.len() / 2
+            };
+            levels[level]
+        })
+        .collect()
+}
+
+const PLOT_COLUMNS: [&str; 6] = ["program_name", "region", "id", "min", "max", "datapoints"];
+
+/// Renders every exported series as a sparkline, one row per account and query, so a glance
+/// shows whether traffic looks the same everywhere. Each row is scaled to its own min and max.
+pub fn metric_data_to_sparklines(
+    all_data: &[AccountMetricData],
+    width: usize,
+    style: PlotStyle,
+) -> String {
+    let mut rows: Vec<Vec<String>> = vec![];
+    for acc in all_data {
+        for series in &acc.series {
+            let values: Vec<f64> = series.datapoints.iter().map(|dp| dp.value).collect();
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let (min, max) = if values.is_empty() {
+                (String::new(), String::new())
+            } else {
+                (format!("{:.2}", min), format!("{:.2}", max))
+            };
+            rows.push(vec![
+                acc.program_name.clone(),
+                acc.region.clone(),
+                series.id.clone(),
+                min,
+                max,
+                sparkline(&values, width, style),
+            ]);
+        }
+    }
+    text_table(&PLOT_COLUMNS, &rows)
+}
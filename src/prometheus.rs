@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use crate::data::{AccountMetricData, MetricSeries};
+
+/// Turns a CloudWatch name like `AWS/Firehose` or `IncomingPutRequests` into the lower snake case
+/// Prometheus names use, like `aws_firehose` or `incoming_put_requests`
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            let word_start = c.is_ascii_uppercase()
+                && previous.map_or(false, |p| p.is_ascii_lowercase() || p.is_ascii_digit());
+            if word_start {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else if !snake.ends_with('_') {
+            snake.push('_');
+        }
+        previous = Some(c);
+    }
+    String::from(snake.trim_matches('_'))
+}
+
+/// Escapes a label value, which is quoted and may not contain raw backslashes, quotes or newlines
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// `cloudwatch_<namespace>_<metric>_<stat>` for a metric, `cloudwatch_<id>` for an expression
+fn family_name(series: &MetricSeries) -> String {
+    if series.metric_name.is_empty() {
+        format!("cloudwatch_{}", snake_case(&series.id))
+    } else {
+        format!(
+            "cloudwatch_{}_{}_{}",
+            snake_case(&series.namespace),
+            snake_case(&series.metric_name),
+            snake_case(&series.stat)
+        )
+    }
+}
+
+/// Writes exported datapoints in the OpenMetrics text format, one gauge per metric and statistic
+/// with `account`, `region` and `namespace` labels plus a label per dimension. Prometheus can
+/// backfill the file with `promtool tsdb create-blocks-from openmetrics`, which Thanos can then
+/// upload. Remote write needs snappy-compressed protobuf, so it isn't supported.
+pub fn metric_data_to_prometheus(all_data: &[AccountMetricData]) -> String {
+    // samples of a family have to be grouped under its TYPE line
+    let mut families: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for acc in all_data {
+        for series in &acc.series {
+            let mut labels = vec![
+                (String::from("account"), acc.program_name.clone()),
+                (String::from("region"), acc.region.clone()),
+            ];
+            if !series.namespace.is_empty() {
+                labels.push((String::from("namespace"), series.namespace.clone()));
+            }
+            labels.extend(
+                series
+                    .dimensions
+                    .iter()
+                    .map(|(name, value)| (snake_case(name), value.clone())),
+            );
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, label_value(value)))
+                .collect();
+            let family = family_name(series);
+            let samples = families.entry(family.clone()).or_default();
+            for dp in &series.datapoints {
+                let timestamp = match dp.date_time() {
+                    Some(timestamp) => timestamp.secs(),
+                    None => continue,
+                };
+                samples.push(format!(
+                    "{}{{{}}} {} {}\n",
+                    family,
+                    labels.join(","),
+                    dp.value,
+                    timestamp
+                ));
+            }
+        }
+    }
+    let mut text = String::new();
+    for (family, samples) in families {
+        text.push_str(&format!("# TYPE {} gauge\n", family));
+        text.extend(samples);
+    }
+    text.push_str("# EOF\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Datapoint;
+
+    #[test]
+    fn label_values_escape_quotes_backslashes_and_newlines() {
+        for (value, escaped) in [
+            ("orders", "orders"),
+            (r#"say "hi""#, r#"say \"hi\""#),
+            (r"C:\logs", r"C:\\logs"),
+            ("two\nlines", r"two\nlines"),
+            (r#"\"#, r#"\\"#),
+            // delimiters outside the quotes need no escaping inside them
+            ("a,b=c {d}", "a,b=c {d}"),
+        ] {
+            assert_eq!(label_value(value), escaped, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn names_replace_delimiters_with_underscores() {
+        for (name, snake) in [
+            ("AWS/Firehose", "aws_firehose"),
+            ("IncomingPutRequests", "incoming_put_requests"),
+            ("p99.9", "p99_9"),
+            ("Stream Name", "stream_name"),
+            ("--cache-hit-rate--", "cache_hit_rate"),
+        ] {
+            assert_eq!(snake_case(name), snake, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn samples_quote_escaped_labels() {
+        let data = AccountMetricData {
+            program_name: String::from("Orders \"prod\""),
+            region: String::from("us-east-1"),
+            account_id: None,
+            series: vec![MetricSeries {
+                id: String::from("errors"),
+                namespace: String::from("AWS/Lambda"),
+                metric_name: String::from("Errors"),
+                dimensions: BTreeMap::from([(
+                    String::from("FunctionName"),
+                    String::from("checkout\nv2"),
+                )]),
+                stat: String::from("Sum"),
+                period: 60,
+                datapoints: vec![Datapoint {
+                    timestamp: String::from("2024-01-01T00:00:00Z"),
+                    value: 3.0,
+                }],
+                ..Default::default()
+            }],
+        };
+        assert_eq!(
+            metric_data_to_prometheus(&[data]),
+            "# TYPE cloudwatch_aws_lambda_errors_sum gauge\n\
+             cloudwatch_aws_lambda_errors_sum{account=\"Orders \\\"prod\\\"\",region=\"us-east-1\",namespace=\"AWS/Lambda\",function_name=\"checkout\\nv2\"} 3 1704067200\n\
+             # EOF\n"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{Dimension, MetricDatum, StandardUnit};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::duration::{parse_time, TimeBound};
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+use crate::template::{render_str, template_context};
+
+/// PutMetricData takes at most this many datapoints per call
+const MAX_DATUMS: usize = 1000;
+/// PutMetricData requests may be at most 1MB, this leaves room for the namespace and the
+/// estimate in `encoded_size` being off
+const MAX_REQUEST_BYTES: usize = 900_000;
+
+/// Datapoint file formats `put` reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatapointFormat {
+    /// A JSON list of datapoints with a `dimensions` object
+    Json,
+    /// A header of `metric_name,value,timestamp,unit,dimensions` with dimensions like
+    /// `Name=Value;Name=Value`
+    Csv,
+}
+
+impl DatapointFormat {
+    /// The format of a datapoints file from its extension, CSV unless it ends in `.json`
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => DatapointFormat::Json,
+            _ => DatapointFormat::Csv,
+        }
+    }
+}
+
+/// One datapoint to publish with PutMetricData
+#[derive(Debug, Clone)]
+pub struct Datapoint {
+    pub metric_name: String,
+    /// Dimension values are templated per account, so `{{NAMESPACE}}` works as in widget JSON
+    pub dimensions: BTreeMap<String, String>,
+    pub value: f64,
+    /// When the datapoint was measured, CloudWatch uses the time it was received when unset
+    pub timestamp: Option<TimeBound>,
+    pub unit: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonDatapoint {
+    metric_name: String,
+    #[serde(default)]
+    dimensions: BTreeMap<String, String>,
+    value: f64,
+    timestamp: Option<String>,
+    unit: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CsvDatapoint {
+    metric_name: String,
+    value: f64,
+    #[serde(default)]
+    timestamp: String,
+    #[serde(default)]
+    unit: String,
+    #[serde(default)]
+    dimensions: String,
+}
+
+impl Datapoint {
+    /// Reads datapoints from a file, or from stdin when `path` is `-`
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        format: DatapointFormat,
+    ) -> Result<Vec<Datapoint>, Error> {
+        let path = path.as_ref();
+        let read_error = |source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        };
+        let contents = if path == Path::new("-") {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(read_error)?;
+            contents
+        } else {
+            std::fs::read_to_string(path).map_err(read_error)?
+        };
+        let invalid = |reason: String| Error::Datapoints {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let timestamp = |value: Option<String>| match value.filter(|value| !value.is_empty()) {
+            Some(value) => parse_time(&value).map(Some).map_err(invalid),
+            None => Ok(None),
+        };
+        match format {
+            DatapointFormat::Json => {
+                let datapoints: Vec<JsonDatapoint> =
+                    serde_json::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+                datapoints
+                    .into_iter()
+                    .map(|datapoint| {
+                        Ok(Datapoint {
+                            metric_name: datapoint.metric_name,
+                            dimensions: datapoint.dimensions,
+                            value: datapoint.value,
+                            timestamp: timestamp(datapoint.timestamp)?,
+                            unit: datapoint.unit,
+                        })
+                    })
+                    .collect()
+            }
+            DatapointFormat::Csv => csv::Reader::from_reader(contents.as_bytes())
+                .deserialize()
+                .map(|row| {
+                    let row: CsvDatapoint = row.map_err(|e| invalid(e.to_string()))?;
+                    let mut dimensions = BTreeMap::new();
+                    for pair in row.dimensions.split(';').filter(|pair| !pair.is_empty()) {
+                        let (name, value) = pair.split_once('=').ok_or_else(|| {
+                            invalid(format!("dimension {:?} is not NAME=VALUE", pair))
+                        })?;
+                        dimensions.insert(String::from(name), String::from(value));
+                    }
+                    Ok(Datapoint {
+                        metric_name: row.metric_name,
+                        dimensions,
+                        value: row.value,
+                        timestamp: timestamp(Some(row.timestamp))?,
+                        unit: Some(row.unit).filter(|unit| !unit.is_empty()),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn to_datum(
+        &self,
+        acc: &AccountConfig,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<MetricDatum, Error> {
+        let context = template_context(acc, "", "", "", vars);
+        let dimensions = self
+            .dimensions
+            .iter()
+            .map(|(name, value)| {
+                Ok(Dimension::builder()
+                    .name(name)
+                    .value(render_str(name, value, &context)?)
+                    .build())
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(MetricDatum::builder()
+            .metric_name(&self.metric_name)
+            .set_dimensions(Some(dimensions))
+            .value(self.value)
+            .set_timestamp(self.timestamp.as_ref().map(TimeBound::date_time))
+            .set_unit(self.unit.as_deref().map(StandardUnit::from))
+            .build())
+    }
+}
+
+/// Roughly how many bytes a datum adds to the form encoded request: its values plus about 60
+/// bytes for each `MetricData.member.N.Field=` key
+fn encoded_size(datum: &MetricDatum) -> usize {
+    let dimensions: usize = datum
+        .dimensions()
+        .unwrap_or_default()
+        .iter()
+        .map(|d| d.name().unwrap_or_default().len() + d.value().unwrap_or_default().len() + 2 * 60)
+        .sum();
+    datum.metric_name().unwrap_or_default().len() + dimensions + 4 * 60 + 60
+}
+
+/// Splits datums into PutMetricData calls of at most 1000 datapoints and about 1MB
+fn batches(datums: Vec<MetricDatum>) -> Vec<Vec<MetricDatum>> {
+    let mut batches: Vec<Vec<MetricDatum>> = vec![];
+    let mut batch = vec![];
+    let mut size = 0;
+    for datum in datums {
+        let datum_size = encoded_size(&datum);
+        if batch.len() == MAX_DATUMS || (!batch.is_empty() && size + datum_size > MAX_REQUEST_BYTES)
+        {
+            batches.push(std::mem::take(&mut batch));
+            size = 0;
+        }
+        size += datum_size;
+        batch.push(datum);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Publishes the same datapoints into a custom namespace of each account, e.g. to backfill
+/// synthetic metrics into test accounts
+#[derive(Debug, Clone)]
+pub struct MetricDataPublisher {
+    namespace: String,
+    datapoints: Vec<Datapoint>,
+    vars: BTreeMap<String, String>,
+    options: RunOptions,
+}
+
+impl MetricDataPublisher {
+    /// `namespace` is the CloudWatch namespace the datapoints are published in, like
+    /// `Synthetic/Backfill`
+    pub fn new(namespace: impl Into<String>, datapoints: Vec<Datapoint>) -> Self {
+        MetricDataPublisher {
+            namespace: namespace.into(),
+            datapoints,
+            vars: BTreeMap::new(),
+            options: RunOptions::default(),
+        }
+    }
+
+    /// Extra `{{KEY}}` template variables for dimension values, overriding the same key in an
+    /// account's `vars`
+    pub fn vars(mut self, vars: BTreeMap<String, String>) -> Self {
+        self.vars = vars;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the number of datapoints published per account
+    pub async fn run(&self, accounts: Vec<AccountConfig>) -> AccountResults<usize> {
+        let publisher = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let publisher = publisher.clone();
+                async move { publisher.publish(&acc).await }
+            })
+            .await
+    }
+
+    pub async fn publish(&self, acc: &AccountConfig) -> Result<usize, Error> {
+        let datums = self
+            .datapoints
+            .iter()
+            .map(|datapoint| datapoint.to_datum(acc, &self.vars))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let client = self.options.cloudwatch_client(acc).await?;
+        let batches = batches(datums);
+        debug!(account = %acc.label(), batches = batches.len(), "putting metric data");
+        let mut published = 0;
+        for batch in batches {
+            let size = batch.len();
+            put_metric_data(&client, &self.options.retry, &self.namespace, batch).await?;
+            published += size;
+        }
+        Ok(published)
+    }
+}
+
+/// Calls AWS CloudWatch PutMetricData API with one batch of datapoints
+/// API Reference: [PutMetricData](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_PutMetricData.html)
+async fn put_metric_data(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    namespace: &str,
+    datums: Vec<MetricDatum>,
+) -> Result<(), aws_sdk_cloudwatch::Error> {
+    let request = client
+        .put_metric_data()
+        .namespace(namespace)
+        .set_metric_data(Some(datums));
+    retry
+        .call("PutMetricData", || request.clone().send())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datum(metric_name: &str, dimensions: usize, value_len: usize) -> MetricDatum {
+        let dimensions = (0..dimensions)
+            .map(|i| {
+                Dimension::builder()
+                    .name(format!("Dimension{}", i))
+                    .value("x".repeat(value_len))
+                    .build()
+            })
+            .collect();
+        MetricDatum::builder()
+            .metric_name(metric_name)
+            .set_dimensions(Some(dimensions))
+            .value(1.0)
+            .build()
+    }
+
+    fn lens(batches: &[Vec<MetricDatum>]) -> Vec<usize> {
+        batches.iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn splits_at_the_datum_limit() {
+        let small = |count: usize| {
+            (0..count)
+                .map(|i| datum(&format!("m{}", i), 1, 8))
+                .collect()
+        };
+        assert!(batches(vec![]).is_empty());
+        assert_eq!(lens(&batches(small(999))), [999]);
+        assert_eq!(lens(&batches(small(1000))), [1000]);
+        assert_eq!(lens(&batches(small(1001))), [1000, 1]);
+        assert_eq!(lens(&batches(small(2000))), [1000, 1000]);
+    }
+
+    #[test]
+    fn splits_before_the_request_size_limit() {
+        // 30 dimensions with 250 character values, about 11KB each, so only about 80 fit
+        let datums: Vec<MetricDatum> = (0..1000)
+            .map(|i| datum(&format!("m{}", i), 30, 250))
+            .collect();
+        let split = batches(datums);
+        assert!(split.len() > 1);
+        for batch in &split {
+            assert!(batch.len() <= MAX_DATUMS);
+            assert!(batch.iter().map(encoded_size).sum::<usize>() <= MAX_REQUEST_BYTES);
+        }
+        // a batch is only cut when the next datum wouldn't fit
+        for pair in split.windows(2) {
+            let size: usize = pair[0].iter().map(encoded_size).sum();
+            assert!(size + encoded_size(&pair[1][0]) > MAX_REQUEST_BYTES);
+        }
+        // every datum is sent once, in order
+        let names: Vec<String> = split
+            .iter()
+            .flatten()
+            .map(|d| String::from(d.metric_name().unwrap()))
+            .collect();
+        let expected: Vec<String> = (0..1000).map(|i| format!("m{}", i)).collect();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn oversized_datum_goes_alone() {
+        let huge = datum("huge", 4000, 250);
+        assert!(encoded_size(&huge) > MAX_REQUEST_BYTES);
+        let split = batches(vec![datum("a", 1, 8), huge, datum("b", 1, 8)]);
+        assert_eq!(lens(&split), [1, 1, 1]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+
+use crate::alarms::MetricAlarmDetails;
+
+/// One downloaded image and the account it belongs to
+#[derive(Debug, Clone)]
+pub struct ReportImage {
+    pub namespace: String,
+    pub region: String,
+    pub path: PathBuf,
+}
+
+/// Alarms described for one account, summarized in the markdown report
+#[derive(Debug, Clone)]
+pub struct ReportAlarms {
+    pub namespace: String,
+    pub region: String,
+    pub alarms: Vec<MetricAlarmDetails>,
+}
+
+/// Gallery of the images saved by one run, grouped by namespace and region
+#[derive(Debug, Clone)]
+pub struct ImageReport {
+    title: String,
+    start: String,
+    end: String,
+    generated: String,
+    images: Vec<ReportImage>,
+    alarms: Vec<ReportAlarms>,
+}
+
+impl ImageReport {
+    pub fn new(title: impl Into<String>, start: impl Into<String>, end: impl Into<String>) -> Self {
+        ImageReport {
+            title: title.into(),
+            start: start.into(),
+            end: end.into(),
+            generated: DateTime::from(SystemTime::now())
+                .fmt(Format::DateTime)
+                .unwrap_or_default(),
+            images: vec![],
+            alarms: vec![],
+        }
+    }
+
+    pub fn image(&mut self, namespace: &str, region: &str, path: impl Into<PathBuf>) {
+        self.images.push(ReportImage {
+            namespace: String::from(namespace),
+            region: String::from(region),
+            path: path.into(),
+        });
+    }
+
+    pub fn alarms(&mut self, namespace: &str, region: &str, alarms: Vec<MetricAlarmDetails>) {
+        self.alarms.push(ReportAlarms {
+            namespace: String::from(namespace),
+            region: String::from(region),
+            alarms,
+        });
+    }
+
+    fn grouped(&self) -> BTreeMap<&str, BTreeMap<&str, Vec<&ReportImage>>> {
+        let mut grouped: BTreeMap<&str, BTreeMap<&str, Vec<&ReportImage>>> = BTreeMap::new();
+        for image in &self.images {
+            grouped
+                .entry(image.namespace.as_str())
+                .or_default()
+                .entry(image.region.as_str())
+                .or_default()
+                .push(image);
+        }
+        grouped
+    }
+
+    /// Renders an `index.html` gallery. Images are linked by file name, so the report belongs in
+    /// the directory the images were saved to.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape_html(&self.title)));
+        html.push_str(
+            "<style>body { font-family: sans-serif; margin: 2em; } \
+             figure { display: inline-block; margin: 0 1em 1em 0; } \
+             img { max-width: 100%; border: 1px solid #ddd; }</style>\n",
+        );
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!("<h1>{}</h1>\n", escape_html(&self.title)));
+        html.push_str(&format!(
+            "<p>Time range: {} to {} ago. Generated {}.</p>\n",
+            escape_html(&self.start),
+            escape_html(&self.end),
+            escape_html(&self.generated)
+        ));
+        for (namespace, regions) in self.grouped() {
+            html.push_str(&format!("<h2>{}</h2>\n", escape_html(namespace)));
+            for (region, images) in regions {
+                html.push_str(&format!("<h3>{}</h3>\n", escape_html(region)));
+                for image in images {
+                    let src = escape_html(&file_name(&image.path));
+                    html.push_str(&format!(
+                        "<figure><a href=\"{src}\"><img src=\"{src}\" alt=\"{src}\"></a>\
+                         <figcaption>{src}</figcaption></figure>\n",
+                        src = src
+                    ));
+                }
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+
+    /// Renders a markdown report with image links per namespace and region and, when alarms were
+    /// added, a table of alarm states per account followed by the alarms currently firing.
+    /// Images are linked by file name, like the HTML report.
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!("# {}\n\n", escape_markdown(&self.title));
+        md.push_str(&format!(
+            "Time range: {} to {} ago. Generated {}.\n",
+            self.start, self.end, self.generated
+        ));
+
+        if !self.alarms.is_empty() {
+            md.push_str("\n## Alarms\n\n");
+            md.push_str("| namespace | region | alarms | ALARM | OK | INSUFFICIENT_DATA |\n");
+            md.push_str("| --- | --- | --- | --- | --- | --- |\n");
+            for account in &self.alarms {
+                let count = |state: &str| {
+                    account
+                        .alarms
+                        .iter()
+                        .filter(|alarm| alarm.state_value == state)
+                        .count()
+                };
+                md.push_str(&format!(
+                    "| {} | {} | {} | {} | {} | {} |\n",
+                    escape_markdown(&account.namespace),
+                    escape_markdown(&account.region),
+                    account.alarms.len(),
+                    count("ALARM"),
+                    count("OK"),
+                    count("INSUFFICIENT_DATA")
+                ));
+            }
+
+            let firing: Vec<(&ReportAlarms, &MetricAlarmDetails)> = self
+                .alarms
+                .iter()
+                .flat_map(|account| {
+                    account
+                        .alarms
+                        .iter()
+                        .filter(|alarm| alarm.state_value == "ALARM")
+                        .map(move |alarm| (account, alarm))
+                })
+                .collect();
+            if !firing.is_empty() {
+                md.push_str("\n### Firing\n\n");
+                md.push_str("| namespace | region | alarm | reason |\n");
+                md.push_str("| --- | --- | --- | --- |\n");
+                for (account, alarm) in firing {
+                    md.push_str(&format!(
+                        "| {} | {} | {} | {} |\n",
+                        escape_markdown(&account.namespace),
+                        escape_markdown(&account.region),
+                        escape_markdown(&alarm.alarm_name),
+                        escape_markdown(&alarm.state_reason)
+                    ));
+                }
+            }
+        }
+
+        for (namespace, regions) in self.grouped() {
+            md.push_str(&format!("\n## {}\n", escape_markdown(namespace)));
+            for (region, images) in regions {
+                md.push_str(&format!("\n### {}\n\n", escape_markdown(region)));
+                for image in images {
+                    let name = file_name(&image.path);
+                    md.push_str(&format!("![{}]({})\n", name, name.replace(' ', "%20")));
+                }
+            }
+        }
+        md
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,357 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use aws_smithy_http::result::SdkError;
+use aws_smithy_types::retry::{ErrorKind, ProvideErrorKind};
+use aws_smithy_types::timeout::TimeoutConfig;
+use rand::Rng;
+use tracing::{debug, warn};
+
+/// Error codes AWS services use when a caller is being rate limited
+const THROTTLING_CODES: [&str; 7] = [
+    "Throttling",
+    "ThrottlingException",
+    "ThrottledException",
+    "RequestLimitExceeded",
+    "RequestThrottled",
+    "TooManyRequestsException",
+    "LimitExceededException",
+];
+
+/// How a failed call is treated by the [`RetryPolicy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The service is rate limiting us
+    Throttling,
+    /// Timeouts, connection failures and 5xx errors
+    Transient,
+    /// Anything else, e.g. access denied or a bad request
+    Other,
+}
+
+/// Classifies an SDK error by its error code, falling back to the SDK's own retry hint
+pub fn classify<E: ProvideErrorKind>(err: &SdkError<E>) -> ErrorClass {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError { .. } => ErrorClass::Transient,
+        SdkError::DispatchFailure(e) if e.is_timeout() || e.is_io() => ErrorClass::Transient,
+        SdkError::ServiceError { err, .. } => {
+            if err
+                .code()
+                .is_some_and(|code| THROTTLING_CODES.contains(&code))
+            {
+                return ErrorClass::Throttling;
+            }
+            match err.retryable_error_kind() {
+                Some(ErrorKind::ThrottlingError) => ErrorClass::Throttling,
+                Some(ErrorKind::TransientError) | Some(ErrorKind::ServerError) => {
+                    ErrorClass::Transient
+                }
+                _ => ErrorClass::Other,
+            }
+        }
+        _ => ErrorClass::Other,
+    }
+}
+
+/// Retries throttled and transient AWS calls with capped exponential backoff and full jitter,
+/// counting the retries per operation so they can be reported at the end of a run.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub retry_throttling: bool,
+    pub retry_transient: bool,
+    /// Longest a single attempt may take before it fails as a transient timeout
+    pub request_timeout: Option<Duration>,
+    retried: Arc<Mutex<BTreeMap<String, u32>>>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(20),
+            retry_throttling: true,
+            retry_transient: true,
+            request_timeout: None,
+            retried: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Total attempts per call, including the first one
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Backoff before the first retry, doubled for every retry after it
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Upper bound for the backoff between two attempts
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn retry_throttling(mut self, retry: bool) -> Self {
+        self.retry_throttling = retry;
+        self
+    }
+
+    pub fn retry_transient(mut self, retry: bool) -> Self {
+        self.retry_transient = retry;
+        self
+    }
+
+    /// Fail an attempt that takes longer than this, so a hung connection is retried instead of
+    /// stalling its account
+    pub fn request_timeout(mut self, request_timeout: Option<Duration>) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// SDK timeouts matching `request_timeout`, for the clients built for an account
+    pub fn timeout_config(&self) -> TimeoutConfig {
+        let mut builder = TimeoutConfig::builder();
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.operation_attempt_timeout(timeout);
+        }
+        builder.build()
+    }
+
+    fn should_retry(&self, class: ErrorClass) -> bool {
+        match class {
+            ErrorClass::Throttling => self.retry_throttling,
+            ErrorClass::Transient => self.retry_transient,
+            ErrorClass::Other => false,
+        }
+    }
+
+    /// Full-jitter backoff: a random delay up to `base_delay * 2^retry`, capped at `max_delay`
+    fn backoff(&self, retry: u32) -> Duration {
+        let cap = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        cap.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    /// Runs `call` until it succeeds, fails with an error that isn't retried, or runs out of
+    /// attempts. `operation` names the call in the retry summary. Attempts taking longer than
+    /// `request_timeout` fail with [`SdkError::TimeoutError`], also for clients loaded without
+    /// [`RetryPolicy::timeout_config`].
+    pub async fn call<T, E, F, Fut>(&self, operation: &str, mut call: F) -> Result<T, SdkError<E>>
+    where
+        E: ProvideErrorKind,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E>>>,
+    {
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let res = match self.request_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, call())
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(SdkError::TimeoutError(
+                            format!("no response within {:?}", timeout).into(),
+                        ))
+                    }),
+                None => call().await,
+            };
+            match res {
+                Err(err) if attempt < self.max_attempts && self.should_retry(classify(&err)) => {
+                    let delay = self.backoff(attempt - 1);
+                    warn!(
+                        operation,
+                        class = ?classify(&err),
+                        ?delay,
+                        attempt = attempt + 1,
+                        max_attempts = self.max_attempts,
+                        "retrying failed call"
+                    );
+                    *self
+                        .retried
+                        .lock()
+                        .unwrap()
+                        .entry(String::from(operation))
+                        .or_default() += 1;
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                res => {
+                    debug!(
+                        operation,
+                        duration_ms = started.elapsed().as_millis() as u64,
+                        attempts = attempt,
+                        ok = res.is_ok(),
+                        "finished call"
+                    );
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Number of retries per operation so far, shared by every clone of this policy
+    pub fn retried_calls(&self) -> BTreeMap<String, u32> {
+        self.retried.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use aws_smithy_http::body::SdkBody;
+    use aws_smithy_http::operation;
+    use aws_smithy_http::result::ConnectorError;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct ServiceError {
+        code: &'static str,
+        kind: Option<ErrorKind>,
+    }
+
+    impl ProvideErrorKind for ServiceError {
+        fn retryable_error_kind(&self) -> Option<ErrorKind> {
+            self.kind
+        }
+
+        fn code(&self) -> Option<&str> {
+            Some(self.code)
+        }
+    }
+
+    fn service_error(status: u16, code: &'static str) -> SdkError<ServiceError> {
+        // the generated clients mark 5xx errors as server errors
+        let kind = (status >= 500).then_some(ErrorKind::ServerError);
+        let raw = hyper::Response::builder()
+            .status(status)
+            .body(SdkBody::empty())
+            .unwrap();
+        SdkError::ServiceError {
+            err: ServiceError { code, kind },
+            raw: operation::Response::new(raw),
+        }
+    }
+
+    #[test]
+    fn classify_retries_throttling_and_server_errors() {
+        for code in THROTTLING_CODES {
+            assert_eq!(
+                classify(&service_error(400, code)),
+                ErrorClass::Throttling,
+                "{}",
+                code
+            );
+        }
+        assert_eq!(
+            classify(&service_error(500, "InternalFailure")),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify(&service_error(503, "ServiceUnavailable")),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify::<ServiceError>(&SdkError::TimeoutError("slow".into())),
+            ErrorClass::Transient
+        );
+        assert_eq!(
+            classify::<ServiceError>(&SdkError::DispatchFailure(ConnectorError::io(
+                "reset".into()
+            ))),
+            ErrorClass::Transient
+        );
+    }
+
+    #[test]
+    fn classify_leaves_client_errors_alone() {
+        for code in ["AccessDenied", "ValidationError", "ResourceNotFound"] {
+            assert_eq!(
+                classify(&service_error(400, code)),
+                ErrorClass::Other,
+                "{}",
+                code
+            );
+        }
+        assert_eq!(
+            classify::<ServiceError>(&SdkError::ConstructionFailure("bad input".into())),
+            ErrorClass::Other
+        );
+    }
+
+    #[test]
+    fn backoff_stays_under_the_cap() {
+        let policy = RetryPolicy::default()
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(2));
+        for retry in 0..40 {
+            let cap = Duration::from_millis(100)
+                .saturating_mul(2u32.saturating_pow(retry))
+                .min(Duration::from_secs(2));
+            for _ in 0..100 {
+                assert!(policy.backoff(retry) <= cap, "retry {}", retry);
+            }
+        }
+    }
+
+    async fn attempts(policy: &RetryPolicy, status: u16, code: &'static str) -> u32 {
+        let calls = AtomicU32::new(0);
+        let res: Result<(), _> = policy
+            .call("GetMetricData", || {
+                calls.fetch_add(1, Ordering::SeqCst);
+                std::future::ready(Err(service_error(status, code)))
+            })
+            .await;
+        assert!(res.is_err());
+        calls.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn call_stops_at_max_attempts() {
+        let policy = RetryPolicy::default()
+            .max_attempts(3)
+            .base_delay(Duration::from_millis(1));
+        assert_eq!(attempts(&policy, 400, "ThrottlingException").await, 3);
+        assert_eq!(attempts(&policy, 503, "ServiceUnavailable").await, 3);
+        assert_eq!(attempts(&policy, 403, "AccessDenied").await, 1);
+        assert_eq!(
+            policy.retried_calls(),
+            BTreeMap::from([(String::from("GetMetricData"), 4)])
+        );
+
+        let policy = policy.retry_throttling(false);
+        assert_eq!(attempts(&policy, 400, "ThrottlingException").await, 1);
+    }
+
+    #[tokio::test]
+    async fn call_returns_the_first_success() {
+        let policy = RetryPolicy::default().base_delay(Duration::from_millis(1));
+        let calls = AtomicU32::new(0);
+        let res = policy
+            .call("GetMetricData", || {
+                let call = calls.fetch_add(1, Ordering::SeqCst);
+                std::future::ready(if call < 2 {
+                    Err(service_error(400, "Throttling"))
+                } else {
+                    Ok(call)
+                })
+            })
+            .await;
+        assert_eq!(res.unwrap(), 2);
+        assert_eq!(policy.retried_calls()["GetMetricData"], 2);
+    }
+}
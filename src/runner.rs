@@ -0,0 +1,362 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use aws_sdk_cloudwatch::Client as cloudwatchClient;
+use aws_sdk_cloudwatchlogs::Client as logsClient;
+use aws_sdk_sns::Client as snsClient;
+use aws_types::SdkConfig;
+use futures_util::FutureExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use tokio::task::JoinSet;
+use tracing::{debug, info_span, warn, Instrument};
+
+use crate::aws_regions;
+use crate::client::{get_config_with_role, get_sts_client, AssumeRoleOptions, CredentialCache};
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+
+/// How long accounts in flight may keep running after Ctrl-C before they are abandoned
+const INTERRUPT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// One `(account label, result)` pair per account, in config order
+pub type AccountResults<T> = Vec<(String, Result<T, Error>)>;
+
+/// Settings shared by every exporter for how accounts are processed
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub concurrency: usize,
+    pub session_name: Option<String>,
+    pub session_duration: Option<i32>,
+    pub credential_cache: Arc<CredentialCache>,
+    pub retry: RetryPolicy,
+    pub progress: bool,
+    pub fail_fast: bool,
+    pub deadline: Option<Instant>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            concurrency: 4,
+            session_name: None,
+            session_duration: None,
+            credential_cache: Arc::new(CredentialCache::default()),
+            retry: RetryPolicy::default(),
+            progress: false,
+            fail_fast: false,
+            deadline: None,
+        }
+    }
+}
+
+impl RunOptions {
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn session_name(mut self, session_name: Option<String>) -> Self {
+        self.session_name = session_name;
+        self
+    }
+
+    pub fn session_duration(mut self, seconds: Option<i32>) -> Self {
+        self.session_duration = seconds;
+        self
+    }
+
+    pub fn credential_cache(mut self, credential_cache: Arc<CredentialCache>) -> Self {
+        self.credential_cache = credential_cache;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Show a progress bar on stderr with the accounts completed and each account's outcome
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Stop starting accounts once one fails. Accounts already in flight still finish, the rest
+    /// fail with [`Error::Skipped`].
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Stop once this instant passes: no more accounts are started and those in flight fail
+    /// with [`Error::DeadlineExceeded`]
+    pub fn deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn assume_role(&self, acc: &AccountConfig) -> AssumeRoleOptions {
+        AssumeRoleOptions::for_account(acc, self.session_name.as_deref(), self.session_duration)
+    }
+
+    /// Loads an SDK config for the account's region using its assumed role
+    pub async fn sdk_config(&self, acc: &AccountConfig) -> Result<SdkConfig, Error> {
+        let region = aws_regions::parse_region(&acc.region).map_err(Error::Region)?;
+        let sts_client = get_sts_client(&region).await;
+        get_config_with_role(
+            &region,
+            &self.assume_role(acc),
+            &sts_client,
+            &self.credential_cache,
+            &self.retry,
+        )
+        .await
+    }
+
+    /// Builds a CloudWatch client for the account's region using its assumed role
+    pub async fn cloudwatch_client(&self, acc: &AccountConfig) -> Result<cloudwatchClient, Error> {
+        Ok(cloudwatchClient::new(&self.sdk_config(acc).await?))
+    }
+
+    /// Builds a CloudWatch Logs client for the account's region using its assumed role
+    pub async fn logs_client(&self, acc: &AccountConfig) -> Result<logsClient, Error> {
+        Ok(logsClient::new(&self.sdk_config(acc).await?))
+    }
+
+    /// Builds an SNS client for the account's region using its assumed role
+    pub async fn sns_client(&self, acc: &AccountConfig) -> Result<snsClient, Error> {
+        Ok(snsClient::new(&self.sdk_config(acc).await?))
+    }
+
+    /// Runs `task` for every account with at most `concurrency` accounts in flight. A failure
+    /// or panic in one account does not stop the others unless `fail_fast` is set; results are
+    /// returned in config order. On Ctrl-C no more accounts are started and those in flight get
+    /// a grace period to finish; the rest fail with [`Error::Interrupted`]. Past the `deadline`
+    /// the unfinished accounts fail with [`Error::DeadlineExceeded`].
+    pub async fn run<T, F, Fut>(&self, accounts: Vec<AccountConfig>, task: F) -> AccountResults<T>
+    where
+        T: Send + 'static,
+        F: Fn(AccountConfig) -> Fut,
+        Fut: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let progress = self.progress_bar(accounts.len());
+        let finish = |(index, account, res): (usize, String, Result<T, Error>)| {
+            let status = if res.is_ok() { "done" } else { "failed" };
+            progress.println(format!("{:>6} {}", status, account));
+            progress.inc(1);
+            (index, account, res)
+        };
+
+        let mut results = vec![];
+        // the first account to fail, after which `fail_fast` skips the rest
+        let mut first_failure: Option<String> = None;
+        // set by Ctrl-C: no more accounts are started and the ones in flight get until then
+        let mut deadline: Option<tokio::time::Instant> = None;
+        let run_deadline = self.deadline.map(tokio::time::Instant::from_std);
+        let mut timed_out = false;
+        let mut in_flight: BTreeMap<usize, String> = BTreeMap::new();
+        let mut pending = accounts.into_iter().enumerate();
+        let mut tasks = JoinSet::new();
+        // kept across passes of the loop so a Ctrl-C between two passes isn't missed
+        let mut ctrl_c = Box::pin(tokio::signal::ctrl_c());
+        loop {
+            if run_deadline.is_some_and(|at| at <= tokio::time::Instant::now()) {
+                timed_out = true;
+                break;
+            }
+            while deadline.is_none() && tasks.len() < self.concurrency.max(1) {
+                let Some((index, acc)) = pending.next() else {
+                    break;
+                };
+                let account = acc.label();
+                if let Some(failed) = &first_failure {
+                    progress.inc(1);
+                    results.push((index, account, Err(Error::Skipped(failed.clone()))));
+                    continue;
+                }
+                in_flight.insert(index, account.clone());
+                self.spawn(&mut tasks, index, acc, &task);
+                progress.set_message(account);
+            }
+            if tasks.is_empty() {
+                break;
+            }
+            tokio::select! {
+                Some(joined) = tasks.join_next() => {
+                    let (index, account, res) =
+                        finish(joined.expect("account tasks catch their own panics and aren't aborted yet"));
+                    in_flight.remove(&index);
+                    if self.fail_fast && res.is_err() && first_failure.is_none() {
+                        first_failure = Some(account.clone());
+                    }
+                    results.push((index, account, res));
+                }
+                _ = &mut ctrl_c => {
+                    ctrl_c.set(tokio::signal::ctrl_c());
+                    if deadline.is_some() {
+                        break;
+                    }
+                    warn!(
+                        accounts = in_flight.len(),
+                        "interrupted, waiting up to {}s for the accounts in flight, press Ctrl-C again to stop now",
+                        INTERRUPT_GRACE_PERIOD.as_secs()
+                    );
+                    deadline = Some(tokio::time::Instant::now() + INTERRUPT_GRACE_PERIOD);
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => {
+                    break;
+                }
+                _ = tokio::time::sleep_until(run_deadline.unwrap_or_else(tokio::time::Instant::now)), if run_deadline.is_some() => {
+                    timed_out = true;
+                    break;
+                }
+            }
+        }
+        // whatever is left was stopped before starting or finishing. The account futures run in
+        // the join set itself, so once it's drained none of them is running anymore.
+        tasks.abort_all();
+        while let Some(joined) = tasks.join_next().await {
+            // an account may have finished before its abort was seen
+            if let Ok((index, account, res)) = joined {
+                in_flight.remove(&index);
+                results.push(finish((index, account, res)));
+            }
+        }
+        if timed_out {
+            warn!(
+                accounts = in_flight.len() + pending.len(),
+                "run deadline passed, giving up on the unfinished accounts"
+            );
+        }
+        let unfinished = || {
+            if timed_out {
+                Error::DeadlineExceeded
+            } else {
+                Error::Interrupted
+            }
+        };
+        for (index, account) in in_flight {
+            results.push((index, account, Err(unfinished())));
+        }
+        for (index, acc) in pending {
+            results.push((index, acc.label(), Err(unfinished())));
+        }
+        progress.finish_and_clear();
+        results.sort_by_key(|(index, _, _)| *index);
+        results
+            .into_iter()
+            .map(|(_, account, res)| (account, res))
+            .collect()
+    }
+
+    /// Starts an account's task in its own span, reporting a panic against the account
+    fn spawn<T, F, Fut>(
+        &self,
+        tasks: &mut JoinSet<(usize, String, Result<T, Error>)>,
+        index: usize,
+        acc: AccountConfig,
+        task: &F,
+    ) where
+        T: Send + 'static,
+        F: Fn(AccountConfig) -> Fut,
+        Fut: Future<Output = Result<T, Error>> + Send + 'static,
+    {
+        let account = acc.label();
+        // every event logged while working on the account carries its namespace and region
+        let span = info_span!("account", account = %acc.namespace, region = %acc.region);
+        let started = Instant::now();
+        let work = task(acc);
+        tasks.spawn(
+            async move {
+                // catch a panic here so it's reported against the account
+                let res = match AssertUnwindSafe(work).catch_unwind().await {
+                    Ok(res) => res,
+                    Err(panic) => Err(Error::Panic(panic_message(panic.as_ref()))),
+                };
+                let duration_ms = started.elapsed().as_millis() as u64;
+                match &res {
+                    Ok(_) => debug!(duration_ms, "account succeeded"),
+                    Err(e) => debug!(duration_ms, error = %e, "account failed"),
+                }
+                (index, account, res)
+            }
+            .instrument(span),
+        );
+    }
+
+    fn progress_bar(&self, accounts: usize) -> ProgressBar {
+        if !self.progress {
+            return ProgressBar::hidden();
+        }
+        let progress = ProgressBar::new(accounts as u64);
+        progress.set_style(
+            ProgressStyle::with_template("{bar:30} {pos}/{len} accounts, last started: {msg}")
+                .expect("progress template is valid"),
+        );
+        progress
+    }
+}
+
+/// The message a panic was raised with, when it's a string
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|message| String::from(*message))
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("panicked"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    fn account(namespace: &str) -> AccountConfig {
+        AccountConfig::new(
+            namespace,
+            "us-east-1",
+            "arn:aws:iam::111111111111:role/Test",
+        )
+    }
+
+    #[tokio::test]
+    async fn deadline_drops_accounts_still_running() {
+        let finished = Arc::new(AtomicBool::new(false));
+        let options =
+            RunOptions::default().deadline(Some(Instant::now() + Duration::from_millis(50)));
+        let flag = finished.clone();
+        let results = options
+            .run(vec![account("slow")], move |_| {
+                let flag = flag.clone();
+                async move {
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    flag.store(true, Ordering::SeqCst);
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(matches!(results[0].1, Err(Error::DeadlineExceeded)));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!finished.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn panic_is_reported_against_its_account() {
+        let results = RunOptions::default()
+            .run(vec![account("ok"), account("broken")], |acc| async move {
+                if acc.namespace == "broken" {
+                    panic!("boom");
+                }
+                Ok(acc.namespace)
+            })
+            .await;
+        assert_eq!(results[0].1.as_ref().unwrap(), "ok");
+        assert!(matches!(&results[1].1, Err(Error::Panic(message)) if message == "boom"));
+    }
+}
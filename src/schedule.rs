@@ -0,0 +1,651 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{Datelike, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::error::Error;
+use crate::output::write_output;
+
+/// How far ahead to look for a matching time, long enough for a job on February 29th
+const SEARCH_DAYS: i64 = 8 * 366;
+
+/// Longest single sleep while waiting for a job, so a suspended host catches up soon after waking
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A schedule file of `[[job]]` tables, each running one `cw-metrics` command
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScheduleConfig {
+    /// Timezone the cron expressions are in, like `Europe/Berlin`; UTC when unset
+    pub timezone: Option<String>,
+    pub job: Vec<ScheduleJob>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScheduleJob {
+    /// Names the job's log file and its entry in the state file
+    pub name: String,
+    /// Five fields (minute, hour, day of month, month, day of week) like `0 6 * * mon-fri`, or
+    /// `@hourly`, `@daily`, `@weekly` and `@monthly`
+    pub cron: String,
+    /// Subcommand and its arguments, e.g. `["alarms", "-o", "./out/alarms.csv", "accounts.toml"]`
+    pub args: Vec<String>,
+}
+
+impl ScheduleConfig {
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: PathBuf::from(path),
+            source,
+        })?;
+        toml::from_str(&contents).map_err(|source| Error::Schedule {
+            path: PathBuf::from(path),
+            source,
+        })
+    }
+}
+
+/// Minutes, hours, days and so on matched by a cron expression, one bit per value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Like cron, when both the day of month and day of week are restricted either can match
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidArgument {
+            name: "cron expression",
+            value: String::from(expression),
+        };
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(invalid());
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAYS, 0).ok_or_else(invalid)?;
+        // both 0 and 7 are Sunday
+        if weekdays & 1 << 7 != 0 {
+            weekdays |= 1;
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(minute, 0, 59, &[], 0).ok_or_else(invalid)?,
+            hours: parse_field(hour, 0, 23, &[], 0).ok_or_else(invalid)?,
+            days: parse_field(day, 1, 31, &[], 0).ok_or_else(invalid)?,
+            months: parse_field(month, 1, 12, &MONTHS, 1).ok_or_else(invalid)?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+}
+
+impl CronSchedule {
+    /// The first matching minute strictly after `after`. Local times skipped by a daylight saving
+    /// change don't run, and repeated ones run at their first occurrence still to come.
+    pub fn next_after<T: TimeZone>(
+        &self,
+        after: &chrono::DateTime<T>,
+    ) -> Option<chrono::DateTime<T>> {
+        let tz = after.timezone();
+        let local = after.naive_local();
+        let mut t = local.date().and_hms_opt(local.hour(), local.minute(), 0)?
+            + chrono::Duration::minutes(1);
+        let limit = t + chrono::Duration::days(SEARCH_DAYS);
+        while t < limit {
+            let date = t.date();
+            if !matches(self.months, date.month()) {
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                t = midnight(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.matches_day(date) {
+                t = midnight(date.succ_opt()?);
+            } else if !matches(self.hours, t.hour()) {
+                t = date.and_hms_opt(t.hour(), 0, 0)? + chrono::Duration::hours(1);
+            } else if !matches(self.minutes, t.minute()) {
+                t += chrono::Duration::minutes(1);
+            } else {
+                match tz.from_local_datetime(&t) {
+                    LocalResult::Single(at) if at > *after => return Some(at),
+                    // starting during the repeated hour, its first pass may be behind us
+                    LocalResult::Ambiguous(earlier, _) if earlier > *after => return Some(earlier),
+                    LocalResult::Ambiguous(_, later) if later > *after => return Some(later),
+                    _ => t += chrono::Duration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = matches(self.days, date.day());
+        let weekday = matches(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (false, true) => day,
+            (true, false) => weekday,
+            (false, false) => day || weekday,
+        }
+    }
+}
+
+fn matches(bits: u64, value: u32) -> bool {
+    bits & 1 << value != 0
+}
+
+fn midnight(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).expect("midnight is a valid time")
+}
+
+/// Parses a comma separated list of `*`, values and `a-b` ranges, each optionally with a `/step`.
+/// `names` are accepted in place of the values starting at `first_name`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], first_name: u32) -> Option<u64> {
+    let value = |s: &str| -> Option<u32> {
+        match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(index) => Some(index as u32 + first_name),
+            None => s.parse().ok(),
+        }
+    };
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` means every 15 starting at 5
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for v in (start..=end).step_by(step) {
+            bits |= 1 << v;
+        }
+    }
+    Some(bits)
+}
+
+/// What the state file records for each job, kept across restarts
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct JobState {
+    pub next_run: Option<String>,
+    pub last_start: Option<String>,
+    pub last_finish: Option<String>,
+    /// `succeeded` or `failed`
+    pub last_status: Option<String>,
+    pub last_exit_code: Option<i32>,
+    pub last_duration_secs: Option<u64>,
+    pub runs: u64,
+    pub failures: u64,
+}
+
+/// Runs each job of a schedule as a child process whenever its cron expression matches, until
+/// Ctrl-C. Every job's output is appended to `<state dir>/logs/<name>.log` and the outcome of
+/// its last run is kept in `<state dir>/state.json`. A job still running when it's due again is
+/// skipped rather than started twice.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    jobs: Vec<(ScheduleJob, CronSchedule)>,
+    timezone: Tz,
+    state_dir: PathBuf,
+    program: PathBuf,
+}
+
+impl Scheduler {
+    /// Checks every job's cron expression and arguments, and that job names are unique
+    pub fn new(config: ScheduleConfig) -> Result<Self, Error> {
+        let timezone = match &config.timezone {
+            Some(timezone) => timezone
+                .parse()
+                .map_err(|_| Error::InvalidSchedule(format!("unknown timezone {:?}", timezone)))?,
+            None => Tz::UTC,
+        };
+        if config.job.is_empty() {
+            return Err(Error::InvalidSchedule(String::from("no [[job]] tables")));
+        }
+        let mut jobs: Vec<(ScheduleJob, CronSchedule)> = vec![];
+        for job in config.job {
+            if job.name.is_empty() || job.name.contains(['/', '\\']) {
+                return Err(Error::InvalidSchedule(format!(
+                    "job name {:?} can't be empty or contain a path separator",
+                    job.name
+                )));
+            }
+            if jobs.iter().any(|(other, _)| other.name == job.name) {
+                return Err(Error::InvalidSchedule(format!(
+                    "job {} is defined more than once",
+                    job.name
+                )));
+            }
+            match job.args.first().map(String::as_str) {
+                None => {
+                    return Err(Error::InvalidSchedule(format!(
+                        "job {} has no args",
+                        job.name
+                    )))
+                }
+                Some("schedule") => {
+                    return Err(Error::InvalidSchedule(format!(
+                        "job {} can't run schedule itself",
+                        job.name
+                    )))
+                }
+                Some(_) => {}
+            }
+            let cron = job
+                .cron
+                .parse::<CronSchedule>()
+                .map_err(|e| Error::InvalidSchedule(format!("job {}: {}", job.name, e)))?;
+            jobs.push((job, cron));
+        }
+        Ok(Scheduler {
+            jobs,
+            timezone,
+            state_dir: PathBuf::from("schedule-state"),
+            program: std::env::current_exe().unwrap_or_else(|_| PathBuf::from("cw-metrics")),
+        })
+    }
+
+    /// Directory for the state file and job logs, created if it doesn't exist
+    pub fn state_dir(mut self, state_dir: impl Into<PathBuf>) -> Self {
+        self.state_dir = state_dir.into();
+        self
+    }
+
+    /// Executable the jobs run, the current one by default
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    /// The next `count` times each job runs, in the schedule's timezone
+    pub fn upcoming(&self, count: usize) -> Vec<(&ScheduleJob, Vec<chrono::DateTime<Tz>>)> {
+        let now = Utc::now().with_timezone(&self.timezone);
+        self.jobs
+            .iter()
+            .map(|(job, cron)| {
+                let runs = std::iter::successors(cron.next_after(&now), |at| cron.next_after(at))
+                    .take(count)
+                    .collect();
+                (job, runs)
+            })
+            .collect()
+    }
+
+    /// Schedules jobs until Ctrl-C, then waits for the running ones to finish
+    pub async fn run(&self) -> Result<(), Error> {
+        let logs_dir = self.state_dir.join("logs");
+        tokio::fs::create_dir_all(&logs_dir)
+            .await
+            .map_err(|source| Error::Write {
+                path: logs_dir.clone(),
+                source,
+            })?;
+        let state_path = self.state_dir.join("state.json");
+        let state = Arc::new(Mutex::new(load_state(&state_path).await));
+        let mut running: BTreeMap<String, JoinHandle<()>> = BTreeMap::new();
+
+        'schedule: loop {
+            let now = Utc::now().with_timezone(&self.timezone);
+            let next: Vec<_> = self
+                .jobs
+                .iter()
+                .map(|(job, cron)| (job, cron.next_after(&now)))
+                .collect();
+            let Some(due_at) = next.iter().filter_map(|(_, at)| *at).min() else {
+                warn!("no job is scheduled to run again");
+                break;
+            };
+            {
+                let mut state = state.lock().await;
+                for (job, at) in &next {
+                    state.entry(job.name.clone()).or_default().next_run =
+                        at.as_ref().map(|at| at.to_rfc3339());
+                }
+                save_state(&state_path, &state).await;
+            }
+            debug!(at = %due_at.to_rfc3339(), "waiting for the next job");
+
+            loop {
+                let remaining = (due_at - Utc::now().with_timezone(&self.timezone))
+                    .to_std()
+                    .unwrap_or_default();
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(remaining.min(MAX_SLEEP)) => {}
+                    _ = tokio::signal::ctrl_c() => break 'schedule,
+                }
+            }
+
+            for (job, _) in next.iter().filter(|(_, at)| at.as_ref() == Some(&due_at)) {
+                if running
+                    .get(&job.name)
+                    .is_some_and(|handle| !handle.is_finished())
+                {
+                    warn!(job = %job.name, "job is still running, skipping this run");
+                    continue;
+                }
+                let span = info_span!("job", job = %job.name);
+                let task = run_job(
+                    self.program.clone(),
+                    (*job).clone(),
+                    logs_dir.join(format!("{}.log", job.name)),
+                    state.clone(),
+                    state_path.clone(),
+                )
+                .instrument(span);
+                running.insert(job.name.clone(), tokio::spawn(task));
+            }
+        }
+
+        let pending: Vec<_> = running
+            .into_values()
+            .filter(|handle| !handle.is_finished())
+            .collect();
+        if !pending.is_empty() {
+            info!(
+                jobs = pending.len(),
+                "stopping, waiting for running jobs to finish"
+            );
+        }
+        for handle in pending {
+            handle.await.ok();
+        }
+        Ok(())
+    }
+}
+
+/// Runs one job with its output appended to its log, then records the outcome. Failures are
+/// logged rather than returned so they never stop the schedule.
+async fn run_job(
+    program: PathBuf,
+    job: ScheduleJob,
+    log_path: PathBuf,
+    state: Arc<Mutex<BTreeMap<String, JobState>>>,
+    state_path: PathBuf,
+) {
+    let started = Instant::now();
+    let start = Utc::now().to_rfc3339();
+    {
+        let mut state = state.lock().await;
+        let entry = state.entry(job.name.clone()).or_default();
+        entry.last_start = Some(start.clone());
+        entry.runs += 1;
+        save_state(&state_path, &state).await;
+    }
+    info!(args = ?job.args, "starting job");
+
+    let status = match open_log(
+        &log_path,
+        &format!("==> {} {}\n", start, job.args.join(" ")),
+    ) {
+        Ok(log) => match log.try_clone() {
+            Ok(stderr) => tokio::process::Command::new(&program)
+                .args(&job.args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::from(log))
+                .stderr(Stdio::from(stderr))
+                .status()
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        Err(e) => Err(e.to_string()),
+    };
+    let duration = started.elapsed();
+    let exit_code = status.as_ref().ok().and_then(|status| status.code());
+    let succeeded = status.as_ref().is_ok_and(|status| status.success());
+    let outcome = match &status {
+        Ok(status) => status.to_string(),
+        Err(e) => format!("failed to start: {}", e),
+    };
+    if let Err(e) = open_log(
+        &log_path,
+        &format!(
+            "<== {} {} after {}s\n",
+            Utc::now().to_rfc3339(),
+            outcome,
+            duration.as_secs()
+        ),
+    ) {
+        warn!(path = %log_path.display(), error = %e, "failed to write job log");
+    }
+    if succeeded {
+        info!(duration_ms = duration.as_millis() as u64, "job succeeded");
+    } else {
+        warn!(duration_ms = duration.as_millis() as u64, outcome = %outcome, log = %log_path.display(), "job failed");
+    }
+
+    let mut state = state.lock().await;
+    let entry = state.entry(job.name.clone()).or_default();
+    entry.last_finish = Some(Utc::now().to_rfc3339());
+    entry.last_status = Some(String::from(if succeeded { "succeeded" } else { "failed" }));
+    entry.last_exit_code = exit_code;
+    entry.last_duration_secs = Some(duration.as_secs());
+    if !succeeded {
+        entry.failures += 1;
+    }
+    save_state(&state_path, &state).await;
+}
+
+/// Appends `line` to the log, returning it open for the job's output
+fn open_log(path: &Path, line: &str) -> std::io::Result<std::fs::File> {
+    use std::io::Write;
+    let mut log = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    log.write_all(line.as_bytes())?;
+    Ok(log)
+}
+
+/// Reads the state left by an earlier run, starting over when there is none or it can't be parsed
+async fn load_state(path: &Path) -> BTreeMap<String, JobState> {
+    let Ok(contents) = tokio::fs::read(path).await else {
+        return BTreeMap::new();
+    };
+    serde_json::from_slice(&contents).unwrap_or_else(|e| {
+        warn!(path = %path.display(), error = %e, "ignoring unreadable schedule state");
+        BTreeMap::new()
+    })
+}
+
+async fn save_state(path: &Path, state: &BTreeMap<String, JobState>) {
+    let contents = serde_json::to_vec_pretty(state).expect("job state serializes to JSON");
+    if let Err(e) = write_output(path, contents).await {
+        warn!(error = %e, "failed to save schedule state");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use chrono_tz::Europe::Berlin;
+
+    use super::*;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    fn next(cron: &str, after: DateTime<Utc>) -> DateTime<Utc> {
+        cron.parse::<CronSchedule>()
+            .unwrap()
+            .next_after(&after)
+            .unwrap()
+    }
+
+    fn next_in_berlin(cron: &str, after: DateTime<Utc>) -> DateTime<Utc> {
+        cron.parse::<CronSchedule>()
+            .unwrap()
+            .next_after(&after.with_timezone(&Berlin))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn steps_and_ranges() {
+        assert_eq!(
+            next("*/15 * * * *", utc(2024, 1, 1, 10, 7)),
+            utc(2024, 1, 1, 10, 15)
+        );
+        assert_eq!(
+            next("*/15 * * * *", utc(2024, 1, 1, 10, 15)),
+            utc(2024, 1, 1, 10, 30)
+        );
+        // `5/15` is every 15 minutes starting at 5
+        assert_eq!(
+            next("5/15 * * * *", utc(2024, 1, 1, 10, 5)),
+            utc(2024, 1, 1, 10, 20)
+        );
+        assert_eq!(
+            next("5/15 * * * *", utc(2024, 1, 1, 10, 50)),
+            utc(2024, 1, 1, 11, 5)
+        );
+        assert_eq!(
+            next("0 1-5/2 * * *", utc(2024, 1, 1, 1, 0)),
+            utc(2024, 1, 1, 3, 0)
+        );
+        assert_eq!(
+            next("0 1-5/2 * * *", utc(2024, 1, 1, 5, 0)),
+            utc(2024, 1, 2, 1, 0)
+        );
+        assert_eq!(
+            next("@hourly", utc(2024, 1, 1, 10, 0)),
+            utc(2024, 1, 1, 11, 0)
+        );
+    }
+
+    #[test]
+    fn names_and_sunday_as_seven() {
+        // 2024-01-05 is a Friday
+        assert_eq!(
+            next("0 9 * jan mon-fri", utc(2024, 1, 5, 10, 0)),
+            utc(2024, 1, 8, 9, 0)
+        );
+        assert_eq!(
+            next("0 9 * jan mon-fri", utc(2024, 1, 31, 10, 0)),
+            utc(2025, 1, 1, 9, 0)
+        );
+        for sunday in ["0 0 * * 0", "0 0 * * 7", "0 0 * * sun", "0 0 * * SUN"] {
+            assert_eq!(next(sunday, utc(2024, 1, 1, 0, 0)), utc(2024, 1, 7, 0, 0));
+        }
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        // with both restricted either one matches: Friday the 12th and Saturday the 13th
+        assert_eq!(
+            next("0 0 13 * fri", utc(2024, 1, 6, 0, 0)),
+            utc(2024, 1, 12, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 13 * fri", utc(2024, 1, 12, 0, 0)),
+            utc(2024, 1, 13, 0, 0)
+        );
+        // with only one restricted the other doesn't widen it
+        assert_eq!(
+            next("0 0 13 * *", utc(2024, 1, 6, 0, 0)),
+            utc(2024, 1, 13, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 * * fri", utc(2024, 1, 6, 0, 0)),
+            utc(2024, 1, 12, 0, 0)
+        );
+    }
+
+    #[test]
+    fn february_29th() {
+        assert_eq!(
+            next("0 0 29 2 *", utc(2024, 3, 1, 0, 0)),
+            utc(2028, 2, 29, 0, 0)
+        );
+    }
+
+    #[test]
+    fn skips_times_missing_on_spring_forward() {
+        // Berlin skips from 02:00 to 03:00 on 2024-03-31
+        assert_eq!(
+            next_in_berlin("30 2 * * *", utc(2024, 3, 30, 12, 0)),
+            utc(2024, 4, 1, 0, 30)
+        );
+    }
+
+    #[test]
+    fn repeated_times_run_once_and_never_in_the_past() {
+        // Berlin repeats 02:00 to 03:00 on 2024-10-27, first at +02:00 and then at +01:00
+        let first = utc(2024, 10, 27, 0, 30);
+        let second = utc(2024, 10, 27, 1, 30);
+        assert_eq!(
+            next_in_berlin("30 2 * * *", utc(2024, 10, 26, 23, 0)),
+            first
+        );
+        // after running in the first pass it waits for the next day
+        assert_eq!(
+            next_in_berlin("30 2 * * *", first),
+            utc(2024, 10, 28, 1, 30)
+        );
+        // started during the second pass, the first one is already behind us
+        assert_eq!(
+            next_in_berlin("30 2 * * *", utc(2024, 10, 27, 1, 10)),
+            second
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for invalid in [
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * *",
+            "* * * * * *",
+            "@reboot",
+            "0 9 * * funday",
+        ] {
+            assert!(invalid.parse::<CronSchedule>().is_err(), "{}", invalid);
+        }
+    }
+}
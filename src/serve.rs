@@ -0,0 +1,402 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use hyper::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE, WWW_AUTHENTICATE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use subtle::ConstantTimeEq;
+use tracing::{debug, info};
+
+use crate::error::Error;
+use crate::report::escape_html;
+
+/// Serves an output directory over HTTP with a gallery of its images at `/`, so the latest run
+/// can be viewed from a browser
+#[derive(Debug, Clone)]
+pub struct GalleryServer {
+    root: PathBuf,
+    address: SocketAddr,
+    refresh: Option<Duration>,
+    basic_auth: Option<String>,
+}
+
+impl GalleryServer {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        GalleryServer {
+            root: root.into(),
+            address: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            refresh: None,
+            basic_auth: None,
+        }
+    }
+
+    /// Address to listen on, `0.0.0.0:8080` to let other hosts connect
+    pub fn address(mut self, address: SocketAddr) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Reload the gallery in the browser every interval, e.g. to follow `images --watch`
+    pub fn refresh(mut self, refresh: Option<Duration>) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Require HTTP basic auth with these `user:password` credentials
+    pub fn basic_auth(mut self, credentials: Option<String>) -> Self {
+        self.basic_auth = credentials.map(|credentials| base64::encode(credentials.as_bytes()));
+        self
+    }
+
+    /// Serves until Ctrl-C is pressed
+    pub async fn run(&self) -> Result<(), Error> {
+        let server = Arc::new(self.clone());
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.handle(req).await) }
+                }))
+            }
+        });
+        let listener = Server::try_bind(&self.address).map_err(Error::Serve)?;
+        info!(address = %self.address, root = %self.root.display(), "serving");
+        listener
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                tokio::signal::ctrl_c().await.ok();
+            })
+            .await
+            .map_err(Error::Serve)
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        debug!(method = %req.method(), path = %req.uri().path(), "request");
+        if !self.authorized(&req) {
+            return Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(WWW_AUTHENTICATE, "Basic realm=\"cw-metrics\"")
+                .body(Body::from("unauthorized"))
+                .expect("response is valid");
+        }
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        if req.uri().path() == "/" {
+            return match self.gallery() {
+                Ok(html) => Response::builder()
+                    .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                    .header(CACHE_CONTROL, "no-cache")
+                    .body(Body::from(html))
+                    .expect("response is valid"),
+                Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+            };
+        }
+        let Some(path) = self.file_path(req.uri().path()) else {
+            return status(StatusCode::NOT_FOUND);
+        };
+        match tokio::fs::read(&path).await {
+            Ok(contents) => Response::builder()
+                .header(CONTENT_TYPE, content_type(&path))
+                .header(CACHE_CONTROL, "no-cache")
+                .body(Body::from(contents))
+                .expect("response is valid"),
+            Err(_) => status(StatusCode::NOT_FOUND),
+        }
+    }
+
+    fn authorized(&self, req: &Request<Body>) -> bool {
+        let Some(expected) = &self.basic_auth else {
+            return true;
+        };
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            // compared in constant time so response times don't reveal how much of a guess matched
+            .is_some_and(|credentials| {
+                credentials
+                    .trim()
+                    .as_bytes()
+                    .ct_eq(expected.as_bytes())
+                    .into()
+            })
+    }
+
+    /// Maps a request path to a file under the root, refusing anything that would leave it
+    fn file_path(&self, uri_path: &str) -> Option<PathBuf> {
+        let relative = PathBuf::from(percent_decode(uri_path.trim_start_matches('/'))?);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        let path = self.root.join(relative);
+        path.is_file().then_some(path)
+    }
+
+    /// Lists every file under the root by directory, showing images inline and linking the rest
+    fn gallery(&self) -> std::io::Result<String> {
+        let mut files = vec![];
+        list_files(&self.root, &mut files)?;
+        files.sort();
+
+        let title = escape_html(&self.root.display().to_string());
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        if let Some(refresh) = self.refresh {
+            html.push_str(&format!(
+                "<meta http-equiv=\"refresh\" content=\"{}\">\n",
+                refresh.as_secs().max(1)
+            ));
+        }
+        html.push_str(&format!("<title>{}</title>\n", title));
+        html.push_str(
+            "<style>body { font-family: sans-serif; margin: 2em; } \
+             figure { display: inline-block; margin: 0 1em 1em 0; } \
+             img { max-width: 100%; border: 1px solid #ddd; }</style>\n",
+        );
+        html.push_str("</head>\n<body>\n");
+        html.push_str(&format!("<h1>{}</h1>\n", title));
+        html.push_str(&format!(
+            "<p>{} files. Generated {}.</p>\n",
+            files.len(),
+            escape_html(
+                &DateTime::from(SystemTime::now())
+                    .fmt(Format::DateTime)
+                    .unwrap_or_default()
+            )
+        ));
+        let mut current_dir: Option<&Path> = None;
+        for file in &files {
+            let relative = file.strip_prefix(&self.root).unwrap_or(file);
+            let dir = relative.parent().unwrap_or_else(|| Path::new(""));
+            if current_dir != Some(dir) {
+                let heading = if dir.as_os_str().is_empty() {
+                    String::from("/")
+                } else {
+                    dir.display().to_string()
+                };
+                html.push_str(&format!("<h2>{}</h2>\n", escape_html(&heading)));
+                current_dir = Some(dir);
+            }
+            let href = percent_encode(&relative.to_string_lossy());
+            let name = escape_html(&relative.file_name().unwrap_or_default().to_string_lossy());
+            if is_image(relative) {
+                html.push_str(&format!(
+                    "<figure><a href=\"/{href}\"><img src=\"/{href}\" alt=\"{name}\"></a>\
+                     <figcaption>{name}</figcaption></figure>\n",
+                    href = href,
+                    name = name
+                ));
+            } else {
+                html.push_str(&format!("<p><a href=\"/{}\">{}</a></p>\n", href, name));
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        Ok(html)
+    }
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(status.to_string()))
+        .expect("response is valid")
+}
+
+fn is_image(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("png" | "svg")
+    )
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("md" | "txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Escapes everything but unreserved characters and `/` in a URL path
+fn percent_encode(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                char::from(b).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes, refusing truncated escapes, invalid UTF-8 and an encoded `/`, which
+/// would otherwise sneak a separator past the check of the path's components
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            let byte = u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?;
+            if byte == b'/' || byte == b'\\' || byte == 0 {
+                return None;
+            }
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A root with an image under it and a secret file next to it
+    fn gallery(name: &str) -> (PathBuf, GalleryServer) {
+        let dir =
+            std::env::temp_dir().join(format!("cw-metrics-serve-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("public").join("images")).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::fs::write(dir.join("public").join("images").join("a.png"), "png").unwrap();
+        let server = GalleryServer::new(dir.join("public"));
+        (dir, server)
+    }
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut req = Request::builder().uri("/images/a.png");
+        if let Some(authorization) = authorization {
+            req = req.header(AUTHORIZATION, authorization);
+        }
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn serves_files_under_the_root() {
+        let (dir, server) = gallery("files");
+        let image = dir.join("public").join("images").join("a.png");
+        assert_eq!(server.file_path("/images/a.png"), Some(image.clone()));
+        assert_eq!(server.file_path("/images/%61.png"), Some(image));
+        assert_eq!(server.file_path("/images/missing.png"), None);
+        assert_eq!(server.file_path("/images"), None);
+    }
+
+    #[test]
+    fn refuses_paths_leaving_the_root() {
+        let (dir, server) = gallery("traversal");
+        let secret = dir.join("secret.txt");
+        for path in [
+            "/../secret.txt",
+            "/images/../../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/%2E%2E/secret.txt",
+            "/images/%2e%2e/%2e%2e/secret.txt",
+            "/%2fsecret.txt",
+            "/images%2f..%2f..%2fsecret.txt",
+            "/..%5csecret.txt",
+        ] {
+            assert_eq!(server.file_path(path), None, "{}", path);
+        }
+        // absolute paths are taken relative to the root
+        let absolute = format!("/{}", secret.display());
+        assert_eq!(server.file_path(&absolute), None);
+        assert_eq!(server.file_path(&format!("/{}", absolute)), None);
+    }
+
+    #[test]
+    fn refuses_invalid_escapes() {
+        let (_, server) = gallery("escapes");
+        for path in [
+            "/images/a.png%",
+            "/images/a.png%2",
+            "/images/%zz.png",
+            "/images/%+1.png",
+            "/images/%ff.png",
+            "/images/a.png%00",
+        ] {
+            assert_eq!(server.file_path(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(percent_decode("a%20b.png").as_deref(), Some("a b.png"));
+        assert_eq!(percent_decode("caf%C3%A9").as_deref(), Some("café"));
+        assert_eq!(percent_decode("%2e%2e").as_deref(), Some(".."));
+        assert_eq!(percent_decode("a%2Fb"), None);
+        assert_eq!(percent_decode("%C3"), None);
+        assert_eq!(percent_decode("%"), None);
+        assert_eq!(percent_decode("%4"), None);
+    }
+
+    #[tokio::test]
+    async fn requires_basic_auth_when_set() {
+        let (_, server) = gallery("auth");
+        let server = server.basic_auth(Some(String::from("user:pass")));
+        let basic = |credentials: &str| format!("Basic {}", base64::encode(credentials));
+
+        let missing = server.handle(request(None)).await;
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        assert!(missing.headers().contains_key(WWW_AUTHENTICATE));
+        for authorization in [
+            basic("user:wrong"),
+            basic("user:pas"),
+            basic("user:passs"),
+            basic("other:pass"),
+            format!("Bearer {}", base64::encode("user:pass")),
+            String::from("Basic"),
+        ] {
+            let res = server.handle(request(Some(&authorization))).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{}", authorization);
+        }
+        let res = server.handle(request(Some(&basic("user:pass")))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn open_without_basic_auth() {
+        let (_, server) = gallery("open");
+        assert_eq!(server.handle(request(None)).await.status(), StatusCode::OK);
+        let res = server
+            .handle(
+                Request::builder()
+                    .uri("/%2e%2e/secret.txt")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
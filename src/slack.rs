@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::error::Error;
+
+const SLACK_API: &str = "https://slack.com/api";
+
+/// Images matching `pattern` are uploaded to `channel` with a bot token, since incoming webhooks
+/// can only post text
+#[derive(Debug, Clone)]
+struct SlackUpload {
+    token: String,
+    channel: String,
+    pattern: Regex,
+}
+
+/// Posts a run summary to a Slack incoming webhook, and optionally uploads some of the saved
+/// images to a channel
+#[derive(Debug, Clone)]
+pub struct SlackNotifier {
+    webhook_url: String,
+    upload: Option<SlackUpload>,
+    client: reqwest::Client,
+}
+
+impl SlackNotifier {
+    pub fn new(webhook_url: impl Into<String>) -> Self {
+        SlackNotifier {
+            webhook_url: webhook_url.into(),
+            upload: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Also upload saved PNG and SVG images whose file name matches `pattern` to the channel ID,
+    /// using a bot token with the `files:write` scope
+    pub fn upload(
+        mut self,
+        token: impl Into<String>,
+        channel: impl Into<String>,
+        pattern: Regex,
+    ) -> Self {
+        self.upload = Some(SlackUpload {
+            token: token.into(),
+            channel: channel.into(),
+            pattern,
+        });
+        self
+    }
+
+    /// Posts `text` in Slack's mrkdwn format, then uploads the matching images among `files`.
+    /// Returns the paths of the uploaded images.
+    pub async fn notify(&self, text: &str, files: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+        let resp = self
+            .client
+            .post(&self.webhook_url)
+            .json(&json!({ "text": text }))
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| slack_error("posting to the webhook", e))?;
+        debug!(status = %resp.status(), "posted run summary to Slack");
+
+        let Some(upload) = &self.upload else {
+            return Ok(vec![]);
+        };
+        let mut uploaded = vec![];
+        for path in files
+            .iter()
+            .filter(|path| is_selected_image(path, &upload.pattern))
+        {
+            self.upload_file(upload, path).await?;
+            uploaded.push(path.clone());
+        }
+        Ok(uploaded)
+    }
+
+    /// Uploads with files.getUploadURLExternal, a POST of the contents to the returned URL and
+    /// files.completeUploadExternal to share it in the channel
+    async fn upload_file(&self, upload: &SlackUpload, path: &Path) -> Result<(), Error> {
+        let contents = tokio::fs::read(path).await.map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let length = contents.len().to_string();
+        let resp = self
+            .api_call(
+                upload,
+                "files.getUploadURLExternal",
+                self.client
+                    .post(format!("{}/files.getUploadURLExternal", SLACK_API))
+                    .form(&[
+                        ("filename", file_name.as_str()),
+                        ("length", length.as_str()),
+                    ]),
+            )
+            .await?;
+        let (Some(upload_url), Some(file_id)) =
+            (resp["upload_url"].as_str(), resp["file_id"].as_str())
+        else {
+            return Err(Error::Slack(String::from(
+                "files.getUploadURLExternal returned no upload URL",
+            )));
+        };
+        self.client
+            .post(upload_url)
+            .body(contents)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| slack_error("uploading the file", e))?;
+        self.api_call(
+            upload,
+            "files.completeUploadExternal",
+            self.client
+                .post(format!("{}/files.completeUploadExternal", SLACK_API))
+                .json(&json!({
+                    "files": [{ "id": file_id, "title": file_name }],
+                    "channel_id": upload.channel,
+                })),
+        )
+        .await?;
+        debug!(path = %path.display(), "uploaded image to Slack");
+        Ok(())
+    }
+
+    /// Sends a Web API request, which reports failures with `"ok": false` rather than a status
+    async fn api_call(
+        &self,
+        upload: &SlackUpload,
+        method: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<Value, Error> {
+        let resp: Value = request
+            .bearer_auth(&upload.token)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(|e| slack_error(method, e))?
+            .json()
+            .await
+            .map_err(|e| slack_error(method, e))?;
+        if resp["ok"] != true {
+            return Err(Error::Slack(format!(
+                "{} failed: {}",
+                method,
+                resp["error"].as_str().unwrap_or("unknown error")
+            )));
+        }
+        Ok(resp)
+    }
+}
+
+fn is_selected_image(path: &Path, pattern: &Regex) -> bool {
+    let is_image = matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("png" | "svg")
+    );
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    is_image && pattern.is_match(&name)
+}
+
+fn slack_error(step: &str, error: reqwest::Error) -> Error {
+    Error::Slack(format!("{} failed: {}", step, error))
+}
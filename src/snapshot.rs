@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::alarms::MetricAlarmDetails;
+use crate::baseline::{differences_to_csv, differences_to_table, AlarmDifference, DifferenceKind};
+use crate::error::Error;
+use crate::output::write_output;
+
+/// File names of snapshots start with this, followed by the time they were taken
+const SNAPSHOT_PREFIX: &str = "alarms-";
+
+/// The alarms of every account that could be described in one `alarms` run
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AlarmSnapshot {
+    pub taken_at: String,
+    /// Alarms by account label. Accounts that failed are left out, so they don't show up as
+    /// deleted alarms in the next drift report.
+    pub accounts: BTreeMap<String, Vec<MetricAlarmDetails>>,
+}
+
+impl AlarmSnapshot {
+    pub fn new() -> Self {
+        AlarmSnapshot {
+            taken_at: DateTime::from(SystemTime::now())
+                .fmt(Format::DateTime)
+                .unwrap_or_default(),
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    /// Saves the snapshot as `alarms-<taken at>.json` in `dir` and returns its path
+    pub async fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        // timestamps have colons, which aren't allowed in Windows file names
+        let name = format!("{}{}.json", SNAPSHOT_PREFIX, self.taken_at.replace(':', ""));
+        let path = dir.join(name);
+        let json = serde_json::to_vec_pretty(self).expect("snapshot serializes to JSON");
+        write_output(&path, json).await?;
+        Ok(path)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<AlarmSnapshot, Error> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        serde_json::from_str(&contents).map_err(|source| Error::Snapshot {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// Paths of the two most recent snapshots in `dir`, oldest first
+pub fn latest_snapshots(dir: &Path) -> Result<(PathBuf, PathBuf), Error> {
+    let entries = std::fs::read_dir(dir).map_err(|source| Error::Read {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+    let mut snapshots: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str());
+            name.map_or(false, |name| {
+                name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(".json")
+            })
+        })
+        .collect();
+    // the timestamps in the names sort in the order the snapshots were taken
+    snapshots.sort();
+    match snapshots.len() {
+        0 | 1 => Err(Error::NotEnoughSnapshots(dir.to_path_buf())),
+        n => Ok((snapshots[n - 2].clone(), snapshots[n - 1].clone())),
+    }
+}
+
+/// Alarms created or deleted between two snapshots, and those whose threshold, comparison
+/// operator or actions changed. `expected` is the older value and `actual` the newer one. Only
+/// accounts in both snapshots are compared.
+pub fn drift(older: &AlarmSnapshot, newer: &AlarmSnapshot) -> Vec<AlarmDifference> {
+    let mut differences = vec![];
+    for (account, new_alarms) in &newer.accounts {
+        let old_alarms = match older.accounts.get(account) {
+            Some(old_alarms) => old_alarms,
+            None => continue,
+        };
+        let mut push = |alarm_name: &str, kind, expected: String, actual: String| {
+            differences.push(AlarmDifference {
+                account: account.clone(),
+                alarm_name: String::from(alarm_name),
+                kind,
+                expected,
+                actual,
+            })
+        };
+        for old in old_alarms {
+            if !new_alarms.iter().any(|a| a.alarm_name == old.alarm_name) {
+                push(
+                    &old.alarm_name,
+                    DifferenceKind::Deleted,
+                    String::from("present"),
+                    String::from("deleted"),
+                );
+            }
+        }
+        for new in new_alarms {
+            let old = match old_alarms.iter().find(|a| a.alarm_name == new.alarm_name) {
+                Some(old) => old,
+                None => {
+                    push(
+                        &new.alarm_name,
+                        DifferenceKind::Created,
+                        String::from("absent"),
+                        String::from("created"),
+                    );
+                    continue;
+                }
+            };
+            if old.threshold != new.threshold {
+                push(
+                    &new.alarm_name,
+                    DifferenceKind::Threshold,
+                    old.threshold.to_string(),
+                    new.threshold.to_string(),
+                );
+            }
+            if old.comparison_operator != new.comparison_operator {
+                push(
+                    &new.alarm_name,
+                    DifferenceKind::ComparisonOperator,
+                    old.comparison_operator.clone(),
+                    new.comparison_operator.clone(),
+                );
+            }
+            let (mut old_actions, mut new_actions) =
+                (old.alarm_actions.clone(), new.alarm_actions.clone());
+            old_actions.sort();
+            new_actions.sort();
+            if old_actions != new_actions {
+                push(
+                    &new.alarm_name,
+                    DifferenceKind::AlarmActions,
+                    old_actions.join(";"),
+                    new_actions.join(";"),
+                );
+            }
+        }
+    }
+    differences
+}
+
+/// Compares the two latest snapshots in `dir` and saves what changed between them as `csv`,
+/// `table` or `json`, to `output_path` or `alarms-drift.<extension>`. Returns the saved path and
+/// the number of changes.
+pub async fn write_drift(
+    dir: &Path,
+    format: &str,
+    output_path: Option<&Path>,
+) -> Result<(PathBuf, usize), Error> {
+    let (older, newer) = latest_snapshots(dir)?;
+    info!(older = %older.display(), newer = %newer.display(), "comparing snapshots");
+    let differences = drift(
+        &AlarmSnapshot::from_path(&older)?,
+        &AlarmSnapshot::from_path(&newer)?,
+    );
+
+    let (extension, contents) = match format {
+        "csv" => ("csv", differences_to_csv(&differences)?),
+        "table" => ("txt", differences_to_table(&differences).into_bytes()),
+        _ => (
+            "json",
+            serde_json::to_vec(&differences).expect("differences serialize to JSON"),
+        ),
+    };
+    let path = match output_path {
+        Some(path) => path.to_path_buf(),
+        None => Path::new("alarms-drift").with_extension(extension),
+    };
+    write_output(&path, contents).await?;
+    Ok((path, differences.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alarms::test_alarm;
+
+    fn alarm(name: &str, threshold: f64) -> MetricAlarmDetails {
+        MetricAlarmDetails {
+            threshold,
+            ..test_alarm(name)
+        }
+    }
+
+    fn snapshot(taken_at: &str, alarms: Vec<MetricAlarmDetails>) -> AlarmSnapshot {
+        AlarmSnapshot {
+            taken_at: String::from(taken_at),
+            accounts: BTreeMap::from([(String::from("Orders us-east-1"), alarms)]),
+        }
+    }
+
+    fn snapshot_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cw-metrics-snapshot-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn drift_compares_the_two_latest_snapshots() {
+        let dir = snapshot_dir("drift");
+        let oldest = snapshot("2024-01-01T00:00:00Z", vec![alarm("Stale", 1.0)]);
+        let older = snapshot(
+            "2024-01-08T00:00:00Z",
+            vec![alarm("Errors", 1.0), alarm("Deleted", 1.0)],
+        );
+        let newer = snapshot(
+            "2024-01-15T00:00:00Z",
+            vec![alarm("Errors", 5.0), alarm("Created", 1.0)],
+        );
+        for snapshot in [&newer, &oldest, &older] {
+            snapshot.save(&dir).await.unwrap();
+        }
+
+        let output = dir.join("drift.json");
+        let (path, changes) = write_drift(&dir, "json", Some(&output)).await.unwrap();
+        assert_eq!(path, output);
+        assert_eq!(changes, 3);
+        let differences: Vec<serde_json::Value> =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let mut names: Vec<&str> = differences
+            .iter()
+            .map(|d| d["alarm_name"].as_str().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["Created", "Deleted", "Errors"]);
+    }
+
+    #[tokio::test]
+    async fn drift_needs_two_snapshots() {
+        let dir = snapshot_dir("single");
+        snapshot("2024-01-01T00:00:00Z", vec![])
+            .save(&dir)
+            .await
+            .unwrap();
+        let res = write_drift(&dir, "table", Some(&dir.join("drift.txt"))).await;
+        assert!(matches!(res, Err(Error::NotEnoughSnapshots(_))));
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+use tracing::{debug, info, warn};
+
+use crate::error::Error;
+
+/// A profile whose credentials come from AWS IAM Identity Center (SSO)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SsoProfile {
+    pub profile: String,
+    pub start_url: String,
+    /// The `[sso-session]` the profile uses, if it isn't configured the legacy way
+    pub session: Option<String>,
+}
+
+/// The parts of a cached `aws sso login` token we look at
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CachedToken {
+    expires_at: String,
+}
+
+/// The profile the SDK would use: `profile` when given, else `$AWS_PROFILE`, else `default`
+pub fn profile_name(profile: Option<&str>) -> String {
+    profile
+        .map(String::from)
+        .or_else(|| std::env::var("AWS_PROFILE").ok().filter(|p| !p.is_empty()))
+        .unwrap_or_else(|| String::from("default"))
+}
+
+impl SsoProfile {
+    /// Reads the profile from the AWS config file, `None` when it doesn't exist or isn't an SSO
+    /// profile
+    pub fn load(profile: &str) -> Option<Self> {
+        let contents = std::fs::read_to_string(aws_config_path()?).ok()?;
+        let sections = parse_ini(&contents);
+        let section = if profile == "default" {
+            sections
+                .get("default")
+                .or_else(|| sections.get("profile default"))?
+        } else {
+            sections.get(&format!("profile {}", profile))?
+        };
+        let (start_url, session) = match section.get("sso_session") {
+            Some(session) => {
+                let start_url = sections
+                    .get(&format!("sso-session {}", session))?
+                    .get("sso_start_url")?;
+                (start_url.clone(), Some(session.clone()))
+            }
+            None => (section.get("sso_start_url")?.clone(), None),
+        };
+        Some(SsoProfile {
+            profile: String::from(profile),
+            start_url,
+            session,
+        })
+    }
+
+    /// The command that signs in again, e.g. `aws sso login --profile dev`
+    pub fn login_command(&self) -> String {
+        if self.profile == "default" {
+            String::from("aws sso login")
+        } else {
+            format!("aws sso login --profile {}", self.profile)
+        }
+    }
+
+    /// When the cached sign-in token expires, `None` when there is none
+    pub fn token_expiry(&self) -> Option<DateTime<Utc>> {
+        // the AWS CLI names the cache file after the session name, or the start URL for
+        // legacy profiles
+        let key = self.session.as_deref().unwrap_or(&self.start_url);
+        let path = aws_dir()?
+            .join("sso")
+            .join("cache")
+            .join(format!("{:x}.json", Sha1::digest(key.as_bytes())));
+        let contents = std::fs::read(&path).ok()?;
+        let token: CachedToken = serde_json::from_slice(&contents).ok()?;
+        // the v1 CLI wrote `2024-01-01T00:00:00UTC`
+        let expires_at = token.expires_at.replace("UTC", "Z");
+        DateTime::parse_from_rfc3339(&expires_at)
+            .ok()
+            .map(|at| at.with_timezone(&Utc))
+    }
+
+    /// Signs in with `aws sso login`, which opens the browser for the device authorization
+    pub async fn login(&self) -> Result<(), Error> {
+        let command = self.login_command();
+        info!(command = %command, "signing in to AWS SSO");
+        let mut args = vec!["sso", "login"];
+        if self.profile != "default" {
+            args.extend(["--profile", self.profile.as_str()]);
+        }
+        let status = tokio::process::Command::new("aws")
+            .args(&args)
+            .stdin(Stdio::inherit())
+            .status()
+            .await
+            .map_err(|e| Error::SsoLogin {
+                command: command.clone(),
+                reason: e.to_string(),
+            })?;
+        if !status.success() {
+            return Err(Error::SsoLogin {
+                command,
+                reason: status.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks the SSO sign-in of the profile the run starts from, if it's an SSO profile. When the
+/// token is missing or expired it signs in again with `login`, or else warns with the command
+/// to run, since every account's role would fail to be assumed.
+pub async fn check_login(profile: Option<&str>, login: bool) -> Result<(), Error> {
+    let Some(sso) = SsoProfile::load(&profile_name(profile)) else {
+        return Ok(());
+    };
+    match sso.token_expiry() {
+        Some(expiry) if expiry > Utc::now() => {
+            debug!(profile = %sso.profile, expiry = %expiry.to_rfc3339(), "SSO token is valid");
+            Ok(())
+        }
+        _ if login => sso.login().await,
+        expiry => {
+            let state = if expiry.is_some() {
+                "expired"
+            } else {
+                "missing"
+            };
+            warn!(
+                profile = %sso.profile,
+                "the AWS SSO token is {}, run `{}` (or pass --sso-login) to sign in again",
+                state,
+                sso.login_command()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// `~/.aws`, where the AWS CLI keeps its config and SSO cache
+fn aws_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".aws"))
+}
+
+/// `$AWS_CONFIG_FILE`, or else `~/.aws/config`
+fn aws_config_path() -> Option<PathBuf> {
+    std::env::var_os("AWS_CONFIG_FILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(aws_dir()?.join("config")))
+}
+
+/// The `key = value` pairs of every `[section]`, keyed by the section name
+fn parse_ini(contents: &str) -> HashMap<String, HashMap<String, String>> {
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+            sections.entry(name.clone()).or_default();
+            current = Some(name);
+        } else if let (Some(section), Some((key, value))) = (&current, line.split_once('=')) {
+            sections
+                .entry(section.clone())
+                .or_default()
+                .insert(String::from(key.trim()), String::from(value.trim()));
+        }
+    }
+    sections
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use rusqlite::{params, Connection};
+
+use crate::data::{AccountMetricData, Watermarks};
+use crate::error::Error;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS accounts (
+    id INTEGER PRIMARY KEY,
+    program_name TEXT NOT NULL,
+    region TEXT NOT NULL,
+    UNIQUE (program_name, region)
+);
+CREATE TABLE IF NOT EXISTS metrics (
+    id INTEGER PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES accounts (id),
+    query_id TEXT NOT NULL,
+    label TEXT NOT NULL,
+    UNIQUE (account_id, query_id)
+);
+CREATE TABLE IF NOT EXISTS datapoints (
+    metric_id INTEGER NOT NULL REFERENCES metrics (id),
+    timestamp TEXT NOT NULL,
+    value REAL NOT NULL,
+    PRIMARY KEY (metric_id, timestamp)
+);
+";
+
+/// SQLite database of exported datapoints. Saving the same datapoint again updates its value,
+/// so overlapping exports can be written without duplicates.
+#[derive(Debug)]
+pub struct MetricStore {
+    conn: Connection,
+}
+
+impl MetricStore {
+    /// Opens the database at `path`, creating it and its tables if they don't exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(MetricStore { conn })
+    }
+
+    /// Upserts every datapoint of an account's export, returning how many were written
+    pub fn save(&mut self, data: &AccountMetricData) -> Result<usize, Error> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR IGNORE INTO accounts (program_name, region) VALUES (?1, ?2)",
+            params![data.program_name, data.region],
+        )?;
+        let account_id: i64 = tx.query_row(
+            "SELECT id FROM accounts WHERE program_name = ?1 AND region = ?2",
+            params![data.program_name, data.region],
+            |row| row.get(0),
+        )?;
+
+        let mut written = 0;
+        for series in &data.series {
+            tx.execute(
+                "INSERT INTO metrics (account_id, query_id, label) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (account_id, query_id) DO UPDATE SET label = excluded.label",
+                params![account_id, series.id, series.label],
+            )?;
+            let metric_id: i64 = tx.query_row(
+                "SELECT id FROM metrics WHERE account_id = ?1 AND query_id = ?2",
+                params![account_id, series.id],
+                |row| row.get(0),
+            )?;
+            let mut upsert = tx.prepare_cached(
+                "INSERT INTO datapoints (metric_id, timestamp, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (metric_id, timestamp) DO UPDATE SET value = excluded.value",
+            )?;
+            for dp in &series.datapoints {
+                written += upsert.execute(params![metric_id, dp.timestamp, dp.value])?;
+            }
+        }
+        tx.commit()?;
+        Ok(written)
+    }
+
+    /// Latest stored timestamp per account and query, used to only request newer datapoints
+    pub fn watermarks(&self) -> Result<Watermarks, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT a.program_name, a.region, m.query_id, MAX(d.timestamp)
+             FROM datapoints d
+             JOIN metrics m ON m.id = d.metric_id
+             JOIN accounts a ON a.id = m.account_id
+             GROUP BY a.program_name, a.region, m.query_id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+
+        let mut watermarks = Watermarks::new();
+        for row in rows {
+            let (program_name, region, query_id, timestamp) = row?;
+            if let Ok(timestamp) = DateTime::from_str(&timestamp, Format::DateTime) {
+                watermarks
+                    .entry((program_name, region))
+                    .or_default()
+                    .insert(query_id, timestamp);
+            }
+        }
+        Ok(watermarks)
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use aws_sdk_cloudwatch::model::{MetricStreamEntry, MetricStreamFilter};
+use aws_sdk_cloudwatch::output::GetMetricStreamOutput;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::output::text_table;
+use crate::retry::RetryPolicy;
+use crate::runner::{AccountResults, RunOptions};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MetricStreamDetails {
+    pub program_name: String,
+    pub region: String,
+    pub name: String,
+    pub state: String,
+    pub output_format: String,
+    /// The Firehose delivery stream the metrics are sent to, which names the account receiving
+    /// them
+    pub firehose_arn: String,
+    pub role_arn: String,
+    /// Namespaces streamed, every namespace when both lists are empty
+    pub include_namespaces: Vec<String>,
+    /// Namespaces left out of the stream
+    pub exclude_namespaces: Vec<String>,
+}
+
+/// Lists the metric streams of each account with their destinations and namespace filters
+#[derive(Debug, Clone, Default)]
+pub struct MetricStreamExporter {
+    options: RunOptions,
+}
+
+impl MetricStreamExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub async fn run(
+        &self,
+        accounts: Vec<AccountConfig>,
+    ) -> AccountResults<Vec<MetricStreamDetails>> {
+        let exporter = Arc::new(self.clone());
+        self.options
+            .run(accounts, |acc| {
+                let exporter = exporter.clone();
+                async move { exporter.list(&acc).await }
+            })
+            .await
+    }
+
+    /// Lists the account's metric streams, then gets each one for its namespace filters, which
+    /// ListMetricStreams leaves out
+    pub async fn list(&self, acc: &AccountConfig) -> Result<Vec<MetricStreamDetails>, Error> {
+        let client = self.options.cloudwatch_client(acc).await?;
+        let entries = list_metric_streams(&client, &self.options.retry).await?;
+        let mut streams = vec![];
+        for entry in &entries {
+            let name = entry.name().unwrap_or_default();
+            debug!(account = %acc.label(), stream = %name, "getting metric stream");
+            let stream = get_metric_stream(&client, &self.options.retry, name).await?;
+            streams.push(to_stream_details(acc, entry, &stream));
+        }
+        Ok(streams)
+    }
+}
+
+fn to_stream_details(
+    acc: &AccountConfig,
+    entry: &MetricStreamEntry,
+    stream: &GetMetricStreamOutput,
+) -> MetricStreamDetails {
+    let namespaces = |filters: Option<&[MetricStreamFilter]>| -> Vec<String> {
+        filters
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|filter| filter.namespace().map(String::from))
+            .collect()
+    };
+    MetricStreamDetails {
+        program_name: acc.namespace.clone(),
+        region: acc.region.clone(),
+        name: String::from(entry.name().unwrap_or_default()),
+        state: String::from(stream.state().or(entry.state()).unwrap_or_default()),
+        output_format: String::from(
+            stream
+                .output_format()
+                .or(entry.output_format())
+                .map(|format| format.as_str())
+                .unwrap_or_default(),
+        ),
+        firehose_arn: String::from(
+            stream
+                .firehose_arn()
+                .or(entry.firehose_arn())
+                .unwrap_or_default(),
+        ),
+        role_arn: String::from(stream.role_arn().unwrap_or_default()),
+        include_namespaces: namespaces(stream.include_filters()),
+        exclude_namespaces: namespaces(stream.exclude_filters()),
+    }
+}
+
+const STREAM_COLUMNS: [&str; 9] = [
+    "program_name",
+    "region",
+    "name",
+    "state",
+    "output_format",
+    "firehose_arn",
+    "role_arn",
+    "include_namespaces",
+    "exclude_namespaces",
+];
+
+fn stream_row(stream: &MetricStreamDetails) -> Vec<String> {
+    vec![
+        stream.program_name.clone(),
+        stream.region.clone(),
+        stream.name.clone(),
+        stream.state.clone(),
+        stream.output_format.clone(),
+        stream.firehose_arn.clone(),
+        stream.role_arn.clone(),
+        stream.include_namespaces.join(";"),
+        stream.exclude_namespaces.join(";"),
+    ]
+}
+
+/// Writes metric streams as CSV with one row per stream, namespaces joined by `;`
+pub fn streams_to_csv(streams: &[MetricStreamDetails]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(STREAM_COLUMNS)?;
+    for stream in streams {
+        writer.write_record(stream_row(stream))?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Renders metric streams as a plain text table with space-padded columns
+pub fn streams_to_table(streams: &[MetricStreamDetails]) -> String {
+    let rows: Vec<Vec<String>> = streams.iter().map(stream_row).collect();
+    text_table(&STREAM_COLUMNS, &rows)
+}
+
+/// Calls AWS CloudWatch ListMetricStreams API, following `next_token` until every stream is
+/// returned
+/// API Reference: [ListMetricStreams](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_ListMetricStreams.html)
+async fn list_metric_streams(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+) -> Result<Vec<MetricStreamEntry>, aws_sdk_cloudwatch::Error> {
+    let mut entries: Vec<MetricStreamEntry> = vec![];
+    let mut next_token: Option<String> = None;
+    loop {
+        let request = client.list_metric_streams().set_next_token(next_token);
+        let resp = retry
+            .call("ListMetricStreams", || request.clone().send())
+            .await?;
+        entries.extend_from_slice(resp.entries().unwrap_or_default());
+        next_token = resp.next_token().map(String::from);
+        if next_token.is_none() {
+            break;
+        }
+    }
+    Ok(entries)
+}
+
+/// Calls AWS CloudWatch GetMetricStream API
+/// API Reference: [GetMetricStream](https://docs.aws.amazon.com/AmazonCloudWatch/latest/APIReference/API_GetMetricStream.html)
+async fn get_metric_stream(
+    client: &aws_sdk_cloudwatch::Client,
+    retry: &RetryPolicy,
+    name: &str,
+) -> Result<GetMetricStreamOutput, aws_sdk_cloudwatch::Error> {
+    let request = client.get_metric_stream().name(name);
+    Ok(retry
+        .call("GetMetricStream", || request.clone().send())
+        .await?)
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use tracing::{info, warn};
+
+use crate::config::AccountConfig;
+use crate::error::Error;
+use crate::manifest::{Manifest, ManifestFile};
+use crate::notify::SnsPublisher;
+use crate::retry::RetryPolicy;
+use crate::slack::SlackNotifier;
+
+/// Exit code when the run could not start or its output could not be written
+pub const EXIT_FATAL: u8 = 1;
+
+/// Exit code when the run finished but at least one account failed
+pub const EXIT_ACCOUNT_FAILURES: u8 = 2;
+
+/// Exit code when the run finished but every account failed
+pub const EXIT_ALL_ACCOUNTS_FAILED: u8 = 3;
+
+/// Exit code when Ctrl-C stopped the run before every account finished, as for SIGINT
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// Files listed in a notification, the rest are only counted
+const MESSAGE_MAX_FILES: usize = 20;
+
+/// Per-account outcomes of a run, printed at the end and used for the exit code
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub succeeded: usize,
+    pub failed: Vec<(String, String)>,
+    /// Accounts that Ctrl-C stopped before they started or finished
+    pub interrupted: usize,
+    pub manifest: Manifest,
+    pub retried: BTreeMap<String, u32>,
+    /// `s3://` URIs of the files uploaded with `--s3-uri`
+    pub uploaded: Vec<String>,
+    pub slack: Option<SlackNotifier>,
+}
+
+impl RunSummary {
+    /// Remembers a file saved by the run so it can be listed in the manifest and uploaded
+    pub fn output(&mut self, path: &Path) {
+        self.manifest.files.push(ManifestFile::new(path));
+    }
+
+    /// Remembers a file saved for one account
+    pub fn account_output(&mut self, path: &Path, acc: &AccountConfig) {
+        self.manifest
+            .files
+            .push(ManifestFile::for_account(path, acc));
+    }
+
+    /// Paths of every file saved by the run so far
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.manifest
+            .files
+            .iter()
+            .map(|file| file.path.clone())
+            .collect()
+    }
+
+    /// Starts over for another round of a watch or wait loop, keeping the notifier and the
+    /// inputs the run was started with
+    pub fn next_round(&mut self) {
+        *self = RunSummary {
+            manifest: Manifest {
+                template: self.manifest.template.take(),
+                start: self.manifest.start.take(),
+                end: self.manifest.end.take(),
+                ..Default::default()
+            },
+            slack: self.slack.take(),
+            ..Default::default()
+        };
+    }
+
+    /// Remembers what the run's files were made from, for the manifest
+    pub fn inputs(&mut self, template: Option<&str>, start: &str, end: &str) {
+        self.manifest.template = template.map(PathBuf::from);
+        self.manifest.start = Some(String::from(start));
+        self.manifest.end = Some(String::from(end));
+    }
+
+    pub fn record<T>(&mut self, account: &str, res: &Result<T, Error>) {
+        self.manifest.account(account, res);
+        match res {
+            Ok(_) => self.succeeded += 1,
+            Err(Error::Interrupted) => self.interrupted += 1,
+            Err(e) => self.failed.push((String::from(account), e.to_string())),
+        }
+    }
+
+    /// Records a second pass over accounts already recorded, like the alarms sheet of
+    /// `data --format xlsx`: a failure fails an account that succeeded the first pass, and a
+    /// success isn't counted again
+    pub fn record_again<T>(&mut self, account: &str, res: &Result<T, Error>) {
+        let Err(e) = res else {
+            return;
+        };
+        let Some(entry) = self
+            .manifest
+            .accounts
+            .iter_mut()
+            .rfind(|entry| entry.account == account)
+        else {
+            return self.record(account, res);
+        };
+        if entry.status != "ok" {
+            // already counted as failed or interrupted
+            return;
+        }
+        entry.status = "failed";
+        entry.error = Some(e.to_string());
+        self.succeeded -= 1;
+        match e {
+            Error::Interrupted => self.interrupted += 1,
+            e => self.failed.push((String::from(account), e.to_string())),
+        }
+    }
+
+    /// Summary for notifications: the outcome, each failure and the files saved, linked to S3
+    /// when they were uploaded
+    pub fn message(&self, command: &str) -> String {
+        let mut text = format!(
+            "`{}` finished: {} accounts succeeded, {} failed\n",
+            command,
+            self.succeeded,
+            self.failed.len()
+        );
+        for (account, error) in &self.failed {
+            text.push_str(&format!("• {}: {}\n", account, error));
+        }
+        let links: Vec<String> = if self.uploaded.is_empty() {
+            self.manifest
+                .files
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect()
+        } else {
+            self.uploaded.clone()
+        };
+        if !links.is_empty() {
+            text.push_str(&format!("{} files saved:\n", links.len()));
+            for link in links.iter().take(MESSAGE_MAX_FILES) {
+                text.push_str(&format!("• {}\n", link));
+            }
+            if links.len() > MESSAGE_MAX_FILES {
+                text.push_str(&format!("• and {} more\n", links.len() - MESSAGE_MAX_FILES));
+            }
+        }
+        text
+    }
+
+    /// Posts the summary to Slack when `--notify-slack` is set, uploading the selected images.
+    /// Returns how many images were uploaded, or `None` without a notifier.
+    pub async fn notify_slack(&self, command: &str) -> Result<Option<usize>, Error> {
+        let Some(slack) = &self.slack else {
+            return Ok(None);
+        };
+        let uploaded = slack.notify(&self.message(command), &self.files()).await?;
+        Ok(Some(uploaded.len()))
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.exit_status())
+    }
+
+    /// The exit status of the run: 0, or one of the `EXIT_*` codes
+    pub fn exit_status(&self) -> u8 {
+        if self.interrupted > 0 {
+            EXIT_INTERRUPTED
+        } else if self.failed.is_empty() {
+            0
+        } else if self.succeeded == 0 {
+            EXIT_ALL_ACCOUNTS_FAILED
+        } else {
+            EXIT_ACCOUNT_FAILURES
+        }
+    }
+}
+
+/// The end-of-run report: the account counts, each failure and the retried calls. Empty when no
+/// account ran.
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.succeeded == 0 && self.failed.is_empty() && self.interrupted == 0 {
+            return Ok(());
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{} accounts succeeded, {} failed",
+            self.succeeded,
+            self.failed.len()
+        )?;
+        if self.interrupted > 0 {
+            writeln!(
+                f,
+                "interrupted: {} accounts did not finish and their output is missing",
+                self.interrupted
+            )?;
+        }
+        let width = self
+            .failed
+            .iter()
+            .map(|(account, _)| account.chars().count())
+            .max()
+            .unwrap_or_default();
+        for (account, error) in &self.failed {
+            writeln!(f, "  {:width$}  {}", account, error, width = width)?;
+        }
+        if !self.retried.is_empty() {
+            writeln!(f, "retried calls:")?;
+            for (operation, retries) in &self.retried {
+                writeln!(f, "  {}: {}", operation, retries)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Subject and message to publish for the run, including when it failed before finishing.
+/// `None` when it succeeded and only failures are published.
+pub fn sns_notification(
+    command: &str,
+    result: &Result<RunSummary, Error>,
+    only_failures: bool,
+) -> Option<(String, String)> {
+    let (subject, message) = match result {
+        Ok(summary) if summary.failed.is_empty() => {
+            if only_failures {
+                return None;
+            }
+            (format!("{} succeeded", command), summary.message(command))
+        }
+        Ok(summary) => (
+            format!("{} had {} failed accounts", command, summary.failed.len()),
+            summary.message(command),
+        ),
+        Err(e) => (
+            format!("{} failed", command),
+            format!("`{}` failed: {}\n", command, e),
+        ),
+    };
+    Some((format!("cloudwatch-metrics-dl {}", subject), message))
+}
+
+/// Publishes the run's outcome to SNS. Failing to publish is logged without changing the exit
+/// code.
+pub async fn notify_sns(
+    topic_arn: &str,
+    retry: RetryPolicy,
+    command: &str,
+    result: &Result<RunSummary, Error>,
+    only_failures: bool,
+) {
+    let Some((subject, message)) = sns_notification(command, result, only_failures) else {
+        return;
+    };
+    let published = match SnsPublisher::new(topic_arn, retry).await {
+        Ok(publisher) => publisher.publish(&subject, &message).await,
+        Err(e) => Err(e),
+    };
+    match published {
+        Ok(message_id) => {
+            info!(topic = topic_arn, message_id = %message_id, "published run summary")
+        }
+        Err(e) => warn!(topic = topic_arn, error = %e, "failed to publish run summary"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure() -> Result<(), Error> {
+        Err(Error::NoBaseline(String::from("us-east-1")))
+    }
+
+    #[test]
+    fn exit_status_follows_the_accounts() {
+        let mut summary = RunSummary::default();
+        assert_eq!(summary.exit_status(), 0);
+        summary.record("a", &Ok(()));
+        assert_eq!(summary.exit_status(), 0);
+        summary.record("b", &failure());
+        assert_eq!(summary.exit_status(), EXIT_ACCOUNT_FAILURES);
+        summary.record("c", &Err::<(), _>(Error::Interrupted));
+        assert_eq!(summary.exit_status(), EXIT_INTERRUPTED);
+
+        let mut summary = RunSummary::default();
+        summary.record("a", &failure());
+        summary.record("b", &failure());
+        assert_eq!(summary.exit_status(), EXIT_ALL_ACCOUNTS_FAILED);
+        assert_eq!(summary.manifest.accounts.len(), 2);
+    }
+
+    #[test]
+    fn record_again_fails_accounts_once() {
+        let mut summary = RunSummary::default();
+        summary.record("a", &Ok(()));
+        summary.record("b", &Ok(()));
+        summary.record("c", &failure());
+
+        summary.record_again("a", &Ok(()));
+        summary.record_again("b", &failure());
+        summary.record_again("c", &failure());
+        summary.record_again("d", &failure());
+
+        assert_eq!(summary.succeeded, 1);
+        let failed: Vec<&str> = summary.failed.iter().map(|(a, _)| a.as_str()).collect();
+        assert_eq!(failed, ["c", "b", "d"]);
+        let statuses: Vec<(&str, &str)> = summary
+            .manifest
+            .accounts
+            .iter()
+            .map(|entry| (entry.account.as_str(), entry.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("a", "ok"),
+                ("b", "failed"),
+                ("c", "failed"),
+                ("d", "failed")
+            ]
+        );
+        assert_eq!(summary.exit_status(), EXIT_ACCOUNT_FAILURES);
+    }
+
+    #[test]
+    fn next_round_keeps_the_notifier_and_inputs() {
+        let mut summary = RunSummary {
+            slack: Some(SlackNotifier::new("https://hooks.slack.com/services/T/B/X")),
+            ..Default::default()
+        };
+        summary.inputs(Some("traffic.json"), "24H", "0H");
+        summary.record("a", &failure());
+        summary.output(Path::new("index.html"));
+        summary
+            .uploaded
+            .push(String::from("s3://bucket/index.html"));
+
+        summary.next_round();
+
+        assert!(summary.slack.is_some());
+        assert_eq!(
+            summary.manifest.template.as_deref(),
+            Some(Path::new("traffic.json"))
+        );
+        assert_eq!(summary.manifest.start.as_deref(), Some("24H"));
+        assert_eq!(summary.manifest.end.as_deref(), Some("0H"));
+        assert!(summary.failed.is_empty());
+        assert!(summary.manifest.accounts.is_empty());
+        assert!(summary.files().is_empty());
+        assert!(summary.uploaded.is_empty());
+    }
+
+    #[test]
+    fn display_aligns_the_failures() {
+        let mut summary = RunSummary::default();
+        assert_eq!(summary.to_string(), "");
+        summary.record("a", &Ok(()));
+        summary.record("prod", &failure());
+        summary.record("b", &failure());
+        summary.retried.insert(String::from("GetMetricData"), 3);
+        assert_eq!(
+            summary.to_string(),
+            "\n1 accounts succeeded, 2 failed\n  \
+             prod  no account matching us-east-1 has alarms to compare against\n  \
+             b     no account matching us-east-1 has alarms to compare against\n\
+             retried calls:\n  GetMetricData: 3\n"
+        );
+    }
+
+    #[test]
+    fn message_lists_failures_and_some_files() {
+        let mut summary = RunSummary::default();
+        summary.record("a", &Ok(()));
+        summary.record("b", &failure());
+        for i in 0..25 {
+            summary.output(Path::new(&format!("image-{}.png", i)));
+        }
+        let message = summary.message("images");
+        assert!(message.starts_with("`images` finished: 1 accounts succeeded, 1 failed\n"));
+        assert!(message.contains("• b: no account matching us-east-1"));
+        assert!(message.contains("25 files saved:\n"));
+        assert!(message.contains("• image-19.png\n"));
+        assert!(!message.contains("image-20.png"));
+        assert!(message.ends_with("• and 5 more\n"));
+
+        // uploaded files are linked instead of the local paths
+        summary.uploaded = vec![String::from("s3://bucket/image-0.png")];
+        let message = summary.message("images");
+        assert!(message.contains("1 files saved:\n• s3://bucket/image-0.png\n"));
+        assert!(!message.contains("and 5 more"));
+    }
+
+    #[test]
+    fn sns_notification_subjects() {
+        let mut summary = RunSummary::default();
+        summary.record("a", &Ok(()));
+        let ok = Ok(summary);
+        assert_eq!(sns_notification("alarms", &ok, true), None);
+        let (subject, _) = sns_notification("alarms", &ok, false).unwrap();
+        assert_eq!(subject, "cloudwatch-metrics-dl alarms succeeded");
+
+        let mut summary = RunSummary::default();
+        summary.record("a", &failure());
+        let (subject, message) = sns_notification("alarms", &Ok(summary), true).unwrap();
+        assert_eq!(
+            subject,
+            "cloudwatch-metrics-dl alarms had 1 failed accounts"
+        );
+        assert!(message.contains("• a: "));
+
+        let fatal = Err(Error::NoBaseline(String::from("prod")));
+        let (subject, message) = sns_notification("alarms", &fatal, true).unwrap();
+        assert_eq!(subject, "cloudwatch-metrics-dl alarms failed");
+        assert!(message.starts_with("`alarms` failed: no account matching prod"));
+    }
+}
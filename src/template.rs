@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use tera::{Context, Tera};
+use tracing::trace;
+
+use crate::config::AccountConfig;
+use crate::error::Error;
+
+/// Builds the [Tera](https://keats.github.io/tera/docs/) context for an account's templates.
+///
+/// The original placeholders stay top-level variables, so `{{NAMESPACE}}`, `{{REGION}}`,
+/// `{{PERIOD_START}}` (e.g. `72H` for a start time of `3d`), `{{PERIOD_END}}` and `{{PERIOD}}`
+/// keep working. Template variables are top-level too, from the account's `vars` table
+/// overridden by `vars` given for the whole run, and are also available as `vars`. `account`
+/// has the account's fields.
+pub(crate) fn template_context(
+    acc: &AccountConfig,
+    start: &str,
+    end: &str,
+    period: &str,
+    vars: &BTreeMap<String, String>,
+) -> Context {
+    let mut merged = acc.vars.clone();
+    merged.extend(vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+    let mut context = Context::new();
+    for (key, value) in &merged {
+        context.insert(key.as_str(), value);
+    }
+    context.insert("vars", &merged);
+
+    let mut account = BTreeMap::new();
+    account.insert("namespace", acc.namespace.as_str());
+    account.insert("region", acc.region.as_str());
+    account.insert("role_arn", acc.role_arn.as_str());
+    context.insert("account", &account);
+
+    // built-in variables are inserted last so they can't be overridden
+    context.insert("NAMESPACE", &acc.namespace);
+    context.insert("REGION", &acc.region);
+    context.insert("PERIOD_START", start);
+    context.insert("PERIOD_END", end);
+    context.insert("PERIOD", period);
+    context
+}
+
+/// Reads the template at `filepath` and renders it with `context`
+pub(crate) fn render_template(filepath: &Path, context: &Context) -> Result<String, Error> {
+    let contents = std::fs::read_to_string(filepath).map_err(|source| Error::Read {
+        path: filepath.to_path_buf(),
+        source,
+    })?;
+    let rendered = render_str(&filepath.display().to_string(), &contents, context)?;
+
+    trace!(path = %filepath.display(), templated = %rendered, "rendered template");
+
+    Ok(rendered)
+}
+
+/// Reads the template at `filepath` and parses it without rendering, so an unreadable file or
+/// broken syntax stops a run up front instead of failing every account
+pub(crate) fn check_template(filepath: &Path) -> Result<(), Error> {
+    let contents = std::fs::read_to_string(filepath).map_err(|source| Error::Read {
+        path: filepath.to_path_buf(),
+        source,
+    })?;
+    let name = filepath.display().to_string();
+    Tera::default()
+        .add_raw_template(&name, &contents)
+        .map_err(|source| Error::Render { name, source })
+}
+
+/// Renders a template given inline, `name` identifies it in errors
+pub(crate) fn render_str(name: &str, template: &str, context: &Context) -> Result<String, Error> {
+    Tera::one_off(template, context, false).map_err(|source| Error::Render {
+        name: String::from(name),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        let acc = AccountConfig::new(
+            "Orders",
+            "us-east-1",
+            "arn:aws:iam::111111111111:role/ReadOnly",
+        )
+        .var("STAGE", "beta")
+        .var("SERVICE", "orders");
+        let vars = BTreeMap::from([(String::from("STAGE"), String::from("prod"))]);
+        template_context(&acc, "72H", "0H", "300", &vars)
+    }
+
+    #[test]
+    fn renders_original_placeholders() {
+        let rendered = render_str(
+            "widget.json",
+            r#"{"title": "{{NAMESPACE}} {{REGION}} {{STAGE}}", "start": "-PT{{PERIOD_START}}", "end": "-PT{{PERIOD_END}}", "period": {{PERIOD}}}"#,
+            &context(),
+        )
+        .unwrap();
+        assert_eq!(
+            rendered,
+            r#"{"title": "Orders us-east-1 prod", "start": "-PT72H", "end": "-PT0H", "period": 300}"#
+        );
+    }
+
+    #[test]
+    fn renders_vars_and_account_fields() {
+        let rendered = render_str(
+            "widget.json",
+            "{{ vars.SERVICE }} {{ account.namespace | lower }} {% if STAGE == \"prod\" %}paged{% endif %}",
+            &context(),
+        )
+        .unwrap();
+        assert_eq!(rendered, "orders orders paged");
+    }
+
+    #[test]
+    fn keeps_literal_braces_in_widget_json() {
+        let widget = r#"{"metrics": [["AWS/Lambda", "Errors", {"stat": "Sum"}]], "yAxis": {"left": {"min": 0}}}"#;
+        assert_eq!(
+            render_str("widget.json", widget, &context()).unwrap(),
+            widget
+        );
+    }
+
+    #[test]
+    fn undefined_variable_names_the_template() {
+        let err = render_str("widget.json", "{{ MISSING }}", &context()).unwrap_err();
+        assert!(matches!(&err, Error::Render { name, .. } if name == "widget.json"));
+        assert!(err.to_string().contains("MISSING"), "{}", err);
+    }
+}
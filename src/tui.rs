@@ -0,0 +1,390 @@
+use std::io::Stdout;
+use std::path::PathBuf;
+
+use aws_sdk_cloudwatch::model::Metric;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use serde_json::json;
+
+use crate::alarms::{describe_alarms, to_alarm_details, MetricAlarmDetails};
+use crate::config::AccountConfig;
+use crate::coverage::list_metrics;
+use crate::error::Error;
+use crate::images::{get_metric_image, ImageFormat};
+use crate::runner::RunOptions;
+
+const MENU: [&str; 2] = ["metrics", "alarms"];
+const HELP: &str = "↑/↓ move, enter select, esc back, q quit";
+
+/// What one level of the browser lists
+enum Screen {
+    Accounts,
+    Menu,
+    Namespaces(Vec<String>),
+    Metrics(String, Vec<Metric>),
+    Alarms(Vec<MetricAlarmDetails>),
+}
+
+struct View {
+    screen: Screen,
+    state: ListState,
+}
+
+impl View {
+    fn new(screen: Screen) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        View { screen, state }
+    }
+
+    fn selected(&self) -> usize {
+        self.state.selected().unwrap_or_default()
+    }
+}
+
+/// Interactive terminal browser: pick an account, then browse its namespaces and metrics or its
+/// alarms. Enter on a metric downloads a widget image of its last day.
+pub struct Browser {
+    accounts: Vec<AccountConfig>,
+    account: usize,
+    output_path: Option<PathBuf>,
+    options: RunOptions,
+    stack: Vec<View>,
+    status: String,
+}
+
+impl Browser {
+    pub fn new(accounts: Vec<AccountConfig>) -> Self {
+        Browser {
+            accounts,
+            account: 0,
+            output_path: None,
+            options: RunOptions::default(),
+            stack: vec![View::new(Screen::Accounts)],
+            status: String::from(HELP),
+        }
+    }
+
+    /// Directory metric images are saved in, defaults to the current directory
+    pub fn output_path(mut self, output_path: Option<PathBuf>) -> Self {
+        self.output_path = output_path;
+        self
+    }
+
+    pub fn options(mut self, options: RunOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Takes over the terminal until `q` is pressed, restoring it even when drawing fails
+    pub async fn run(mut self) -> Result<(), Error> {
+        enable_raw_mode().map_err(Error::Terminal)?;
+        let mut stdout = std::io::stdout();
+        crossterm::execute!(stdout, EnterAlternateScreen).map_err(Error::Terminal)?;
+        let res = match Terminal::new(CrosstermBackend::new(stdout)) {
+            Ok(mut terminal) => self.event_loop(&mut terminal).await,
+            Err(e) => Err(Error::Terminal(e)),
+        };
+        disable_raw_mode().map_err(Error::Terminal)?;
+        crossterm::execute!(std::io::stdout(), LeaveAlternateScreen).map_err(Error::Terminal)?;
+        res
+    }
+
+    async fn event_loop(
+        &mut self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    ) -> Result<(), Error> {
+        loop {
+            terminal.draw(|f| self.draw(f)).map_err(Error::Terminal)?;
+            let key = match event::read().map_err(Error::Terminal)? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Esc | KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
+                    if self.stack.len() > 1 {
+                        self.stack.pop();
+                    }
+                }
+                KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                    self.status = String::from("loading...");
+                    terminal.draw(|f| self.draw(f)).map_err(Error::Terminal)?;
+                    // failed calls are shown in the status line rather than closing the browser
+                    if let Err(e) = self.select().await {
+                        self.status = format!("error: {}", e);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn view(&self) -> &View {
+        self.stack
+            .last()
+            .expect("the accounts view is never popped")
+    }
+
+    fn items(&self) -> Vec<ListItem<'static>> {
+        match &self.view().screen {
+            Screen::Accounts => self
+                .accounts
+                .iter()
+                .map(|acc| ListItem::new(acc.label()))
+                .collect(),
+            Screen::Menu => MENU.iter().map(|item| ListItem::new(*item)).collect(),
+            Screen::Namespaces(namespaces) => namespaces
+                .iter()
+                .map(|namespace| ListItem::new(namespace.clone()))
+                .collect(),
+            Screen::Metrics(_, metrics) => metrics
+                .iter()
+                .map(|metric| ListItem::new(metric_label(metric)))
+                .collect(),
+            Screen::Alarms(alarms) => alarms
+                .iter()
+                .map(|alarm| {
+                    ListItem::new(format!("{:<17} {}", alarm.state_value, alarm.alarm_name))
+                        .style(Style::default().fg(state_color(&alarm.state_value)))
+                })
+                .collect(),
+        }
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        let len = self.items().len();
+        let view = self
+            .stack
+            .last_mut()
+            .expect("the accounts view is never popped");
+        if len > 0 {
+            let selected = view.selected() as isize + by;
+            view.state
+                .select(Some(selected.clamp(0, len as isize - 1) as usize));
+        }
+    }
+
+    async fn select(&mut self) -> Result<(), Error> {
+        let selected = self.view().selected();
+        let screen = match &self.view().screen {
+            Screen::Accounts if selected < self.accounts.len() => {
+                self.account = selected;
+                Screen::Menu
+            }
+            Screen::Menu if selected == 0 => {
+                let client = self.client().await?;
+                let mut namespaces: Vec<String> = list_metrics(&client, &self.options.retry, None)
+                    .await?
+                    .iter()
+                    .filter_map(|metric| metric.namespace().map(String::from))
+                    .collect();
+                namespaces.sort_unstable();
+                namespaces.dedup();
+                Screen::Namespaces(namespaces)
+            }
+            Screen::Menu => {
+                let client = self.client().await?;
+                let program_name = &self.accounts[self.account].namespace;
+                let alarms = describe_alarms(&client, &self.options.retry, None, None)
+                    .await?
+                    .iter()
+                    .map(|alarm| to_alarm_details(program_name, alarm))
+                    .collect();
+                Screen::Alarms(alarms)
+            }
+            Screen::Namespaces(namespaces) if selected < namespaces.len() => {
+                let namespace = namespaces[selected].clone();
+                let client = self.client().await?;
+                let mut metrics =
+                    list_metrics(&client, &self.options.retry, Some(&namespace)).await?;
+                metrics.sort_by_key(metric_label);
+                Screen::Metrics(namespace, metrics)
+            }
+            Screen::Metrics(_, metrics) if selected < metrics.len() => {
+                let path = self.download(&metrics[selected]).await?;
+                self.status = format!("saved {}", path.display());
+                return Ok(());
+            }
+            _ => {
+                self.status = String::from(HELP);
+                return Ok(());
+            }
+        };
+        self.status = String::from(HELP);
+        self.stack.push(View::new(screen));
+        Ok(())
+    }
+
+    async fn client(&self) -> Result<aws_sdk_cloudwatch::Client, Error> {
+        self.options
+            .cloudwatch_client(&self.accounts[self.account])
+            .await
+    }
+
+    /// Saves a widget image of the metric's average over the last day
+    async fn download(&self, metric: &Metric) -> Result<PathBuf, Error> {
+        let acc = &self.accounts[self.account];
+        let mut series = vec![
+            String::from(metric.namespace().unwrap_or_default()),
+            String::from(metric.metric_name().unwrap_or_default()),
+        ];
+        for dimension in metric.dimensions().unwrap_or_default() {
+            series.push(String::from(dimension.name().unwrap_or_default()));
+            series.push(String::from(dimension.value().unwrap_or_default()));
+        }
+        let widget = json!({
+            "metrics": [series],
+            "view": "timeSeries",
+            "stat": "Average",
+            "period": 300,
+            "start": "-PT24H",
+            "region": acc.region,
+            "title": metric_label(metric),
+        });
+        let name: String = format!(
+            "{}-{}-{}-{}",
+            acc.namespace,
+            acc.region,
+            metric.namespace().unwrap_or_default(),
+            metric.metric_name().unwrap_or_default()
+        )
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+        let path = self
+            .output_path
+            .clone()
+            .unwrap_or_default()
+            .join(name)
+            .with_extension(ImageFormat::Png.extension());
+        let client = self.client().await?;
+        get_metric_image(
+            &client,
+            &self.options.retry,
+            &widget.to_string(),
+            ImageFormat::Png,
+            &path,
+        )
+        .await?;
+        Ok(path)
+    }
+
+    fn title(&self) -> String {
+        let mut title = vec![String::from("accounts")];
+        for view in &self.stack[1..] {
+            title.push(match &view.screen {
+                Screen::Accounts => String::new(),
+                Screen::Menu => self.accounts[self.account].label(),
+                Screen::Namespaces(_) => String::from("metrics"),
+                Screen::Metrics(namespace, _) => namespace.clone(),
+                Screen::Alarms(_) => String::from("alarms"),
+            });
+        }
+        title.join(" > ")
+    }
+
+    fn draw<B: Backend>(&mut self, f: &mut Frame<'_, B>) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(1)])
+            .split(f.size());
+        let list = List::new(self.items())
+            .block(Block::default().borders(Borders::ALL).title(self.title()))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> ");
+        let details = match &self.view().screen {
+            Screen::Alarms(alarms) => alarms.get(self.view().selected()).map(alarm_details),
+            _ => None,
+        };
+        let view = self
+            .stack
+            .last_mut()
+            .expect("the accounts view is never popped");
+        match details {
+            Some(details) => {
+                let columns = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(rows[0]);
+                f.render_stateful_widget(list, columns[0], &mut view.state);
+                f.render_widget(
+                    Paragraph::new(details)
+                        .block(Block::default().borders(Borders::ALL).title("details"))
+                        .wrap(Wrap { trim: false }),
+                    columns[1],
+                );
+            }
+            None => f.render_stateful_widget(list, rows[0], &mut view.state),
+        }
+        f.render_widget(Paragraph::new(self.status.clone()), rows[1]);
+    }
+}
+
+/// The metric name followed by its dimensions, like `Errors FunctionName=checkout`
+fn metric_label(metric: &Metric) -> String {
+    let mut label = String::from(metric.metric_name().unwrap_or_default());
+    for dimension in metric.dimensions().unwrap_or_default() {
+        label.push_str(&format!(
+            " {}={}",
+            dimension.name().unwrap_or_default(),
+            dimension.value().unwrap_or_default()
+        ));
+    }
+    label
+}
+
+fn state_color(state: &str) -> Color {
+    match state {
+        "ALARM" => Color::Red,
+        "OK" => Color::Green,
+        _ => Color::Yellow,
+    }
+}
+
+fn alarm_details(alarm: &MetricAlarmDetails) -> String {
+    let dimensions: Vec<String> = alarm
+        .dimensions
+        .iter()
+        .map(|d| format!("{}={}", d.name, d.value))
+        .collect();
+    let statistic = if alarm.statistic.is_empty() {
+        &alarm.extended_statistic
+    } else {
+        &alarm.statistic
+    };
+    format!(
+        "{}\n\nstate: {}\nreason: {}\n\nmetric: {} {}\ndimensions: {}\nstatistic: {} over {}s\ncondition: {} {} for {} of {} periods\nmissing data: {}\n\nactions enabled: {}\nactions:\n  {}\n\n{}",
+        alarm.alarm_name,
+        alarm.state_value,
+        alarm.state_reason,
+        alarm.namespace,
+        alarm.metric_name,
+        dimensions.join(", "),
+        statistic,
+        alarm.period,
+        alarm.comparison_operator,
+        alarm.threshold,
+        alarm.datapoints_to_alarm.unwrap_or(alarm.evaluation_periods),
+        alarm.evaluation_periods,
+        alarm.treat_missing_data,
+        alarm.actions_enabled,
+        alarm.alarm_actions.join("\n  "),
+        alarm.alarm_description
+    )
+}
@@ -0,0 +1,143 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use aws_sdk_s3::types::ByteStream;
+use aws_sdk_s3::Client as s3Client;
+use aws_smithy_types::date_time::Format;
+use aws_smithy_types::DateTime;
+use aws_types::region::Region;
+
+use crate::client::{
+    base_config, get_config_with_role, get_sts_client, AssumeRoleOptions, CredentialCache,
+    DEFAULT_SESSION_NAME,
+};
+use crate::error::Error;
+use crate::retry::RetryPolicy;
+
+/// Region used for the upload role's STS call when the environment doesn't set one
+const DEFAULT_UPLOAD_REGION: &str = "us-east-1";
+
+/// Uploads saved outputs to `s3://bucket/prefix/YYYY/MM/DD/<path>`
+#[derive(Debug, Clone)]
+pub struct S3Uploader {
+    client: s3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Uploader {
+    /// Builds an uploader for `s3_uri` using the default credential chain, or `upload_role` when
+    /// the shared bucket is only writable from a dedicated role
+    pub async fn new(
+        s3_uri: &str,
+        upload_role: Option<&AssumeRoleOptions>,
+        credential_cache: &CredentialCache,
+        retry: &RetryPolicy,
+    ) -> Result<Self, Error> {
+        let (bucket, prefix) = parse_s3_uri(s3_uri).ok_or_else(|| Error::InvalidArgument {
+            name: "S3 URI",
+            value: String::from(s3_uri),
+        })?;
+        let shared_config = base_config().await.clone();
+        let shared_config = match upload_role {
+            Some(role) => {
+                let region = shared_config
+                    .region()
+                    .cloned()
+                    .unwrap_or_else(|| Region::new(DEFAULT_UPLOAD_REGION));
+                let sts_client = get_sts_client(&region).await;
+                get_config_with_role(&region, role, &sts_client, credential_cache, retry).await?
+            }
+            None => shared_config,
+        };
+        Ok(S3Uploader {
+            client: s3Client::new(&shared_config),
+            bucket,
+            prefix,
+        })
+    }
+
+    /// Uploads the file at `path`, returning the `s3://` URI it was saved to
+    pub async fn upload(&self, path: &Path) -> Result<String, Error> {
+        let key = self.key_for(path);
+        let body = tokio::fs::read(path).await.map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| Error::Upload {
+                key: key.clone(),
+                source: e.into(),
+            })?;
+        Ok(format!("s3://{}/{}", self.bucket, key))
+    }
+
+    /// Date-partitioned key for a saved file, keeping its relative directories so outputs from
+    /// different runs of the same day don't collide unless they had the same local path
+    fn key_for(&self, path: &Path) -> String {
+        let today = DateTime::from(SystemTime::now())
+            .fmt(Format::DateTime)
+            .unwrap_or_default();
+        let date = today.get(..10).unwrap_or_default().replace('-', "/");
+        let relative: Vec<String> = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+        format!("{}{}/{}", self.prefix, date, relative.join("/"))
+    }
+}
+
+/// Uploads every file saved by the run, with the upload role when one is given, and returns
+/// their `s3://` URIs in the same order
+pub async fn upload_outputs(
+    s3_uri: &str,
+    role_arn: Option<&str>,
+    retry: &RetryPolicy,
+    outputs: &[PathBuf],
+) -> Result<Vec<String>, Error> {
+    let upload_role = role_arn.map(|role_arn| AssumeRoleOptions {
+        role_arn: String::from(role_arn),
+        session_name: String::from(DEFAULT_SESSION_NAME),
+        session_duration: None,
+        external_id: None,
+        via_role_arn: None,
+        mfa_serial: None,
+    });
+    let uploader = S3Uploader::new(
+        s3_uri,
+        upload_role.as_ref(),
+        &CredentialCache::default(),
+        retry,
+    )
+    .await?;
+    let mut uris = vec![];
+    for path in outputs {
+        uris.push(uploader.upload(path).await?);
+    }
+    Ok(uris)
+}
+
+/// Splits `s3://bucket/prefix/` into the bucket and a prefix that is empty or ends with `/`
+fn parse_s3_uri(s3_uri: &str) -> Option<(String, String)> {
+    let rest = s3_uri.strip_prefix("s3://")?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return None;
+    }
+    let prefix = prefix.trim_matches('/');
+    let prefix = if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", prefix)
+    };
+    Some((String::from(bucket), prefix))
+}
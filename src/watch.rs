@@ -0,0 +1,244 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::alarms::MetricAlarmDetails;
+use crate::error::Error;
+use crate::runner::AccountResults;
+use crate::summary::RunSummary;
+
+/// The alarms of one `watch_alarms` refresh
+#[derive(Debug, Default)]
+pub struct Refresh {
+    /// Every alarm described, with its account
+    pub alarms: Vec<(String, MetricAlarmDetails)>,
+    /// `account: error` for each account that failed
+    pub failed: Vec<String>,
+}
+
+impl Refresh {
+    /// How many alarms are in `state`
+    pub fn count(&self, state: &str) -> usize {
+        self.alarms
+            .iter()
+            .filter(|(_, alarm)| alarm.state_value == state)
+            .count()
+    }
+}
+
+/// Describes the alarms each interval and hands every refresh to `show` until Ctrl-C. Only the
+/// latest refresh counts towards the summary.
+pub async fn watch_alarms<F, Fut, S>(
+    mut describe: F,
+    interval: Duration,
+    summary: &mut RunSummary,
+    mut show: S,
+) -> Result<(), Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AccountResults<Vec<MetricAlarmDetails>>>,
+    S: FnMut(&Refresh),
+{
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        let results = tokio::select! {
+            results = describe() => results,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        summary.next_round();
+        let mut refresh = Refresh::default();
+        for (account, res) in results {
+            summary.record(&account, &res);
+            match res {
+                Ok(res) => refresh
+                    .alarms
+                    .extend(res.into_iter().map(|alarm| (account.clone(), alarm))),
+                Err(e) => refresh.failed.push(format!("{}: {}", account, e)),
+            }
+        }
+        show(&refresh);
+    }
+    info!("stopped watching");
+    Ok(())
+}
+
+/// Polls `describe` every `interval` until every alarm it returns is in `state` in every
+/// account, returning how many alarms matched, or fails once `timeout` has passed. Only the
+/// last poll counts towards the summary.
+pub async fn wait_for_alarm_state<F, Fut>(
+    mut describe: F,
+    state: &str,
+    interval: Duration,
+    timeout: Duration,
+    summary: &mut RunSummary,
+) -> Result<usize, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = AccountResults<Vec<MetricAlarmDetails>>>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        summary.next_round();
+        let mut matched = 0;
+        let mut pending = vec![];
+        for (account, res) in describe().await {
+            summary.record(&account, &res);
+            match res {
+                Ok(alarms) => {
+                    matched += alarms.len();
+                    pending.extend(
+                        alarms
+                            .iter()
+                            .filter(|alarm| alarm.state_value != state)
+                            .map(|alarm| {
+                                format!(
+                                    "{}: {} is {}",
+                                    account, alarm.alarm_name, alarm.state_value
+                                )
+                            }),
+                    );
+                }
+                Err(e) => warn!(account = %account, error = %e, "failed to describe alarms"),
+            }
+        }
+        // an account that failed might still have alarms in another state
+        if pending.is_empty() && summary.failed.is_empty() {
+            if matched == 0 {
+                warn!("no alarms match, nothing to wait for");
+            }
+            return Ok(matched);
+        }
+        if Instant::now() + interval > deadline {
+            for alarm in &pending {
+                warn!("{}", alarm);
+            }
+            return Err(Error::WaitTimeout {
+                state: String::from(state),
+                pending: pending.len(),
+            });
+        }
+        info!(
+            pending = pending.len(),
+            "waiting for alarms to be {}", state
+        );
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alarms::test_alarm;
+
+    fn alarm(name: &str, state: &str) -> MetricAlarmDetails {
+        MetricAlarmDetails {
+            state_value: String::from(state),
+            ..test_alarm(name)
+        }
+    }
+
+    /// The alarms of each account in one poll, `None` for an account that fails
+    type Poll = Vec<(&'static str, Option<Vec<MetricAlarmDetails>>)>;
+
+    /// Describes the alarms of each poll in turn, repeating the last poll
+    fn polls(
+        polls: Vec<Poll>,
+    ) -> impl FnMut() -> std::future::Ready<AccountResults<Vec<MetricAlarmDetails>>> {
+        let mut polls = polls.into_iter();
+        let mut last = vec![];
+        move || {
+            if let Some(poll) = polls.next() {
+                last = poll;
+            }
+            let results = last
+                .iter()
+                .map(|(account, alarms)| {
+                    let res = alarms
+                        .clone()
+                        .ok_or_else(|| Error::NoBaseline(String::from(*account)));
+                    (String::from(*account), res)
+                })
+                .collect();
+            std::future::ready(results)
+        }
+    }
+
+    #[tokio::test]
+    async fn waits_until_every_alarm_is_in_the_state() {
+        let mut summary = RunSummary::default();
+        let describe = polls(vec![
+            vec![
+                ("a", Some(vec![alarm("Latency", "ALARM")])),
+                ("b", Some(vec![alarm("Latency", "OK")])),
+            ],
+            vec![
+                ("a", Some(vec![alarm("Latency", "OK")])),
+                ("b", Some(vec![alarm("Latency", "OK")])),
+            ],
+        ]);
+        let res = wait_for_alarm_state(
+            describe,
+            "OK",
+            Duration::from_millis(10),
+            Duration::from_secs(5),
+            &mut summary,
+        )
+        .await;
+        assert_eq!(res.unwrap(), 2);
+        // only the last poll counts
+        assert_eq!(summary.succeeded, 2);
+        assert_eq!(summary.manifest.accounts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn times_out_with_the_pending_alarms() {
+        let mut summary = RunSummary::default();
+        let describe = polls(vec![vec![
+            (
+                "a",
+                Some(vec![alarm("Latency", "ALARM"), alarm("Errors", "OK")]),
+            ),
+            ("b", Some(vec![alarm("Latency", "INSUFFICIENT_DATA")])),
+        ]]);
+        let res = wait_for_alarm_state(
+            describe,
+            "OK",
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+            &mut summary,
+        )
+        .await;
+        match res {
+            Err(Error::WaitTimeout { state, pending }) => {
+                assert_eq!(state, "OK");
+                assert_eq!(pending, 2);
+            }
+            res => panic!("expected a timeout, got {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_waiting_while_an_account_fails() {
+        let mut summary = RunSummary::default();
+        let describe = polls(vec![vec![
+            ("a", Some(vec![alarm("Latency", "OK")])),
+            ("b", None),
+        ]]);
+        let res = wait_for_alarm_state(
+            describe,
+            "OK",
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+            &mut summary,
+        )
+        .await;
+        assert!(matches!(res, Err(Error::WaitTimeout { pending: 0, .. })));
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashSet};
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet, XlsxError};
+
+use crate::alarms::{alarm_row, MetricAlarmDetails, ALARM_COLUMNS};
+use crate::data::{metric_summary_row, AccountMetricData, SUMMARY_COLUMNS};
+
+/// What each sheet of the review workbook covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheetGrouping {
+    /// One sheet per account's program name
+    Account,
+    /// One sheet per CloudWatch namespace, like `AWS/Kinesis`, across accounts
+    Namespace,
+}
+
+/// Sheet for series and alarms that use metric math instead of a single namespace
+const EXPRESSIONS_SHEET: &str = "expressions";
+
+/// Writes a review workbook with a metric summary table and an alarms table on each sheet.
+/// Numeric cells are written as numbers so they can be sorted and charted in Excel.
+pub fn review_to_xlsx(
+    all_data: &[AccountMetricData],
+    alarms: &[MetricAlarmDetails],
+    grouping: SheetGrouping,
+) -> Result<Vec<u8>, XlsxError> {
+    let sheet_key = |program_name: &str, namespace: &str| match grouping {
+        SheetGrouping::Account => String::from(program_name),
+        SheetGrouping::Namespace if namespace.is_empty() => String::from(EXPRESSIONS_SHEET),
+        SheetGrouping::Namespace => String::from(namespace),
+    };
+    let mut sheets: BTreeMap<String, (Vec<Vec<String>>, Vec<Vec<String>>)> = BTreeMap::new();
+    for acc in all_data {
+        for series in &acc.series {
+            let row = metric_summary_row(acc, series);
+            sheets
+                .entry(sheet_key(&acc.program_name, &series.namespace))
+                .or_default()
+                .0
+                .push(row);
+        }
+    }
+    for alarm in alarms {
+        sheets
+            .entry(sheet_key(&alarm.program_name, &alarm.namespace))
+            .or_default()
+            .1
+            .push(alarm_row(alarm));
+    }
+
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let mut used_names: HashSet<String> = HashSet::new();
+    for (key, (summaries, alarms)) in sheets {
+        let sheet = workbook.add_worksheet();
+        sheet.set_name(&sheet_name(&key, &mut used_names))?;
+        let row = write_table(
+            sheet,
+            0,
+            "Metric summary",
+            &SUMMARY_COLUMNS,
+            &summaries,
+            &bold,
+        )?;
+        write_table(sheet, row + 1, "Alarms", &ALARM_COLUMNS, &alarms, &bold)?;
+        sheet.autofit();
+    }
+    workbook.save_to_buffer()
+}
+
+/// Writes a bold title and header followed by the rows, returning the row after the table
+fn write_table(
+    sheet: &mut Worksheet,
+    mut row: u32,
+    title: &str,
+    columns: &[&str],
+    rows: &[Vec<String>],
+    bold: &Format,
+) -> Result<u32, XlsxError> {
+    sheet.write_string_with_format(row, 0, title, bold)?;
+    row += 1;
+    for (col, column) in (0u16..).zip(columns) {
+        sheet.write_string_with_format(row, col, *column, bold)?;
+    }
+    row += 1;
+    for cells in rows {
+        for (col, cell) in (0u16..).zip(cells) {
+            match cell.parse::<f64>() {
+                Ok(number) if number.is_finite() => sheet.write_number(row, col, number)?,
+                _ => sheet.write_string(row, col, cell)?,
+            };
+        }
+        row += 1;
+    }
+    Ok(row)
+}
+
+/// Excel sheet names are at most 31 characters, can't contain `[]:*?/\` and must be unique
+/// ignoring case
+fn sheet_name(key: &str, used_names: &mut HashSet<String>) -> String {
+    let base: String = key
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .take(31)
+        .collect();
+    let base = if base.trim().is_empty() {
+        String::from("Sheet")
+    } else {
+        base
+    };
+    let mut name = base.clone();
+    let mut n = 2;
+    while !used_names.insert(name.to_lowercase()) {
+        let suffix = format!(" ({})", n);
+        name = base
+            .chars()
+            .take(31 - suffix.len())
+            .chain(suffix.chars())
+            .collect();
+        n += 1;
+    }
+    name
+}